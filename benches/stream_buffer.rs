@@ -0,0 +1,77 @@
+//! File download throughput of the HTTP server at different stream buffer sizes
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use oservers::servers::http::{self, HttpConfig};
+use oservers::servers::{ServerConfig, ServerState, SharedState};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Size of the file downloaded per iteration
+const FILE_SIZE: usize = 64 * 1024 * 1024;
+
+const BUFFER_SIZES: [usize; 4] = [4 * 1024, 64 * 1024, 512 * 1024, 4 * 1024 * 1024];
+
+/// Download `/file.bin` from the server on `port`, returning the bytes received
+async fn download(port: u16) -> usize {
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /file.bin HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::with_capacity(FILE_SIZE + 1024);
+    stream.read_to_end(&mut response).await.unwrap();
+    response.len()
+}
+
+fn stream_buffer(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let root = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("file.bin"), vec![0x5a; FILE_SIZE]).unwrap();
+
+    let mut group = c.benchmark_group("stream_buffer");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.sample_size(20);
+    for size in BUFFER_SIZES {
+        let config = HttpConfig {
+            root_dir: root.path().to_path_buf(),
+            port: 0,
+            auto_stop_seconds: None,
+            stream_buffer_size_bytes: size,
+            // Logging every download would be part of what is measured
+            log_sample_rate: 0.0,
+            ..HttpConfig::default()
+        };
+        let state: SharedState = Arc::new(RwLock::new(ServerState::new(ServerConfig::default())));
+        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let server = rt.spawn(http::start_server(config, state.clone(), shutdown_rx));
+        let port = rt.block_on(async {
+            loop {
+                if let Some(port) = state.read().bound_port {
+                    break port;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &port, |b, &port| {
+            b.iter(|| {
+                let received = rt.block_on(download(port));
+                assert!(received > FILE_SIZE, "incomplete download");
+            })
+        });
+
+        rt.block_on(async {
+            shutdown_tx.send(()).await.unwrap();
+            server.await.unwrap().unwrap();
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stream_buffer);
+criterion_main!(benches);
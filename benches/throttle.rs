@@ -0,0 +1,38 @@
+//! Overhead of `ThrottledStream` on writes its limit never holds back
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use oservers::servers::throttle::ThrottledStream;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Bytes written per iteration
+const TOTAL: usize = 16 * 1024 * 1024;
+
+/// Size of each write, as a file response would send it
+const CHUNK: usize = 64 * 1024;
+
+async fn write_all(mut out: impl AsyncWrite + Unpin) {
+    let chunk = vec![0u8; CHUNK];
+    for _ in 0..TOTAL / CHUNK {
+        out.write_all(&chunk).await.unwrap();
+    }
+}
+
+fn egress(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("egress");
+    group.throughput(Throughput::Bytes(TOTAL as u64));
+    group.bench_function("plain", |b| {
+        b.iter(|| rt.block_on(write_all(tokio::io::sink())))
+    });
+    // A limit far above what the sink can take, so only the bookkeeping is measured
+    group.bench_function("throttled", |b| {
+        b.iter(|| rt.block_on(write_all(ThrottledStream::new(tokio::io::sink(), u32::MAX))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, egress);
+criterion_main!(benches);
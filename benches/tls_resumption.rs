@@ -0,0 +1,79 @@
+//! HTTPS handshake time with and without session resumption
+//!
+//! Handshakes run over an in-memory pipe so only the TLS work is measured.
+//! The certificate in `benches/tls/` is self-signed for `localhost`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use oservers::servers::http::TlsConfig;
+use oservers::servers::tls::{self, TicketKeys};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+fn tls_config() -> TlsConfig {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches/tls");
+    TlsConfig {
+        cert_path: dir.join("cert.pem"),
+        key_path: dir.join("key.pem"),
+    }
+}
+
+/// Client trusting the bench certificate, keeping tickets between connections
+fn connector(tls: &TlsConfig) -> TlsConnector {
+    let pem = std::fs::read(&tls.cert_path).unwrap();
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+/// Complete one handshake and the first byte after it, which carries the tickets
+async fn handshake(acceptor: &TlsAcceptor, connector: &TlsConnector) {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let server = async {
+        let mut stream = acceptor.accept(server).await.unwrap();
+        stream.write_all(b"x").await.unwrap();
+        stream.flush().await.unwrap();
+        stream
+    };
+    let client = async {
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector.connect(name, client).await.unwrap();
+        stream.read_u8().await.unwrap();
+        stream
+    };
+    let _ = tokio::join!(server, client);
+}
+
+fn tls_resumption(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let tls = tls_config();
+    let mut group = c.benchmark_group("tls_handshake");
+    for (name, ticket_keys) in [
+        ("full", None),
+        ("resumed", Some(Arc::new(TicketKeys::generate().unwrap()))),
+    ] {
+        let acceptor = TlsAcceptor::from(Arc::new(tls::server_config(&tls, ticket_keys).unwrap()));
+        // One connector for all iterations, so later handshakes can present a ticket
+        let connector = connector(&tls);
+        group.bench_function(name, |b| {
+            b.iter(|| rt.block_on(handshake(&acceptor, &connector)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tls_resumption);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+//! Embeds the CJK font left in `assets/fonts/` by `cargo xtask bundle-fonts`
+//!
+//! Without one the app falls back to looking for system fonts at runtime.
+
+use std::path::Path;
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(bundled_font)");
+    println!("cargo::rerun-if-changed=assets/fonts");
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let fonts = Path::new(&manifest_dir).join("assets").join("fonts");
+    let bundled = ["cjk.ttc", "cjk.ttf", "cjk.otf"]
+        .iter()
+        .map(|name| fonts.join(name))
+        .find(|path| path.is_file());
+    if let Some(path) = bundled {
+        println!("cargo::rustc-cfg=bundled_font");
+        println!("cargo::rustc-env=OSERVERS_BUNDLED_FONT={}", path.display());
+    }
+}
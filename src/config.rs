@@ -1,21 +1,143 @@
 //! Application configuration management
 
-use crate::servers::{ftp::FtpConfig, http::HttpConfig, ssh::SshConfig, tftp::TftpConfig};
+use crate::gui::i18n::Language;
+use crate::servers::{
+    ServerType, ftp::FtpConfig, http::HttpConfig, ssh::SshConfig, tftp::TftpConfig,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+/// Maximum number of entries kept in the recent directories list
+const MAX_RECENT_DIRS: usize = 10;
+
+/// Prefix of the environment variables overriding server settings
+const ENV_PREFIX: &str = "OSERVERS_";
+
+/// Servers whose settings can be overridden from the environment
+const ENV_SERVERS: [&str; 4] = ["http", "ftp", "tftp", "ssh"];
+
+/// Short setting names accepted in environment variables
+const ENV_ALIASES: [(&str, &str); 2] = [("root", "root_dir"), ("user", "username")];
 
 /// Main application configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub http: HttpConfig,
     pub ftp: FtpConfig,
     pub tftp: TftpConfig,
     pub ssh: SshConfig,
+    /// Recently chosen root directories, most recent first
+    #[serde(default)]
+    pub recent_dirs: Vec<PathBuf>,
+    /// Log message patterns pinned above the log panel (`*` is a wildcard)
+    #[serde(default)]
+    pub pinned_patterns: Vec<String>,
+    /// UI language
+    #[serde(default)]
+    pub language: Language,
+    /// Show the first-run tutorial, configs from before it existed skip it
+    #[serde(default)]
+    pub show_tutorial: bool,
+    /// Repaint only once per second while the window is unfocused
+    #[serde(default = "default_true")]
+    pub slow_repaint_when_unfocused: bool,
+    /// Show a read-only dashboard of all servers instead of the settings panel
+    #[serde(default)]
+    pub monitor_mode: bool,
+    /// Length of passwords made by the generate buttons
+    #[serde(default = "default_password_length")]
+    pub generated_password_length: usize,
+    /// Save changed settings this often instead of only on close
+    #[serde(default = "default_auto_save_interval")]
+    pub auto_save_interval_secs: Option<u64>,
+    /// Look for a newer release on startup, off unless the user opts in
+    #[serde(default)]
+    pub check_for_updates: bool,
+    /// Version manifest the update check fetches
+    #[serde(default = "default_update_manifest_url")]
+    pub update_manifest_url: String,
+    /// Root directory new server instances start with, the working directory if unset
+    #[serde(default)]
+    pub default_root_dir: Option<PathBuf>,
+    /// How the user arranged parts of the window
+    #[serde(default)]
+    pub window_layout: WindowLayout,
+    /// Settings taken from the environment, kept out of the saved file
+    #[serde(skip)]
+    env_overrides: Vec<EnvOverride>,
+}
+
+/// A setting the environment overrode, with the file value to save in its place
+#[derive(Debug, Clone)]
+struct EnvOverride {
+    /// Keys leading to the setting, the server first
+    path: Vec<String>,
+    file_value: serde_json::Value,
+    env_value: serde_json::Value,
+}
+
+/// Arrangement of parts of the window that can be moved around
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowLayout {
+    /// Offset of each server's node from the top left of the dependency graph
+    pub dependency_nodes: HashMap<ServerType, [f32; 2]>,
+}
+
+/// Interval of the periodic settings save, unless disabled
+pub const DEFAULT_AUTO_SAVE_SECS: u64 = 30;
+
+fn default_auto_save_interval() -> Option<u64> {
+    Some(DEFAULT_AUTO_SAVE_SECS)
+}
+
+fn default_update_manifest_url() -> String {
+    crate::gui::update_check::DEFAULT_MANIFEST_URL.to_string()
+}
+
+fn default_password_length() -> usize {
+    20
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            http: HttpConfig::default(),
+            ftp: FtpConfig::default(),
+            tftp: TftpConfig::default(),
+            ssh: SshConfig::default(),
+            recent_dirs: Vec::new(),
+            pinned_patterns: Vec::new(),
+            language: Language::default(),
+            show_tutorial: true,
+            slow_repaint_when_unfocused: true,
+            monitor_mode: false,
+            generated_password_length: default_password_length(),
+            auto_save_interval_secs: default_auto_save_interval(),
+            check_for_updates: false,
+            update_manifest_url: default_update_manifest_url(),
+            default_root_dir: None,
+            window_layout: WindowLayout::default(),
+            env_overrides: Vec::new(),
+        }
+    }
 }
 
 impl AppConfig {
-    /// Load configuration from file
+    /// Load configuration from file, with environment variables taking precedence
     pub fn load() -> Self {
+        Self::load_file().with_env_overrides(std::env::vars())
+    }
+
+    fn load_file() -> Self {
+        Self::migrate_legacy_config();
         let config_path = Self::config_path();
         if config_path.exists() {
             match std::fs::read_to_string(&config_path) {
@@ -33,23 +155,276 @@ impl AppConfig {
         Self::default()
     }
 
-    /// Save configuration to file
-    pub fn save(&self) -> anyhow::Result<()> {
+    /// Override server settings from `OSERVERS_<SERVER>_<SETTING>` variables
+    ///
+    /// `<SERVER>` is `HTTP`, `FTP`, `TFTP` or `SSH` and `<SETTING>` the
+    /// setting's name in `config.json` in upper case, with `__` stepping
+    /// into nested settings: `OSERVERS_HTTP_PORT=8080`,
+    /// `OSERVERS_FTP_READ_ONLY=true`, `OSERVERS_SSH_STARTUP__AUTO_START=true`.
+    /// `ROOT` and `USER` are short for `ROOT_DIR` and `USERNAME`. Values are
+    /// read as JSON where that fits the setting and as text otherwise.
+    /// Unknown settings and values of the wrong type are skipped with a warning.
+    /// Overridden settings are saved with their value from the file.
+    fn with_env_overrides(self, vars: impl Iterator<Item = (String, String)>) -> Self {
+        let Ok(file_value) = serde_json::to_value(&self) else {
+            return self;
+        };
+        let mut value = file_value.clone();
+        let mut paths: Vec<Vec<String>> = Vec::new();
+        for (name, raw) in vars {
+            let Some((server, setting)) = name
+                .strip_prefix(ENV_PREFIX)
+                .and_then(|rest| rest.split_once('_'))
+            else {
+                continue;
+            };
+            let server = server.to_lowercase();
+            if !ENV_SERVERS.contains(&server.as_str()) {
+                continue;
+            }
+            let setting = setting.to_lowercase();
+            let setting = ENV_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == setting)
+                .map_or(setting.as_str(), |(_, field)| field);
+
+            // Typed JSON first, so `8080` becomes a number, then plain text
+            let candidates = serde_json::from_str(&raw)
+                .into_iter()
+                .chain([serde_json::Value::String(raw.clone())]);
+            let path: Vec<String> = std::iter::once(server.clone())
+                .chain(setting.split("__").map(str::to_string))
+                .collect();
+            let mut accepted = None;
+            for candidate in candidates {
+                let mut updated = value.clone();
+                let Some(slot) = json_at(&mut updated, &path) else {
+                    break;
+                };
+                *slot = candidate;
+                if serde_json::from_value::<AppConfig>(updated.clone()).is_ok() {
+                    accepted = Some(updated);
+                    break;
+                }
+            }
+            match accepted {
+                Some(updated) => {
+                    // Names only, values may be passwords
+                    tracing::info!("Config: {} set from the environment", name);
+                    value = updated;
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+                None => tracing::warn!("Config: ignoring {}, not a valid setting", name),
+            }
+        }
+        if paths.is_empty() {
+            return self;
+        }
+        let Ok(mut config) = serde_json::from_value::<AppConfig>(value) else {
+            return self;
+        };
+        // Compared on save as the config serializes, which may differ from the raw variable
+        let Ok(mut value) = serde_json::to_value(&config) else {
+            return self;
+        };
+        let mut file_value = file_value;
+        config.env_overrides = paths
+            .into_iter()
+            .filter_map(|path| {
+                Some(EnvOverride {
+                    file_value: json_at(&mut file_value, &path)?.clone(),
+                    env_value: json_at(&mut value, &path)?.clone(),
+                    path,
+                })
+            })
+            .collect();
+        config
+    }
+
+    /// Move a config file from the old config-dir location into the data directory
+    fn migrate_legacy_config() {
+        let Some(legacy_path) = directories::ProjectDirs::from("com", "oservers", "oservers")
+            .map(|dirs| dirs.config_dir().join("config.json"))
+        else {
+            return;
+        };
+        let config_path = Self::config_path();
+        if legacy_path == config_path || config_path.exists() || !legacy_path.exists() {
+            return;
+        }
+
+        let result = std::fs::create_dir_all(data_dir()).and_then(|_| {
+            std::fs::rename(&legacy_path, &config_path)
+                .or_else(|_| std::fs::copy(&legacy_path, &config_path).map(|_| ()))
+        });
+        match result {
+            Ok(()) => tracing::info!(
+                "Migrated config from {} to {}",
+                legacy_path.display(),
+                config_path.display()
+            ),
+            Err(e) => tracing::warn!("Failed to migrate config: {}", e),
+        }
+    }
+
+    /// Configuration as saved to file, settings from the environment as they were in the file
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        for env in &self.env_overrides {
+            // Changed in the UI since, that change is saved
+            if let Some(slot) =
+                json_at(&mut value, &env.path).filter(|slot| **slot == env.env_value)
+            {
+                *slot = env.file_value.clone();
+            }
+        }
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    /// Write configuration already turned into JSON by `to_json`
+    pub fn write(content: &str) -> anyhow::Result<()> {
         let config_path = Self::config_path();
         if let Some(parent) = config_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        let content = serde_json::to_string_pretty(self)?;
-        std::fs::write(&config_path, content)?;
+        // Written aside and renamed over, so a crash mid-write leaves the old file intact
+        let temp_path = config_path.with_extension("json.tmp");
+        std::fs::write(&temp_path, content)?;
+        std::fs::rename(&temp_path, &config_path)?;
         Ok(())
     }
 
+    /// Remember a chosen root directory, keeping the list deduplicated and capped
+    pub fn add_recent_dir(&mut self, path: PathBuf) {
+        self.recent_dirs.retain(|p| p != &path);
+        self.recent_dirs.insert(0, path);
+        self.recent_dirs.truncate(MAX_RECENT_DIRS);
+    }
+
     /// Get configuration file path
     fn config_path() -> PathBuf {
-        if let Some(proj_dirs) = directories::ProjectDirs::from("com", "oservers", "oservers") {
-            proj_dirs.config_dir().join("config.json")
-        } else {
-            PathBuf::from("config.json")
+        data_dir().join("config.json")
+    }
+}
+
+/// Writes the configuration file on a thread of its own
+///
+/// Writes happen one at a time in the order they were queued, so an older
+/// configuration can never land on top of a newer one.
+pub struct ConfigWriter {
+    queue: Option<mpsc::Sender<String>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ConfigWriter {
+    pub fn spawn() -> Self {
+        let (queue, contents) = mpsc::channel::<String>();
+        let thread = std::thread::spawn(move || {
+            for content in contents {
+                if let Err(e) = AppConfig::write(&content) {
+                    tracing::error!("Failed to save config: {}", e);
+                }
+            }
+        });
+        Self {
+            queue: Some(queue),
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue configuration turned into JSON by `AppConfig::to_json` for writing
+    pub fn write(&self, content: String) {
+        if let Some(queue) = &self.queue {
+            let _ = queue.send(content);
+        }
+    }
+
+    /// Wait until everything queued is written, later writes are ignored
+    pub fn finish(&mut self) {
+        self.queue.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
         }
     }
 }
+
+/// The setting `path` leads to within `value`, if it exists
+fn json_at<'a>(
+    value: &'a mut serde_json::Value,
+    path: &[String],
+) -> Option<&'a mut serde_json::Value> {
+    path.iter()
+        .try_fold(value, |node, key| node.get_mut(key.as_str()))
+}
+
+/// Root directory for all persistent data (config, logs, stores)
+///
+/// Defaults to the platform data directory and can be relocated with the
+/// `OSERVERS_DATA_DIR` environment variable.
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("OSERVERS_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+    if let Some(proj_dirs) = directories::ProjectDirs::from("com", "oservers", "oservers") {
+        proj_dirs.data_dir().to_path_buf()
+    } else {
+        PathBuf::from(".")
+    }
+}
+
+/// Directory server logs are exported to on stop, unless another is chosen
+pub fn logs_dir() -> PathBuf {
+    data_dir().join("logs")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AppConfig;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn env_overrides_are_not_saved() {
+        let mut file = AppConfig::default();
+        file.ftp.password = "from-file".to_string();
+        file.http.port = 8000;
+        let config = file.with_env_overrides(vars(&[
+            ("OSERVERS_FTP_PASSWORD", "from-env"),
+            ("OSERVERS_HTTP_PORT", "9000"),
+        ]));
+        assert_eq!(config.ftp.password, "from-env");
+        assert_eq!(config.http.port, 9000);
+
+        let saved: AppConfig = serde_json::from_str(&config.to_json().unwrap()).unwrap();
+        assert_eq!(saved.ftp.password, "from-file");
+        assert_eq!(saved.http.port, 8000);
+    }
+
+    #[test]
+    fn settings_changed_after_an_override_are_saved() {
+        let mut config =
+            AppConfig::default().with_env_overrides(vars(&[("OSERVERS_HTTP_PORT", "9000")]));
+        config.http.port = 9100;
+        let saved: AppConfig = serde_json::from_str(&config.to_json().unwrap()).unwrap();
+        assert_eq!(saved.http.port, 9100);
+    }
+
+    #[test]
+    fn passive_host_comes_from_the_environment() {
+        let config = AppConfig::default()
+            .with_env_overrides(vars(&[("OSERVERS_FTP_PASSIVE_HOST", "192.168.1.20")]));
+        assert_eq!(config.ftp.passive_host.as_deref(), Some("192.168.1.20"));
+        // docker-compose.yml passes an empty value when FTP_PASSIVE_HOST is unset
+        let config =
+            AppConfig::default().with_env_overrides(vars(&[("OSERVERS_FTP_PASSIVE_HOST", "")]));
+        assert_eq!(config.ftp.passive_host.as_deref(), Some(""));
+    }
+}
@@ -1,53 +1,70 @@
 //! Main application GUI using egui
 
-use crate::config::AppConfig;
+use super::dependency_graph;
+use super::diagnostics;
+use super::dialogs::{self, Picked};
+use super::file_activity::FileActivity;
+use super::i18n::{self, Language, tr};
+use super::markdown;
+use super::tutorial;
+use super::update_check::{self, UpdateStatus};
+use crate::config::{AppConfig, ConfigWriter, DEFAULT_AUTO_SAVE_SECS};
 use crate::servers::{
-    ftp::{self, FtpConfig},
-    http::{self, HttpConfig},
+    LogLevel, LogMessage, OverwritePolicy, RestartBehavior, RestartPolicy, ServerError,
+    ServerStatus, ServerType, SharedState, StartupConfig, TransferProgress, find_free_port,
+    ftp::{self, CompressionAlgorithm, FtpConfig},
+    glob_match,
+    http::{self, AbTestConfig, HttpConfig, HttpMode, QosRule, TlsConfig, VirtualFile},
+    integrity::{HashAlgorithm, IntegrityConfig},
+    local_ip,
+    response_signing::{SigningAlgorithm, SigningConfig},
     ssh::{self, SshConfig},
     tftp::{self, TftpConfig},
-    LogLevel, LogMessage, ServerStatus, SharedState,
+    transfer_notify::{NotificationConfig, NotificationMode, NotificationTrigger, SmtpConfig},
+    webhook::{WebhookConfig, WebhookTrigger},
 };
 use eframe::egui;
+use futures_util::FutureExt;
+use ipnet::IpNet;
 use parking_lot::RwLock;
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
 
-/// Server type enumeration
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ServerType {
-    Http,
-    Ftp,
-    Tftp,
-    Ssh,
-}
+/// Throughput samples kept per server for the monitor sparkline, one per second
+const THROUGHPUT_SAMPLES: usize = 60;
+
+/// Width of a server card in monitor mode
+const MONITOR_CARD_WIDTH: f32 = 280.0;
+
+/// CJK font embedded by `build.rs` after `cargo xtask bundle-fonts`
+#[cfg(bundled_font)]
+const BUNDLED_FONT: Option<&[u8]> = Some(include_bytes!(env!("OSERVERS_BUNDLED_FONT")));
+#[cfg(not(bundled_font))]
+const BUNDLED_FONT: Option<&[u8]> = None;
+
+/// Repaint interval while the window is in the background
+const UNFOCUSED_REPAINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
-impl ServerType {
-    const ALL: [ServerType; 4] = [
-        ServerType::Http,
-        ServerType::Ftp,
-        ServerType::Tftp,
-        ServerType::Ssh,
-    ];
+/// Period `max_restarts_per_hour` counts restarts over, also the longest backoff
+const RESTART_WINDOW: Duration = Duration::from_secs(3600);
 
+/// Translated display name of a server type
+trait ServerTypeName {
+    fn name(&self) -> &'static str;
+}
+
+impl ServerTypeName for ServerType {
     fn name(&self) -> &'static str {
-        match self {
+        tr(match self {
             ServerType::Http => "HTTP Server",
             ServerType::Ftp => "FTP Server",
             ServerType::Tftp => "TFTP Server",
             ServerType::Ssh => "SSH/SFTP Server",
-        }
-    }
-
-    fn default_port(&self) -> u16 {
-        match self {
-            ServerType::Http => 7777,
-            ServerType::Ftp => 2121,
-            ServerType::Tftp => 69,
-            ServerType::Ssh => 2222,
-        }
+        })
     }
 }
 
@@ -56,19 +73,41 @@ struct ServerEntry {
     server_type: ServerType,
     state: SharedState,
     shutdown_tx: Option<mpsc::Sender<()>>,
+    /// Task running the server, finished once the server has released its port
+    task: Option<tokio::task::JoinHandle<Result<(), ServerError>>>,
+    /// Restarts made by the restart policy within the last hour, oldest first
+    restarts: VecDeque<Instant>,
+    /// When the restart policy starts the server again
+    restart_at: Option<Instant>,
+    /// When the server was last seen entering the running state
+    running_since: Option<Instant>,
+    /// Bytes sent per second while running, oldest first
+    throughput: VecDeque<u64>,
+    /// Time and sent byte count of the last throughput sample
+    last_sample: Option<(Instant, u64)>,
 }
 
 impl ServerEntry {
-    fn new(server_type: ServerType) -> Self {
+    /// A stopped server serving `default_root`, or the working directory without one
+    fn new(server_type: ServerType, default_root: Option<&Path>) -> Self {
         let config = crate::servers::ServerConfig {
-            root_dir: std::env::current_dir().unwrap_or_default(),
+            root_dir: default_root
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| std::env::current_dir().unwrap_or_default()),
             port: server_type.default_port(),
             auto_stop_seconds: None,
+            egress_throttle_kbps: None,
         };
         Self {
             server_type,
             state: Arc::new(RwLock::new(crate::servers::ServerState::new(config))),
             shutdown_tx: None,
+            task: None,
+            restarts: VecDeque::new(),
+            restart_at: None,
+            running_since: None,
+            throughput: VecDeque::new(),
+            last_sample: None,
         }
     }
 
@@ -80,9 +119,127 @@ impl ServerEntry {
         matches!(self.status(), ServerStatus::Running)
     }
 
+    /// Whether a server task exists, running or still starting or stopping
+    fn is_active(&self) -> bool {
+        self.task.as_ref().is_some_and(|task| !task.is_finished())
+    }
+
+    /// Starting or stopping, start requests are ignored until this settles
+    fn is_transitioning(&self) -> bool {
+        self.is_active() && !self.is_running()
+    }
+
     fn logs(&self) -> Vec<LogMessage> {
         self.state.read().logs.clone()
     }
+
+    /// Transfers in progress, oldest first
+    fn transfers(&self) -> Vec<TransferProgress> {
+        let mut transfers: Vec<_> = self.state.read().transfers.values().cloned().collect();
+        transfers.sort_by_key(|t| t.started);
+        transfers
+    }
+
+    /// Track the uptime and sample the throughput, at most once per second
+    fn sample(&mut self) {
+        let (running, bytes_sent) = {
+            let s = self.state.read();
+            (s.status == ServerStatus::Running, s.metrics.bytes_sent)
+        };
+        if !running {
+            self.running_since = None;
+            self.last_sample = None;
+            return;
+        }
+        let now = Instant::now();
+        if self.running_since.is_none() {
+            self.running_since = Some(now);
+            self.throughput.clear();
+        }
+        match self.last_sample {
+            Some((at, bytes)) if now.duration_since(at) >= Duration::from_secs(1) => {
+                let elapsed = now.duration_since(at).as_secs_f64();
+                let per_sec = bytes_sent.saturating_sub(bytes) as f64 / elapsed;
+                self.throughput.push_back(per_sec as u64);
+                if self.throughput.len() > THROUGHPUT_SAMPLES {
+                    self.throughput.pop_front();
+                }
+                self.last_sample = Some((now, bytes_sent));
+            }
+            Some(_) => {}
+            None => self.last_sample = Some((now, bytes_sent)),
+        }
+    }
+}
+
+/// Editable auto-start settings of one server
+struct StartupFields {
+    auto_start: bool,
+    start_after: Vec<ServerType>,
+    delay_secs: String,
+    wait_for_root: bool,
+    wait_for_root_secs: String,
+}
+
+impl StartupFields {
+    fn new(config: &StartupConfig) -> Self {
+        Self {
+            auto_start: config.auto_start,
+            start_after: config.start_after.clone(),
+            delay_secs: config.startup_delay_secs.to_string(),
+            wait_for_root: config.wait_for_root_secs.is_some(),
+            wait_for_root_secs: config.wait_for_root_secs.unwrap_or(60).to_string(),
+        }
+    }
+
+    fn config(&self) -> StartupConfig {
+        StartupConfig {
+            auto_start: self.auto_start,
+            start_after: self.start_after.clone(),
+            startup_delay_secs: self.delay_secs.parse().unwrap_or(0),
+            wait_for_root_secs: if self.wait_for_root {
+                self.wait_for_root_secs.parse().ok()
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Server waiting to be auto-started
+struct PendingStart {
+    idx: usize,
+    /// Servers that have to be running first
+    after: Vec<ServerType>,
+    delay: Duration,
+    /// When all of `after` were first seen running
+    ready_since: Option<Instant>,
+    /// Whether waiting for `after` has been logged
+    logged_wait: bool,
+}
+
+/// Progress of an FTP test login, shown in a window until closed
+#[derive(Clone)]
+enum LoginTest {
+    NotRunning,
+    Testing,
+    Done(Result<(), String>),
+}
+
+/// Scan of the HTTP root for sensitive files before starting the server at `idx`
+struct SensitiveScan {
+    idx: usize,
+    /// Files found, `None` while the scan runs
+    found: Arc<RwLock<Option<Vec<PathBuf>>>>,
+}
+
+/// Settings every server type has, copied between servers as a group
+struct SharedFields {
+    root_dir: String,
+    auto_stop: bool,
+    auto_stop_secs: String,
+    export_log: bool,
+    export_log_dir: String,
 }
 
 /// Main application state
@@ -90,7 +247,12 @@ pub struct OServersApp {
     config: AppConfig,
     servers: Vec<ServerEntry>,
     selected_server: Option<usize>,
-    runtime: Arc<Runtime>,
+    /// Async runtime the servers run on, `None` if it could not be created
+    runtime: Option<Arc<Runtime>>,
+    /// Why the runtime could not be created
+    runtime_error: Option<String>,
+    /// Which CJK font was loaded, for diagnostics
+    font_status: String,
 
     // Temporary UI state for editing
     http_port: String,
@@ -98,193 +260,767 @@ pub struct OServersApp {
     http_allow_listing: bool,
     http_auto_stop: bool,
     http_auto_stop_secs: String,
+    http_startup: StartupFields,
+    http_restart: RestartPolicy,
+    http_scan_sensitive: bool,
+    http_sensitive_patterns: String,
+    http_idle_page: bool,
+    http_idle_page_html: String,
+    /// Landing, not found, forbidden and maintenance page files, empty for the built-in page
+    http_page_index: String,
+    http_page_not_found: String,
+    http_page_forbidden: String,
+    http_page_maintenance: String,
+    http_maintenance: bool,
+    http_qos_rules: String,
+    http_webhooks: Vec<WebhookConfig>,
+    http_virtual_files: Vec<VirtualFile>,
+    http_limit_depth: bool,
+    http_max_depth: String,
+    http_limit_downloads: bool,
+    http_max_downloads: String,
+    http_queue_timeout_secs: String,
+    http_listing_cache_secs: String,
+    http_listing_banner: String,
+    http_listing_csp: bool,
+    http_stats_page: bool,
+    http_stats_path: String,
+    http_stats_username: String,
+    http_stats_password: String,
+    http_health: bool,
+    http_health_path: String,
+    http_base_path: String,
+    http_trusted_proxies: String,
+    http_signed_urls: bool,
+    http_signed_url_secret: String,
+    http_signed_url_admin_token: String,
+    http_zip_downloads: bool,
+    http_json_listing: bool,
+    http_slash_redirect: bool,
+    http_max_zip_mb: String,
+    http_content_negotiation: bool,
+    http_proxy_protocol: bool,
+    http_reverse_dns: bool,
+    http_log_sample_rate: f32,
+    http_stream_buffer_size: usize,
+    http_access_log_exclude: String,
+    http_tls: bool,
+    http_tls_cert: String,
+    http_tls_key: String,
+    http_tls_session_resumption: bool,
+    http_sign_responses: bool,
+    http_signing_key: String,
+    http_signing_algorithm: SigningAlgorithm,
+    http_https_port: String,
+    http_egress_throttle: bool,
+    http_egress_kbps: String,
+    http_ab_test: bool,
+    http_ab_cookie: String,
+    http_ab_weight: u8,
+    http_ab_group_b_root: String,
+    http_mdns: bool,
+    http_tor: bool,
+    http_tor_control_port: String,
+    /// Empty when no GeoIP database is used
+    http_geoip_db: String,
+    http_kv_mode: bool,
+    http_kv_store_path: String,
+    http_accept_compressed: bool,
+    http_integrity_check: bool,
+    http_integrity_interval_secs: String,
+    http_integrity_algorithm: HashAlgorithm,
+    http_integrity_manifest: String,
+    http_notes: String,
+    http_export_log: bool,
+    http_export_log_dir: String,
 
     ftp_port: String,
     ftp_root_dir: String,
     ftp_username: String,
     ftp_password: String,
     ftp_anonymous: bool,
+    ftp_custom_messages: HashMap<u16, String>,
     ftp_passive_mode: bool,
     ftp_passive_ports_start: String,
     ftp_passive_ports_end: String,
+    ftp_passive_host: String,
+    ftp_discovery: bool,
+    ftp_mdns: bool,
+    ftp_overwrite_policy: OverwritePolicy,
+    ftp_compress: bool,
+    ftp_compress_algorithm: CompressionAlgorithm,
+    ftp_decompress_on_download: bool,
+    ftp_safe_delete: bool,
+    ftp_trash_retention_days: String,
+    ftp_notify: bool,
+    /// Notify by email rather than webhook
+    ftp_notify_email: bool,
+    ftp_notify_url: String,
+    ftp_notify_smtp: SmtpConfig,
+    ftp_notify_trigger: NotificationTrigger,
+    ftp_notify_min_kb: String,
+    ftp_discovery_port: String,
+    ftp_notes: String,
+    ftp_auto_stop: bool,
+    ftp_auto_stop_secs: String,
+    ftp_export_log: bool,
+    ftp_export_log_dir: String,
+    ftp_startup: StartupFields,
+    ftp_restart: RestartPolicy,
 
     tftp_port: String,
     tftp_root_dir: String,
     tftp_read_only: bool,
+    tftp_retry_timeout_ms: String,
+    tftp_max_retries: String,
+    tftp_ipv6: bool,
+    tftp_allow_patterns: String,
+    tftp_notes: String,
+    tftp_auto_stop: bool,
+    tftp_auto_stop_secs: String,
+    tftp_export_log: bool,
+    tftp_export_log_dir: String,
+    /// Empty when uploads are written straight to the root directory
+    tftp_staging_dir: String,
+    tftp_startup: StartupFields,
+    tftp_restart: RestartPolicy,
 
     ssh_port: String,
     ssh_root_dir: String,
     ssh_username: String,
     ssh_password: String,
+    ssh_notes: String,
+    ssh_auto_stop: bool,
+    ssh_auto_stop_secs: String,
+    ssh_export_log: bool,
+    ssh_export_log_dir: String,
+    ssh_startup: StartupFields,
+    ssh_restart: RestartPolicy,
+
+    /// Show the notes editor instead of the rendered notes
+    editing_notes: bool,
+
+    pinned_patterns: Vec<String>,
+    /// Show changes in the root directory instead of the server output
+    show_file_activity: bool,
+    /// Watcher behind the file activity tab, only while the tab is on screen
+    file_activity: Option<FileActivity>,
+
+    /// Server waiting to start on a sensitive file scan, then on confirmation if it found any
+    sensitive_scan: Option<SensitiveScan>,
+    /// FTP test login whose result is on screen
+    login_test: Arc<RwLock<Option<LoginTest>>>,
+    /// Servers configured to start with the application that have not been started yet
+    auto_start: Vec<PendingStart>,
+
+    show_settings: bool,
+    /// Show the start order graph instead of the selected server
+    show_dependencies: bool,
+    tutorial_step: usize,
+    tutorial_targets: tutorial::Targets,
+
+    /// File shared on its own from the command line
+    share_file: Option<PathBuf>,
+    /// Host used in the share link
+    share_host: String,
+    /// Config as last written to disk, to skip auto saves without changes
+    saved_config: String,
+    last_auto_save: Instant,
+    /// Writes saved configs in the order they were saved
+    config_writer: ConfigWriter,
+    /// Result of the last update check, `None` before the first one
+    update_status: Arc<RwLock<Option<UpdateStatus>>>,
+    /// Whether the update notification was closed
+    update_dismissed: bool,
 }
 
 impl OServersApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, share_file: Option<PathBuf>) -> Self {
         // Configure Chinese font support
-        Self::setup_fonts(&cc.egui_ctx);
+        let font_status = match Self::setup_fonts(&cc.egui_ctx) {
+            Some(path) => format!("CJK font loaded from {}", path),
+            None => "no CJK font found, using egui defaults".to_string(),
+        };
 
         let config = AppConfig::load();
-        let runtime = Arc::new(Runtime::new().expect("Failed to create tokio runtime"));
+        i18n::set_language(config.language);
+        let (runtime, runtime_error) = match Runtime::new() {
+            Ok(runtime) => (Some(Arc::new(runtime)), None),
+            Err(e) => {
+                tracing::error!("Failed to create tokio runtime: {}", e);
+                (None, Some(e.to_string()))
+            }
+        };
 
         let servers = ServerType::ALL
             .iter()
-            .map(|&st| ServerEntry::new(st))
+            .map(|&st| ServerEntry::new(st, config.default_root_dir.as_deref()))
             .collect();
 
-        Self {
+        let ftp_notify = config.ftp.transfer_notifications.clone();
+        let (ftp_notify_url, ftp_notify_smtp) = match ftp_notify.as_ref().map(|n| &n.mode) {
+            Some(NotificationMode::Webhook(url)) => (url.clone(), None),
+            Some(NotificationMode::Email(smtp)) => (String::new(), Some(smtp.clone())),
+            None => (String::new(), None),
+        };
+
+        let mut app = Self {
             http_port: config.http.port.to_string(),
             http_root_dir: config.http.root_dir.display().to_string(),
             http_allow_listing: config.http.allow_directory_listing,
             http_auto_stop: config.http.auto_stop_seconds.is_some(),
             http_auto_stop_secs: config.http.auto_stop_seconds.unwrap_or(360).to_string(),
+            http_startup: StartupFields::new(&config.http.startup),
+            http_restart: config.http.restart,
+            http_scan_sensitive: config.http.scan_sensitive,
+            http_sensitive_patterns: config.http.sensitive_patterns.join(", "),
+            http_idle_page: config.http.idle_page_html.is_some(),
+            http_idle_page_html: config
+                .http
+                .idle_page_html
+                .clone()
+                .unwrap_or_else(|| http::DEFAULT_IDLE_PAGE.to_string()),
+            http_page_index: path_text(&config.http.pages.index),
+            http_page_not_found: path_text(&config.http.pages.not_found),
+            http_page_forbidden: path_text(&config.http.pages.forbidden),
+            http_page_maintenance: path_text(&config.http.pages.maintenance),
+            http_maintenance: config.http.maintenance_mode,
+            http_qos_rules: config
+                .http
+                .qos_rules
+                .iter()
+                .map(|rule| format!("{}={}", rule.extension_pattern, rule.max_kbps))
+                .collect::<Vec<_>>()
+                .join(", "),
+            http_webhooks: config.http.webhooks.clone(),
+            http_virtual_files: config.http.virtual_files.clone(),
+            http_limit_depth: config.http.max_path_depth.is_some(),
+            http_max_depth: config.http.max_path_depth.unwrap_or(16).to_string(),
+            http_limit_downloads: config.http.max_concurrent_downloads.is_some(),
+            http_max_downloads: config
+                .http
+                .max_concurrent_downloads
+                .unwrap_or(4)
+                .to_string(),
+            http_queue_timeout_secs: config.http.download_queue_timeout_secs.to_string(),
+            http_listing_cache_secs: config.http.listing_cache_ttl_secs.to_string(),
+            http_listing_banner: config.http.listing_banner.clone().unwrap_or_default(),
+            http_listing_csp: config.http.csp_enabled,
+            http_stats_page: config.http.stats_page_path.is_some(),
+            http_stats_path: config
+                .http
+                .stats_page_path
+                .clone()
+                .unwrap_or_else(|| "/server-status".to_string()),
+            http_stats_username: config.http.stats_username.clone(),
+            http_stats_password: config.http.stats_password.clone(),
+            http_health: config.http.health_path.is_some(),
+            http_health_path: config
+                .http
+                .health_path
+                .clone()
+                .unwrap_or_else(|| "/health".to_string()),
+            http_base_path: config.http.base_path.clone().unwrap_or_default(),
+            http_trusted_proxies: config
+                .http
+                .trusted_proxies
+                .iter()
+                .map(|net| net.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            http_signed_urls: config.http.signed_url_secret.is_some(),
+            http_signed_url_secret: config.http.signed_url_secret.clone().unwrap_or_default(),
+            http_signed_url_admin_token: config
+                .http
+                .signed_url_admin_token
+                .clone()
+                .unwrap_or_default(),
+            http_zip_downloads: config.http.zip_downloads,
+            http_json_listing: config.http.json_listing,
+            http_slash_redirect: config.http.redirect_directory_slash,
+            http_content_negotiation: config.http.enable_content_negotiation,
+            http_proxy_protocol: config.http.proxy_protocol,
+            http_reverse_dns: config.http.reverse_dns,
+            http_log_sample_rate: config.http.log_sample_rate,
+            http_stream_buffer_size: config.http.stream_buffer_size_bytes,
+            http_access_log_exclude: config.http.access_log_exclude_paths.join("\n"),
+            http_tls: config.http.tls.is_some(),
+            http_tls_cert: config
+                .http
+                .tls
+                .as_ref()
+                .map(|tls| tls.cert_path.display().to_string())
+                .unwrap_or_default(),
+            http_tls_key: config
+                .http
+                .tls
+                .as_ref()
+                .map(|tls| tls.key_path.display().to_string())
+                .unwrap_or_default(),
+            http_tls_session_resumption: config.http.tls_session_resumption,
+            http_sign_responses: config.http.sign_responses.is_some(),
+            http_signing_key: config
+                .http
+                .sign_responses
+                .as_ref()
+                .map(|signing| signing.private_key_path.display().to_string())
+                .unwrap_or_default(),
+            http_signing_algorithm: config
+                .http
+                .sign_responses
+                .as_ref()
+                .map(|signing| signing.algorithm)
+                .unwrap_or_default(),
+            http_egress_throttle: config.http.egress_throttle_kbps.is_some(),
+            http_egress_kbps: config.http.egress_throttle_kbps.unwrap_or(8000).to_string(),
+            http_https_port: config
+                .http
+                .https_port
+                .map(|port| port.to_string())
+                .unwrap_or_default(),
+            http_ab_test: config.http.ab_test.is_some(),
+            http_ab_cookie: config.http.ab_test.clone().unwrap_or_default().cookie_name,
+            http_ab_weight: config
+                .http
+                .ab_test
+                .as_ref()
+                .map_or(50, |ab| ab.group_a_weight),
+            http_ab_group_b_root: config
+                .http
+                .ab_test
+                .as_ref()
+                .map(|ab| ab.group_b_root.display().to_string())
+                .unwrap_or_default(),
+            http_mdns: config.http.mdns_announce,
+            http_tor: config.http.tor_hidden_service,
+            http_tor_control_port: config.http.tor_control_port.to_string(),
+            http_geoip_db: path_text(&config.http.geoip_db),
+            http_kv_mode: matches!(config.http.mode, HttpMode::KeyValueStore { .. }),
+            http_accept_compressed: config.http.accept_compressed_uploads,
+            http_kv_store_path: match &config.http.mode {
+                HttpMode::KeyValueStore { store_path } => store_path.clone(),
+                HttpMode::FileServer => crate::config::data_dir().join("kv-store.json"),
+            }
+            .display()
+            .to_string(),
+            http_integrity_check: config.http.integrity_check.is_some(),
+            http_integrity_interval_secs: config
+                .http
+                .integrity_check
+                .as_ref()
+                .map_or(3600, |c| c.check_interval_secs)
+                .to_string(),
+            http_integrity_algorithm: config
+                .http
+                .integrity_check
+                .as_ref()
+                .map(|c| c.algorithm)
+                .unwrap_or_default(),
+            http_integrity_manifest: config
+                .http
+                .integrity_check
+                .as_ref()
+                .map(|c| c.manifest_path.clone())
+                .unwrap_or_else(|| crate::config::data_dir().join("integrity-manifest.json"))
+                .display()
+                .to_string(),
+            http_max_zip_mb: (config.http.max_zip_bytes / (1024 * 1024)).to_string(),
+            http_notes: config.http.notes.clone(),
+            http_export_log: config.http.export_log_on_stop.is_some(),
+            http_export_log_dir: config
+                .http
+                .export_log_on_stop
+                .clone()
+                .unwrap_or_else(crate::config::logs_dir)
+                .display()
+                .to_string(),
 
             ftp_port: config.ftp.port.to_string(),
             ftp_root_dir: config.ftp.root_dir.display().to_string(),
             ftp_username: config.ftp.username.clone(),
             ftp_password: config.ftp.password.clone(),
             ftp_anonymous: config.ftp.anonymous_access,
+            ftp_custom_messages: config.ftp.custom_messages.clone(),
             ftp_passive_mode: config.ftp.passive_mode,
             ftp_passive_ports_start: config.ftp.passive_ports.0.to_string(),
             ftp_passive_ports_end: config.ftp.passive_ports.1.to_string(),
+            ftp_passive_host: config.ftp.passive_host.clone().unwrap_or_default(),
+            ftp_discovery: config.ftp.enable_discovery_broadcast,
+            ftp_mdns: config.ftp.mdns_announce,
+            ftp_overwrite_policy: config.ftp.overwrite_policy,
+            ftp_compress: config.ftp.compress_uploads.is_some(),
+            ftp_compress_algorithm: config
+                .ftp
+                .compress_uploads
+                .unwrap_or(CompressionAlgorithm::Gzip),
+            ftp_decompress_on_download: config.ftp.decompress_on_download,
+            ftp_safe_delete: config.ftp.safe_delete,
+            ftp_trash_retention_days: config.ftp.trash_retention_days.to_string(),
+            ftp_notify: ftp_notify.is_some(),
+            ftp_notify_email: ftp_notify_smtp.is_some(),
+            ftp_notify_url,
+            ftp_notify_smtp: ftp_notify_smtp.unwrap_or_default(),
+            ftp_notify_trigger: ftp_notify.as_ref().map(|n| n.trigger).unwrap_or_default(),
+            ftp_notify_min_kb: ftp_notify
+                .as_ref()
+                .map_or(0, |n| n.min_size_bytes / 1024)
+                .to_string(),
+            ftp_discovery_port: config.ftp.discovery_port.to_string(),
+            ftp_notes: config.ftp.notes.clone(),
+            ftp_auto_stop: config.ftp.auto_stop_seconds.is_some(),
+            ftp_auto_stop_secs: config.ftp.auto_stop_seconds.unwrap_or(360).to_string(),
+            ftp_startup: StartupFields::new(&config.ftp.startup),
+            ftp_restart: config.ftp.restart,
+            ftp_export_log: config.ftp.export_log_on_stop.is_some(),
+            ftp_export_log_dir: config
+                .ftp
+                .export_log_on_stop
+                .clone()
+                .unwrap_or_else(crate::config::logs_dir)
+                .display()
+                .to_string(),
 
             tftp_port: config.tftp.port.to_string(),
             tftp_root_dir: config.tftp.root_dir.display().to_string(),
             tftp_read_only: config.tftp.read_only,
+            tftp_retry_timeout_ms: config.tftp.retry_timeout_ms.to_string(),
+            tftp_max_retries: config.tftp.max_retries.to_string(),
+            tftp_ipv6: config.tftp.ipv6,
+            tftp_allow_patterns: config.tftp.allow_patterns.join("\n"),
+            tftp_notes: config.tftp.notes.clone(),
+            tftp_staging_dir: path_text(&config.tftp.staging_dir),
+            tftp_auto_stop: config.tftp.auto_stop_seconds.is_some(),
+            tftp_auto_stop_secs: config.tftp.auto_stop_seconds.unwrap_or(360).to_string(),
+            tftp_startup: StartupFields::new(&config.tftp.startup),
+            tftp_restart: config.tftp.restart,
+            tftp_export_log: config.tftp.export_log_on_stop.is_some(),
+            tftp_export_log_dir: config
+                .tftp
+                .export_log_on_stop
+                .clone()
+                .unwrap_or_else(crate::config::logs_dir)
+                .display()
+                .to_string(),
 
             ssh_port: config.ssh.port.to_string(),
             ssh_root_dir: config.ssh.root_dir.display().to_string(),
             ssh_username: config.ssh.username.clone(),
             ssh_password: config.ssh.password.clone(),
+            ssh_notes: config.ssh.notes.clone(),
+            ssh_auto_stop: config.ssh.auto_stop_seconds.is_some(),
+            ssh_auto_stop_secs: config.ssh.auto_stop_seconds.unwrap_or(360).to_string(),
+            ssh_startup: StartupFields::new(&config.ssh.startup),
+            ssh_restart: config.ssh.restart,
+            ssh_export_log: config.ssh.export_log_on_stop.is_some(),
+            ssh_export_log_dir: config
+                .ssh
+                .export_log_on_stop
+                .clone()
+                .unwrap_or_else(crate::config::logs_dir)
+                .display()
+                .to_string(),
+            editing_notes: false,
 
+            pinned_patterns: config.pinned_patterns.clone(),
+            show_file_activity: false,
+            file_activity: None,
+            sensitive_scan: None,
+            login_test: Arc::new(RwLock::new(None)),
+            auto_start: Vec::new(),
+            show_settings: false,
+            tutorial_step: 0,
+            tutorial_targets: tutorial::Targets::default(),
+
+            saved_config: config.to_json().unwrap_or_default(),
+            last_auto_save: Instant::now(),
+            config_writer: ConfigWriter::spawn(),
+            update_status: Arc::new(RwLock::new(None)),
+            update_dismissed: false,
             config,
             servers,
             selected_server: Some(0),
+            show_dependencies: false,
             runtime,
+            runtime_error,
+            font_status,
+            share_file: None,
+            share_host: String::new(),
+        };
+
+        // Share a file passed on the command line over HTTP straight away
+        if let Some(file) = share_file {
+            if let Some(idx) = app
+                .servers
+                .iter()
+                .position(|entry| entry.server_type == ServerType::Http)
+            {
+                app.share_file = Some(file);
+                app.share_host = local_ip()
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_else(|| "localhost".to_string());
+                app.selected_server = Some(idx);
+                app.start_server(idx);
+            }
         }
+        app.schedule_auto_start();
+        app.close_on_terminate(&cc.egui_ctx);
+        if app.config.check_for_updates {
+            app.check_for_updates(&cc.egui_ctx);
+        }
+
+        app
     }
 
     /// Setup Chinese font support
-    fn setup_fonts(ctx: &egui::Context) {
+    /// Install fonts, returning the path of the CJK font that was loaded
+    fn setup_fonts(ctx: &egui::Context) -> Option<&'static str> {
         let mut fonts = egui::FontDefinitions::default();
 
-        // Try to load Microsoft YaHei (微软雅黑) from Windows
+        // A font bundled at build time wins, system fonts are the fallback
+        let mut loaded = BUNDLED_FONT.map(|data| {
+            fonts.font_data.insert(
+                "chinese_font".to_owned(),
+                std::sync::Arc::new(egui::FontData::from_static(data)),
+            );
+            "the bundled font"
+        });
+
+        // Try to load Microsoft YaHei (微软雅黑) from Windows, then macOS and Linux fonts
         let font_paths = [
             "C:\\Windows\\Fonts\\msyh.ttc",   // Microsoft YaHei
             "C:\\Windows\\Fonts\\msyhbd.ttc", // Microsoft YaHei Bold
             "C:\\Windows\\Fonts\\simsun.ttc", // SimSun
             "C:\\Windows\\Fonts\\simhei.ttf", // SimHei
+            "/System/Library/Fonts/PingFang.ttc",
+            "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+            "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+            "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
         ];
+        if loaded.is_none() {
+            for font_path in font_paths {
+                if let Ok(font_data) = std::fs::read(font_path) {
+                    fonts.font_data.insert(
+                        "chinese_font".to_owned(),
+                        std::sync::Arc::new(egui::FontData::from_owned(font_data)),
+                    );
+                    loaded = Some(font_path);
+                    break;
+                }
+            }
+        }
 
-        for font_path in &font_paths {
-            if let Ok(font_data) = std::fs::read(font_path) {
-                fonts.font_data.insert(
-                    "chinese_font".to_owned(),
-                    std::sync::Arc::new(egui::FontData::from_owned(font_data)),
-                );
+        if let Some(source) = loaded {
+            // Add Chinese font as FALLBACK (push to end, not insert at 0)
+            // This way the default font handles emojis, Chinese font handles CJK characters
+            fonts
+                .families
+                .entry(egui::FontFamily::Proportional)
+                .or_default()
+                .push("chinese_font".to_owned());
 
-                // Add Chinese font as FALLBACK (push to end, not insert at 0)
-                // This way the default font handles emojis, Chinese font handles CJK characters
-                fonts
-                    .families
-                    .entry(egui::FontFamily::Proportional)
-                    .or_default()
-                    .push("chinese_font".to_owned());
-
-                // Add to monospace fonts (used for code/logs)
-                fonts
-                    .families
-                    .entry(egui::FontFamily::Monospace)
-                    .or_default()
-                    .push("chinese_font".to_owned());
-
-                tracing::info!("Loaded Chinese font from: {}", font_path);
-                break;
-            }
+            // Add to monospace fonts (used for code/logs)
+            fonts
+                .families
+                .entry(egui::FontFamily::Monospace)
+                .or_default()
+                .push("chinese_font".to_owned());
+
+            tracing::info!("Loaded Chinese font from: {}", source);
         }
 
         ctx.set_fonts(fonts);
+        loaded
+    }
+
+    /// Read-only dashboard with a card per server, shown instead of the settings
+    fn monitor_view(&self, ui: &mut egui::Ui) {
+        ui.heading(tr("📈 Monitor"));
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for entry in &self.servers {
+                        monitor_card(ui, entry);
+                    }
+                });
+            });
+    }
+
+    /// Graph of the start order, clicking a server selects it
+    fn dependency_view(&mut self, ui: &mut egui::Ui) {
+        ui.heading(tr("🔗 Dependencies"));
+        ui.separator();
+        let nodes: Vec<_> = self
+            .servers
+            .iter()
+            .map(|entry| {
+                let status = entry.status();
+                dependency_graph::Node {
+                    server_type: entry.server_type,
+                    color: status_color(&status),
+                    status: status_text(&status),
+                    start_after: self.startup(entry.server_type).start_after.clone(),
+                }
+            })
+            .collect();
+        let cycle = self.startup_cycle().unwrap_or_default();
+        let selected = self
+            .selected_server
+            .map(|idx| self.servers[idx].server_type);
+        let response = dependency_graph::show(
+            ui,
+            &nodes,
+            selected,
+            &cycle,
+            &mut self.config.window_layout.dependency_nodes,
+        );
+        let index_of = |server_type| {
+            self.servers
+                .iter()
+                .position(|entry| entry.server_type == server_type)
+        };
+        if let Some(idx) = response.clicked.and_then(index_of) {
+            self.selected_server = Some(idx);
+        }
+        if let Some(idx) = response.opened.and_then(index_of) {
+            self.selected_server = Some(idx);
+            self.show_dependencies = false;
+        }
+    }
+
+    /// Explain that the async runtime failed to start and offer to retry or quit
+    fn runtime_error_screen(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() / 3.0);
+                ui.heading(tr("⚠ Servers cannot run"));
+                ui.label(tr(
+                    "The background runtime could not be started, the system may be out of threads or memory.",
+                ));
+                if let Some(e) = &self.runtime_error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, e);
+                }
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button(tr("Retry")).clicked() {
+                        match Runtime::new() {
+                            Ok(runtime) => {
+                                self.runtime = Some(Arc::new(runtime));
+                                self.runtime_error = None;
+                            }
+                            Err(e) => self.runtime_error = Some(e.to_string()),
+                        }
+                    }
+                    if ui.button(tr("Quit")).clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+            });
+        });
     }
 
     fn start_server(&mut self, idx: usize) {
         let entry = &mut self.servers[idx];
-        if entry.is_running() {
+        // Also covers a previous run still stopping, which may hold the port
+        if entry.is_active() {
             return;
         }
+        let Some(runtime) = self.runtime.clone() else {
+            entry.state.write().add_log(LogMessage::error(
+                "Cannot start server: the async runtime is not available",
+            ));
+            return;
+        };
 
         let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
         entry.shutdown_tx = Some(shutdown_tx);
+        entry.restart_at = None;
+        // Set right away so the buttons are disabled before the task gets to run
+        entry.state.write().status = ServerStatus::Starting;
         let state = entry.state.clone();
 
-        match entry.server_type {
+        let task = match entry.server_type {
             ServerType::Http => {
-                let config = HttpConfig {
-                    port: self.http_port.parse().unwrap_or(7777),
-                    root_dir: PathBuf::from(&self.http_root_dir),
-                    allow_directory_listing: self.http_allow_listing,
-                    auto_stop_seconds: if self.http_auto_stop {
-                        self.http_auto_stop_secs.parse().ok()
-                    } else {
-                        None
-                    },
-                };
-                self.runtime.spawn(async move {
-                    let _ = http::start_server(config, state, shutdown_rx).await;
-                });
+                let config = self.http_config();
+                runtime.spawn(async move { http::start_server(config, state, shutdown_rx).await })
             }
             ServerType::Ftp => {
-                let config = FtpConfig {
-                    port: self.ftp_port.parse().unwrap_or(2121),
-                    root_dir: PathBuf::from(&self.ftp_root_dir),
-                    username: self.ftp_username.clone(),
-                    password: self.ftp_password.clone(),
-                    anonymous_access: self.ftp_anonymous,
-                    passive_mode: self.ftp_passive_mode,
-                    passive_ports: (
-                        self.ftp_passive_ports_start.parse().unwrap_or(50000),
-                        self.ftp_passive_ports_end.parse().unwrap_or(50100),
-                    ),
-                };
-                self.runtime.spawn(async move {
-                    let _ = ftp::start_server(config, state, shutdown_rx).await;
-                });
+                let config = self.ftp_config();
+                runtime.spawn(async move { ftp::start_server(config, state, shutdown_rx).await })
             }
             ServerType::Tftp => {
-                let config = TftpConfig {
-                    port: self.tftp_port.parse().unwrap_or(69),
-                    root_dir: PathBuf::from(&self.tftp_root_dir),
-                    read_only: self.tftp_read_only,
-                };
-                self.runtime.spawn(async move {
-                    let _ = tftp::start_server(config, state, shutdown_rx).await;
-                });
+                let config = self.tftp_config();
+                runtime.spawn(async move { tftp::start_server(config, state, shutdown_rx).await })
             }
             ServerType::Ssh => {
-                let config = SshConfig {
-                    port: self.ssh_port.parse().unwrap_or(2222),
-                    root_dir: PathBuf::from(&self.ssh_root_dir),
-                    username: self.ssh_username.clone(),
-                    password: self.ssh_password.clone(),
-                };
-                self.runtime.spawn(async move {
-                    let _ = ssh::start_server(config, state, shutdown_rx).await;
+                let config = self.ssh_config();
+                runtime.spawn(async move { ssh::start_server(config, state, shutdown_rx).await })
+            }
+        };
+        self.servers[idx].task = Some(task);
+    }
+
+    /// Start a server, asking for confirmation first if HTTP would expose sensitive files
+    fn request_start(&mut self, idx: usize, ctx: &egui::Context) {
+        if self.servers[idx].server_type == ServerType::Http
+            && self.http_scan_sensitive
+            && self.share_file.is_none()
+        {
+            if self.sensitive_scan.is_some() {
+                return;
+            }
+            if let Some(runtime) = &self.runtime {
+                // A large or network root can take a while to walk
+                let config = self.http_config();
+                let found = Arc::new(RwLock::new(None));
+                let result = found.clone();
+                let ctx = ctx.clone();
+                runtime.spawn(async move {
+                    let scan = tokio::task::spawn_blocking(move || {
+                        http::scan_sensitive(&config.root_dir, &config.sensitive_patterns)
+                    });
+                    *result.write() = Some(scan.await.unwrap_or_default());
+                    ctx.request_repaint();
                 });
+                self.sensitive_scan = Some(SensitiveScan { idx, found });
+                return;
             }
         }
+        self.start_server(idx);
     }
 
     fn stop_server(&mut self, idx: usize) {
         let entry = &mut self.servers[idx];
-        if let Some(tx) = entry.shutdown_tx.take() {
-            let _ = tx.try_send(());
+        // A starting server may be waiting for its root directory, stopping ends the wait
+        let stoppable = matches!(
+            entry.status(),
+            ServerStatus::Running | ServerStatus::Starting
+        );
+        if !entry.is_active() || !stoppable {
+            return;
         }
+        // Before signalling, so this cannot overwrite the task's final status
         {
             let mut s = entry.state.write();
             s.status = ServerStatus::Stopping;
         }
+        if let Some(tx) = entry.shutdown_tx.take() {
+            let _ = tx.try_send(());
+        }
     }
 
-    fn save_config(&mut self) {
-        self.config.http = HttpConfig {
+    /// Build the HTTP configuration from the UI fields
+    fn http_config(&self) -> HttpConfig {
+        HttpConfig {
+            accept_compressed_uploads: self.http_accept_compressed,
+            mode: if self.http_kv_mode {
+                HttpMode::KeyValueStore {
+                    store_path: PathBuf::from(&self.http_kv_store_path),
+                }
+            } else {
+                HttpMode::FileServer
+            },
             port: self.http_port.parse().unwrap_or(7777),
             root_dir: PathBuf::from(&self.http_root_dir),
             allow_directory_listing: self.http_allow_listing,
@@ -293,130 +1029,1763 @@ impl OServersApp {
             } else {
                 None
             },
-        };
-        self.config.ftp = FtpConfig {
+            scan_sensitive: self.http_scan_sensitive,
+            sensitive_patterns: self
+                .http_sensitive_patterns
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+            idle_page_html: if self.http_idle_page {
+                Some(self.http_idle_page_html.clone())
+            } else {
+                None
+            },
+            pages: http::Pages {
+                index: optional_path(&self.http_page_index),
+                not_found: optional_path(&self.http_page_not_found),
+                forbidden: optional_path(&self.http_page_forbidden),
+                maintenance: optional_path(&self.http_page_maintenance),
+            },
+            maintenance_mode: self.http_maintenance,
+            qos_rules: self
+                .http_qos_rules
+                .split(',')
+                .filter_map(|rule| {
+                    let (pattern, kbps) = rule.split_once('=')?;
+                    Some(QosRule {
+                        extension_pattern: pattern.trim().to_string(),
+                        max_kbps: kbps.trim().parse().ok()?,
+                    })
+                })
+                .collect(),
+            webhooks: self
+                .http_webhooks
+                .iter()
+                .filter(|w| !w.url.trim().is_empty())
+                .cloned()
+                .collect(),
+            virtual_files: self
+                .http_virtual_files
+                .iter()
+                .filter(|f| !f.path.trim().is_empty())
+                .cloned()
+                .collect(),
+            max_path_depth: if self.http_limit_depth {
+                self.http_max_depth.parse().ok()
+            } else {
+                None
+            },
+            max_concurrent_downloads: if self.http_limit_downloads {
+                self.http_max_downloads.parse().ok()
+            } else {
+                None
+            },
+            download_queue_timeout_secs: self.http_queue_timeout_secs.parse().unwrap_or(30),
+            listing_cache_ttl_secs: self.http_listing_cache_secs.parse().unwrap_or(0),
+            listing_banner: Some(self.http_listing_banner.trim().to_string())
+                .filter(|banner| !banner.is_empty()),
+            csp_enabled: self.http_listing_csp,
+            stats_page_path: if self.http_stats_page {
+                Some(self.http_stats_path.clone())
+            } else {
+                None
+            },
+            stats_username: self.http_stats_username.clone(),
+            stats_password: self.http_stats_password.clone(),
+            base_path: Some(self.http_base_path.trim().to_string()).filter(|p| !p.is_empty()),
+            trusted_proxies: parse_networks(&self.http_trusted_proxies).0,
+            health_path: if self.http_health {
+                Some(self.http_health_path.clone())
+            } else {
+                None
+            },
+            signed_url_secret: self
+                .http_signed_urls
+                .then(|| self.http_signed_url_secret.clone()),
+            signed_url_admin_token: Some(self.http_signed_url_admin_token.clone())
+                .filter(|token| !token.is_empty()),
+            zip_downloads: self.http_zip_downloads,
+            json_listing: self.http_json_listing,
+            redirect_directory_slash: self.http_slash_redirect,
+            max_zip_bytes: self.http_max_zip_mb.parse::<u64>().unwrap_or(1024) * 1024 * 1024,
+            enable_content_negotiation: self.http_content_negotiation,
+            proxy_protocol: self.http_proxy_protocol,
+            reverse_dns: self.http_reverse_dns,
+            log_sample_rate: self.http_log_sample_rate,
+            stream_buffer_size_bytes: self.http_stream_buffer_size,
+            access_log_exclude_paths: self
+                .http_access_log_exclude
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            sign_responses: self.http_sign_responses.then(|| SigningConfig {
+                private_key_path: PathBuf::from(&self.http_signing_key),
+                algorithm: self.http_signing_algorithm,
+            }),
+            tls: self.http_tls.then(|| TlsConfig {
+                cert_path: PathBuf::from(&self.http_tls_cert),
+                key_path: PathBuf::from(&self.http_tls_key),
+            }),
+            tls_session_resumption: self.http_tls_session_resumption,
+            https_port: self.http_https_port.trim().parse().ok(),
+            egress_throttle_kbps: if self.http_egress_throttle {
+                Some(self.http_egress_kbps.parse().unwrap_or(8000))
+            } else {
+                None
+            },
+            ab_test: self.http_ab_test.then(|| AbTestConfig {
+                cookie_name: match self.http_ab_cookie.trim() {
+                    "" => AbTestConfig::default().cookie_name,
+                    name => name.to_string(),
+                },
+                group_a_weight: self.http_ab_weight,
+                group_b_root: PathBuf::from(&self.http_ab_group_b_root),
+            }),
+            mdns_announce: self.http_mdns,
+            tor_hidden_service: self.http_tor,
+            tor_control_port: self.http_tor_control_port.parse().unwrap_or(9051),
+            geoip_db: optional_path(&self.http_geoip_db),
+            integrity_check: if self.http_integrity_check {
+                Some(IntegrityConfig {
+                    check_interval_secs: self.http_integrity_interval_secs.parse().unwrap_or(3600),
+                    algorithm: self.http_integrity_algorithm,
+                    manifest_path: PathBuf::from(&self.http_integrity_manifest),
+                })
+            } else {
+                None
+            },
+            notes: self.http_notes.clone(),
+            export_log_on_stop: self
+                .http_export_log
+                .then(|| PathBuf::from(&self.http_export_log_dir)),
+            startup: self.http_startup.config(),
+            restart: self.http_restart,
+            single_file: self.share_file.clone(),
+        }
+    }
+
+    /// Build the FTP configuration from the UI fields
+    fn ftp_config(&self) -> FtpConfig {
+        FtpConfig {
             port: self.ftp_port.parse().unwrap_or(2121),
             root_dir: PathBuf::from(&self.ftp_root_dir),
             username: self.ftp_username.clone(),
             password: self.ftp_password.clone(),
             anonymous_access: self.ftp_anonymous,
+            custom_messages: self
+                .ftp_custom_messages
+                .iter()
+                .filter(|(_, text)| !text.trim().is_empty())
+                .map(|(&code, text)| (code, text.clone()))
+                .collect(),
             passive_mode: self.ftp_passive_mode,
             passive_ports: (
                 self.ftp_passive_ports_start.parse().unwrap_or(50000),
                 self.ftp_passive_ports_end.parse().unwrap_or(50100),
             ),
-        };
-        self.config.tftp = TftpConfig {
+            passive_host: Some(self.ftp_passive_host.trim().to_string())
+                .filter(|host| !host.is_empty()),
+            enable_discovery_broadcast: self.ftp_discovery,
+            mdns_announce: self.ftp_mdns,
+            overwrite_policy: self.ftp_overwrite_policy,
+            compress_uploads: self.ftp_compress.then_some(self.ftp_compress_algorithm),
+            decompress_on_download: self.ftp_decompress_on_download,
+            safe_delete: self.ftp_safe_delete,
+            trash_retention_days: self.ftp_trash_retention_days.parse().unwrap_or(30),
+            transfer_notifications: self.ftp_notify.then(|| NotificationConfig {
+                mode: if self.ftp_notify_email {
+                    NotificationMode::Email(self.ftp_notify_smtp.clone())
+                } else {
+                    NotificationMode::Webhook(self.ftp_notify_url.trim().to_string())
+                },
+                trigger: self.ftp_notify_trigger,
+                min_size_bytes: self
+                    .ftp_notify_min_kb
+                    .trim()
+                    .parse::<u64>()
+                    .unwrap_or(0)
+                    .saturating_mul(1024),
+            }),
+            discovery_port: self.ftp_discovery_port.parse().unwrap_or(21210),
+            notes: self.ftp_notes.clone(),
+            auto_stop_seconds: if self.ftp_auto_stop {
+                self.ftp_auto_stop_secs.parse().ok()
+            } else {
+                None
+            },
+            export_log_on_stop: self
+                .ftp_export_log
+                .then(|| PathBuf::from(&self.ftp_export_log_dir)),
+            startup: self.ftp_startup.config(),
+            restart: self.ftp_restart,
+        }
+    }
+
+    /// Build the TFTP configuration from the UI fields
+    fn tftp_config(&self) -> TftpConfig {
+        TftpConfig {
             port: self.tftp_port.parse().unwrap_or(69),
             root_dir: PathBuf::from(&self.tftp_root_dir),
             read_only: self.tftp_read_only,
-        };
-        self.config.ssh = SshConfig {
+            retry_timeout_ms: self.tftp_retry_timeout_ms.parse().unwrap_or(3000),
+            max_retries: self.tftp_max_retries.parse().unwrap_or(100),
+            ipv6: self.tftp_ipv6,
+            allow_patterns: self
+                .tftp_allow_patterns
+                .lines()
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+            notes: self.tftp_notes.clone(),
+            auto_stop_seconds: if self.tftp_auto_stop {
+                self.tftp_auto_stop_secs.parse().ok()
+            } else {
+                None
+            },
+            export_log_on_stop: self
+                .tftp_export_log
+                .then(|| PathBuf::from(&self.tftp_export_log_dir)),
+            staging_dir: optional_path(&self.tftp_staging_dir),
+            startup: self.tftp_startup.config(),
+            restart: self.tftp_restart,
+        }
+    }
+
+    /// Build the SSH configuration from the UI fields
+    fn ssh_config(&self) -> SshConfig {
+        SshConfig {
             port: self.ssh_port.parse().unwrap_or(2222),
             root_dir: PathBuf::from(&self.ssh_root_dir),
             username: self.ssh_username.clone(),
             password: self.ssh_password.clone(),
-        };
-        if let Err(e) = self.config.save() {
-            tracing::error!("Failed to save config: {}", e);
+            notes: self.ssh_notes.clone(),
+            auto_stop_seconds: if self.ssh_auto_stop {
+                self.ssh_auto_stop_secs.parse().ok()
+            } else {
+                None
+            },
+            export_log_on_stop: self
+                .ssh_export_log
+                .then(|| PathBuf::from(&self.ssh_export_log_dir)),
+            startup: self.ssh_startup.config(),
+            restart: self.ssh_restart,
         }
     }
-}
 
-impl eframe::App for OServersApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Request continuous updates for real-time log display
-        ctx.request_repaint();
+    /// Auto-start settings of `server_type`
+    fn startup(&self, server_type: ServerType) -> &StartupFields {
+        match server_type {
+            ServerType::Http => &self.http_startup,
+            ServerType::Ftp => &self.ftp_startup,
+            ServerType::Tftp => &self.tftp_startup,
+            ServerType::Ssh => &self.ssh_startup,
+        }
+    }
 
-        egui::SidePanel::left("server_list")
-            .resizable(true)
-            .min_width(200.0)
-            .show(ctx, |ui| {
-                ui.heading("🖥 Servers");
-                ui.separator();
+    /// Servers forming a loop in the start order, first one repeated at the end
+    fn startup_cycle(&self) -> Option<Vec<ServerType>> {
+        fn visit(
+            app: &OServersApp,
+            server: ServerType,
+            path: &mut Vec<ServerType>,
+            done: &mut Vec<ServerType>,
+        ) -> Option<Vec<ServerType>> {
+            if let Some(pos) = path.iter().position(|s| *s == server) {
+                let mut cycle = path[pos..].to_vec();
+                cycle.push(server);
+                return Some(cycle);
+            }
+            if done.contains(&server) {
+                return None;
+            }
+            path.push(server);
+            for dep in &app.startup(server).start_after {
+                let cycle = visit(app, *dep, path, done);
+                if cycle.is_some() {
+                    return cycle;
+                }
+            }
+            path.pop();
+            done.push(server);
+            None
+        }
 
-                for (idx, entry) in self.servers.iter().enumerate() {
-                    let is_selected = self.selected_server == Some(idx);
-                    let status = entry.status();
+        let mut done = Vec::new();
+        ServerType::ALL
+            .iter()
+            .find_map(|server| visit(self, *server, &mut Vec::new(), &mut done))
+    }
 
-                    // Use colored circles instead of emoji for reliable display
-                    let status_color = match &status {
-                        ServerStatus::Stopped => egui::Color32::GRAY,
-                        ServerStatus::Starting => egui::Color32::YELLOW,
-                        ServerStatus::Running => egui::Color32::GREEN,
-                        ServerStatus::Stopping => egui::Color32::from_rgb(255, 165, 0), // Orange
-                        ServerStatus::Error(_) => egui::Color32::RED,
-                    };
+    /// Queue the servers configured to start with the application
+    fn schedule_auto_start(&mut self) {
+        let cycle = self.startup_cycle().unwrap_or_default();
+        for idx in 0..self.servers.len() {
+            let server_type = self.servers[idx].server_type;
+            let startup = self.startup(server_type).config();
+            if !startup.auto_start {
+                continue;
+            }
+            let mut after = startup.start_after;
+            if cycle.contains(&server_type) {
+                let order: Vec<_> = cycle.iter().map(ServerType::label).collect();
+                self.servers[idx]
+                    .state
+                    .write()
+                    .add_log(LogMessage::warning(format!(
+                        "Auto start: ignoring circular start order {}",
+                        order.join(" → ")
+                    )));
+                after.clear();
+            }
+            self.auto_start.push(PendingStart {
+                idx,
+                after,
+                delay: Duration::from_secs(startup.startup_delay_secs),
+                ready_since: None,
+                logged_wait: false,
+            });
+        }
+    }
 
-                    ui.horizontal(|ui| {
-                        // Draw a colored circle as status indicator
-                        let (rect, _response) =
-                            ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
-                        let center = rect.center();
-                        ui.painter().circle_filled(center, 6.0, status_color);
+    fn restart_policy(&self, server_type: ServerType) -> RestartPolicy {
+        match server_type {
+            ServerType::Http => self.http_restart,
+            ServerType::Ftp => self.ftp_restart,
+            ServerType::Tftp => self.tftp_restart,
+            ServerType::Ssh => self.ssh_restart,
+        }
+    }
 
-                        if ui
-                            .selectable_label(is_selected, entry.server_type.name())
-                            .clicked()
-                        {
-                            self.selected_server = Some(idx);
-                        }
-                    });
+    /// Close the window on SIGTERM, going through the close handler so logs are flushed
+    #[cfg(unix)]
+    fn close_on_terminate(&self, ctx: &egui::Context) {
+        use tokio::signal::unix::{SignalKind, signal};
+        let Some(runtime) = &self.runtime else {
+            return;
+        };
+        let ctx = ctx.clone();
+        runtime.spawn(async move {
+            let mut term = match signal(SignalKind::terminate()) {
+                Ok(term) => term,
+                Err(e) => {
+                    tracing::warn!("Cannot listen for SIGTERM: {}", e);
+                    return;
                 }
-            });
-
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(idx) = self.selected_server {
-                // Extract all needed data from entry first to avoid borrow conflicts
-                let server_type = self.servers[idx].server_type;
-                let status = self.servers[idx].status();
-                let is_running = self.servers[idx].is_running();
-                let logs = self.servers[idx].logs();
+            };
+            term.recv().await;
+            tracing::info!("SIGTERM received, closing");
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            ctx.request_repaint();
+        });
+    }
 
-                // Track button clicks
-                let mut start_clicked = false;
-                let mut stop_clicked = false;
+    #[cfg(not(unix))]
+    fn close_on_terminate(&self, _ctx: &egui::Context) {}
 
-                ui.horizontal(|ui| {
-                    ui.heading(format!("{} Settings", server_type.name()));
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        if is_running {
-                            if ui.button("⏹ Stop").clicked() {
-                                stop_clicked = true;
-                            }
-                        } else if ui.button("▶ Start").clicked() {
-                            start_clicked = true;
-                        }
-                    });
-                });
+    /// Apply the restart policy to servers that ended on their own and
+    /// start the ones whose restart is due
+    fn supervise(&mut self) {
+        let now = Instant::now();
+        for idx in 0..self.servers.len() {
+            let policy = self.restart_policy(self.servers[idx].server_type);
+            let entry = &mut self.servers[idx];
+            if entry.restart_at.is_some_and(|at| at <= now) {
+                self.start_server(idx);
+                continue;
+            }
+            if !entry.task.as_ref().is_some_and(|task| task.is_finished()) {
+                continue;
+            }
+            let Some(outcome) = entry.task.take().and_then(FutureExt::now_or_never) else {
+                continue;
+            };
+            // A stop the user asked for ends with `Ok` and a stopped status
+            let (behavior, reason) = match outcome {
+                Ok(Ok(())) => match entry.status() {
+                    ServerStatus::Error(e) => (policy.on_error_status, e),
+                    _ => continue,
+                },
+                Ok(Err(e)) => (policy.on_crash, e.to_string()),
+                Err(e) => (policy.on_crash, e.to_string()),
+            };
+            entry
+                .restarts
+                .retain(|at| now.duration_since(*at) < RESTART_WINDOW);
+            let delay = match behavior {
+                RestartBehavior::DoNothing => continue,
+                RestartBehavior::RestartImmediately => Duration::ZERO,
+                RestartBehavior::RestartWithBackoff(secs) => {
+                    let factor = 1u64 << entry.restarts.len().min(10);
+                    Duration::from_secs(secs.saturating_mul(factor)).min(RESTART_WINDOW)
+                }
+            };
+            let name = entry.server_type.name();
+            if entry.restarts.len() >= usize::from(policy.max_restarts_per_hour) {
+                let mut state = entry.state.write();
+                state.status = ServerStatus::Error("Restart limit reached".to_string());
+                state.add_log(LogMessage::error(format!(
+                    "Not restarting: {} restarts within the last hour",
+                    entry.restarts.len()
+                )));
+                desktop_notification(
+                    &format!("{} server stopped", name),
+                    &format!("Restart limit reached, last error: {}", reason),
+                );
+                continue;
+            }
+            entry.restarts.push_back(now);
+            entry.state.write().add_log(LogMessage::warning(format!(
+                "Restarting in {}s after: {}",
+                delay.as_secs(),
+                reason
+            )));
+            entry.restart_at = Some(now + delay);
+        }
+    }
 
-                // Handle button clicks after the closure
-                if stop_clicked {
-                    self.stop_server(idx);
+    /// Start queued servers whose dependencies are running and whose delay has passed
+    fn run_auto_start(&mut self, ctx: &egui::Context) {
+        let pending: Vec<ServerType> = self
+            .auto_start
+            .iter()
+            .map(|p| self.servers[p.idx].server_type)
+            .collect();
+        let mut due = Vec::new();
+        for start in &mut self.auto_start {
+            let state = self.servers[start.idx].state.clone();
+            // Stop waiting for servers that are neither running nor going to start
+            start.after.retain(|dep| {
+                let starting = pending.contains(dep)
+                    || self
+                        .servers
+                        .iter()
+                        .any(|e| e.server_type == *dep && e.is_active());
+                if !starting {
+                    state.write().add_log(LogMessage::warning(format!(
+                        "Auto start: not waiting for {}, it is not starting",
+                        dep.label()
+                    )));
                 }
-                if start_clicked {
-                    self.start_server(idx);
+                starting
+            });
+            let waiting: Vec<&str> = start
+                .after
+                .iter()
+                .filter(|dep| {
+                    !self
+                        .servers
+                        .iter()
+                        .any(|e| e.server_type == **dep && e.is_running())
+                })
+                .map(ServerType::label)
+                .collect();
+            if !waiting.is_empty() {
+                if !start.logged_wait {
+                    start.logged_wait = true;
+                    state.write().add_log(LogMessage::info(format!(
+                        "Auto start: waiting for {}",
+                        waiting.join(", ")
+                    )));
                 }
+                continue;
+            }
+            let ready_since = *start.ready_since.get_or_insert_with(|| {
+                if !start.delay.is_zero() {
+                    state.write().add_log(LogMessage::info(format!(
+                        "Auto start: starting in {}s",
+                        start.delay.as_secs()
+                    )));
+                }
+                Instant::now()
+            });
+            if ready_since.elapsed() >= start.delay {
+                due.push(start.idx);
+            }
+        }
+        self.auto_start.retain(|start| !due.contains(&start.idx));
+        for idx in due {
+            self.request_start(idx, ctx);
+        }
+    }
 
-                ui.separator();
-
-                // Status display
-                let status_text = match &status {
-                    ServerStatus::Stopped => "Stopped".to_string(),
-                    ServerStatus::Starting => "Starting...".to_string(),
-                    ServerStatus::Running => "Running".to_string(),
-                    ServerStatus::Stopping => "Stopping...".to_string(),
-                    ServerStatus::Error(e) => format!("Error: {}", e),
-                };
-                ui.horizontal(|ui| {
-                    ui.label("Status:");
-                    ui.label(&status_text);
-                });
-
-                ui.separator();
+    /// Notes field of the settings for `server_type`
+    fn notes_mut(&mut self, server_type: ServerType) -> &mut String {
+        match server_type {
+            ServerType::Http => &mut self.http_notes,
+            ServerType::Ftp => &mut self.ftp_notes,
+            ServerType::Tftp => &mut self.tftp_notes,
+            ServerType::Ssh => &mut self.ssh_notes,
+        }
+    }
 
-                // Server-specific settings
-                egui::ScrollArea::vertical()
+    /// The settings every server type has, as entered in the UI
+    fn shared_fields(&self, server: ServerType) -> SharedFields {
+        let (root_dir, auto_stop, auto_stop_secs, export_log, export_log_dir) = match server {
+            ServerType::Http => (
+                &self.http_root_dir,
+                self.http_auto_stop,
+                &self.http_auto_stop_secs,
+                self.http_export_log,
+                &self.http_export_log_dir,
+            ),
+            ServerType::Ftp => (
+                &self.ftp_root_dir,
+                self.ftp_auto_stop,
+                &self.ftp_auto_stop_secs,
+                self.ftp_export_log,
+                &self.ftp_export_log_dir,
+            ),
+            ServerType::Tftp => (
+                &self.tftp_root_dir,
+                self.tftp_auto_stop,
+                &self.tftp_auto_stop_secs,
+                self.tftp_export_log,
+                &self.tftp_export_log_dir,
+            ),
+            ServerType::Ssh => (
+                &self.ssh_root_dir,
+                self.ssh_auto_stop,
+                &self.ssh_auto_stop_secs,
+                self.ssh_export_log,
+                &self.ssh_export_log_dir,
+            ),
+        };
+        SharedFields {
+            root_dir: root_dir.clone(),
+            auto_stop,
+            auto_stop_secs: auto_stop_secs.clone(),
+            export_log,
+            export_log_dir: export_log_dir.clone(),
+        }
+    }
+
+    /// Overwrite the shared settings of `to` with those of `from`
+    fn copy_shared_fields(&mut self, from: ServerType, to: ServerType) {
+        let fields = self.shared_fields(from);
+        let (root_dir, auto_stop, auto_stop_secs, export_log, export_log_dir) = match to {
+            ServerType::Http => (
+                &mut self.http_root_dir,
+                &mut self.http_auto_stop,
+                &mut self.http_auto_stop_secs,
+                &mut self.http_export_log,
+                &mut self.http_export_log_dir,
+            ),
+            ServerType::Ftp => (
+                &mut self.ftp_root_dir,
+                &mut self.ftp_auto_stop,
+                &mut self.ftp_auto_stop_secs,
+                &mut self.ftp_export_log,
+                &mut self.ftp_export_log_dir,
+            ),
+            ServerType::Tftp => (
+                &mut self.tftp_root_dir,
+                &mut self.tftp_auto_stop,
+                &mut self.tftp_auto_stop_secs,
+                &mut self.tftp_export_log,
+                &mut self.tftp_export_log_dir,
+            ),
+            ServerType::Ssh => (
+                &mut self.ssh_root_dir,
+                &mut self.ssh_auto_stop,
+                &mut self.ssh_auto_stop_secs,
+                &mut self.ssh_export_log,
+                &mut self.ssh_export_log_dir,
+            ),
+        };
+        *root_dir = fields.root_dir;
+        *auto_stop = fields.auto_stop;
+        *auto_stop_secs = fields.auto_stop_secs;
+        *export_log = fields.export_log;
+        *export_log_dir = fields.export_log_dir;
+    }
+
+    /// The configuration as currently entered in the UI
+    fn effective_config(&self) -> AppConfig {
+        let mut config = self.config.clone();
+        config.http = self.http_config();
+        config.ftp = self.ftp_config();
+        config.tftp = self.tftp_config();
+        config.ssh = self.ssh_config();
+        config.pinned_patterns = self.pinned_patterns.clone();
+        config
+    }
+
+    /// Redacted config, environment and logs of all servers for bug reports
+    fn diagnostics_bundle(&self) -> String {
+        let logs: Vec<_> = self
+            .servers
+            .iter()
+            .map(|entry| (entry.server_type.name(), entry.logs()))
+            .collect();
+        diagnostics::bundle(
+            &self.effective_config(),
+            &logs,
+            &self.font_status,
+            self.runtime_error.as_deref(),
+        )
+    }
+
+    /// Ask for a file and write the diagnostics bundle to it
+    fn save_diagnostics(&self) {
+        let path = match dialogs::save_file("oservers-diagnostics.txt") {
+            Picked::Chosen(path) => path,
+            Picked::Cancelled => return,
+            Picked::Unavailable => {
+                tracing::warn!("Cannot save diagnostics without a file dialog, copy them instead");
+                return;
+            }
+        };
+        if let Err(e) = std::fs::write(&path, self.diagnostics_bundle()) {
+            tracing::error!("Failed to save diagnostics: {}", e);
+        }
+    }
+
+    fn save_config(&mut self) {
+        self.config = self.effective_config();
+        if let Ok(content) = self.config.to_json() {
+            self.saved_config = content.clone();
+            self.config_writer.write(content);
+        }
+    }
+
+    /// Queue the config for writing once the auto save interval has passed,
+    /// if anything changed since it was last written
+    fn auto_save(&mut self) {
+        let Some(secs) = self.config.auto_save_interval_secs else {
+            return;
+        };
+        if self.last_auto_save.elapsed() < Duration::from_secs(secs.max(1)) {
+            return;
+        }
+        self.last_auto_save = Instant::now();
+        let config = self.effective_config();
+        let Ok(content) = config.to_json() else {
+            return;
+        };
+        if content == self.saved_config {
+            return;
+        }
+        self.config = config;
+        self.saved_config = content.clone();
+        self.config_writer.write(content);
+    }
+
+    /// Log in to the FTP server at `idx` in the background and show the outcome
+    fn test_ftp_login(&mut self, idx: usize, ctx: &egui::Context) {
+        if self.servers[idx].state.read().status != ServerStatus::Running {
+            *self.login_test.write() = Some(LoginTest::NotRunning);
+            return;
+        }
+        let Some(runtime) = &self.runtime else {
+            return;
+        };
+        *self.login_test.write() = Some(LoginTest::Testing);
+        let config = self.ftp_config();
+        let state = self.servers[idx].state.clone();
+        let login_test = self.login_test.clone();
+        let ctx = ctx.clone();
+        runtime.spawn(async move {
+            let result = ftp::test_login(config, state).await;
+            // Closed while testing, the outcome is still in the log
+            let mut login_test = login_test.write();
+            if login_test.is_some() {
+                *login_test = Some(LoginTest::Done(result));
+            }
+            ctx.request_repaint();
+        });
+    }
+
+    /// Window with the outcome of the FTP test login
+    fn login_test_window(&mut self, ctx: &egui::Context) {
+        let Some(test) = self.login_test.read().clone() else {
+            return;
+        };
+        let username = &self.ftp_username;
+        let mut close = false;
+        egui::Window::new(tr("FTP test login"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                match &test {
+                    LoginTest::NotRunning => {
+                        ui.label(tr("Server not running — start it first"));
+                    }
+                    LoginTest::Testing => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(format!("{} '{}'...", tr("Logging in as"), username));
+                        });
+                    }
+                    LoginTest::Done(Ok(())) => {
+                        ui.colored_label(
+                            egui::Color32::GREEN,
+                            format!("✔ {} '{}'", tr("Logged in as"), username),
+                        );
+                    }
+                    LoginTest::Done(Err(e)) => {
+                        ui.colored_label(
+                            egui::Color32::LIGHT_RED,
+                            format!("✖ {} '{}': {}", tr("Login failed as"), username, e),
+                        );
+                    }
+                }
+                ui.separator();
+                if ui.button(tr("Close")).clicked() {
+                    close = true;
+                }
+            });
+        if close {
+            *self.login_test.write() = None;
+        }
+    }
+
+    /// Progress of the sensitive file scan, then the files it found for
+    /// confirmation, starting the server right away if there are none
+    fn sensitive_scan_window(&mut self, ctx: &egui::Context) {
+        let Some(scan) = &self.sensitive_scan else {
+            return;
+        };
+        let idx = scan.idx;
+        let found = scan.found.read().clone();
+        if found.as_ref().is_some_and(|found| found.is_empty()) {
+            self.sensitive_scan = None;
+            self.start_server(idx);
+            return;
+        }
+        let title = if found.is_some() {
+            tr("⚠ Sensitive files found")
+        } else {
+            tr("Checking for sensitive files")
+        };
+        let mut proceed = false;
+        let mut cancel = false;
+        egui::Window::new(title)
+            .id(egui::Id::new("sensitive_scan"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                match &found {
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(tr("Looking for sensitive files in the root directory..."));
+                        });
+                    }
+                    Some(found) => {
+                        ui.label(tr("Starting the HTTP server would expose these files:"));
+                        egui::ScrollArea::vertical()
+                            .max_height(200.0)
+                            .show(ui, |ui| {
+                                for path in found {
+                                    ui.monospace(path.display().to_string());
+                                }
+                            });
+                    }
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button(tr("Start anyway")).clicked() {
+                        proceed = true;
+                    }
+                    if ui.button(tr("Cancel")).clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+        // A scan still running finishes unseen
+        if proceed {
+            self.sensitive_scan = None;
+            self.start_server(idx);
+        } else if cancel {
+            self.sensitive_scan = None;
+        }
+    }
+
+    /// Fetch the version manifest in the background
+    fn check_for_updates(&mut self, ctx: &egui::Context) {
+        let Some(runtime) = &self.runtime else {
+            return;
+        };
+        *self.update_status.write() = Some(UpdateStatus::Checking);
+        self.update_dismissed = false;
+        let url = self.config.update_manifest_url.clone();
+        let status = self.update_status.clone();
+        let ctx = ctx.clone();
+        runtime.spawn(async move {
+            let result = update_check::check(&url).await;
+            if let UpdateStatus::Failed(e) = &result {
+                tracing::warn!("Update check against {} failed: {}", url, e);
+            }
+            *status.write() = Some(result);
+            ctx.request_repaint();
+        });
+    }
+
+    /// Small corner notice when a newer release is out
+    fn update_notification(&mut self, ctx: &egui::Context) {
+        if self.update_dismissed {
+            return;
+        }
+        let Some(UpdateStatus::Available { version, url }) = self.update_status.read().clone()
+        else {
+            return;
+        };
+        egui::Window::new(tr("Update available"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+            .collapsible(false)
+            .resizable(false)
+            .title_bar(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} {}",
+                    tr("OServers is available in version"),
+                    version
+                ));
+                ui.horizontal(|ui| {
+                    if !url.is_empty() {
+                        ui.hyperlink_to(tr("Release page"), &url);
+                    }
+                    if ui.button(tr("Dismiss")).clicked() {
+                        self.update_dismissed = true;
+                    }
+                });
+            });
+    }
+}
+
+fn status_color(status: &ServerStatus) -> egui::Color32 {
+    match status {
+        ServerStatus::Stopped => egui::Color32::GRAY,
+        ServerStatus::Starting => egui::Color32::YELLOW,
+        ServerStatus::Running => egui::Color32::GREEN,
+        ServerStatus::Stopping => egui::Color32::from_rgb(255, 165, 0), // Orange
+        ServerStatus::Error(_) => egui::Color32::RED,
+    }
+}
+
+/// Colored circle showing a server's status, emoji do not render reliably
+fn status_dot(ui: &mut egui::Ui, status: &ServerStatus) {
+    let color = status_color(status);
+    let (rect, _response) = ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+    ui.painter().circle_filled(rect.center(), 6.0, color);
+}
+
+fn status_text(status: &ServerStatus) -> String {
+    match status {
+        ServerStatus::Stopped => tr("Stopped").to_string(),
+        ServerStatus::Starting => tr("Starting...").to_string(),
+        ServerStatus::Running => tr("Running").to_string(),
+        ServerStatus::Stopping => tr("Stopping...").to_string(),
+        ServerStatus::Error(e) => format!("{} {}", tr("Error:"), e),
+    }
+}
+
+/// Read-only card of one server for monitor mode
+fn monitor_card(ui: &mut egui::Ui, entry: &ServerEntry) {
+    let (status, metrics, active_downloads, errors) = {
+        let s = entry.state.read();
+        let errors: Vec<LogMessage> = s
+            .logs
+            .iter()
+            .filter(|log| log.level == LogLevel::Error)
+            .rev()
+            .take(3)
+            .cloned()
+            .collect();
+        (s.status.clone(), s.metrics, s.active_downloads, errors)
+    };
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.set_width(MONITOR_CARD_WIDTH);
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                status_dot(ui, &status);
+                ui.strong(entry.server_type.name());
+            });
+            egui::Grid::new(("monitor_card", entry.server_type))
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label(tr("Status:"));
+                    ui.label(status_text(&status));
+                    ui.end_row();
+
+                    ui.label(tr("Uptime:"));
+                    ui.label(entry.running_since.map_or_else(
+                        || "-".to_string(),
+                        |since| http::format_duration(since.elapsed()),
+                    ));
+                    ui.end_row();
+
+                    ui.label(tr("Active downloads:"));
+                    ui.label(active_downloads.to_string());
+                    ui.end_row();
+
+                    ui.label(tr("Requests:"));
+                    ui.label(metrics.requests.to_string());
+                    ui.end_row();
+
+                    ui.label(tr("Throughput:"));
+                    let current = entry.throughput.back().copied().unwrap_or(0);
+                    ui.label(format!("{}/s", http::format_size(current)));
+                    ui.end_row();
+                });
+            sparkline(ui, &entry.throughput, egui::vec2(MONITOR_CARD_WIDTH, 40.0));
+            ui.label(tr("Recent errors:"));
+            if errors.is_empty() {
+                ui.weak(tr("None"));
+            }
+            for error in &errors {
+                log_line(ui, error);
+            }
+        });
+    });
+}
+
+/// Line chart of `samples` scaled to the largest one
+fn sparkline(ui: &mut egui::Ui, samples: &VecDeque<u64>, size: egui::Vec2) {
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let max = samples.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let step = rect.width() / (THROUGHPUT_SAMPLES - 1) as f32;
+    // Newest sample at the right edge
+    let offset = THROUGHPUT_SAMPLES - samples.len();
+    let points: Vec<egui::Pos2> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            egui::pos2(
+                rect.left() + (offset + i) as f32 * step,
+                rect.bottom() - value as f32 / max * rect.height(),
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.5, egui::Color32::LIGHT_GREEN),
+    ));
+}
+
+/// Render a single log entry, returning the response of the message label
+fn log_line(ui: &mut egui::Ui, log: &LogMessage) -> egui::Response {
+    let timestamp = log.timestamp.format("[%H:%M:%S%.3f]").to_string();
+    let color = match log.level {
+        LogLevel::Debug => egui::Color32::LIGHT_GRAY,
+        LogLevel::Info => egui::Color32::LIGHT_GREEN,
+        LogLevel::Warning => egui::Color32::YELLOW,
+        LogLevel::Error => egui::Color32::LIGHT_RED,
+    };
+    ui.horizontal(|ui| {
+        ui.label(egui::RichText::new(&timestamp).color(egui::Color32::GRAY));
+        let message = egui::RichText::new(&log.message).color(color);
+        // Multi-line entries like the startup report are laid out in columns
+        if log.message.contains('\n') {
+            ui.label(message.monospace())
+        } else {
+            ui.label(message)
+        }
+    })
+    .inner
+}
+
+/// Live table of transfers with their progress and average rate
+fn transfers_table(ui: &mut egui::Ui, transfers: &[TransferProgress]) {
+    egui::Grid::new("transfers")
+        .num_columns(5)
+        .striped(true)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            ui.strong(tr("Client"));
+            ui.strong(tr("File"));
+            ui.label("");
+            ui.strong(tr("Progress"));
+            ui.strong(tr("Rate"));
+            ui.end_row();
+            for transfer in transfers {
+                let bytes = transfer.bytes.load(std::sync::atomic::Ordering::Relaxed);
+                let elapsed = transfer.started.elapsed().as_secs_f64().max(0.001);
+                ui.label(&transfer.client);
+                ui.label(&transfer.file);
+                ui.label(if transfer.upload { "⬆" } else { "⬇" })
+                    .on_hover_text(if transfer.upload {
+                        tr("Upload")
+                    } else {
+                        tr("Download")
+                    });
+                let bar = match transfer.total.filter(|&total| total > 0) {
+                    Some(total) => {
+                        let fraction = (bytes as f32 / total as f32).min(1.0);
+                        egui::ProgressBar::new(fraction).text(format!(
+                            "{:.0}%  {} / {}",
+                            fraction * 100.0,
+                            http::format_size(bytes),
+                            http::format_size(total)
+                        ))
+                    }
+                    // Without a size only the byte count can be shown
+                    None => egui::ProgressBar::new(0.0).text(http::format_size(bytes)),
+                };
+                ui.add(bar.desired_width(220.0));
+                ui.label(format!(
+                    "{}/s",
+                    http::format_size((bytes as f64 / elapsed) as u64)
+                ));
+                ui.end_row();
+            }
+        });
+}
+
+/// Editable table of webhook URLs and their triggers
+fn webhooks_table(ui: &mut egui::Ui, webhooks: &mut Vec<WebhookConfig>) {
+    ui.label(tr("Webhooks:"));
+    let mut remove = None;
+    egui::Grid::new("webhooks")
+        .num_columns(4)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            for (i, webhook) in webhooks.iter_mut().enumerate() {
+                egui::ComboBox::from_id_salt(("webhook_trigger", i))
+                    .selected_text(tr(webhook.trigger.name()))
+                    .show_ui(ui, |ui| {
+                        for trigger in WebhookTrigger::ALL {
+                            ui.selectable_value(&mut webhook.trigger, trigger, tr(trigger.name()));
+                        }
+                    });
+                ui.add(
+                    egui::TextEdit::singleline(&mut webhook.url)
+                        .hint_text("https://example.com/hook"),
+                );
+                ui.checkbox(&mut webhook.include_request_details, tr("Details"));
+                if ui.small_button("🗑").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+    if let Some(i) = remove {
+        webhooks.remove(i);
+    }
+    if ui.button(tr("➕ Add webhook")).clicked() {
+        webhooks.push(WebhookConfig::default());
+    }
+}
+
+/// Editable table of files served from memory
+fn virtual_files_table(ui: &mut egui::Ui, files: &mut Vec<VirtualFile>) {
+    ui.label(tr("Virtual files:")).on_hover_text(tr(
+        "{{server_port}} and {{server_ip}} in the content are filled in",
+    ));
+    let mut remove = None;
+    egui::Grid::new("virtual_files")
+        .num_columns(4)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            for (i, file) in files.iter_mut().enumerate() {
+                ui.add(
+                    egui::TextEdit::singleline(&mut file.path)
+                        .hint_text("/robots.txt")
+                        .desired_width(120.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut file.content_type)
+                        .hint_text("text/plain")
+                        .desired_width(120.0),
+                );
+                ui.add(
+                    egui::TextEdit::multiline(&mut file.content)
+                        .desired_rows(2)
+                        .code_editor(),
+                );
+                if ui.small_button("🗑").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+    if let Some(i) = remove {
+        files.remove(i);
+    }
+    if ui.button(tr("➕ Add virtual file")).clicked() {
+        files.push(VirtualFile::default());
+    }
+}
+
+/// Text for an optional path field, empty when unset
+fn path_text(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+/// Path from an optional path field, `None` when left empty
+fn optional_path(text: &str) -> Option<PathBuf> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| PathBuf::from(text))
+}
+
+/// Port field with a button filling in the first free port from its value upwards
+fn port_field(ui: &mut egui::Ui, port: &mut String, default: u16, udp: bool) {
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(port).desired_width(60.0));
+        let find = ui.button(tr("🔍 Find free port")).on_hover_text(tr(
+            "Fill in the first port from this one upwards that is not in use",
+        ));
+        let free = find
+            .clicked()
+            .then(|| find_free_port(port.trim().parse().unwrap_or(default), udp))
+            .flatten();
+        if let Some(free) = free {
+            *port = free.to_string();
+        }
+    });
+}
+
+/// Characters generated passwords are made of, without look-alikes like `l` and `1`
+const PASSWORD_ALPHABET: &[u8] =
+    b"abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789-_.!@#%+=";
+
+/// Random password of `length` characters from the OS CSPRNG
+fn generate_password(length: usize) -> Option<String> {
+    // Largest multiple of the alphabet size, higher bytes are redrawn to avoid bias
+    let limit = 256 - 256 % PASSWORD_ALPHABET.len();
+    let mut password = String::with_capacity(length);
+    let mut buf = [0u8; 64];
+    while password.len() < length {
+        getrandom::getrandom(&mut buf).ok()?;
+        for &byte in buf.iter().filter(|&&b| (b as usize) < limit) {
+            if password.len() == length {
+                break;
+            }
+            password.push(PASSWORD_ALPHABET[byte as usize % PASSWORD_ALPHABET.len()] as char);
+        }
+    }
+    Some(password)
+}
+
+/// Masked password field with a button filling in a generated password
+///
+/// The generated password is also copied to the clipboard for sharing.
+fn password_field(ui: &mut egui::Ui, password: &mut String, generated_length: usize) {
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(password).password(true));
+        let generate = ui
+            .button(tr("🎲 Generate"))
+            .on_hover_text(tr("Fill in a random password and copy it to the clipboard"));
+        let generated = generate
+            .clicked()
+            .then(|| generate_password(generated_length))
+            .flatten();
+        if let Some(generated) = generated {
+            ui.ctx().copy_text(generated.clone());
+            *password = generated;
+        }
+    });
+}
+
+/// Checkbox and seconds field for stopping a server after a period without activity
+fn auto_stop_picker(ui: &mut egui::Ui, enabled: &mut bool, secs: &mut String) {
+    ui.horizontal(|ui| {
+        ui.checkbox(enabled, tr("Stop server after"));
+        ui.add_enabled(
+            *enabled,
+            egui::TextEdit::singleline(secs).desired_width(50.0),
+        );
+        ui.label(tr("seconds idle"));
+    });
+}
+
+/// Restart policy of the server `own`, for crashes and for error statuses
+fn restart_picker(ui: &mut egui::Ui, policy: &mut RestartPolicy, own: ServerType) {
+    ui.vertical(|ui| {
+        for (label, behavior) in [
+            (tr("On crash"), &mut policy.on_crash),
+            (tr("On error"), &mut policy.on_error_status),
+        ] {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                egui::ComboBox::from_id_salt(format!("restart_{}_{}", own.name(), label))
+                    .selected_text(tr(behavior.name()))
+                    .show_ui(ui, |ui| {
+                        let backoff = match *behavior {
+                            RestartBehavior::RestartWithBackoff(secs) => secs,
+                            _ => 5,
+                        };
+                        for option in [
+                            RestartBehavior::DoNothing,
+                            RestartBehavior::RestartImmediately,
+                            RestartBehavior::RestartWithBackoff(backoff),
+                        ] {
+                            ui.selectable_value(behavior, option, tr(option.name()));
+                        }
+                    });
+                if let RestartBehavior::RestartWithBackoff(secs) = behavior {
+                    ui.add(egui::DragValue::new(secs).range(1..=3600));
+                    ui.label(tr("seconds, doubled each time"));
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label(tr("At most"));
+            ui.add(egui::DragValue::new(&mut policy.max_restarts_per_hour).range(1..=60));
+            ui.label(tr("restarts per hour"));
+        });
+    });
+}
+
+/// Comma separated networks like `10.0.0.0/8` or single addresses, and the entries that are neither
+fn parse_networks(text: &str) -> (Vec<IpNet>, Vec<&str>) {
+    let mut networks = Vec::new();
+    let mut invalid = Vec::new();
+    for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.parse::<IpNet>() {
+            Ok(net) => networks.push(net),
+            Err(_) => match entry.parse::<std::net::IpAddr>() {
+                Ok(ip) => networks.push(IpNet::from(ip)),
+                Err(_) => invalid.push(entry),
+            },
+        }
+    }
+    (networks, invalid)
+}
+
+/// Show a notification on the desktop, off the UI thread as it may block
+fn desktop_notification(summary: &str, body: &str) {
+    let mut notification = notify_rust::Notification::new();
+    notification.appname("OServers").summary(summary).body(body);
+    std::thread::spawn(move || {
+        if let Err(e) = notification.show() {
+            tracing::warn!("Desktop notification failed: {}", e);
+        }
+    });
+}
+
+/// Draw `text` as a QR code `size` points wide
+fn qr_code(ui: &mut egui::Ui, text: &str, size: f32) {
+    let Ok(code) = qrcode::QrCode::new(text) else {
+        return;
+    };
+    let width = code.width();
+    // Four modules of quiet zone on each side so phones can read it
+    let module = size / (width + 8) as f32;
+    let (response, painter) = ui.allocate_painter(egui::vec2(size, size), egui::Sense::hover());
+    let origin = response.rect.min + egui::vec2(4.0 * module, 4.0 * module);
+    painter.rect_filled(response.rect, 0.0, egui::Color32::WHITE);
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == qrcode::Color::Dark {
+            let pos = origin + egui::vec2((i % width) as f32, (i / width) as f32) * module;
+            painter.rect_filled(
+                egui::Rect::from_min_size(pos, egui::vec2(module, module)),
+                0.0,
+                egui::Color32::BLACK,
+            );
+        }
+    }
+}
+
+/// Auto-start settings of the server `own`: start order, delay and waiting for the root directory
+fn startup_picker(ui: &mut egui::Ui, fields: &mut StartupFields, own: ServerType) {
+    ui.vertical(|ui| {
+        ui.checkbox(&mut fields.auto_start, tr("Start when OServers launches"));
+        ui.add_enabled_ui(fields.auto_start, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr("After"));
+                for other in ServerType::ALL {
+                    if other == own {
+                        continue;
+                    }
+                    let mut after = fields.start_after.contains(&other);
+                    if ui.checkbox(&mut after, other.name()).changed() {
+                        if after {
+                            fields.start_after.push(other);
+                        } else {
+                            fields.start_after.retain(|s| *s != other);
+                        }
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label(tr("Delay"));
+                ui.add(egui::TextEdit::singleline(&mut fields.delay_secs).desired_width(50.0));
+                ui.label(tr("seconds"));
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut fields.wait_for_root, tr("Wait up to"));
+            ui.add_enabled(
+                fields.wait_for_root,
+                egui::TextEdit::singleline(&mut fields.wait_for_root_secs).desired_width(50.0),
+            );
+            ui.label(tr("seconds for the root directory"));
+        });
+    });
+}
+
+/// Checkbox and directory picker for writing the log to a file when a server stops
+fn log_export_picker(ui: &mut egui::Ui, enabled: &mut bool, dir: &mut String) {
+    ui.horizontal(|ui| {
+        ui.checkbox(enabled, tr("Write to"));
+        ui.add_enabled_ui(*enabled, |ui| {
+            let field = ui.text_edit_singleline(dir);
+            if let Some(path) = dialogs::folder_button(ui, &field) {
+                *dir = path.display().to_string();
+            }
+        });
+    });
+}
+
+/// "Copy from" menu listing the other servers, returning the one picked
+fn copy_from_menu(ui: &mut egui::Ui, current: ServerType) -> Option<ServerType> {
+    let mut picked = None;
+    ui.menu_button(tr("Copy from…"), |ui| {
+        for server in ServerType::ALL.into_iter().filter(|&s| s != current) {
+            if ui.button(server.name()).clicked() {
+                picked = Some(server);
+                ui.close_menu();
+            }
+        }
+    })
+    .response
+    .on_hover_text(tr(
+        "Take the root directory, auto stop and log export settings of another server",
+    ));
+    picked
+}
+
+/// Root directory text field with a folder picker and a recent directories dropdown,
+/// returning the area it covers
+fn root_dir_picker(ui: &mut egui::Ui, root_dir: &mut String, config: &mut AppConfig) -> egui::Rect {
+    ui.horizontal(|ui| {
+        let field = ui.text_edit_singleline(root_dir);
+        if let Some(path) = dialogs::folder_button(ui, &field) {
+            *root_dir = path.display().to_string();
+            config.add_recent_dir(path);
+        }
+
+        let mut chosen = None;
+        ui.add_enabled_ui(!config.recent_dirs.is_empty(), |ui| {
+            ui.menu_button("🕘", |ui| {
+                for dir in &config.recent_dirs {
+                    if ui.button(dir.display().to_string()).clicked() {
+                        chosen = Some(dir.clone());
+                        ui.close_menu();
+                    }
+                }
+            })
+            .response
+            .on_hover_text(tr("Recent directories"));
+        });
+        if let Some(path) = chosen {
+            *root_dir = path.display().to_string();
+            config.add_recent_dir(path);
+        }
+
+        ui.menu_button("📋", |ui| {
+            for (label, format) in [
+                (tr("Copy as Markdown table"), http::ListingFormat::Markdown),
+                (tr("Copy as CSV"), http::ListingFormat::Csv),
+            ] {
+                if ui.button(label).clicked() {
+                    let listing = http::export_listing(Path::new(root_dir.trim()), format);
+                    if let Some(text) = listing {
+                        ui.ctx().copy_text(text);
+                    }
+                    ui.close_menu();
+                }
+            }
+        })
+        .response
+        .on_hover_text(tr("Copy the directory's contents (name, size, modified)"));
+    })
+    .response
+    .rect
+}
+
+impl eframe::App for OServersApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Without a runtime no server can run, offer to retry instead
+        if self.runtime.is_none() {
+            self.runtime_error_screen(ctx);
+            return;
+        }
+
+        // Request continuous updates for real-time log display, slowing
+        // down while unfocused so statuses still refresh without busy looping
+        let focused = ctx.input(|i| i.viewport().focused).unwrap_or(true);
+        if focused || !self.config.slow_repaint_when_unfocused {
+            ctx.request_repaint();
+        } else {
+            ctx.request_repaint_after(UNFOCUSED_REPAINT_INTERVAL);
+        }
+        self.tutorial_targets = tutorial::Targets::default();
+        self.run_auto_start(ctx);
+        self.supervise();
+        self.auto_save();
+        for entry in &mut self.servers {
+            entry.sample();
+        }
+
+        let server_list = egui::SidePanel::left("server_list")
+            .resizable(true)
+            .min_width(200.0)
+            .show(ctx, |ui| {
+                ui.heading(tr("🖥 Servers"));
+                ui.separator();
+
+                for (idx, entry) in self.servers.iter().enumerate() {
+                    let is_selected = self.selected_server == Some(idx);
+                    let status = entry.status();
+
+                    ui.horizontal(|ui| {
+                        status_dot(ui, &status);
+
+                        if ui
+                            .selectable_label(is_selected, entry.server_type.name())
+                            .clicked()
+                        {
+                            self.selected_server = Some(idx);
+                            self.show_dependencies = false;
+                        }
+                    });
+                }
+
+                ui.separator();
+                if ui
+                    .selectable_label(self.show_dependencies, tr("🔗 Dependencies"))
+                    .clicked()
+                {
+                    self.show_dependencies = !self.show_dependencies;
+                }
+
+                ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
+                    if ui.button(tr("⚙ Settings")).clicked() {
+                        self.show_settings = !self.show_settings;
+                    }
+                });
+            });
+        self.tutorial_targets.server_list = Some(server_list.response.rect);
+
+        // Application settings window
+        let mut show_settings = self.show_settings;
+        egui::Window::new(tr("Settings"))
+            .open(&mut show_settings)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("app_settings")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label(tr("Language:"));
+                        egui::ComboBox::from_id_salt("language")
+                            .selected_text(self.config.language.name())
+                            .show_ui(ui, |ui| {
+                                for language in Language::ALL {
+                                    if ui
+                                        .selectable_value(
+                                            &mut self.config.language,
+                                            language,
+                                            language.name(),
+                                        )
+                                        .clicked()
+                                    {
+                                        i18n::set_language(language);
+                                    }
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label(tr("Layout:"));
+                        ui.checkbox(
+                            &mut self.config.monitor_mode,
+                            tr("Monitor mode, a read-only dashboard of all servers"),
+                        );
+                        ui.end_row();
+
+                        ui.label(tr("Generated passwords:"));
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut self.config.generated_password_length)
+                                    .range(12..=64),
+                            );
+                            ui.label(tr("characters"));
+                        });
+                        ui.end_row();
+
+                        ui.label(tr("Power saving:"));
+                        ui.checkbox(
+                            &mut self.config.slow_repaint_when_unfocused,
+                            tr("Refresh once per second while in the background"),
+                        );
+                        ui.end_row();
+
+                        ui.label(tr("Auto save:"));
+                        ui.horizontal(|ui| {
+                            let mut enabled = self.config.auto_save_interval_secs.is_some();
+                            let mut secs = self
+                                .config
+                                .auto_save_interval_secs
+                                .unwrap_or(DEFAULT_AUTO_SAVE_SECS);
+                            ui.checkbox(&mut enabled, tr("Save settings every"));
+                            ui.add_enabled(enabled, egui::DragValue::new(&mut secs).range(5..=3600));
+                            ui.label(tr("seconds"));
+                            self.config.auto_save_interval_secs = enabled.then_some(secs);
+                        });
+                        ui.end_row();
+
+                        ui.label(tr("New server root:"));
+                        ui.horizontal(|ui| {
+                            let mut enabled = self.config.default_root_dir.is_some();
+                            let mut dir = self
+                                .config
+                                .default_root_dir
+                                .as_ref()
+                                .map(|dir| dir.display().to_string())
+                                .unwrap_or_default();
+                            ui.checkbox(&mut enabled, tr("Start new servers in"));
+                            ui.add_enabled(
+                                enabled,
+                                egui::TextEdit::singleline(&mut dir)
+                                    .hint_text(tr("Working directory"))
+                                    .desired_width(200.0),
+                            );
+                            self.config.default_root_dir = enabled.then(|| PathBuf::from(dir));
+                        });
+                        ui.end_row();
+
+                        ui.label(tr("Updates:"));
+                        ui.vertical(|ui| {
+                            ui.checkbox(
+                                &mut self.config.check_for_updates,
+                                tr("Check for a new version on startup"),
+                            );
+                            ui.horizontal(|ui| {
+                                ui.label(tr("Manifest URL:"));
+                                ui.text_edit_singleline(&mut self.config.update_manifest_url);
+                            });
+                            ui.horizontal(|ui| {
+                                let status = self.update_status.read().clone();
+                                let checking = matches!(status, Some(UpdateStatus::Checking));
+                                if ui
+                                    .add_enabled(!checking, egui::Button::new(tr("Check for updates")))
+                                    .clicked()
+                                {
+                                    self.check_for_updates(ui.ctx());
+                                }
+                                match status {
+                                    Some(UpdateStatus::Checking) => {
+                                        ui.spinner();
+                                    }
+                                    Some(UpdateStatus::UpToDate) => {
+                                        ui.label(tr("You are on the latest version"));
+                                    }
+                                    Some(UpdateStatus::Available { version, .. }) => {
+                                        ui.label(format!("{} {}", tr("New version:"), version));
+                                    }
+                                    Some(UpdateStatus::Failed(e)) => {
+                                        ui.colored_label(egui::Color32::LIGHT_RED, tr("Check failed"))
+                                            .on_hover_text(e);
+                                    }
+                                    None => {}
+                                }
+                            });
+                        });
+                        ui.end_row();
+
+                        ui.label(tr("Diagnostics:"));
+                        ui.horizontal(|ui| {
+                            if ui.button(tr("📋 Copy")).clicked() {
+                                ui.ctx().copy_text(self.diagnostics_bundle());
+                            }
+                            if ui.button(tr("💾 Save...")).clicked() {
+                                self.save_diagnostics();
+                            }
+                        })
+                        .response
+                        .on_hover_text(tr(
+                            "Version, platform, configuration (secrets masked) and recent logs for bug reports",
+                        ));
+                        ui.end_row();
+
+                        ui.label(tr("Tutorial:"));
+                        if ui.button(tr("Restart tutorial")).clicked() {
+                            self.config.show_tutorial = true;
+                            self.tutorial_step = 0;
+                        }
+                        ui.end_row();
+                    });
+            });
+        self.show_settings = show_settings;
+        self.update_notification(ctx);
+
+        let mut file_activity_shown = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.config.monitor_mode {
+                self.monitor_view(ui);
+            } else if self.show_dependencies {
+                self.dependency_view(ui);
+            } else if let Some(idx) = self.selected_server {
+                // Extract all needed data from entry first to avoid borrow conflicts
+                let server_type = self.servers[idx].server_type;
+                let status = self.servers[idx].status();
+                let is_running = self.servers[idx].is_running();
+                let transitioning = self.servers[idx].is_transitioning();
+                let logs = self.servers[idx].logs();
+
+                // Track button clicks
+                let mut start_clicked = false;
+                let mut stop_clicked = false;
+                let mut test_login_clicked = false;
+                let mut start_rect = None;
+
+                ui.horizontal(|ui| {
+                    ui.heading(format!("{} {}", server_type.name(), tr("Settings")));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if is_running || status == ServerStatus::Starting {
+                            let stop = ui.button(tr("⏹ Stop"));
+                            start_rect = Some(stop.rect);
+                            if stop.clicked() {
+                                stop_clicked = true;
+                            }
+                        } else {
+                            let start = ui.add_enabled(
+                                !transitioning,
+                                egui::Button::new(tr("▶ Start")),
+                            );
+                            start_rect = Some(start.rect);
+                            if start.clicked() {
+                                start_clicked = true;
+                            }
+                        }
+                        if server_type == ServerType::Ftp
+                            && ui
+                                .button(tr("🔑 Test login"))
+                                .on_hover_text(tr(
+                                    "Log in to the running server with the configured credentials",
+                                ))
+                                .clicked()
+                        {
+                            test_login_clicked = true;
+                        }
+                    });
+                });
+
+                self.tutorial_targets.start_button = start_rect;
+
+                // Handle button clicks after the closure
+                if stop_clicked {
+                    self.stop_server(idx);
+                }
+                if start_clicked {
+                    self.request_start(idx, ctx);
+                }
+                if test_login_clicked {
+                    self.test_ftp_login(idx, ctx);
+                }
+
+                ui.separator();
+
+                // Status display
+                let status_text = status_text(&status);
+                ui.horizontal(|ui| {
+                    ui.label(tr("Status:"));
+                    ui.label(&status_text);
+                    if server_type == ServerType::Http {
+                        let metrics = self.servers[idx].state.read().metrics;
+                        if metrics.requests > 0 {
+                            ui.separator();
+                            ui.label(format!(
+                                "{} {} {} {} {}",
+                                tr("Served"),
+                                http::format_size(metrics.bytes_sent),
+                                tr("across"),
+                                metrics.requests,
+                                tr("requests")
+                            ));
+                        }
+                    }
+                    if is_running && server_type == ServerType::Http && self.http_ab_test {
+                        let [a, b] = self.servers[idx].state.read().metrics.ab_requests;
+                        let share = |n: u64| n * 100 / (a + b).max(1);
+                        ui.separator();
+                        ui.label(format!(
+                            "{} A {} ({}%), B {} ({}%)",
+                            tr("A/B test:"),
+                            a,
+                            share(a),
+                            b,
+                            share(b)
+                        ));
+                    }
+                    if is_running && server_type == ServerType::Http && self.http_limit_downloads {
+                        let (active, queued) = {
+                            let s = self.servers[idx].state.read();
+                            (s.active_downloads, s.queued_downloads)
+                        };
+                        ui.separator();
+                        ui.label(format!(
+                            "{} {} {}, {} {}",
+                            tr("Downloads:"),
+                            active,
+                            tr("active"),
+                            queued,
+                            tr("queued")
+                        ));
+                    }
+                });
+
+                // Link for a file shared from the command line
+                let mut stop_sharing = false;
+                if let (ServerType::Http, Some(file)) = (server_type, &self.share_file) {
+                    let link = format!(
+                        "http://{}:{}{}",
+                        self.share_host,
+                        self.http_port,
+                        http::single_file_url_path(file)
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label(tr("Share link:"));
+                        ui.add(egui::TextEdit::singleline(&mut link.as_str()).desired_width(300.0));
+                        if ui.button("📋").on_hover_text(tr("Copy")).clicked() {
+                            ui.ctx().copy_text(link.clone());
+                        }
+                        if ui
+                            .button("✖")
+                            .on_hover_text(tr("Serve the root directory again"))
+                            .clicked()
+                        {
+                            stop_sharing = true;
+                        }
+                    });
+                }
+                if stop_sharing {
+                    self.share_file = None;
+                }
+
+                // Onion address while the HTTP server is published over Tor
+                let onion = self.servers[idx].state.read().onion_address.clone();
+                if let (ServerType::Http, Some(onion)) = (server_type, onion) {
+                    let link = format!("http://{}", onion);
+                    ui.horizontal(|ui| {
+                        qr_code(ui, &link, 120.0);
+                        ui.vertical(|ui| {
+                            ui.label(tr("Onion address:"));
+                            ui.label(egui::RichText::new(&link).strong().monospace());
+                            if ui.button(tr("📋 Copy")).clicked() {
+                                ui.ctx().copy_text(link.clone());
+                            }
+                        });
+                    });
+                }
+
+                ui.separator();
+
+                // Server-specific settings
+                egui::ScrollArea::vertical()
                     .id_salt(format!("settings_scroll_{}", idx))
                     .max_height(200.0)
                     .show(ui, |ui| {
@@ -426,99 +2795,780 @@ impl eframe::App for OServersApp {
                                     .num_columns(2)
                                     .spacing([10.0, 8.0])
                                     .show(ui, |ui| {
-                                        ui.label("Root directory:");
+                                        ui.label(tr("Mode:"));
+                                        ui.horizontal(|ui| {
+                                            ui.radio_value(
+                                                &mut self.http_kv_mode,
+                                                false,
+                                                tr("File server"),
+                                            );
+                                            ui.radio_value(
+                                                &mut self.http_kv_mode,
+                                                true,
+                                                tr("Key-value store"),
+                                            )
+                                            .on_hover_text(tr(
+                                                "GET/PUT/DELETE /kv/<key>, GET /kv lists keys",
+                                            ));
+                                        });
+                                        ui.end_row();
+
+                                        if self.http_kv_mode {
+                                            ui.label(tr("Store file:"));
+                                            ui.text_edit_singleline(&mut self.http_kv_store_path);
+                                            ui.end_row();
+
+                                            ui.label(tr("Compressed values:"));
+                                            ui.checkbox(
+                                                &mut self.http_accept_compressed,
+                                                tr("Decode gzip, deflate and br request bodies"),
+                                            );
+                                            ui.end_row();
+                                        }
+
+                                        ui.label(tr("Root directory:"));
+                                        self.tutorial_targets.root_dir = Some(root_dir_picker(
+                                            ui,
+                                            &mut self.http_root_dir,
+                                            &mut self.config,
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Copy settings:"));
+                                        if let Some(source) = copy_from_menu(ui, ServerType::Http) {
+                                            self.copy_shared_fields(source, ServerType::Http);
+                                        }
+                                        ui.end_row();
+
+                                        ui.label(tr("A/B test:"));
                                         ui.horizontal(|ui| {
-                                            ui.text_edit_singleline(&mut self.http_root_dir);
-                                            if ui.button("📁").clicked() {
+                                            ui.checkbox(
+                                                &mut self.http_ab_test,
+                                                tr("Serve group B from"),
+                                            );
+                                            ui.add_enabled_ui(self.http_ab_test, |ui| {
+                                                let field = ui.text_edit_singleline(
+                                                    &mut self.http_ab_group_b_root,
+                                                );
                                                 if let Some(path) =
-                                                    rfd::FileDialog::new().pick_folder()
+                                                    dialogs::folder_button(ui, &field)
                                                 {
-                                                    self.http_root_dir = path.display().to_string();
+                                                    self.http_ab_group_b_root =
+                                                        path.display().to_string();
+                                                }
+                                            });
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("A/B split:"));
+                                        ui.add_enabled_ui(self.http_ab_test, |ui| {
+                                            ui.horizontal(|ui| {
+                                                ui.add(
+                                                    egui::Slider::new(
+                                                        &mut self.http_ab_weight,
+                                                        0..=100,
+                                                    )
+                                                    .suffix(tr("% to group A")),
+                                                );
+                                                ui.label(tr("Cookie:"));
+                                                ui.add(
+                                                    egui::TextEdit::singleline(
+                                                        &mut self.http_ab_cookie,
+                                                    )
+                                                    .desired_width(100.0),
+                                                );
+                                            });
+                                        })
+                                        .response
+                                        .on_hover_text(tr(
+                                            "New visitors are assigned at random, the cookie keeps them in their group",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Listening port:"));
+                                        port_field(ui, &mut self.http_port, 7777, false);
+                                        ui.end_row();
+
+                                        ui.label(tr("HTTPS:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(&mut self.http_tls, tr("Enable, HTTPS port"));
+                                            ui.add_enabled(
+                                                self.http_tls,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_https_port,
+                                                )
+                                                .hint_text(tr("same"))
+                                                .desired_width(50.0),
+                                            )
+                                            .on_hover_text(tr(
+                                                "Leave empty to serve only HTTPS on the listening port",
+                                            ));
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Certificate:"));
+                                        ui.add_enabled(
+                                            self.http_tls,
+                                            egui::TextEdit::singleline(&mut self.http_tls_cert)
+                                                .hint_text(tr("PEM certificate chain")),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Private key:"));
+                                        ui.add_enabled(
+                                            self.http_tls,
+                                            egui::TextEdit::singleline(&mut self.http_tls_key)
+                                                .hint_text(tr("PEM private key")),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Session resumption:"));
+                                        ui.add_enabled(
+                                            self.http_tls,
+                                            egui::Checkbox::new(
+                                                &mut self.http_tls_session_resumption,
+                                                tr("Session tickets, key rotated daily"),
+                                            ),
+                                        )
+                                        .on_hover_text(tr(
+                                            "Returning clients skip the full TLS handshake",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Response signing:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(&mut self.http_sign_responses, tr("Sign with"));
+                                            ui.add_enabled_ui(self.http_sign_responses, |ui| {
+                                                egui::ComboBox::from_id_salt("http_signing_algorithm")
+                                                    .selected_text(self.http_signing_algorithm.name())
+                                                    .show_ui(ui, |ui| {
+                                                        for algorithm in SigningAlgorithm::ALL {
+                                                            ui.selectable_value(
+                                                                &mut self.http_signing_algorithm,
+                                                                algorithm,
+                                                                algorithm.name(),
+                                                            );
+                                                        }
+                                                    });
+                                            });
+                                        })
+                                        .response
+                                        .on_hover_text(tr(
+                                            "Adds X-Content-Signature over status, length, type and ETag to every response",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Signing key:"));
+                                        ui.add_enabled(
+                                            self.http_sign_responses,
+                                            egui::TextEdit::singleline(&mut self.http_signing_key)
+                                                .hint_text(tr("PEM private key (PKCS#8)")),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Directory listing:"));
+                                        ui.checkbox(
+                                            &mut self.http_allow_listing,
+                                            tr("Allow users to list directory content"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Listing cache:"));
+                                        ui.horizontal(|ui| {
+                                            ui.add_enabled(
+                                                self.http_allow_listing,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_listing_cache_secs,
+                                                )
+                                                .desired_width(50.0),
+                                            );
+                                            ui.label(tr("seconds (0 disables)"));
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Listing banner:"));
+                                        ui.add_enabled(
+                                            self.http_allow_listing,
+                                            egui::TextEdit::multiline(&mut self.http_listing_banner)
+                                                .hint_text(tr("e.g. Confidential, internal use only"))
+                                                .desired_rows(2),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Listing CSP:"));
+                                        ui.add_enabled(
+                                            self.http_allow_listing,
+                                            egui::Checkbox::new(
+                                                &mut self.http_listing_csp,
+                                                tr("Only allow the listing's own styles, nothing else may load"),
+                                            ),
+                                        )
+                                        .on_hover_text(tr(
+                                            "Sends a Content-Security-Policy with a new nonce on every listing",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Zip downloads:"));
+                                        ui.horizontal(|ui| {
+                                            ui.add_enabled(
+                                                self.http_allow_listing,
+                                                egui::Checkbox::new(
+                                                    &mut self.http_zip_downloads,
+                                                    tr("Allow directories up to"),
+                                                ),
+                                            );
+                                            ui.add_enabled(
+                                                self.http_allow_listing && self.http_zip_downloads,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_max_zip_mb,
+                                                )
+                                                .desired_width(60.0),
+                                            );
+                                            ui.label("MB");
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("JSON listing:"));
+                                        ui.add_enabled(
+                                            self.http_allow_listing,
+                                            egui::Checkbox::new(
+                                                &mut self.http_json_listing,
+                                                tr("For clients accepting application/json"),
+                                            ),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Directory URLs:"));
+                                        ui.checkbox(
+                                            &mut self.http_slash_redirect,
+                                            tr("Redirect /dir to /dir/ so relative links work"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Trusted proxies:"));
+                                        ui.vertical(|ui| {
+                                            ui.add(
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_trusted_proxies,
+                                                )
+                                                .hint_text("10.0.0.0/8, 192.168.1.5")
+                                                .desired_width(220.0),
+                                            );
+                                            let invalid = parse_networks(&self.http_trusted_proxies).1;
+                                            if !invalid.is_empty() {
+                                                ui.colored_label(
+                                                    egui::Color32::LIGHT_RED,
+                                                    format!(
+                                                        "{} {}",
+                                                        tr("Not an address or network:"),
+                                                        invalid.join(", ")
+                                                    ),
+                                                );
+                                            }
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Base path:"));
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut self.http_base_path)
+                                                .hint_text(tr("e.g. /files behind a reverse proxy"))
+                                                .desired_width(160.0),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Languages:"));
+                                        ui.checkbox(
+                                            &mut self.http_content_negotiation,
+                                            tr("Serve page.zh.html etc. by browser language"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("PROXY protocol:"));
+                                        ui.checkbox(
+                                            &mut self.http_proxy_protocol,
+                                            tr("Behind a load balancer sending PROXY headers"),
+                                        )
+                                        .on_hover_text(tr(
+                                            "Connections without a PROXY v1/v2 header are rejected",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("mDNS:"));
+                                        ui.checkbox(
+                                            &mut self.http_mdns,
+                                            tr("Announce on the LAN (Bonjour/Avahi)"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Tor:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(
+                                                &mut self.http_tor,
+                                                tr("Publish as onion service, control port"),
+                                            );
+                                            ui.add_enabled(
+                                                self.http_tor,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_tor_control_port,
+                                                )
+                                                .desired_width(60.0),
+                                            );
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Egress limit:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(
+                                                &mut self.http_egress_throttle,
+                                                tr("Limit each connection to"),
+                                            );
+                                            ui.add_enabled(
+                                                self.http_egress_throttle,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_egress_kbps,
+                                                )
+                                                .desired_width(60.0),
+                                            );
+                                            ui.label("kbps");
+                                        })
+                                        .response
+                                        .on_hover_text(tr(
+                                            "Applies to everything sent, not available with HTTPS",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Stream buffer:"));
+                                        egui::ComboBox::from_id_salt("http_stream_buffer")
+                                            .selected_text(http::format_size(
+                                                self.http_stream_buffer_size as u64,
+                                            ))
+                                            .show_ui(ui, |ui| {
+                                                for size in [
+                                                    4 * 1024,
+                                                    http::DEFAULT_STREAM_BUFFER_SIZE,
+                                                    512 * 1024,
+                                                    4 * 1024 * 1024,
+                                                ] {
+                                                    ui.selectable_value(
+                                                        &mut self.http_stream_buffer_size,
+                                                        size,
+                                                        http::format_size(size as u64),
+                                                    );
                                                 }
+                                            })
+                                            .response
+                                            .on_hover_text(tr(
+                                                "Larger suits a fast disk and slow network, smaller gets the first bytes from a slow disk out sooner",
+                                            ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Log sampling:"));
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.http_log_sample_rate,
+                                                0.0..=1.0,
+                                            )
+                                            .custom_formatter(|v, _| format!("{:.0}%", v * 100.0))
+                                            .custom_parser(|s| {
+                                                s.trim_end_matches('%')
+                                                    .trim()
+                                                    .parse::<f64>()
+                                                    .ok()
+                                                    .map(|v| v / 100.0)
+                                            }),
+                                        )
+                                        .on_hover_text(tr(
+                                            "Share of successful requests written to the log, errors are always logged",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Not logged:"));
+                                        ui.vertical(|ui| {
+                                            ui.add(
+                                                egui::TextEdit::multiline(
+                                                    &mut self.http_access_log_exclude,
+                                                )
+                                                .hint_text(tr(
+                                                    "One path per line, /dir/* for everything below",
+                                                ))
+                                                .desired_rows(2),
+                                            )
+                                            .on_hover_text(tr(
+                                                "Successful requests to these paths are left out of the log",
+                                            ));
+                                            let invalid: Vec<&str> = self
+                                                .http_access_log_exclude
+                                                .lines()
+                                                .map(str::trim)
+                                                .filter(|line| {
+                                                    !line.is_empty() && !line.starts_with('/')
+                                                })
+                                                .collect();
+                                            if !invalid.is_empty() {
+                                                ui.colored_label(
+                                                    egui::Color32::LIGHT_RED,
+                                                    format!(
+                                                        "{} {}",
+                                                        tr("Must start with /:"),
+                                                        invalid.join(", ")
+                                                    ),
+                                                );
                                             }
                                         });
                                         ui.end_row();
 
-                                        ui.label("Listening port:");
-                                        ui.text_edit_singleline(&mut self.http_port);
+                                        ui.label(tr("Client lookup:"));
+                                        ui.checkbox(
+                                            &mut self.http_reverse_dns,
+                                            tr("Reverse DNS host names in the log"),
+                                        );
                                         ui.end_row();
 
-                                        ui.label("Directory listing:");
-                                        ui.checkbox(
-                                            &mut self.http_allow_listing,
-                                            "Allow users to list directory content",
+                                        ui.label(tr("GeoIP database:"));
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut self.http_geoip_db)
+                                                .hint_text(tr("MaxMind .mmdb file (optional)")),
                                         );
                                         ui.end_row();
 
-                                        ui.label("Auto stop:");
+                                        ui.label(tr("Integrity check:"));
                                         ui.horizontal(|ui| {
                                             ui.checkbox(
-                                                &mut self.http_auto_stop,
-                                                "Stop server after",
+                                                &mut self.http_integrity_check,
+                                                tr("Hash files every"),
                                             );
                                             ui.add_enabled(
-                                                self.http_auto_stop,
+                                                self.http_integrity_check,
                                                 egui::TextEdit::singleline(
-                                                    &mut self.http_auto_stop_secs,
+                                                    &mut self.http_integrity_interval_secs,
                                                 )
                                                 .desired_width(50.0),
                                             );
-                                            ui.label("seconds");
+                                            ui.label(tr("seconds using"));
+                                            ui.add_enabled_ui(self.http_integrity_check, |ui| {
+                                                egui::ComboBox::from_id_salt("http_integrity_algorithm")
+                                                    .selected_text(self.http_integrity_algorithm.name())
+                                                    .show_ui(ui, |ui| {
+                                                        for algorithm in HashAlgorithm::ALL {
+                                                            ui.selectable_value(
+                                                                &mut self.http_integrity_algorithm,
+                                                                algorithm,
+                                                                algorithm.name(),
+                                                            );
+                                                        }
+                                                    });
+                                            });
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Manifest file:"));
+                                        ui.add_enabled(
+                                            self.http_integrity_check,
+                                            egui::TextEdit::singleline(
+                                                &mut self.http_integrity_manifest,
+                                            ),
+                                        )
+                                        .on_hover_text(tr(
+                                            "Created from the first scan if missing, changed files are logged as warnings",
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Auto stop:"));
+                                        auto_stop_picker(
+                                            ui,
+                                            &mut self.http_auto_stop,
+                                            &mut self.http_auto_stop_secs,
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Auto start:"));
+                                        startup_picker(ui, &mut self.http_startup, ServerType::Http);
+                                        ui.end_row();
+
+                                        ui.label(tr("Restart policy:"));
+                                        restart_picker(ui, &mut self.http_restart, ServerType::Http);
+                                        ui.end_row();
+
+                                        ui.label(tr("Sensitive files:"));
+                                        ui.checkbox(
+                                            &mut self.http_scan_sensitive,
+                                            tr("Scan root directory before starting"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Sensitive patterns:"));
+                                        ui.add_enabled(
+                                            self.http_scan_sensitive,
+                                            egui::TextEdit::singleline(
+                                                &mut self.http_sensitive_patterns,
+                                            )
+                                            .hint_text(".env, id_rsa, *.pem, .git/"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Idle page:"));
+                                        ui.add_enabled(
+                                            !self.http_allow_listing,
+                                            egui::Checkbox::new(
+                                                &mut self.http_idle_page,
+                                                tr("Serve a status page for directories without an index"),
+                                            ),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label("");
+                                        ui.add_enabled(
+                                            !self.http_allow_listing && self.http_idle_page,
+                                            egui::TextEdit::multiline(&mut self.http_idle_page_html)
+                                                .code_editor()
+                                                .desired_rows(6),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Page files:"));
+                                        egui::Grid::new("http_pages").num_columns(2).show(
+                                            ui,
+                                            |ui| {
+                                                for (label, path) in [
+                                                    (tr("Landing"), &mut self.http_page_index),
+                                                    (tr("Not found"), &mut self.http_page_not_found),
+                                                    (tr("Forbidden"), &mut self.http_page_forbidden),
+                                                    (
+                                                        tr("Maintenance"),
+                                                        &mut self.http_page_maintenance,
+                                                    ),
+                                                ] {
+                                                    ui.label(label);
+                                                    ui.add(
+                                                        egui::TextEdit::singleline(path)
+                                                            .hint_text(tr("Built-in page")),
+                                                    );
+                                                    ui.end_row();
+                                                }
+                                            },
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Maintenance:"));
+                                        ui.checkbox(
+                                            &mut self.http_maintenance,
+                                            tr("Answer all requests with the maintenance page"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Path depth:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(&mut self.http_limit_depth, tr("Limit to"));
+                                            ui.add_enabled(
+                                                self.http_limit_depth,
+                                                egui::TextEdit::singleline(&mut self.http_max_depth)
+                                                    .desired_width(40.0),
+                                            );
+                                            ui.label(tr("segments"));
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Concurrent downloads:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(
+                                                &mut self.http_limit_downloads,
+                                                tr("Limit to"),
+                                            );
+                                            ui.add_enabled(
+                                                self.http_limit_downloads,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_max_downloads,
+                                                )
+                                                .desired_width(40.0),
+                                            );
+                                            ui.label(tr("queue timeout"));
+                                            ui.add_enabled(
+                                                self.http_limit_downloads,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_queue_timeout_secs,
+                                                )
+                                                .desired_width(40.0),
+                                            );
+                                            ui.label(tr("seconds"));
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Statistics page:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(
+                                                &mut self.http_stats_page,
+                                                tr("Serve at"),
+                                            );
+                                            ui.add_enabled(
+                                                self.http_stats_page,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_stats_path,
+                                                )
+                                                .desired_width(120.0),
+                                            );
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Statistics login:"));
+                                        ui.add_enabled_ui(self.http_stats_page, |ui| {
+                                            ui.horizontal(|ui| {
+                                                ui.add(
+                                                    egui::TextEdit::singleline(
+                                                        &mut self.http_stats_username,
+                                                    )
+                                                    .hint_text(tr("Username"))
+                                                    .desired_width(100.0),
+                                                );
+                                                ui.add(
+                                                    egui::TextEdit::singleline(
+                                                        &mut self.http_stats_password,
+                                                    )
+                                                    .hint_text(tr("Password"))
+                                                    .password(true)
+                                                    .desired_width(100.0),
+                                                );
+                                                if self.http_stats_page
+                                                    && http::stats_login_problem(
+                                                        &self.http_stats_username,
+                                                        &self.http_stats_password,
+                                                    )
+                                                    .is_some()
+                                                {
+                                                    ui.colored_label(
+                                                        egui::Color32::LIGHT_RED,
+                                                        tr("Set a login other than admin/admin"),
+                                                    );
+                                                }
+                                            });
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Health check:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(&mut self.http_health, tr("Serve at"));
+                                            ui.add_enabled(
+                                                self.http_health,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.http_health_path,
+                                                )
+                                                .desired_width(120.0),
+                                            );
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Signed URLs:"));
+                                        ui.checkbox(
+                                            &mut self.http_signed_urls,
+                                            tr("Serve time-limited links at /__signed"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Signing secret:"));
+                                        ui.add_enabled_ui(self.http_signed_urls, |ui| {
+                                            password_field(
+                                                ui,
+                                                &mut self.http_signed_url_secret,
+                                                self.config.generated_password_length,
+                                            );
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Admin token:"))
+                                            .on_hover_text(tr(
+                                                "Bearer token for POST /__admin/sign, empty disables issuing links",
+                                            ));
+                                        ui.add_enabled_ui(self.http_signed_urls, |ui| {
+                                            password_field(
+                                                ui,
+                                                &mut self.http_signed_url_admin_token,
+                                                self.config.generated_password_length,
+                                            );
                                         });
                                         ui.end_row();
+
+                                        ui.label(tr("Bandwidth limits:"));
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut self.http_qos_rules)
+                                                .hint_text("*.mp4=1000, *.iso=5000 (kbps)"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Log on stop:"));
+                                        log_export_picker(
+                                            ui,
+                                            &mut self.http_export_log,
+                                            &mut self.http_export_log_dir,
+                                        );
+                                        ui.end_row();
                                     });
+
+                                ui.add_space(8.0);
+                                webhooks_table(ui, &mut self.http_webhooks);
+                                ui.add_space(8.0);
+                                virtual_files_table(ui, &mut self.http_virtual_files);
                             }
                             ServerType::Ftp => {
                                 egui::Grid::new("ftp_settings")
                                     .num_columns(2)
                                     .spacing([10.0, 8.0])
                                     .show(ui, |ui| {
-                                        ui.label("Root directory:");
-                                        ui.horizontal(|ui| {
-                                            ui.text_edit_singleline(&mut self.ftp_root_dir);
-                                            if ui.button("📁").clicked() {
-                                                if let Some(path) =
-                                                    rfd::FileDialog::new().pick_folder()
-                                                {
-                                                    self.ftp_root_dir = path.display().to_string();
-                                                }
-                                            }
-                                        });
+                                        ui.label(tr("Root directory:"));
+                                        self.tutorial_targets.root_dir = Some(root_dir_picker(
+                                            ui,
+                                            &mut self.ftp_root_dir,
+                                            &mut self.config,
+                                        ));
                                         ui.end_row();
 
-                                        ui.label("Listening port:");
-                                        ui.text_edit_singleline(&mut self.ftp_port);
+                                        ui.label(tr("Copy settings:"));
+                                        if let Some(source) = copy_from_menu(ui, ServerType::Ftp) {
+                                            self.copy_shared_fields(source, ServerType::Ftp);
+                                        }
                                         ui.end_row();
 
-                                        ui.label("Username:");
+                                        ui.label(tr("Listening port:"));
+                                        port_field(ui, &mut self.ftp_port, 2121, false);
+                                        ui.end_row();
+
+                                        ui.label(tr("Username:"));
                                         ui.text_edit_singleline(&mut self.ftp_username);
                                         ui.end_row();
 
-                                        ui.label("Password:");
-                                        ui.add(
-                                            egui::TextEdit::singleline(&mut self.ftp_password)
-                                                .password(true),
+                                        ui.label(tr("Password:"));
+                                        password_field(
+                                            ui,
+                                            &mut self.ftp_password,
+                                            self.config.generated_password_length,
                                         );
                                         ui.end_row();
 
-                                        ui.label("Anonymous:");
+                                        ui.label(tr("Anonymous:"));
                                         ui.checkbox(
                                             &mut self.ftp_anonymous,
-                                            "Allow anonymous access",
+                                            tr("Allow anonymous access"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Greeting:"));
+                                        ui.add(
+                                            egui::TextEdit::singleline(
+                                                self.ftp_custom_messages.entry(220).or_default(),
+                                            )
+                                            .hint_text(tr("220 reply sent when a client connects")),
                                         );
                                         ui.end_row();
 
-                                        ui.label("Transfer mode:");
+                                        ui.label(tr("Transfer mode:"));
                                         ui.horizontal(|ui| {
                                             ui.checkbox(
                                                 &mut self.ftp_passive_mode,
-                                                "Enable passive mode (PASV)",
+                                                tr("Enable passive mode (PASV)"),
                                             );
-                                            ui.label("(Active mode PORT always enabled)");
+                                            ui.label(tr("(Active mode PORT always enabled)"));
                                         });
                                         ui.end_row();
 
-                                        ui.label("Passive ports:");
+                                        ui.label(tr("Passive ports:"));
                                         ui.horizontal(|ui| {
                                             ui.text_edit_singleline(
                                                 &mut self.ftp_passive_ports_start,
@@ -529,6 +3579,220 @@ impl eframe::App for OServersApp {
                                             );
                                         });
                                         ui.end_row();
+                                        let passive_range = (
+                                            self.ftp_passive_ports_start.trim().parse::<u16>(),
+                                            self.ftp_passive_ports_end.trim().parse::<u16>(),
+                                        );
+                                        match passive_range {
+                                            (Ok(start), Ok(end)) if start > end => {
+                                                ui.label("");
+                                                ui.colored_label(
+                                                    egui::Color32::LIGHT_RED,
+                                                    tr("The first passive port is above the last, passive transfers cannot work"),
+                                                );
+                                                ui.end_row();
+                                            }
+                                            (Ok(start), Ok(end)) if start == end => {
+                                                ui.label("");
+                                                ui.weak(tr("A single passive port allows one transfer at a time"));
+                                                ui.end_row();
+                                            }
+                                            _ => {}
+                                        }
+
+                                        ui.label(tr("Passive host:"));
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut self.ftp_passive_host)
+                                                .hint_text(tr("Address clients connect to for passive transfers, e.g. behind NAT")),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Overwrite existing files:"));
+                                        egui::ComboBox::from_id_salt("ftp_overwrite_policy")
+                                            .selected_text(tr(self.ftp_overwrite_policy.name()))
+                                            .show_ui(ui, |ui| {
+                                                for policy in OverwritePolicy::ALL {
+                                                    ui.selectable_value(
+                                                        &mut self.ftp_overwrite_policy,
+                                                        policy,
+                                                        tr(policy.name()),
+                                                    );
+                                                }
+                                            });
+                                        ui.end_row();
+
+                                        ui.label(tr("Compression:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(
+                                                &mut self.ftp_compress,
+                                                tr("Compress uploads with"),
+                                            );
+                                            ui.add_enabled_ui(self.ftp_compress, |ui| {
+                                                egui::ComboBox::from_id_salt("ftp_compress_algorithm")
+                                                    .selected_text(self.ftp_compress_algorithm.name())
+                                                    .show_ui(ui, |ui| {
+                                                        for algorithm in CompressionAlgorithm::ALL {
+                                                            ui.selectable_value(
+                                                                &mut self.ftp_compress_algorithm,
+                                                                algorithm,
+                                                                algorithm.name(),
+                                                            );
+                                                        }
+                                                    });
+                                            });
+                                            ui.checkbox(
+                                                &mut self.ftp_decompress_on_download,
+                                                tr("Decompress on download"),
+                                            );
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Deleted files:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(
+                                                &mut self.ftp_safe_delete,
+                                                tr("Move to .trash, keep for"),
+                                            );
+                                            ui.add_enabled(
+                                                self.ftp_safe_delete,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.ftp_trash_retention_days,
+                                                )
+                                                .desired_width(40.0),
+                                            )
+                                            .on_hover_text(tr("0 keeps them until removed by hand"));
+                                            ui.label(tr("days"));
+                                            let trash = PathBuf::from(&self.ftp_root_dir).join(".trash");
+                                            if ui
+                                                .add_enabled(trash.is_dir(), egui::Button::new(tr("🗑 Trash")))
+                                                .on_hover_text(tr("Open the trash folder"))
+                                                .clicked()
+                                            {
+                                                dialogs::open_folder(&trash);
+                                            }
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Notifications:"));
+                                        ui.vertical(|ui| {
+                                            ui.horizontal(|ui| {
+                                                ui.checkbox(&mut self.ftp_notify, tr("Report finished"));
+                                                ui.add_enabled_ui(self.ftp_notify, |ui| {
+                                                    egui::ComboBox::from_id_salt("ftp_notify_trigger")
+                                                        .selected_text(tr(self.ftp_notify_trigger.name()))
+                                                        .show_ui(ui, |ui| {
+                                                            for trigger in NotificationTrigger::ALL {
+                                                                ui.selectable_value(
+                                                                    &mut self.ftp_notify_trigger,
+                                                                    trigger,
+                                                                    tr(trigger.name()),
+                                                                );
+                                                            }
+                                                        });
+                                                    ui.label(tr("of at least"));
+                                                    ui.add(
+                                                        egui::TextEdit::singleline(&mut self.ftp_notify_min_kb)
+                                                            .desired_width(60.0),
+                                                    );
+                                                    ui.label("KB");
+                                                });
+                                            });
+                                            ui.add_enabled_ui(self.ftp_notify, |ui| {
+                                                ui.horizontal(|ui| {
+                                                    ui.radio_value(&mut self.ftp_notify_email, false, tr("Webhook"));
+                                                    ui.radio_value(&mut self.ftp_notify_email, true, tr("Email"));
+                                                });
+                                                if !self.ftp_notify_email {
+                                                    ui.add(
+                                                        egui::TextEdit::singleline(&mut self.ftp_notify_url)
+                                                            .hint_text("https://example.com/hook"),
+                                                    );
+                                                    return;
+                                                }
+                                                let smtp = &mut self.ftp_notify_smtp;
+                                                egui::Grid::new("ftp_notify_smtp")
+                                                    .num_columns(2)
+                                                    .show(ui, |ui| {
+                                                        ui.label(tr("SMTP server:"));
+                                                        ui.horizontal(|ui| {
+                                                            ui.add(
+                                                                egui::TextEdit::singleline(&mut smtp.host)
+                                                                    .hint_text("smtp.example.com")
+                                                                    .desired_width(140.0),
+                                                            );
+                                                            ui.add(egui::DragValue::new(&mut smtp.port));
+                                                            ui.checkbox(&mut smtp.implicit_tls, tr("Implicit TLS"))
+                                                                .on_hover_text(tr(
+                                                                    "Connect with TLS right away (usually port 465) instead of STARTTLS",
+                                                                ));
+                                                        });
+                                                        ui.end_row();
+                                                        ui.label(tr("Username:"));
+                                                        ui.text_edit_singleline(&mut smtp.username);
+                                                        ui.end_row();
+                                                        ui.label(tr("Password:"));
+                                                        ui.add(
+                                                            egui::TextEdit::singleline(&mut smtp.password)
+                                                                .password(true),
+                                                        );
+                                                        ui.end_row();
+                                                        ui.label(tr("From:"));
+                                                        ui.text_edit_singleline(&mut smtp.from);
+                                                        ui.end_row();
+                                                        ui.label(tr("To:"));
+                                                        ui.text_edit_singleline(&mut smtp.to);
+                                                        ui.end_row();
+                                                    });
+                                            });
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Discovery:"));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(
+                                                &mut self.ftp_discovery,
+                                                tr("Broadcast on LAN, UDP port"),
+                                            );
+                                            ui.add_enabled(
+                                                self.ftp_discovery,
+                                                egui::TextEdit::singleline(
+                                                    &mut self.ftp_discovery_port,
+                                                )
+                                                .desired_width(60.0),
+                                            );
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("mDNS:"));
+                                        ui.checkbox(
+                                            &mut self.ftp_mdns,
+                                            tr("Announce on the LAN (Bonjour/Avahi)"),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Auto stop:"));
+                                        auto_stop_picker(
+                                            ui,
+                                            &mut self.ftp_auto_stop,
+                                            &mut self.ftp_auto_stop_secs,
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Auto start:"));
+                                        startup_picker(ui, &mut self.ftp_startup, ServerType::Ftp);
+                                        ui.end_row();
+
+                                        ui.label(tr("Restart policy:"));
+                                        restart_picker(ui, &mut self.ftp_restart, ServerType::Ftp);
+                                        ui.end_row();
+
+                                        ui.label(tr("Log on stop:"));
+                                        log_export_picker(
+                                            ui,
+                                            &mut self.ftp_export_log,
+                                            &mut self.ftp_export_log_dir,
+                                        );
+                                        ui.end_row();
                                     });
                             }
                             ServerType::Tftp => {
@@ -536,25 +3800,107 @@ impl eframe::App for OServersApp {
                                     .num_columns(2)
                                     .spacing([10.0, 8.0])
                                     .show(ui, |ui| {
-                                        ui.label("Root directory:");
+                                        ui.label(tr("Root directory:"));
+                                        self.tutorial_targets.root_dir = Some(root_dir_picker(
+                                            ui,
+                                            &mut self.tftp_root_dir,
+                                            &mut self.config,
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Copy settings:"));
+                                        if let Some(source) = copy_from_menu(ui, ServerType::Tftp) {
+                                            self.copy_shared_fields(source, ServerType::Tftp);
+                                        }
+                                        ui.end_row();
+
+                                        ui.label(tr("Listening port:"));
+                                        port_field(ui, &mut self.tftp_port, 69, true);
+                                        ui.end_row();
+
+                                        ui.label(tr("Address family:"));
                                         ui.horizontal(|ui| {
-                                            ui.text_edit_singleline(&mut self.tftp_root_dir);
-                                            if ui.button("📁").clicked() {
+                                            ui.radio_value(&mut self.tftp_ipv6, false, "IPv4");
+                                            ui.radio_value(
+                                                &mut self.tftp_ipv6,
+                                                true,
+                                                tr("IPv6 (dual-stack)"),
+                                            );
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Mode:"));
+                                        ui.checkbox(&mut self.tftp_read_only, tr("Read-only mode"));
+                                        ui.end_row();
+
+                                        ui.label(tr("Staging directory:"));
+                                        ui.add_enabled_ui(!self.tftp_read_only, |ui| {
+                                            ui.horizontal(|ui| {
+                                                let field = ui.add(
+                                                    egui::TextEdit::singleline(
+                                                        &mut self.tftp_staging_dir,
+                                                    )
+                                                    .hint_text(tr("Empty writes uploads directly")),
+                                                );
                                                 if let Some(path) =
-                                                    rfd::FileDialog::new().pick_folder()
+                                                    dialogs::folder_button(ui, &field)
                                                 {
-                                                    self.tftp_root_dir = path.display().to_string();
+                                                    self.tftp_staging_dir =
+                                                        path.display().to_string();
                                                 }
-                                            }
+                                            });
                                         });
                                         ui.end_row();
 
-                                        ui.label("Listening port:");
-                                        ui.text_edit_singleline(&mut self.tftp_port);
+                                        ui.label(tr("Retry timeout:"));
+                                        ui.horizontal(|ui| {
+                                            ui.add(
+                                                egui::TextEdit::singleline(
+                                                    &mut self.tftp_retry_timeout_ms,
+                                                )
+                                                .desired_width(60.0),
+                                            );
+                                            ui.label("ms");
+                                        });
+                                        ui.end_row();
+
+                                        ui.label(tr("Max retries:"));
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut self.tftp_max_retries)
+                                                .desired_width(60.0),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Allowed file patterns:"));
+                                        ui.add(
+                                            egui::TextEdit::multiline(&mut self.tftp_allow_patterns)
+                                                .hint_text(tr("One per line, e.g. *.bin (empty allows all)"))
+                                                .desired_rows(3),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Auto stop:"));
+                                        auto_stop_picker(
+                                            ui,
+                                            &mut self.tftp_auto_stop,
+                                            &mut self.tftp_auto_stop_secs,
+                                        );
                                         ui.end_row();
 
-                                        ui.label("Mode:");
-                                        ui.checkbox(&mut self.tftp_read_only, "Read-only mode");
+                                        ui.label(tr("Auto start:"));
+                                        startup_picker(ui, &mut self.tftp_startup, ServerType::Tftp);
+                                        ui.end_row();
+
+                                        ui.label(tr("Restart policy:"));
+                                        restart_picker(ui, &mut self.tftp_restart, ServerType::Tftp);
+                                        ui.end_row();
+
+                                        ui.label(tr("Log on stop:"));
+                                        log_export_picker(
+                                            ui,
+                                            &mut self.tftp_export_log,
+                                            &mut self.tftp_export_log_dir,
+                                        );
                                         ui.end_row();
                                     });
                             }
@@ -563,69 +3909,209 @@ impl eframe::App for OServersApp {
                                     .num_columns(2)
                                     .spacing([10.0, 8.0])
                                     .show(ui, |ui| {
-                                        ui.label("Root directory:");
-                                        ui.horizontal(|ui| {
-                                            ui.text_edit_singleline(&mut self.ssh_root_dir);
-                                            if ui.button("📁").clicked() {
-                                                if let Some(path) =
-                                                    rfd::FileDialog::new().pick_folder()
-                                                {
-                                                    self.ssh_root_dir = path.display().to_string();
-                                                }
-                                            }
-                                        });
+                                        ui.label(tr("Root directory:"));
+                                        self.tutorial_targets.root_dir = Some(root_dir_picker(
+                                            ui,
+                                            &mut self.ssh_root_dir,
+                                            &mut self.config,
+                                        ));
+                                        ui.end_row();
+
+                                        ui.label(tr("Copy settings:"));
+                                        if let Some(source) = copy_from_menu(ui, ServerType::Ssh) {
+                                            self.copy_shared_fields(source, ServerType::Ssh);
+                                        }
                                         ui.end_row();
 
-                                        ui.label("Listening port:");
-                                        ui.text_edit_singleline(&mut self.ssh_port);
+                                        ui.label(tr("Listening port:"));
+                                        port_field(ui, &mut self.ssh_port, 2222, false);
                                         ui.end_row();
 
-                                        ui.label("Username:");
+                                        ui.label(tr("Username:"));
                                         ui.text_edit_singleline(&mut self.ssh_username);
                                         ui.end_row();
 
-                                        ui.label("Password:");
-                                        ui.add(
-                                            egui::TextEdit::singleline(&mut self.ssh_password)
-                                                .password(true),
+                                        ui.label(tr("Password:"));
+                                        password_field(
+                                            ui,
+                                            &mut self.ssh_password,
+                                            self.config.generated_password_length,
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Auto stop:"));
+                                        auto_stop_picker(
+                                            ui,
+                                            &mut self.ssh_auto_stop,
+                                            &mut self.ssh_auto_stop_secs,
+                                        );
+                                        ui.end_row();
+
+                                        ui.label(tr("Auto start:"));
+                                        startup_picker(ui, &mut self.ssh_startup, ServerType::Ssh);
+                                        ui.end_row();
+
+                                        ui.label(tr("Restart policy:"));
+                                        restart_picker(ui, &mut self.ssh_restart, ServerType::Ssh);
+                                        ui.end_row();
+
+                                        ui.label(tr("Log on stop:"));
+                                        log_export_picker(
+                                            ui,
+                                            &mut self.ssh_export_log,
+                                            &mut self.ssh_export_log_dir,
                                         );
                                         ui.end_row();
                                     });
                             }
                         });
+
+                        // Notes stay editable while the server runs
+                        egui::CollapsingHeader::new(tr("Notes"))
+                            .id_salt(("notes", idx))
+                            .show(ui, |ui| {
+                                let editing = self.editing_notes;
+                                let notes = self.notes_mut(server_type);
+                                let has_notes = !notes.trim().is_empty();
+                                if editing || !has_notes {
+                                    ui.add(
+                                        egui::TextEdit::multiline(notes)
+                                            .hint_text(tr(
+                                                "Purpose, special setup, troubleshooting history (markdown)",
+                                            ))
+                                            .desired_rows(4)
+                                            .desired_width(f32::INFINITY),
+                                    );
+                                } else {
+                                    markdown::show(ui, notes);
+                                }
+                                if has_notes
+                                    && ui
+                                        .button(if editing { tr("Done") } else { tr("✏ Edit") })
+                                        .clicked()
+                                {
+                                    self.editing_notes = !editing;
+                                }
+                            });
                     });
 
                 ui.separator();
 
-                // Server output log
-                ui.heading("Server output");
-                egui::ScrollArea::vertical()
-                    .id_salt(format!("logs_scroll_{}", idx))
-                    .auto_shrink([false; 2])
-                    .max_height(300.0)
-                    .stick_to_bottom(true)
-                    .show(ui, |ui| {
-                        for log in &logs {
-                            let timestamp = log.timestamp.format("[%H:%M:%S%.3f]").to_string();
-                            let color = match log.level {
-                                LogLevel::Info => egui::Color32::LIGHT_GREEN,
-                                LogLevel::Warning => egui::Color32::YELLOW,
-                                LogLevel::Error => egui::Color32::LIGHT_RED,
-                            };
+                // Pinned log patterns, always visible above the scrolling log
+                let mut unpin = None;
+                if !self.pinned_patterns.is_empty() {
+                    ui.group(|ui| {
+                        for (i, pattern) in self.pinned_patterns.iter_mut().enumerate() {
                             ui.horizontal(|ui| {
-                                ui.label(
-                                    egui::RichText::new(&timestamp).color(egui::Color32::GRAY),
-                                );
-                                ui.label(egui::RichText::new(&log.message).color(color));
+                                ui.label("📌");
+                                ui.text_edit_singleline(pattern);
+                                if ui.small_button(tr("Unpin")).clicked() {
+                                    unpin = Some(i);
+                                }
                             });
+                            let matches: Vec<_> = logs
+                                .iter()
+                                .filter(|log| glob_match(pattern, &log.message))
+                                .collect();
+                            // Only the most recent matches, the full history is below
+                            for log in matches.iter().skip(matches.len().saturating_sub(5)) {
+                                log_line(ui, log);
+                            }
                         }
                     });
+                }
+                if let Some(i) = unpin {
+                    self.pinned_patterns.remove(i);
+                }
+
+                // Transfers in progress, apart from the message log
+                let transfers = self.servers[idx].transfers();
+                if !transfers.is_empty() {
+                    ui.heading(tr("Active transfers"));
+                    transfers_table(ui, &transfers);
+                    ui.separator();
+                }
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(
+                        &mut self.show_file_activity,
+                        false,
+                        egui::RichText::new(tr("Server output")).heading(),
+                    );
+                    ui.selectable_value(
+                        &mut self.show_file_activity,
+                        true,
+                        egui::RichText::new(tr("File activity")).heading(),
+                    );
+                });
+                if self.show_file_activity {
+                    file_activity_shown = true;
+                    let root = PathBuf::from(self.shared_fields(server_type).root_dir);
+                    let stale = self
+                        .file_activity
+                        .as_ref()
+                        .is_none_or(|activity| activity.root() != root);
+                    if stale {
+                        self.file_activity = Some(FileActivity::watch(&root, ui.ctx()));
+                    }
+                    if let Some(activity) = &mut self.file_activity {
+                        activity.ui(ui);
+                    }
+                } else {
+                    // Server output log
+                    let mut pin = None;
+                    let log_panel = egui::ScrollArea::vertical()
+                        .id_salt(format!("logs_scroll_{}", idx))
+                        .auto_shrink([false; 2])
+                        .max_height(300.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for log in &logs {
+                                log_line(ui, log).context_menu(|ui| {
+                                    if ui.button(tr("📌 Pin this message pattern")).clicked() {
+                                        pin = Some(log.message.clone());
+                                        ui.close_menu();
+                                    }
+                                });
+                            }
+                        });
+                    self.tutorial_targets.log_panel = Some(log_panel.inner_rect);
+                    if let Some(pattern) = pin.filter(|p| !self.pinned_patterns.contains(p)) {
+                        self.pinned_patterns.push(pattern);
+                    }
+                }
             }
         });
 
-        // Save config on close
+        // Stop watching as soon as the file activity tab is off screen
+        if !file_activity_shown {
+            self.file_activity = None;
+        }
+
+        self.login_test_window(ctx);
+
+        self.sensitive_scan_window(ctx);
+
+        // First-run tutorial
+        if self.config.show_tutorial {
+            match tutorial::show(ctx, self.tutorial_step, &self.tutorial_targets) {
+                tutorial::Action::None => {}
+                tutorial::Action::Next if self.tutorial_step + 1 < tutorial::STEP_COUNT => {
+                    self.tutorial_step += 1;
+                }
+                tutorial::Action::Next | tutorial::Action::Skip => {
+                    self.config.show_tutorial = false;
+                    self.tutorial_step = 0;
+                    self.save_config();
+                }
+            }
+        }
+
+        // Save config and write pending logs on close
         if ctx.input(|i| i.viewport().close_requested()) {
             self.save_config();
+            self.config_writer.finish();
+            crate::servers::flush_all_logs();
         }
     }
 }
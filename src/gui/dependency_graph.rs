@@ -0,0 +1,183 @@
+//! Start order of the servers drawn as a graph
+//!
+//! Each server is a node colored by its status, an arrow from one server to
+//! another means the first starts after the second. Nodes can be dragged
+//! around, where they were left is kept in the window layout.
+
+use super::i18n::tr;
+use crate::servers::ServerType;
+use egui::epaint::CubicBezierShape;
+use egui::{Align2, Color32, FontId, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+use std::collections::HashMap;
+
+const NODE_SIZE: Vec2 = egui::vec2(110.0, 40.0);
+
+/// Length of the arrow heads
+const ARROW_SIZE: f32 = 10.0;
+
+/// How far arrows bow out, keeping arrows in both directions between two nodes apart
+const CURVE_OFFSET: f32 = 25.0;
+
+/// A server as the graph shows it
+pub struct Node {
+    pub server_type: ServerType,
+    pub color: Color32,
+    /// Status shown when hovering the node
+    pub status: String,
+    /// Servers this one starts after
+    pub start_after: Vec<ServerType>,
+}
+
+/// What was done with the graph this frame
+#[derive(Default)]
+pub struct GraphResponse {
+    /// Node clicked, to select its server
+    pub clicked: Option<ServerType>,
+    /// Node double-clicked, to open its server's settings
+    pub opened: Option<ServerType>,
+}
+
+/// Draw `nodes` with the servers of `cycle` (first one repeated at the end)
+/// marked in red, `positions` holding node offsets from the top left
+pub fn show(
+    ui: &mut egui::Ui,
+    nodes: &[Node],
+    selected: Option<ServerType>,
+    cycle: &[ServerType],
+    positions: &mut HashMap<ServerType, [f32; 2]>,
+) -> GraphResponse {
+    let mut response = GraphResponse::default();
+    if !cycle.is_empty() {
+        let order: Vec<_> = cycle.iter().map(ServerType::label).collect();
+        ui.colored_label(
+            Color32::LIGHT_RED,
+            format!(
+                "⚠ {} {}",
+                tr("Circular start order, auto start ignores it:"),
+                order.join(" → ")
+            ),
+        );
+    }
+    ui.weak(tr(
+        "Arrows point to the server a server starts after. Drag to arrange, double-click to open settings.",
+    ));
+
+    let (area, painter) = ui.allocate_painter(ui.available_size(), Sense::hover());
+    let area = area.rect;
+    let max_offset = (area.size() - NODE_SIZE).max(Vec2::ZERO);
+    let mut rects = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        let offset = positions
+            .get(&node.server_type)
+            .map(|p| egui::vec2(p[0], p[1]))
+            .unwrap_or_else(|| default_position(i))
+            .clamp(Vec2::ZERO, max_offset);
+        let id = ui.id().with(("dependency_node", node.server_type));
+        let node_response = ui
+            .interact(
+                Rect::from_min_size(area.min + offset, NODE_SIZE),
+                id,
+                Sense::click_and_drag(),
+            )
+            .on_hover_text(&node.status);
+        let offset = (offset + node_response.drag_delta()).clamp(Vec2::ZERO, max_offset);
+        if node_response.dragged() {
+            positions.insert(node.server_type, [offset.x, offset.y]);
+        }
+        if node_response.double_clicked() {
+            response.opened = Some(node.server_type);
+        } else if node_response.clicked() {
+            response.clicked = Some(node.server_type);
+        }
+        rects.push(Rect::from_min_size(area.min + offset, NODE_SIZE));
+    }
+
+    let rect_of = |server_type: ServerType| {
+        nodes
+            .iter()
+            .position(|node| node.server_type == server_type)
+            .map(|i| rects[i])
+    };
+    for (node, from) in nodes.iter().zip(&rects) {
+        for dep in node
+            .start_after
+            .iter()
+            .filter(|dep| **dep != node.server_type)
+        {
+            let Some(to) = rect_of(*dep) else {
+                continue;
+            };
+            let in_cycle = cycle
+                .windows(2)
+                .any(|pair| pair[0] == node.server_type && pair[1] == *dep);
+            let stroke = if in_cycle {
+                Stroke::new(2.5, Color32::RED)
+            } else {
+                Stroke::new(1.5, ui.visuals().text_color())
+            };
+            draw_arrow(&painter, *from, to, stroke);
+        }
+    }
+
+    for (node, rect) in nodes.iter().zip(&rects) {
+        let stroke = if cycle.contains(&node.server_type) {
+            Stroke::new(2.5, Color32::RED)
+        } else if selected == Some(node.server_type) {
+            Stroke::new(2.5, ui.visuals().selection.stroke.color)
+        } else {
+            Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color)
+        };
+        painter.rect(*rect, 6.0, node.color, stroke);
+        // Status colors are all light, dark text stays readable on each
+        painter.text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            node.server_type.label(),
+            FontId::proportional(15.0),
+            Color32::BLACK,
+        );
+    }
+    response
+}
+
+/// Offset of the `i`th node before it has been moved, two to a row
+fn default_position(i: usize) -> Vec2 {
+    egui::vec2(20.0 + (i % 2) as f32 * 200.0, 20.0 + (i / 2) as f32 * 110.0)
+}
+
+/// Point where the line from the center of `rect` towards `toward` leaves it
+fn boundary_point(rect: Rect, toward: Pos2) -> Pos2 {
+    let d = toward - rect.center();
+    if d == Vec2::ZERO {
+        return rect.center();
+    }
+    let scale = (rect.width() / 2.0 / d.x.abs()).min(rect.height() / 2.0 / d.y.abs());
+    rect.center() + d * scale
+}
+
+/// Curved arrow from the edge of `from` to the edge of `to`
+fn draw_arrow(painter: &egui::Painter, from: Rect, to: Rect, stroke: Stroke) {
+    let start = boundary_point(from, to.center());
+    let end = boundary_point(to, from.center());
+    let d = end - start;
+    if d.length() < 1.0 {
+        return;
+    }
+    let bow = d.normalized().rot90() * CURVE_OFFSET;
+    let control1 = start + d / 3.0 + bow;
+    let control2 = start + d * 2.0 / 3.0 + bow;
+    painter.add(CubicBezierShape::from_points_stroke(
+        [start, control1, control2, end],
+        false,
+        Color32::TRANSPARENT,
+        stroke,
+    ));
+    let dir = (end - control2).normalized();
+    let back = end - dir * ARROW_SIZE;
+    let side = dir.rot90() * ARROW_SIZE / 2.0;
+    painter.add(Shape::convex_polygon(
+        vec![end, back + side, back - side],
+        stroke.color,
+        Stroke::NONE,
+    ));
+}
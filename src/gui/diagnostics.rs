@@ -0,0 +1,114 @@
+//! Diagnostics bundle for bug reports
+
+use crate::config::{self, AppConfig};
+use crate::servers::LogMessage;
+
+/// Value shown in place of secrets
+const REDACTED: &str = "********";
+
+/// Words in field names marking their value as a secret
+const SECRET_FIELDS: [&str; 3] = ["password", "secret", "token"];
+
+/// Replace every string field whose name mentions a secret, and webhook URLs
+/// which often carry an access token
+fn redact(value: &mut serde_json::Value, in_webhooks: bool) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                let secret = SECRET_FIELDS.iter().any(|word| key.contains(word))
+                    || (in_webhooks && key == "url");
+                if secret && value.is_string() {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact(value, in_webhooks || key == "webhooks");
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            items.iter_mut().for_each(|item| redact(item, in_webhooks))
+        }
+        _ => {}
+    }
+}
+
+/// Collect version, platform, redacted config and recent logs into one text blob
+pub fn bundle(
+    config: &AppConfig,
+    logs: &[(&str, Vec<LogMessage>)],
+    font_status: &str,
+    runtime_error: Option<&str>,
+) -> String {
+    let mut out = String::new();
+    out.push_str("OServers diagnostics\n");
+    out.push_str(&format!("Version: {}\n", env!("CARGO_PKG_VERSION")));
+    out.push_str(&format!(
+        "Platform: {} {} ({})\n",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::FAMILY
+    ));
+    out.push_str(&format!(
+        "Generated: {}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S %z")
+    ));
+    out.push_str(&format!(
+        "Data directory: {}\n",
+        config::data_dir().display()
+    ));
+    out.push_str(&format!("Fonts: {}\n", font_status));
+    out.push_str(&format!(
+        "Runtime: {}\n",
+        runtime_error.map_or("ok".to_string(), |e| format!("failed ({})", e))
+    ));
+
+    out.push_str("\n== Configuration (secrets masked) ==\n");
+    match serde_json::to_value(config) {
+        Ok(mut value) => {
+            redact(&mut value, false);
+            out.push_str(&serde_json::to_string_pretty(&value).unwrap_or_default());
+        }
+        Err(e) => out.push_str(&format!("<failed to serialize: {}>", e)),
+    }
+    out.push('\n');
+
+    for (server, messages) in logs {
+        out.push_str(&format!("\n== {} log ==\n", server));
+        for log in messages {
+            out.push_str(&format!("{}\n", log));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{REDACTED, redact};
+    use serde_json::json;
+
+    #[test]
+    fn secrets_are_masked() {
+        let mut value = json!({
+            "http": {
+                "port": 8080,
+                "signed_url_secret": "hmac-key",
+                "signed_url_admin_token": "bearer",
+                "stats_password": "hunter2",
+                "webhooks": [{ "url": "https://hooks.example/T0/secret", "method": "POST" }],
+            },
+            "update_manifest_url": "https://example.com/latest.json",
+        });
+        redact(&mut value, false);
+        let http = &value["http"];
+        assert_eq!(http["signed_url_secret"], REDACTED);
+        assert_eq!(http["signed_url_admin_token"], REDACTED);
+        assert_eq!(http["stats_password"], REDACTED);
+        assert_eq!(http["webhooks"][0]["url"], REDACTED);
+        assert_eq!(http["webhooks"][0]["method"], "POST");
+        assert_eq!(http["port"], 8080);
+        assert_eq!(
+            value["update_manifest_url"],
+            "https://example.com/latest.json"
+        );
+    }
+}
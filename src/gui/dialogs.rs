@@ -0,0 +1,87 @@
+//! Native file dialogs that notice when no dialog can be shown
+//!
+//! `rfd` returns `None` both when the user cancels and when the dialog
+//! could not open at all, e.g. on a Linux session without a display or
+//! desktop portal. The latter is told apart by how fast `None` comes back.
+//! A quick `None` may also be a dialog closed right away, so only a missing
+//! display disables dialogs for good, anything else is tried again.
+
+use super::i18n::tr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A `None` returned faster than this means no dialog was shown
+const MIN_INTERACTION: Duration = Duration::from_millis(150);
+
+/// Outcome of showing a dialog
+pub enum Picked {
+    Chosen(PathBuf),
+    Cancelled,
+    Unavailable,
+}
+
+/// Whether file dialogs can be shown at all, false without a display
+pub fn available() -> bool {
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        return true;
+    }
+    std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+fn run(dialog: impl FnOnce() -> Option<PathBuf>) -> Picked {
+    if !available() {
+        return Picked::Unavailable;
+    }
+    let started = Instant::now();
+    match dialog() {
+        Some(path) => Picked::Chosen(path),
+        None if started.elapsed() >= MIN_INTERACTION => Picked::Cancelled,
+        None => {
+            tracing::warn!("File dialog could not be opened, type the path instead");
+            Picked::Unavailable
+        }
+    }
+}
+
+pub fn pick_folder() -> Picked {
+    run(|| rfd::FileDialog::new().pick_folder())
+}
+
+pub fn save_file(file_name: &str) -> Picked {
+    run(|| rfd::FileDialog::new().set_file_name(file_name).save_file())
+}
+
+/// Show `dir` in the system file manager
+pub fn open_folder(dir: &Path) {
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    if let Err(e) = std::process::Command::new(program).arg(dir).spawn() {
+        tracing::warn!("Failed to open {}: {}", dir.display(), e);
+    }
+}
+
+/// 📁 button picking a folder for the text field `field`
+///
+/// Without a display the button is disabled. When a dialog fails to open
+/// the field is focused instead, and the next click tries again.
+pub fn folder_button(ui: &mut egui::Ui, field: &egui::Response) -> Option<PathBuf> {
+    let button = ui
+        .add_enabled(available(), egui::Button::new("📁"))
+        .on_disabled_hover_text(tr("No file dialog available, type the path instead"));
+    if !button.clicked() {
+        return None;
+    }
+    match pick_folder() {
+        Picked::Chosen(path) => Some(path),
+        Picked::Cancelled => None,
+        Picked::Unavailable => {
+            field.request_focus();
+            None
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Live list of changes in a server's root directory
+//!
+//! The watcher only exists while the list is on screen, dropping the
+//! [`FileActivity`] stops it.
+
+use super::i18n::tr;
+use crate::servers::http::format_size;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Events kept in the list, older ones are dropped
+const MAX_EVENTS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileEventKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+impl FileEventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            FileEventKind::Created => "CREATED",
+            FileEventKind::Modified => "MODIFIED",
+            FileEventKind::Deleted => "DELETED",
+            FileEventKind::Renamed => "RENAMED",
+        }
+    }
+
+    fn color(&self) -> egui::Color32 {
+        match self {
+            FileEventKind::Created => egui::Color32::LIGHT_GREEN,
+            FileEventKind::Modified => egui::Color32::YELLOW,
+            FileEventKind::Deleted => egui::Color32::LIGHT_RED,
+            FileEventKind::Renamed => egui::Color32::LIGHT_BLUE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FileEvent {
+    time: chrono::DateTime<chrono::Local>,
+    kind: FileEventKind,
+    /// Path relative to the root, `old → new` for renames
+    name: String,
+    /// Size of the file after the change, if it still is a file
+    size: Option<u64>,
+}
+
+#[derive(Default)]
+struct Events {
+    list: VecDeque<FileEvent>,
+    /// Old name of a rename whose new name has not been reported yet
+    rename_from: Option<String>,
+}
+
+impl Events {
+    fn push(&mut self, event: FileEvent) {
+        // Writes arrive in bursts, one line per file and burst is enough
+        let repeated = self.list.back_mut().filter(|last| {
+            event.kind == FileEventKind::Modified
+                && last.kind == FileEventKind::Modified
+                && last.name == event.name
+        });
+        if let Some(last) = repeated {
+            *last = event;
+            return;
+        }
+        self.list.push_back(event);
+        if self.list.len() > MAX_EVENTS {
+            self.list.pop_front();
+        }
+    }
+}
+
+/// Watcher for one root directory and the events it has seen
+pub struct FileActivity {
+    root: PathBuf,
+    watcher: Option<RecommendedWatcher>,
+    events: Arc<Mutex<Events>>,
+    paused: bool,
+    error: Option<String>,
+}
+
+impl FileActivity {
+    /// Start watching `root` and everything below it
+    pub fn watch(root: &Path, ctx: &egui::Context) -> Self {
+        let mut activity = Self {
+            root: root.to_path_buf(),
+            watcher: None,
+            events: Arc::default(),
+            paused: false,
+            error: None,
+        };
+        activity.start(ctx);
+        activity
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn start(&mut self, ctx: &egui::Context) {
+        let events = self.events.clone();
+        let root = self.root.clone();
+        let ctx = ctx.clone();
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else {
+                return;
+            };
+            record(&mut events.lock(), &root, event);
+            ctx.request_repaint();
+        })
+        .and_then(|mut watcher| {
+            watcher.watch(&self.root, RecursiveMode::Recursive)?;
+            Ok(watcher)
+        });
+        match watcher {
+            Ok(watcher) => {
+                self.watcher = Some(watcher);
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .checkbox(&mut self.paused, tr("Pause watching"))
+                .changed()
+            {
+                if self.paused {
+                    self.watcher = None;
+                } else {
+                    self.start(ui.ctx());
+                }
+            }
+            if ui.button(tr("Clear")).clicked() {
+                self.events.lock().list.clear();
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(
+                egui::Color32::LIGHT_RED,
+                format!("{} {}", tr("Cannot watch the root directory:"), e),
+            );
+        }
+        let events = self.events.lock();
+        egui::ScrollArea::vertical()
+            .id_salt("file_activity_scroll")
+            .auto_shrink([false; 2])
+            .max_height(300.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                if events.list.is_empty() {
+                    ui.weak(tr("No changes yet"));
+                }
+                for event in &events.list {
+                    let size = event
+                        .size
+                        .map(|size| format!(" ({})", format_size(size)))
+                        .unwrap_or_default();
+                    ui.colored_label(
+                        event.kind.color(),
+                        egui::RichText::new(format!(
+                            "[{}] {} {}{}",
+                            event.time.format("%H:%M:%S"),
+                            event.kind.name(),
+                            event.name,
+                            size
+                        ))
+                        .monospace(),
+                    );
+                }
+            });
+    }
+}
+
+/// Add the change described by `event` to `events`
+fn record(events: &mut Events, root: &Path, event: notify::Event) {
+    let relative = |path: &Path| {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let Some(path) = event.paths.first() else {
+        return;
+    };
+    let name = relative(path);
+    let (kind, name, size_of) = match event.kind {
+        EventKind::Create(_) => (FileEventKind::Created, name, Some(path)),
+        EventKind::Remove(_) => (FileEventKind::Deleted, name, None),
+        // Paired up here, inotify also sends a combined event that is skipped
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            // A second old name means the first was moved out of the root
+            let Some(old) = events.rename_from.replace(name) else {
+                return;
+            };
+            (FileEventKind::Renamed, old, None)
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            let name = match events.rename_from.take() {
+                Some(old) => format!("{} → {}", old, name),
+                None => name,
+            };
+            (FileEventKind::Renamed, name, Some(path))
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => return,
+        EventKind::Modify(ModifyKind::Name(_)) => (FileEventKind::Renamed, name, None),
+        EventKind::Modify(_) => (FileEventKind::Modified, name, Some(path)),
+        EventKind::Access(_) | EventKind::Any | EventKind::Other => return,
+    };
+    let size = size_of
+        .and_then(|path| std::fs::metadata(path).ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len());
+    events.push(FileEvent {
+        time: chrono::Local::now(),
+        kind,
+        name,
+        size,
+    });
+}
@@ -0,0 +1,425 @@
+//! UI translations
+//!
+//! English strings double as translation keys, so untranslated labels
+//! simply fall back to English.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// UI language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    English,
+    Chinese,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::Chinese];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Chinese => "简体中文",
+        }
+    }
+}
+
+/// Currently active language, stored as the enum discriminant
+static CURRENT: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+/// Switch the UI language, takes effect on the next frame
+pub fn set_language(language: Language) {
+    CURRENT.store(language as u8, Ordering::Relaxed);
+}
+
+/// Get the active UI language
+pub fn language() -> Language {
+    match CURRENT.load(Ordering::Relaxed) {
+        x if x == Language::Chinese as u8 => Language::Chinese,
+        _ => Language::English,
+    }
+}
+
+/// Translate a UI string into the active language
+pub fn tr(key: &'static str) -> &'static str {
+    match language() {
+        Language::English => key,
+        Language::Chinese => zh_cn(key).unwrap_or(key),
+    }
+}
+
+/// Simplified Chinese string table
+fn zh_cn(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "🖥 Servers" => "🖥 服务器",
+        "⚙ Settings" => "⚙ 设置",
+        "Settings" => "设置",
+        "Language:" => "语言：",
+        "HTTP Server" => "HTTP 服务器",
+        "FTP Server" => "FTP 服务器",
+        "TFTP Server" => "TFTP 服务器",
+        "SSH/SFTP Server" => "SSH/SFTP 服务器",
+        "▶ Start" => "▶ 启动",
+        "⏹ Stop" => "⏹ 停止",
+        "Status:" => "状态：",
+        "Share link:" => "分享链接：",
+        "Copy" => "复制",
+        "Serve the root directory again" => "恢复共享根目录",
+        "Stopped" => "已停止",
+        "Starting..." => "正在启动...",
+        "Running" => "运行中",
+        "Stopping..." => "正在停止...",
+        "Error:" => "错误：",
+        "Root directory:" => "根目录：",
+        "Recent directories" => "最近的目录",
+        "Copy as Markdown table" => "复制为 Markdown 表格",
+        "Copy as CSV" => "复制为 CSV",
+        "Copy the directory's contents (name, size, modified)" => {
+            "复制目录内容（名称、大小、修改时间）"
+        }
+        "Listening port:" => "监听端口：",
+        "Directory listing:" => "目录列表：",
+        "Allow users to list directory content" => "允许用户列出目录内容",
+        "Auto stop:" => "自动停止：",
+        "Stop server after" => "服务器停止于",
+        "seconds" => "秒后",
+        "seconds idle" => "秒无活动后",
+        "Auto start:" => "自动启动：",
+        "Start when OServers launches" => "OServers 启动时启动",
+        "After" => "在以下服务器之后",
+        "Delay" => "延迟",
+        "Wait up to" => "最多等待",
+        "seconds for the root directory" => "秒直到根目录可用",
+        "Sensitive files:" => "敏感文件：",
+        "Scan root directory before starting" => "启动前扫描根目录",
+        "Sensitive patterns:" => "敏感文件模式：",
+        "Idle page:" => "空闲页面：",
+        "Serve a status page for directories without an index" => "为没有索引的目录提供状态页面",
+        "Page files:" => "页面文件：",
+        "Landing" => "首页",
+        "Not found" => "未找到",
+        "Forbidden" => "禁止访问",
+        "Maintenance" => "维护",
+        "Built-in page" => "内置页面",
+        "Maintenance:" => "维护：",
+        "Answer all requests with the maintenance page" => "对所有请求返回维护页面",
+        "Bandwidth limits:" => "带宽限制：",
+        "Webhooks:" => "Webhook：",
+        "Any request" => "任意请求",
+        "Upload" => "上传",
+        "Auth failure" => "认证失败",
+        "Error" => "错误",
+        "Details" => "详细信息",
+        "➕ Add webhook" => "➕ 添加 Webhook",
+        "Username:" => "用户名：",
+        "Password:" => "密码：",
+        "Anonymous:" => "匿名：",
+        "Allow anonymous access" => "允许匿名访问",
+        "Transfer mode:" => "传输模式：",
+        "Enable passive mode (PASV)" => "启用被动模式 (PASV)",
+        "(Active mode PORT always enabled)" => "（主动模式 PORT 始终启用）",
+        "Passive ports:" => "被动端口：",
+        "🔑 Test login" => "🔑 测试登录",
+        "Log in to the running server with the configured credentials" => {
+            "使用当前配置的凭据登录正在运行的服务器"
+        }
+        "Listing cache:" => "列表缓存：",
+        "seconds (0 disables)" => "秒（0 表示禁用）",
+        "Statistics page:" => "统计页面：",
+        "Serve at" => "路径",
+        "Statistics login:" => "统计页面登录：",
+        "Username" => "用户名",
+        "Password" => "密码",
+        "Served" => "已发送",
+        "across" => "，共",
+        "requests" => "个请求",
+        "Concurrent downloads:" => "并发下载：",
+        "Limit to" => "限制为",
+        "Path depth:" => "路径深度：",
+        "segments" => "级",
+        "queue timeout" => "排队超时",
+        "Downloads:" => "下载：",
+        "active" => "进行中",
+        "queued" => "排队",
+        "Zip downloads:" => "ZIP 下载：",
+        "File server" => "文件服务器",
+        "JSON listing:" => "JSON 列表：",
+        "For clients accepting application/json" => "用于接受 application/json 的客户端",
+        "Key-value store" => "键值存储",
+        "GET/PUT/DELETE /kv/<key>, GET /kv lists keys" => {
+            "GET/PUT/DELETE /kv/<键>，GET /kv 列出所有键"
+        }
+        "Store file:" => "存储文件：",
+        "Compressed values:" => "压缩的值：",
+        "Decode gzip, deflate and br request bodies" => "解码 gzip、deflate 和 br 请求体",
+        "Languages:" => "语言：",
+        "PROXY protocol:" => "PROXY 协议：",
+        "Behind a load balancer sending PROXY headers" => "位于发送 PROXY 头的负载均衡器之后",
+        "Connections without a PROXY v1/v2 header are rejected" => {
+            "没有 PROXY v1/v2 头的连接将被拒绝"
+        }
+        "Client lookup:" => "客户端查询：",
+        "Reverse DNS host names in the log" => "在日志中显示反向 DNS 主机名",
+        "GeoIP database:" => "GeoIP 数据库：",
+        "MaxMind .mmdb file (optional)" => "MaxMind .mmdb 文件（可选）",
+        "Integrity check:" => "完整性检查：",
+        "Hash files every" => "每隔",
+        "seconds using" => "秒计算哈希，算法",
+        "Manifest file:" => "清单文件：",
+        "Created from the first scan if missing, changed files are logged as warnings" => {
+            "不存在时由首次扫描生成，变动的文件会记录为警告"
+        }
+        "Serve page.zh.html etc. by browser language" => "按浏览器语言提供 page.zh.html 等",
+        "Allow directories up to" => "允许打包的目录最大为",
+        "Pick a server type here. The dot shows whether it is running." => {
+            "在这里选择服务器类型，圆点表示它是否在运行。"
+        }
+        "Start and stop the selected server with this button." => {
+            "使用此按钮启动或停止所选服务器。"
+        }
+        "Choose the directory the server shares." => "选择服务器共享的目录。",
+        "Requests and errors show up in the server output." => "请求和错误会显示在服务器输出中。",
+        "⚠ Servers cannot run" => "⚠ 服务器无法运行",
+        "The background runtime could not be started, the system may be out of threads or memory." => {
+            "后台运行时无法启动，系统可能缺少线程或内存资源。"
+        }
+        "Retry" => "重试",
+        "Quit" => "退出",
+        "Skip" => "跳过",
+        "Next" => "下一步",
+        "Done" => "完成",
+        "Power saving:" => "节能：",
+        "Refresh once per second while in the background" => "在后台时每秒刷新一次",
+        "Log on stop:" => "停止时保存日志：",
+        "Write to" => "写入",
+        "No file dialog available, type the path instead" => "无法打开文件对话框，请直接输入路径",
+        "Notes" => "备注",
+        "✏ Edit" => "✏ 编辑",
+        "Purpose, special setup, troubleshooting history (markdown)" => {
+            "用途、特殊配置、排障记录（支持 Markdown）"
+        }
+        "Diagnostics:" => "诊断信息：",
+        "📋 Copy" => "📋 复制",
+        "💾 Save..." => "💾 保存...",
+        "Version, platform, configuration (secrets masked) and recent logs for bug reports" => {
+            "用于问题反馈的版本、平台、配置（已隐藏密码和密钥）和最近日志"
+        }
+        "Tutorial:" => "教程：",
+        "Restart tutorial" => "重新开始教程",
+        "Overwrite existing files:" => "覆盖已有文件：",
+        "Allow" => "允许",
+        "Deny" => "拒绝",
+        "Rename" => "重命名",
+        "Compression:" => "压缩：",
+        "Compress uploads with" => "上传文件压缩为",
+        "Decompress on download" => "下载时解压",
+        "Discovery:" => "局域网发现：",
+        "Broadcast on LAN, UDP port" => "在局域网广播，UDP 端口",
+        "mDNS:" => "mDNS：",
+        "Announce on the LAN (Bonjour/Avahi)" => "在局域网中通告（Bonjour/Avahi）",
+        "Tor:" => "Tor：",
+        "Log sampling:" => "日志采样：",
+        "Passive host:" => "被动模式地址：",
+        "Address clients connect to for passive transfers, e.g. behind NAT" => {
+            "客户端进行被动传输时连接的地址，例如位于 NAT 之后时"
+        }
+        "Set a login other than admin/admin" => "请设置 admin/admin 以外的登录信息",
+        "Checking for sensitive files" => "正在检查敏感文件",
+        "Looking for sensitive files in the root directory..." => "正在根目录中查找敏感文件...",
+        "🔗 Dependencies" => "🔗 依赖关系",
+        "Circular start order, auto start ignores it:" => "启动顺序存在循环，自动启动将忽略它：",
+        "Arrows point to the server a server starts after. Drag to arrange, double-click to open settings." => {
+            "箭头指向服务器需要等待其先启动的服务器。拖动可调整位置，双击可打开设置。"
+        }
+        "Session resumption:" => "会话恢复：",
+        "Session tickets, key rotated daily" => "会话票据，密钥每日轮换",
+        "Returning clients skip the full TLS handshake" => "回访的客户端可跳过完整的 TLS 握手",
+        "Not logged:" => "不记录日志：",
+        "One path per line, /dir/* for everything below" => "每行一个路径，/dir/* 表示其下所有路径",
+        "Successful requests to these paths are left out of the log" => {
+            "对这些路径的成功请求不写入日志"
+        }
+        "Must start with /:" => "必须以 / 开头：",
+        "Stream buffer:" => "流式缓冲区：",
+        "Larger suits a fast disk and slow network, smaller gets the first bytes from a slow disk out sooner" => {
+            "较大的值适合快速磁盘和慢速网络，较小的值可让慢速磁盘更快发出首批数据"
+        }
+        "FTP test login" => "FTP 测试登录",
+        "Server not running — start it first" => "服务器未运行——请先启动",
+        "Logging in as" => "正在登录为",
+        "Logged in as" => "已登录为",
+        "Login failed as" => "登录失败，用户",
+        "Close" => "关闭",
+        "Trusted proxies:" => "受信任的代理：",
+        "Not an address or network:" => "不是有效的地址或网段：",
+        "Base path:" => "基础路径：",
+        "e.g. /files behind a reverse proxy" => "如反向代理下的 /files",
+        "Restart policy:" => "重启策略：",
+        "On crash" => "崩溃时",
+        "On error" => "出错时",
+        "Do nothing" => "不处理",
+        "Restart immediately" => "立即重启",
+        "Restart with backoff" => "延迟重启",
+        "seconds, doubled each time" => "秒，每次翻倍",
+        "At most" => "最多",
+        "restarts per hour" => "次重启/小时",
+        "File activity" => "文件活动",
+        "Pause watching" => "暂停监视",
+        "Clear" => "清除",
+        "Cannot watch the root directory:" => "无法监视根目录：",
+        "No changes yet" => "暂无变化",
+        "New server root:" => "新服务器根目录：",
+        "Start new servers in" => "新服务器默认使用",
+        "Working directory" => "工作目录",
+        "Health check:" => "健康检查：",
+        "Active transfers" => "进行中的传输",
+        "Client" => "客户端",
+        "File" => "文件",
+        "Progress" => "进度",
+        "Rate" => "速率",
+        "Download" => "下载",
+        "Copy settings:" => "复制设置：",
+        "Copy from…" => "复制自…",
+        "Take the root directory, auto stop and log export settings of another server" => {
+            "使用另一个服务器的根目录、自动停止和日志导出设置"
+        }
+        "Directory URLs:" => "目录 URL：",
+        "Redirect /dir to /dir/ so relative links work" => {
+            "将 /dir 重定向到 /dir/，使相对链接正常工作"
+        }
+        "Notifications:" => "通知：",
+        "Report finished" => "报告已完成的",
+        "Uploads" => "上传",
+        "Downloads" => "下载",
+        "Uploads and downloads" => "上传和下载",
+        "of at least" => "，最小",
+        "Webhook" => "Webhook",
+        "Email" => "电子邮件",
+        "SMTP server:" => "SMTP 服务器：",
+        "Implicit TLS" => "隐式 TLS",
+        "Connect with TLS right away (usually port 465) instead of STARTTLS" => {
+            "直接以 TLS 连接（通常为 465 端口），而不是使用 STARTTLS"
+        }
+        "From:" => "发件人：",
+        "To:" => "收件人：",
+        "Listing CSP:" => "列表 CSP：",
+        "Only allow the listing's own styles, nothing else may load" => {
+            "只允许列表自身的样式，不加载其他任何内容"
+        }
+        "Sends a Content-Security-Policy with a new nonce on every listing" => {
+            "每次返回列表时发送带有新 nonce 的 Content-Security-Policy"
+        }
+        "Update available" => "有可用更新",
+        "OServers is available in version" => "OServers 新版本可用：",
+        "Release page" => "发布页面",
+        "Dismiss" => "关闭",
+        "Updates:" => "更新：",
+        "Check for a new version on startup" => "启动时检查新版本",
+        "Manifest URL:" => "版本清单 URL：",
+        "Check for updates" => "检查更新",
+        "You are on the latest version" => "已是最新版本",
+        "New version:" => "新版本：",
+        "Check failed" => "检查失败",
+        "The first passive port is above the last, passive transfers cannot work" => {
+            "起始被动端口大于结束端口，被动传输无法进行"
+        }
+        "A single passive port allows one transfer at a time" => {
+            "只有一个被动端口，同一时间只能进行一个传输"
+        }
+        "Response signing:" => "响应签名：",
+        "Sign with" => "签名算法",
+        "Adds X-Content-Signature over status, length, type and ETag to every response" => {
+            "为每个响应添加基于状态码、长度、类型和 ETag 的 X-Content-Signature"
+        }
+        "Signing key:" => "签名密钥文件：",
+        "PEM private key (PKCS#8)" => "PEM 私钥（PKCS#8）",
+        "Listing banner:" => "列表横幅：",
+        "e.g. Confidential, internal use only" => "例如：机密，仅限内部使用",
+        "Deleted files:" => "已删除的文件：",
+        "Move to .trash, keep for" => "移到 .trash，保留",
+        "0 keeps them until removed by hand" => "0 表示一直保留，直到手动删除",
+        "days" => "天",
+        "🗑 Trash" => "🗑 回收站",
+        "Open the trash folder" => "打开回收站文件夹",
+        "🔍 Find free port" => "🔍 查找空闲端口",
+        "Fill in the first port from this one upwards that is not in use" => {
+            "从此端口向上填入第一个未被占用的端口"
+        }
+        "Auto save:" => "自动保存：",
+        "Save settings every" => "保存设置，间隔",
+        "Signed URLs:" => "签名链接：",
+        "Serve time-limited links at /__signed" => "在 /__signed 提供限时链接",
+        "Signing secret:" => "签名密钥：",
+        "Admin token:" => "管理令牌：",
+        "Bearer token for POST /__admin/sign, empty disables issuing links" => {
+            "POST /__admin/sign 使用的 Bearer 令牌，留空则不签发链接"
+        }
+        "🎲 Generate" => "🎲 生成",
+        "Fill in a random password and copy it to the clipboard" => "填入随机密码并复制到剪贴板",
+        "Generated passwords:" => "生成的密码：",
+        "characters" => "个字符",
+        "Greeting:" => "欢迎信息：",
+        "220 reply sent when a client connects" => "客户端连接时发送的 220 回复",
+        "Layout:" => "布局：",
+        "Monitor mode, a read-only dashboard of all servers" => "监控模式，只读显示所有服务器",
+        "📈 Monitor" => "📈 监控",
+        "Uptime:" => "运行时间：",
+        "Active downloads:" => "活动下载：",
+        "Requests:" => "请求数：",
+        "Throughput:" => "吞吐量：",
+        "Recent errors:" => "最近错误：",
+        "None" => "无",
+        "Virtual files:" => "虚拟文件：",
+        "{{server_port}} and {{server_ip}} in the content are filled in" => {
+            "内容中的 {{server_port}} 和 {{server_ip}} 会被替换"
+        }
+        "➕ Add virtual file" => "➕ 添加虚拟文件",
+        "Egress limit:" => "出站限速：",
+        "Limit each connection to" => "每个连接限制为",
+        "Applies to everything sent, not available with HTTPS" => {
+            "作用于发送的全部数据，不能与 HTTPS 同时使用"
+        }
+        "HTTPS:" => "HTTPS：",
+        "Enable, HTTPS port" => "启用，HTTPS 端口",
+        "same" => "相同",
+        "Leave empty to serve only HTTPS on the listening port" => "留空则监听端口只提供 HTTPS",
+        "Certificate:" => "证书：",
+        "PEM certificate chain" => "PEM 证书链",
+        "Private key:" => "私钥：",
+        "PEM private key" => "PEM 私钥",
+        "A/B test:" => "A/B 测试：",
+        "Serve group B from" => "B 组内容目录",
+        "A/B split:" => "A/B 分流：",
+        "% to group A" => "% 分到 A 组",
+        "Cookie:" => "Cookie：",
+        "New visitors are assigned at random, the cookie keeps them in their group" => {
+            "新访客随机分组，之后由 Cookie 保持在同一组"
+        }
+        "Share of successful requests written to the log, errors are always logged" => {
+            "写入日志的成功请求比例，错误请求总是记录"
+        }
+        "Publish as onion service, control port" => "发布为洋葱服务，控制端口",
+        "Onion address:" => "洋葱地址：",
+        "Mode:" => "模式：",
+        "Address family:" => "地址族：",
+        "IPv6 (dual-stack)" => "IPv6（双栈）",
+        "Read-only mode" => "只读模式",
+        "Staging directory:" => "暂存目录：",
+        "Empty writes uploads directly" => "留空则直接写入上传文件",
+        "Retry timeout:" => "重传超时：",
+        "Max retries:" => "最大重试次数：",
+        "Allowed file patterns:" => "允许的文件模式：",
+        "One per line, e.g. *.bin (empty allows all)" => "每行一个，例如 *.bin（留空表示全部允许）",
+        "Unpin" => "取消固定",
+        "Server output" => "服务器输出",
+        "📌 Pin this message pattern" => "📌 固定此消息模式",
+        "⚠ Sensitive files found" => "⚠ 发现敏感文件",
+        "Starting the HTTP server would expose these files:" => {
+            "启动 HTTP 服务器将会暴露以下文件："
+        }
+        "Start anyway" => "仍然启动",
+        "Cancel" => "取消",
+        _ => return None,
+    })
+}
@@ -0,0 +1,105 @@
+//! Minimal markdown rendering for server notes
+//!
+//! Supports headings, bullet lists, fenced code blocks and inline bold,
+//! italic and code spans.
+
+use egui::text::LayoutJob;
+use egui::{RichText, TextFormat, TextStyle, Ui};
+
+/// Render `text` as markdown into `ui`
+pub fn show(ui: &mut Ui, text: &str) {
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            let code: Vec<&str> = lines
+                .by_ref()
+                .take_while(|l| !l.trim_start().starts_with("```"))
+                .collect();
+            egui::Frame::none()
+                .fill(ui.visuals().code_bg_color)
+                .inner_margin(4.0)
+                .rounding(2.0)
+                .show(ui, |ui| {
+                    ui.label(RichText::new(code.join("\n")).monospace());
+                });
+        } else if let Some(heading) = heading(trimmed) {
+            ui.label(RichText::new(heading).heading());
+        } else if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("•");
+                ui.label(inline(ui, item));
+            });
+        } else if trimmed.is_empty() {
+            ui.add_space(4.0);
+        } else {
+            ui.label(inline(ui, line));
+        }
+    }
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    (text.len() < line.len() && line.len() - text.len() <= 6 && text.starts_with(' '))
+        .then(|| text.trim())
+}
+
+/// Lay out `**bold**`, `*italic*` and `` `code` `` spans of a single line
+fn inline(ui: &Ui, text: &str) -> LayoutJob {
+    let body = TextStyle::Body.resolve(ui.style());
+    let mono = TextStyle::Monospace.resolve(ui.style());
+    let visuals = ui.visuals();
+    let format = |bold: bool, italic: bool, code: bool| TextFormat {
+        font_id: if code { mono.clone() } else { body.clone() },
+        color: if bold {
+            visuals.strong_text_color()
+        } else {
+            visuals.text_color()
+        },
+        italics: italic,
+        background: if code {
+            visuals.code_bg_color
+        } else {
+            Default::default()
+        },
+        ..Default::default()
+    };
+
+    let mut job = LayoutJob::default();
+    let (mut bold, mut italic, mut code) = (false, false, false);
+    let mut span = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let marker = if code {
+            (c == '`').then_some(1)
+        } else if rest.starts_with("**") {
+            Some(2)
+        } else if c == '*' || c == '`' {
+            Some(1)
+        } else {
+            None
+        };
+        match marker {
+            Some(len) => {
+                job.append(&span, 0.0, format(bold, italic, code));
+                span.clear();
+                match &rest[..len] {
+                    "**" => bold = !bold,
+                    "*" => italic = !italic,
+                    _ => code = !code,
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                span.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    job.append(&span, 0.0, format(bold, italic, code));
+    job.wrap.max_width = ui.available_width();
+    job
+}
@@ -1,3 +1,23 @@
 //! GUI module
+//!
+//! Only the settings kept in the configuration file, the UI language and
+//! the update check address, are built without the `gui` feature.
 
+#[cfg(feature = "gui")]
 pub mod app;
+#[cfg(feature = "gui")]
+pub mod dependency_graph;
+#[cfg(feature = "gui")]
+pub mod diagnostics;
+#[cfg(feature = "gui")]
+pub mod dialogs;
+#[cfg(feature = "gui")]
+pub mod file_activity;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub mod i18n;
+#[cfg(feature = "gui")]
+pub mod markdown;
+#[cfg(feature = "gui")]
+pub mod tutorial;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub mod update_check;
@@ -0,0 +1,111 @@
+//! First-run tutorial drawn as bubbles over the main window
+
+use super::i18n::tr;
+use egui::{Align, Align2, Color32, Context, Id, LayerId, Layout, Order, Pos2, Rect, Stroke};
+
+/// Number of tutorial steps
+pub const STEP_COUNT: usize = 4;
+
+/// Distance between a bubble and the area it points at
+const BUBBLE_GAP: f32 = 40.0;
+
+/// Screen areas the tutorial points at, recorded while the UI is built
+#[derive(Default)]
+pub struct Targets {
+    pub server_list: Option<Rect>,
+    pub start_button: Option<Rect>,
+    pub root_dir: Option<Rect>,
+    pub log_panel: Option<Rect>,
+}
+
+impl Targets {
+    fn for_step(&self, step: usize) -> Option<Rect> {
+        match step {
+            0 => self.server_list,
+            1 => self.start_button,
+            2 => self.root_dir,
+            _ => self.log_panel,
+        }
+    }
+}
+
+/// Button clicked in a tutorial bubble
+pub enum Action {
+    None,
+    Next,
+    Skip,
+}
+
+fn step_text(step: usize) -> &'static str {
+    match step {
+        0 => "Pick a server type here. The dot shows whether it is running.",
+        1 => "Start and stop the selected server with this button.",
+        2 => "Choose the directory the server shares.",
+        _ => "Requests and errors show up in the server output.",
+    }
+}
+
+/// Draw the bubble for `step` with an arrow to its target, returning the clicked button
+pub fn show(ctx: &Context, step: usize, targets: &Targets) -> Action {
+    let screen = ctx.screen_rect();
+    let target = targets.for_step(step);
+
+    // Place the bubble beside the target, or above it when the target is wide
+    let (pivot, pos, arrow_to) = match target {
+        Some(rect) if rect.width() > screen.width() / 2.0 => (
+            Align2::CENTER_BOTTOM,
+            Pos2::new(rect.center().x, rect.top() - BUBBLE_GAP),
+            Pos2::new(rect.center().x, rect.top() - 4.0),
+        ),
+        Some(rect) if rect.center().x < screen.center().x => (
+            Align2::LEFT_CENTER,
+            Pos2::new(rect.right() + BUBBLE_GAP, rect.center().y),
+            Pos2::new(rect.right() + 4.0, rect.center().y),
+        ),
+        Some(rect) => (
+            Align2::RIGHT_CENTER,
+            Pos2::new(rect.left() - BUBBLE_GAP, rect.center().y),
+            Pos2::new(rect.left() - 4.0, rect.center().y),
+        ),
+        None => (Align2::CENTER_CENTER, screen.center(), screen.center()),
+    };
+
+    let accent = Color32::from_rgb(76, 175, 80);
+    if let Some(rect) = target {
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("tutorial")));
+        painter.rect_stroke(rect.expand(4.0), 4.0, Stroke::new(2.0, accent));
+        painter.arrow(pos, arrow_to - pos, Stroke::new(2.0, accent));
+    }
+
+    let mut action = Action::None;
+    egui::Area::new(Id::new("tutorial_bubble"))
+        .order(Order::Foreground)
+        .pivot(pivot)
+        .fixed_pos(pos)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style())
+                .stroke(Stroke::new(2.0, accent))
+                .show(ui, |ui| {
+                    ui.set_max_width(260.0);
+                    ui.label(tr(step_text(step)));
+                    ui.add_space(4.0);
+                    ui.horizontal(|ui| {
+                        ui.weak(format!("{}/{}", step + 1, STEP_COUNT));
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            let next = if step + 1 == STEP_COUNT {
+                                tr("Done")
+                            } else {
+                                tr("Next")
+                            };
+                            if ui.button(next).clicked() {
+                                action = Action::Next;
+                            }
+                            if ui.button(tr("Skip")).clicked() {
+                                action = Action::Skip;
+                            }
+                        });
+                    });
+                });
+        });
+    action
+}
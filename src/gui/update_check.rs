@@ -0,0 +1,133 @@
+//! Opt-in check for a newer release
+//!
+//! The manifest is JSON with `version` and `url` fields. A GitHub
+//! `releases/latest` response works as well, its `tag_name` and
+//! `html_url` are used instead.
+
+use std::time::Duration;
+
+/// Latest GitHub release of OServers
+pub const DEFAULT_MANIFEST_URL: &str =
+    "https://api.github.com/repos/wuooyun/OServers/releases/latest";
+
+/// Give up on the manifest after this long, the check is never worth waiting for
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of an update check
+#[derive(Debug, Clone)]
+pub enum UpdateStatus {
+    Checking,
+    UpToDate,
+    Available { version: String, url: String },
+    Failed(String),
+}
+
+#[derive(serde::Deserialize)]
+struct Manifest {
+    #[serde(alias = "tag_name")]
+    version: String,
+    url: Option<String>,
+    /// Release page of a GitHub response, whose `url` is the API endpoint
+    html_url: Option<String>,
+}
+
+impl Manifest {
+    /// Page to send the user to for the download
+    fn download_url(self) -> String {
+        self.html_url.or(self.url).unwrap_or_default()
+    }
+}
+
+/// Fetch the manifest at `manifest_url` and compare it with this build
+pub async fn check(manifest_url: &str) -> UpdateStatus {
+    let result = async {
+        reqwest::Client::new()
+            .get(manifest_url)
+            // GitHub rejects API requests without a user agent
+            .header(
+                reqwest::header::USER_AGENT,
+                concat!("OServers/", env!("CARGO_PKG_VERSION")),
+            )
+            .timeout(TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Manifest>()
+            .await
+    }
+    .await;
+    match result {
+        Ok(manifest) if is_newer(&manifest.version, env!("CARGO_PKG_VERSION")) => {
+            UpdateStatus::Available {
+                version: manifest.version.trim_start_matches('v').to_string(),
+                url: manifest.download_url(),
+            }
+        }
+        Ok(_) => UpdateStatus::UpToDate,
+        Err(e) => UpdateStatus::Failed(e.to_string()),
+    }
+}
+
+/// Numeric parts of a version like `v1.2.3-beta`, ignoring the pre-release suffix
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .trim()
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+/// Whether `latest` is a higher version than `current`
+fn is_newer(latest: &str, current: &str) -> bool {
+    let (mut latest, mut current) = (version_parts(latest), version_parts(current));
+    let len = latest.len().max(current.len());
+    latest.resize(len, 0);
+    current.resize(len, 0);
+    latest > current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Manifest;
+
+    #[test]
+    fn github_release_is_parsed() {
+        // Trimmed `releases/latest` response, which has both `url` and `html_url`
+        let manifest: Manifest = serde_json::from_str(
+            r#"{
+                "url": "https://api.github.com/repos/wuooyun/OServers/releases/178463913",
+                "assets_url": "https://api.github.com/repos/wuooyun/OServers/releases/178463913/assets",
+                "html_url": "https://github.com/wuooyun/OServers/releases/tag/v0.3.0",
+                "id": 178463913,
+                "tag_name": "v0.3.0",
+                "target_commitish": "main",
+                "name": "v0.3.0",
+                "draft": false,
+                "prerelease": false,
+                "created_at": "2024-09-30T12:04:11Z",
+                "published_at": "2024-09-30T12:10:52Z",
+                "assets": [],
+                "body": "Bug fixes"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.version, "v0.3.0");
+        assert_eq!(
+            manifest.download_url(),
+            "https://github.com/wuooyun/OServers/releases/tag/v0.3.0"
+        );
+    }
+
+    #[test]
+    fn own_manifest_is_parsed() {
+        let manifest: Manifest =
+            serde_json::from_str(r#"{"version": "1.2.0", "url": "https://example.com/oservers"}"#)
+                .unwrap();
+        assert_eq!(manifest.version, "1.2.0");
+        assert_eq!(manifest.download_url(), "https://example.com/oservers");
+    }
+}
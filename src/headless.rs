@@ -0,0 +1,461 @@
+//! Running the servers without a window, e.g. in a container
+//!
+//! Servers set to start when OServers launches are started in their
+//! configured order and their log is written to standard output until the
+//! process is interrupted or terminated.
+//!
+//! `oservers test-server` runs a single server with default settings for
+//! integration tests, see [`test_server`].
+
+use crate::config::AppConfig;
+use crate::servers::{
+    LogLevel, LogMessage, ServerConfig, ServerError, ServerState, ServerStatus, ServerType,
+    SharedState, StartupConfig, ftp, http, ssh, tftp,
+};
+use parking_lot::RwLock;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How often server logs are copied to standard output
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long stopping servers get before the process exits anyway
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether to run without a window: asked for with `--headless`, or on
+/// Linux when there is no display to open one on
+#[cfg(feature = "gui")]
+pub fn requested() -> bool {
+    if std::env::args().skip(1).any(|arg| arg == "--headless") {
+        return true;
+    }
+    cfg!(target_os = "linux")
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+}
+
+/// A server started by the headless runner
+struct Headless {
+    server_type: ServerType,
+    state: SharedState,
+    shutdown_tx: mpsc::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+/// Start the auto-start servers from `config` and run until a termination signal
+pub fn run(config: AppConfig) -> std::io::Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async move {
+        let startups: Vec<(ServerType, StartupConfig)> = vec![
+            (ServerType::Http, config.http.startup.clone()),
+            (ServerType::Ftp, config.ftp.startup.clone()),
+            (ServerType::Tftp, config.tftp.startup.clone()),
+            (ServerType::Ssh, config.ssh.startup.clone()),
+        ];
+        let starting: Vec<ServerType> = startups
+            .iter()
+            .filter(|(_, startup)| startup.auto_start)
+            .map(|(server_type, _)| *server_type)
+            .collect();
+        if starting.is_empty() {
+            tracing::error!(
+                "Headless: no server is set to start, enable one with e.g. OSERVERS_HTTP_STARTUP__AUTO_START=true"
+            );
+            return Ok(());
+        }
+
+        let states: Vec<(ServerType, SharedState)> = starting
+            .iter()
+            .map(|server_type| {
+                let config = match server_type {
+                    ServerType::Http => ServerConfig::from(config.http.clone()),
+                    ServerType::Ftp => ServerConfig::from(config.ftp.clone()),
+                    ServerType::Tftp => ServerConfig::from(config.tftp.clone()),
+                    ServerType::Ssh => ServerConfig::from(config.ssh.clone()),
+                };
+                let mut state = ServerState::new(config);
+                // Before any task runs, so dependents see it as coming up
+                state.status = ServerStatus::Starting;
+                (*server_type, Arc::new(RwLock::new(state)))
+            })
+            .collect();
+        let log_task = tokio::spawn(print_logs(states.clone()));
+
+        let mut servers = Vec::new();
+        for (server_type, state) in &states {
+            let startup = startups
+                .iter()
+                .find(|(s, _)| s == server_type)
+                .map(|(_, startup)| startup.clone())
+                .unwrap_or_default();
+            let after: Vec<SharedState> = startup
+                .start_after
+                .iter()
+                .filter(|dep| waits_for(&startups, **dep, *server_type))
+                .filter_map(|dep| {
+                    let found = states.iter().find(|(s, _)| s == dep);
+                    if found.is_none() {
+                        tracing::warn!(
+                            "Headless: {} not waiting for {}, it is not starting",
+                            server_type.label(),
+                            dep.label()
+                        );
+                    }
+                    found.map(|(_, state)| state.clone())
+                })
+                .collect();
+            let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+            let task = tokio::spawn(start(
+                *server_type,
+                config.clone(),
+                state.clone(),
+                after,
+                Duration::from_secs(startup.startup_delay_secs),
+                shutdown_rx,
+            ));
+            servers.push(Headless {
+                server_type: *server_type,
+                state: state.clone(),
+                shutdown_tx,
+                task,
+            });
+        }
+
+        wait_for_signal().await;
+        tracing::info!("Headless: stopping servers");
+        for server in &servers {
+            server.state.write().status = ServerStatus::Stopping;
+            let _ = server.shutdown_tx.try_send(());
+        }
+        for server in servers {
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, server.task)
+                .await
+                .is_err()
+            {
+                tracing::warn!(
+                    "Headless: {} did not stop in time",
+                    server.server_type.label()
+                );
+            }
+        }
+        // Servers that did not stop in time have not written their logs yet
+        crate::servers::flush_all_logs();
+        // One last pass so the final messages make it out
+        tokio::time::sleep(LOG_POLL_INTERVAL).await;
+        log_task.abort();
+        Ok(())
+    })
+}
+
+/// Whether `server` should wait for `dep`, which it should not if `dep`
+/// in turn waits for `server` and the two would never start
+fn waits_for(
+    startups: &[(ServerType, StartupConfig)],
+    dep: ServerType,
+    server: ServerType,
+) -> bool {
+    let mut seen = vec![dep];
+    let mut next = vec![dep];
+    while let Some(current) = next.pop() {
+        let deps = startups
+            .iter()
+            .find(|(s, _)| *s == current)
+            .map(|(_, startup)| startup.start_after.as_slice())
+            .unwrap_or_default();
+        for d in deps {
+            if *d == server {
+                tracing::warn!(
+                    "Headless: ignoring circular start order between {} and {}",
+                    server.label(),
+                    dep.label()
+                );
+                return false;
+            }
+            if !seen.contains(d) {
+                seen.push(*d);
+                next.push(*d);
+            }
+        }
+    }
+    true
+}
+
+/// Start one server once the servers in `after` are up or have failed and `delay` has passed
+async fn start(
+    server_type: ServerType,
+    config: AppConfig,
+    state: SharedState,
+    after: Vec<SharedState>,
+    delay: Duration,
+    mut shutdown_rx: mpsc::Receiver<()>,
+) {
+    let ready = async {
+        while after
+            .iter()
+            .any(|dep| dep.read().status == ServerStatus::Starting)
+        {
+            tokio::time::sleep(LOG_POLL_INTERVAL).await;
+        }
+        if !delay.is_zero() {
+            state.write().add_log(LogMessage::info(format!(
+                "Auto start: starting in {}s",
+                delay.as_secs()
+            )));
+            tokio::time::sleep(delay).await;
+        }
+    };
+    tokio::select! {
+        _ = ready => {}
+        _ = shutdown_rx.recv() => {
+            state.write().status = ServerStatus::Stopped;
+            return;
+        }
+    }
+
+    // Failures are in the server's log
+    let _ = serve(server_type, config, state, shutdown_rx).await;
+}
+
+/// Run the server of `server_type` until it stops
+async fn serve(
+    server_type: ServerType,
+    config: AppConfig,
+    state: SharedState,
+    shutdown_rx: mpsc::Receiver<()>,
+) -> Result<(), ServerError> {
+    match server_type {
+        ServerType::Http => http::start_server(config.http, state, shutdown_rx).await,
+        ServerType::Ftp => ftp::start_server(config.ftp, state, shutdown_rx).await,
+        ServerType::Tftp => tftp::start_server(config.tftp, state, shutdown_rx).await,
+        ServerType::Ssh => ssh::start_server(config.ssh, state, shutdown_rx).await,
+    }
+}
+
+const TEST_SERVER_USAGE: &str = "usage: oservers test-server --type http|ftp|tftp|ssh [--port N] [--root DIR] [--duration SECS]";
+
+/// Options of `oservers test-server`
+struct TestServerArgs {
+    server_type: ServerType,
+    port: u16,
+    root: PathBuf,
+    duration: Option<Duration>,
+}
+
+impl TestServerArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut server_type = None;
+        let mut port = 0;
+        let mut root = std::env::current_dir().unwrap_or_default();
+        let mut duration = None;
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", arg))?;
+            match arg.as_str() {
+                "--type" => {
+                    server_type = Some(match value.to_lowercase().as_str() {
+                        "http" => ServerType::Http,
+                        "ftp" => ServerType::Ftp,
+                        "tftp" => ServerType::Tftp,
+                        "ssh" => ServerType::Ssh,
+                        _ => return Err(format!("unknown server type '{}'", value)),
+                    })
+                }
+                "--port" => {
+                    port = value
+                        .parse()
+                        .map_err(|_| format!("invalid port '{}'", value))?
+                }
+                "--root" => root = PathBuf::from(value),
+                "--duration" => {
+                    let secs = value
+                        .parse()
+                        .map_err(|_| format!("invalid duration '{}'", value))?;
+                    duration = Some(Duration::from_secs(secs));
+                }
+                _ => return Err(format!("unknown option '{}'", arg)),
+            }
+        }
+        Ok(Self {
+            server_type: server_type.ok_or("--type is required")?,
+            port,
+            root,
+            duration,
+        })
+    }
+
+    /// Default settings with the port and root directory given, without
+    /// auto stop so only `duration` ends the server
+    fn config(&self) -> AppConfig {
+        let mut config = AppConfig::default();
+        config.http.root_dir = self.root.clone();
+        config.http.port = self.port;
+        config.http.auto_stop_seconds = None;
+        config.ftp.root_dir = self.root.clone();
+        config.ftp.port = self.port;
+        config.ftp.auto_stop_seconds = None;
+        config.tftp.root_dir = self.root.clone();
+        config.tftp.port = self.port;
+        config.tftp.auto_stop_seconds = None;
+        config.ssh.root_dir = self.root.clone();
+        config.ssh.port = self.port;
+        config.ssh.auto_stop_seconds = None;
+        config
+    }
+}
+
+/// Run one server for an integration test harness and return the exit code
+///
+/// Once the server is up `{"port": N}` is printed to standard output with
+/// the port it listens on. It then serves until `--duration` has passed or
+/// a termination signal arrives. The exit code is 0 after a normal
+/// shutdown, 1 when the server failed and 2 for invalid arguments.
+pub fn test_server(args: impl Iterator<Item = String>) -> i32 {
+    let args = match TestServerArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("test-server: {}\n{}", e, TEST_SERVER_USAGE);
+            return 2;
+        }
+    };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            tracing::error!("Failed to create tokio runtime: {}", e);
+            return 1;
+        }
+    };
+    runtime.block_on(async move {
+        let config = args.config();
+        let server_config = match args.server_type {
+            ServerType::Http => ServerConfig::from(config.http.clone()),
+            ServerType::Ftp => ServerConfig::from(config.ftp.clone()),
+            ServerType::Tftp => ServerConfig::from(config.tftp.clone()),
+            ServerType::Ssh => ServerConfig::from(config.ssh.clone()),
+        };
+        let state: SharedState = Arc::new(RwLock::new(ServerState::new(server_config)));
+        let log_task = tokio::spawn(print_logs(vec![(args.server_type, state.clone())]));
+        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let mut task = tokio::spawn(serve(args.server_type, config, state.clone(), shutdown_rx));
+
+        // The server may also fail or stop before it is up
+        let up = async {
+            while !matches!(state.read().status, ServerStatus::Running) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        let finished = tokio::select! {
+            _ = up => None,
+            result = &mut task => Some(result),
+        };
+        let bound_port = state.read().bound_port;
+        let finished = match (finished, bound_port) {
+            (Some(result), _) => Some(result),
+            (None, Some(port)) => {
+                println!("{}", serde_json::json!({ "port": port }));
+                let serving = async {
+                    match args.duration {
+                        Some(duration) => tokio::time::sleep(duration).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    _ = serving => None,
+                    _ = wait_for_signal() => None,
+                    result = &mut task => Some(result),
+                }
+            }
+            (None, None) => {
+                tracing::error!(
+                    "Test server: the port the OS assigned to {} is unknown, pass --port",
+                    args.server_type.label()
+                );
+                let _ = shutdown_tx.try_send(());
+                let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut task).await;
+                tokio::time::sleep(LOG_POLL_INTERVAL).await;
+                return 1;
+            }
+        };
+        let result = match finished {
+            Some(result) => result,
+            None => {
+                let _ = shutdown_tx.try_send(());
+                match tokio::time::timeout(SHUTDOWN_TIMEOUT, task).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        tracing::error!("Test server: server did not stop in time");
+                        return 1;
+                    }
+                }
+            }
+        };
+        // One last pass so the final messages make it out
+        tokio::time::sleep(LOG_POLL_INTERVAL).await;
+        log_task.abort();
+        match result {
+            Ok(Ok(())) => 0,
+            Ok(Err(e)) => {
+                tracing::error!("Test server: {}", e);
+                1
+            }
+            Err(e) => {
+                tracing::error!("Test server: server task failed: {}", e);
+                1
+            }
+        }
+    })
+}
+
+/// Copy new server log messages to standard output through `tracing`
+async fn print_logs(states: Vec<(ServerType, SharedState)>) {
+    let mut printed = vec![None; states.len()];
+    loop {
+        for ((server_type, state), last) in states.iter().zip(printed.iter_mut()) {
+            let state = state.read();
+            for msg in state
+                .logs
+                .iter()
+                .filter(|msg| last.is_none_or(|last| msg.timestamp > last))
+            {
+                let label = server_type.label();
+                match msg.level {
+                    LogLevel::Debug => tracing::debug!("[{}] {}", label, msg.message),
+                    LogLevel::Info => tracing::info!("[{}] {}", label, msg.message),
+                    LogLevel::Warning => tracing::warn!("[{}] {}", label, msg.message),
+                    LogLevel::Error => tracing::error!("[{}] {}", label, msg.message),
+                }
+            }
+            if let Some(msg) = state.logs.last() {
+                *last = Some(msg.timestamp);
+            }
+        }
+        tokio::time::sleep(LOG_POLL_INTERVAL).await;
+    }
+}
+
+/// Wait for Ctrl+C, or on Unix also for SIGTERM as sent by `docker stop`
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Headless: cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
@@ -0,0 +1,6 @@
+//! OServers server implementations
+//!
+//! The application binary builds on this library, and the benchmarks in
+//! `benches/` drive the servers through it.
+
+pub mod servers;
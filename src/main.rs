@@ -5,23 +5,64 @@
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+// Without the GUI settings are only read, never changed or saved
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
 mod config;
 mod gui;
-mod servers;
+mod headless;
 
+#[cfg(feature = "gui")]
 use gui::app::OServersApp;
+use oservers::servers;
+#[cfg(feature = "gui")]
+use std::path::PathBuf;
 
-fn main() -> eframe::Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
-        )
-        .init();
+fn main() {
+    let test_server = std::env::args().nth(1).as_deref() == Some("test-server");
+
+    // Initialize logging, a test server keeps standard output for its port
+    let subscriber = tracing_subscriber::fmt().with_env_filter(
+        tracing_subscriber::EnvFilter::from_default_env()
+            .add_directive(tracing::Level::INFO.into()),
+    );
+    if test_server {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+
+    if test_server {
+        std::process::exit(headless::test_server(std::env::args().skip(2)));
+    }
 
     tracing::info!("Starting OServers application");
 
+    // Built without the GUI, OServers always runs headless
+    #[cfg(feature = "gui")]
+    if !headless::requested() {
+        if let Err(e) = run_gui() {
+            tracing::error!("Failed to open the window: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    tracing::info!("Running headless, no display available or --headless given");
+    if let Err(e) = headless::run(config::AppConfig::load()) {
+        tracing::error!("Failed to create tokio runtime: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "gui")]
+fn run_gui() -> eframe::Result<()> {
+    // A file passed on the command line (e.g. "Open with OServers") is shared right away
+    let share_file = std::env::args_os()
+        .skip(1)
+        .find(|arg| arg != "--headless")
+        .map(PathBuf::from)
+        .filter(|path| path.is_file());
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([800.0, 600.0])
@@ -33,10 +74,11 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "OServers - Server Management",
         native_options,
-        Box::new(|cc| Ok(Box::new(OServersApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(OServersApp::new(cc, share_file)))),
     )
 }
 
+#[cfg(feature = "gui")]
 fn load_icon() -> egui::IconData {
     // Simple default icon - could be replaced with a custom icon
     let size = 32;
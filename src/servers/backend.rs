@@ -0,0 +1,165 @@
+//! Storage backends the servers read shared files from
+//!
+//! Paths handed to a backend are relative to the root of the share. The
+//! local filesystem is the only backend for now, others (an S3 bucket, an
+//! in-memory set of files) only need to implement [`StorageBackend`].
+
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Type, size and modification time of a file or directory
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub is_dir: bool,
+    /// Size in bytes, 0 for directories
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Entry of a directory listing
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// Metadata of the entry, of the target for symbolic links
+    pub metadata: Metadata,
+    /// Whether the entry is a link, which may point outside the share
+    pub is_symlink: bool,
+}
+
+/// Source of the files a server shares
+pub trait StorageBackend: Send + Sync {
+    fn stat(&self, path: &Path) -> std::io::Result<Metadata>;
+
+    /// Entries of the directory at `path`, in no particular order
+    fn list(&self, path: &Path) -> std::io::Result<Vec<DirEntry>>;
+
+    /// Open the file at `path` for reading
+    fn read(&self, path: &Path) -> std::io::Result<Box<dyn Read + Send>>;
+
+    /// Create or truncate the file at `path` for writing
+    #[allow(dead_code)]
+    fn write(&self, path: &Path) -> std::io::Result<Box<dyn Write + Send>>;
+
+    /// Directory the share lives in if it is on the local filesystem
+    ///
+    /// Lets servers hand local files to code that needs a real path,
+    /// e.g. for range requests.
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Files below a directory on the local filesystem
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+impl LocalFs {
+    /// Files below `root`, resolved once so a symlinked root works
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            root: root.canonicalize().unwrap_or(root),
+        }
+    }
+
+    /// Local path of `path`, refusing paths that would leave the root
+    ///
+    /// Symbolic links are followed as long as they lead to a location
+    /// inside the root.
+    fn resolve(&self, path: &Path) -> std::io::Result<PathBuf> {
+        let leaves_root = || {
+            std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "path leaves the shared directory",
+            )
+        };
+        let mut resolved = self.root.clone();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::RootDir | Component::CurDir => {}
+                Component::ParentDir | Component::Prefix(_) => return Err(leaves_root()),
+            }
+        }
+        match resolved.canonicalize() {
+            Ok(real) if !real.starts_with(&self.root) => Err(leaves_root()),
+            _ => Ok(resolved),
+        }
+    }
+}
+
+fn metadata(m: &std::fs::Metadata) -> Metadata {
+    Metadata {
+        is_dir: m.is_dir(),
+        size: if m.is_dir() { 0 } else { m.len() },
+        modified: m.modified().ok(),
+    }
+}
+
+impl StorageBackend for LocalFs {
+    fn stat(&self, path: &Path) -> std::io::Result<Metadata> {
+        Ok(metadata(&std::fs::metadata(self.resolve(path)?)?))
+    }
+
+    fn list(&self, path: &Path) -> std::io::Result<Vec<DirEntry>> {
+        let entries = std::fs::read_dir(self.resolve(path)?)?
+            .filter_map(|e| e.ok())
+            .filter_map(|entry| {
+                let is_symlink = entry.file_type().ok()?.is_symlink();
+                // Broken links are listed with the metadata of the link itself
+                let target = std::fs::metadata(entry.path()).or_else(|_| entry.metadata());
+                Some(DirEntry {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    metadata: metadata(&target.ok()?),
+                    is_symlink,
+                })
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(std::fs::File::open(self.resolve(path)?)?))
+    }
+
+    fn write(&self, path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(std::fs::File::create(self.resolve(path)?)?))
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalFs;
+    use std::path::Path;
+
+    /// A root reached through a symbolic link holding a file and a link
+    /// to a file outside of it
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_root_keeps_links_inside() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let real_root = dir.path().join("share");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir(&real_root).unwrap();
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(real_root.join("inside.txt"), "inside").unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(outside.join("secret.txt"), real_root.join("escape.txt")).unwrap();
+        let linked_root = dir.path().join("linked");
+        symlink(&real_root, &linked_root).unwrap();
+
+        let fs = LocalFs::new(&linked_root);
+        assert!(fs.resolve(Path::new("inside.txt")).is_ok());
+        let err = fs.resolve(Path::new("escape.txt")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(fs.resolve(Path::new("../outside/secret.txt")).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Reverse DNS and GeoIP annotations for client addresses in the log
+//!
+//! Lookups run on the blocking pool and annotate the log entry once they
+//! finish, so request handling never waits for them.
+
+use super::{LogMessage, SharedState};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Most client addresses whose lookup result is kept
+const MAX_CACHED_CLIENTS: usize = 10_000;
+
+enum Lookup {
+    /// Log messages waiting for the lookup in flight
+    Pending(Vec<String>),
+    /// Host name and/or country, empty if nothing was found
+    Done(String),
+}
+
+/// Annotates log entries with the client's host name and country
+pub struct ClientLookup {
+    reverse_dns: bool,
+    geoip: Option<maxminddb::Reader<Vec<u8>>>,
+    cache: Mutex<HashMap<IpAddr, Lookup>>,
+}
+
+impl ClientLookup {
+    /// Set up lookups, opening the MaxMind database at `geoip_db` if given
+    pub fn new(
+        reverse_dns: bool,
+        geoip_db: Option<&Path>,
+    ) -> Result<Arc<Self>, maxminddb::MaxMindDBError> {
+        let geoip = geoip_db.map(maxminddb::Reader::open_readfile).transpose()?;
+        Ok(Arc::new(Self {
+            reverse_dns,
+            geoip,
+            cache: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Host name and country of `ip`, blocking on the DNS lookup
+    fn resolve(&self, ip: IpAddr) -> String {
+        let mut parts = Vec::new();
+        if self.reverse_dns {
+            // Without a PTR record the address itself comes back
+            let host = dns_lookup::lookup_addr(&ip)
+                .ok()
+                .filter(|host| *host != ip.to_string());
+            parts.extend(host);
+        }
+        let country = self.geoip.as_ref().and_then(|reader| {
+            let country: maxminddb::geoip2::Country = reader.lookup(ip).ok()?;
+            Some(country.country?.iso_code?.to_string())
+        });
+        parts.extend(country);
+        parts.join(", ")
+    }
+
+    /// Log `message` about a request from `ip`, annotated once the lookup finished
+    pub fn log(self: &Arc<Self>, ip: IpAddr, message: String, state: &SharedState) {
+        let mut cache = self.cache.lock();
+        match cache.get_mut(&ip) {
+            Some(Lookup::Done(note)) => {
+                state
+                    .write()
+                    .add_log(LogMessage::info(annotated(&message, note)));
+                return;
+            }
+            Some(Lookup::Pending(waiting)) => waiting.push(message.clone()),
+            None => {
+                if cache.len() >= MAX_CACHED_CLIENTS {
+                    cache.retain(|_, lookup| matches!(lookup, Lookup::Pending(_)));
+                }
+                cache.insert(ip, Lookup::Pending(vec![message.clone()]));
+                let lookup = self.clone();
+                let state = state.clone();
+                tokio::task::spawn_blocking(move || {
+                    let note = lookup.resolve(ip);
+                    let waiting = lookup.cache.lock().insert(ip, Lookup::Done(note.clone()));
+                    if let Some(Lookup::Pending(waiting)) = waiting {
+                        let mut s = state.write();
+                        for message in waiting {
+                            s.replace_log(&message, annotated(&message, &note));
+                        }
+                    }
+                });
+            }
+        }
+        // Still holding the cache so the lookup cannot finish before the entry exists
+        state.write().add_log(LogMessage::info(message));
+    }
+}
+
+fn annotated(message: &str, note: &str) -> String {
+    if note.is_empty() {
+        message.to_string()
+    } else {
+        format!("{} [{}]", message, note)
+    }
+}
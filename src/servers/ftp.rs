@@ -1,15 +1,154 @@
 //! FTP Server implementation using libunftp
 
-use super::{LogMessage, ServerConfig, ServerError, ServerHandle, ServerStatus, SharedState};
-use libunftp::auth::DefaultUser;
-use libunftp::options::ActivePassiveMode;
-use std::path::PathBuf;
+use super::mdns;
+use super::startup_report;
+use super::transfer_notify::{ClientIps, NotificationConfig, NotificationMode, TransferNotifier};
+use super::{
+    LogMessage, OverwritePolicy, RestartPolicy, ServerConfig, ServerError, ServerHandle,
+    ServerStatus, SharedState, StartupConfig, TransferGuard, export_log_on_stop, finish_log_export,
+    wait_for_root, wait_for_shutdown,
+};
+use libunftp::auth::UserDetail;
+use libunftp::options::{ActivePassiveMode, PassiveHost};
+use libunftp::storage::{self, ErrorKind, Fileinfo, Metadata, StorageBackend};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::net::{TcpStream, UdpSocket};
 use tokio::sync::mpsc;
-use unftp_sbe_fs::ServerExt;
+use unftp_sbe_fs::Filesystem;
+
+/// Time allowed for a loopback login test
+const TEST_LOGIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between LAN discovery broadcasts
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Directory below the root that deleted files are moved to with safe delete
+const TRASH_DIR: &str = ".trash";
+
+/// Interval between sweeps removing expired items from the trash
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Compression applied to uploaded files as they are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CompressionAlgorithm {
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+impl CompressionAlgorithm {
+    pub const ALL: [CompressionAlgorithm; 3] = [
+        CompressionAlgorithm::Gzip,
+        CompressionAlgorithm::Zstd,
+        CompressionAlgorithm::Lz4,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Zstd => "zstd",
+            CompressionAlgorithm::Lz4 => "lz4",
+        }
+    }
+
+    /// Extension appended to the names of compressed files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Gzip => "gz",
+            CompressionAlgorithm::Zstd => "zst",
+            CompressionAlgorithm::Lz4 => "lz4",
+        }
+    }
+
+    /// `path` with this algorithm's extension appended, `a.txt` becomes `a.txt.gz`
+    fn compressed_path(&self, path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".");
+        name.push(self.extension());
+        PathBuf::from(name)
+    }
+
+    fn encoder(
+        &self,
+        input: impl AsyncRead + Send + Sync + Unpin + 'static,
+    ) -> Box<dyn AsyncRead + Send + Sync + Unpin> {
+        use async_compression::tokio::bufread::{GzipEncoder, Lz4Encoder, ZstdEncoder};
+        let input = BufReader::new(input);
+        match self {
+            CompressionAlgorithm::Gzip => Box::new(GzipEncoder::new(input)),
+            CompressionAlgorithm::Zstd => Box::new(ZstdEncoder::new(input)),
+            CompressionAlgorithm::Lz4 => Box::new(Lz4Encoder::new(input)),
+        }
+    }
+
+    fn decoder(
+        &self,
+        input: Box<dyn AsyncRead + Send + Sync + Unpin>,
+    ) -> Box<dyn AsyncRead + Send + Sync + Unpin> {
+        use async_compression::tokio::bufread::{GzipDecoder, Lz4Decoder, ZstdDecoder};
+        let input = BufReader::new(input);
+        match self {
+            CompressionAlgorithm::Gzip => Box::new(GzipDecoder::new(input)),
+            CompressionAlgorithm::Zstd => Box::new(ZstdDecoder::new(input)),
+            CompressionAlgorithm::Lz4 => Box::new(Lz4Decoder::new(input)),
+        }
+    }
+}
+
+/// Reader counting the bytes passing through it
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        this.count.fetch_add(read as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Reader reporting the bytes passing through it to the live transfers view
+struct TrackedReader<R> {
+    inner: R,
+    transfer: TransferGuard,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TrackedReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        this.transfer.add((buf.filled().len() - before) as u64);
+        result
+    }
+}
 
 /// FTP server specific configuration
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct FtpConfig {
     pub root_dir: PathBuf,
     pub port: u16,
@@ -18,6 +157,39 @@ pub struct FtpConfig {
     pub anonymous_access: bool,
     pub passive_mode: bool,
     pub passive_ports: (u16, u16),
+    /// Address passive replies send clients to, an IPv4 address or a host
+    /// name, instead of the one the client reached the server on
+    pub passive_host: Option<String>,
+    /// Periodically announce the server on the LAN via UDP broadcast
+    pub enable_discovery_broadcast: bool,
+    /// UDP port the discovery announcements are sent to
+    pub discovery_port: u16,
+    /// Announce the server on the LAN as a DNS-SD `_ftp._tcp` service
+    pub mdns_announce: bool,
+    /// How uploads to existing files are handled
+    pub overwrite_policy: OverwritePolicy,
+    /// Compress uploads as they are stored, adding the algorithm's extension; resumed uploads are refused
+    pub compress_uploads: Option<CompressionAlgorithm>,
+    /// Serve a compressed upload decompressed when its original name is requested
+    pub decompress_on_download: bool,
+    /// Stop the server after this many seconds without client activity
+    pub auto_stop_seconds: Option<u64>,
+    /// Move deleted files to `.trash` in the root directory instead of removing them
+    pub safe_delete: bool,
+    /// Days deleted files stay in the trash, 0 keeps them until removed by hand
+    pub trash_retention_days: u64,
+    /// Report finished transfers by email or webhook
+    pub transfer_notifications: Option<NotificationConfig>,
+    /// Reply texts replacing the standard ones, keyed by reply code
+    pub custom_messages: HashMap<u16, String>,
+    /// Free-form markdown notes about this server
+    pub notes: String,
+    /// Directory the session log is written to when the server stops
+    pub export_log_on_stop: Option<PathBuf>,
+    /// Auto-start, ordering and waiting for the root directory
+    pub startup: StartupConfig,
+    /// Restarting the server when it stops on its own
+    pub restart: RestartPolicy,
 }
 
 impl Default for FtpConfig {
@@ -30,6 +202,22 @@ impl Default for FtpConfig {
             anonymous_access: true,
             passive_mode: true,
             passive_ports: (50000, 50100),
+            passive_host: None,
+            enable_discovery_broadcast: false,
+            discovery_port: 21210,
+            mdns_announce: false,
+            overwrite_policy: OverwritePolicy::Allow,
+            compress_uploads: None,
+            decompress_on_download: false,
+            auto_stop_seconds: None,
+            safe_delete: false,
+            trash_retention_days: 30,
+            transfer_notifications: None,
+            custom_messages: HashMap::new(),
+            notes: String::new(),
+            export_log_on_stop: None,
+            startup: StartupConfig::default(),
+            restart: RestartPolicy::default(),
         }
     }
 }
@@ -39,41 +227,620 @@ impl From<FtpConfig> for ServerConfig {
         ServerConfig {
             root_dir: cfg.root_dir,
             port: cfg.port,
-            auto_stop_seconds: None,
+            auto_stop_seconds: cfg.auto_stop_seconds,
+            egress_throttle_kbps: None,
         }
     }
 }
 
+/// Reply code of the greeting sent when a client connects
+const GREETING_REPLY: u16 = 220;
+
+/// A custom reply text on a single line, so it cannot inject further replies
+fn sanitize_reply(text: &str) -> String {
+    text.replace(['\r', '\n'], " ").trim().to_string()
+}
+
+/// `text` as the `&'static str` libunftp takes for the greeting
+///
+/// Each distinct text is leaked once and reused, so restarting the server
+/// does not leak it again.
+fn static_greeting(text: &str) -> &'static str {
+    static GREETINGS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    let mut greetings = GREETINGS.lock();
+    match greetings.iter().find(|&&greeting| greeting == text) {
+        Some(greeting) => greeting,
+        None => {
+            let greeting: &'static str = Box::leak(text.to_string().into_boxed_str());
+            greetings.push(greeting);
+            greeting
+        }
+    }
+}
+
+/// A logged in FTP user, displayed as the name they logged in with
+///
+/// libunftp's `DefaultUser` carries no name, leaving the storage backend
+/// unable to tell users apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FtpUser {
+    username: String,
+}
+
+impl std::fmt::Display for FtpUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.username)
+    }
+}
+
+impl UserDetail for FtpUser {}
+
 /// Simple authenticator for FTP
 #[derive(Debug, Clone)]
 struct SimpleAuthenticator {
     username: String,
     password: String,
     allow_anonymous: bool,
+    /// Where each user logged in from, for transfer notifications
+    client_ips: ClientIps,
+}
+
+impl SimpleAuthenticator {
+    fn logged_in(&self, username: &str, creds: &libunftp::auth::Credentials) -> FtpUser {
+        self.client_ips
+            .lock()
+            .insert(username.to_string(), creds.source_ip);
+        FtpUser {
+            username: username.to_string(),
+        }
+    }
 }
 
 #[async_trait::async_trait]
-impl libunftp::auth::Authenticator<DefaultUser> for SimpleAuthenticator {
+impl libunftp::auth::Authenticator<FtpUser> for SimpleAuthenticator {
     async fn authenticate(
         &self,
         username: &str,
         creds: &libunftp::auth::Credentials,
-    ) -> Result<DefaultUser, libunftp::auth::AuthenticationError> {
+    ) -> Result<FtpUser, libunftp::auth::AuthenticationError> {
         // Allow anonymous if enabled
         if self.allow_anonymous && username == "anonymous" {
-            return Ok(DefaultUser);
+            return Ok(self.logged_in(username, creds));
         }
 
         // Check username and password
         if let Some(password) = creds.password.as_ref() {
             if username == self.username && password == &self.password {
-                return Ok(DefaultUser);
+                return Ok(self.logged_in(username, creds));
             }
         }
         Err(libunftp::auth::AuthenticationError::BadPassword)
     }
 }
 
+/// Announce the server on the LAN every [`DISCOVERY_INTERVAL`] until cancelled
+async fn discovery_broadcast(discovery_port: u16, ftp_port: u16, state: SharedState) {
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            state.write().add_log(LogMessage::warning(format!(
+                "Discovery broadcast disabled: {}",
+                e
+            )));
+            return std::future::pending().await;
+        }
+    };
+    if let Err(e) = socket.set_broadcast(true) {
+        state.write().add_log(LogMessage::warning(format!(
+            "Discovery broadcast disabled: {}",
+            e
+        )));
+        return std::future::pending().await;
+    }
+
+    let host = hostname::get()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let target = SocketAddr::from((Ipv4Addr::BROADCAST, discovery_port));
+    let mut interval = tokio::time::interval(DISCOVERY_INTERVAL);
+    let mut reported_error = false;
+
+    loop {
+        interval.tick().await;
+        // Resolve the LAN address on every tick in case the network changed
+        let payload = serde_json::json!({
+            "service": "ftp",
+            "host": host,
+            "ip": super::local_ip().map(|ip| ip.to_string()),
+            "port": ftp_port,
+        });
+        match socket.send_to(payload.to_string().as_bytes(), target).await {
+            Ok(_) => reported_error = false,
+            Err(e) if !reported_error => {
+                reported_error = true;
+                state.write().add_log(LogMessage::warning(format!(
+                    "Discovery broadcast failed: {}",
+                    e
+                )));
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Storage backend applying the server's upload policies on top of another backend
+struct PolicyStorage<S> {
+    inner: Arc<S>,
+    overwrite_policy: OverwritePolicy,
+    compress_uploads: Option<CompressionAlgorithm>,
+    decompress_on_download: bool,
+    /// Trash directory on disk when deleted files are kept
+    trash: Option<PathBuf>,
+    /// Where each user logged in from, to show with their transfers
+    client_ips: ClientIps,
+    state: SharedState,
+}
+
+// Not derived, that would require `S: Clone`
+impl<S> Clone for PolicyStorage<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            overwrite_policy: self.overwrite_policy,
+            compress_uploads: self.compress_uploads,
+            decompress_on_download: self.decompress_on_download,
+            trash: self.trash.clone(),
+            client_ips: self.client_ips.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S: Debug> Debug for PolicyStorage<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PolicyStorage")
+            .field("inner", &self.inner)
+            .field("overwrite_policy", &self.overwrite_policy)
+            .field("compress_uploads", &self.compress_uploads)
+            .field("decompress_on_download", &self.decompress_on_download)
+            .field("trash", &self.trash)
+            .finish()
+    }
+}
+
+impl<S> PolicyStorage<S> {
+    fn record_activity(&self) {
+        self.state.write().record_activity();
+    }
+
+    /// Address `user` logged in from, for the transfers view
+    fn client(&self, user: &impl UserDetail) -> String {
+        self.client_ips
+            .lock()
+            .get(&user.to_string())
+            .map_or_else(|| "unknown".to_string(), IpAddr::to_string)
+    }
+
+    /// First `name (n).ext` next to `path` for which `exists` is false
+    async fn free_name<F, Fut>(path: &Path, exists: F) -> PathBuf
+    where
+        F: Fn(PathBuf) -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        let mut n = 1;
+        loop {
+            let candidate = path.with_file_name(format!("{} ({}){}", stem, n, ext));
+            if !exists(candidate.clone()).await {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S, User> StorageBackend<User> for PolicyStorage<S>
+where
+    S: StorageBackend<User>,
+    User: UserDetail,
+{
+    type Metadata = S::Metadata;
+
+    fn supported_features(&self) -> u32 {
+        self.inner.supported_features()
+    }
+
+    async fn metadata<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        path: P,
+    ) -> storage::Result<Self::Metadata> {
+        self.record_activity();
+        self.inner.metadata(user, path).await
+    }
+
+    async fn list<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        path: P,
+    ) -> storage::Result<Vec<Fileinfo<PathBuf, Self::Metadata>>>
+    where
+        <Self as StorageBackend<User>>::Metadata: storage::Metadata,
+    {
+        self.record_activity();
+        self.inner.list(user, path).await
+    }
+
+    async fn get<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        path: P,
+        start_pos: u64,
+    ) -> storage::Result<Box<dyn tokio::io::AsyncRead + Send + Sync + Unpin>> {
+        self.record_activity();
+        let path = path.as_ref();
+        let metadata = self.inner.metadata(user, path).await;
+        let mut compressed = None;
+        if self.decompress_on_download && metadata.is_err() {
+            for algorithm in CompressionAlgorithm::ALL {
+                let candidate = algorithm.compressed_path(path);
+                if self.inner.metadata(user, &candidate).await.is_ok() {
+                    compressed = Some((algorithm, candidate));
+                    break;
+                }
+            }
+        }
+        let reader: Box<dyn tokio::io::AsyncRead + Send + Sync + Unpin> = match compressed {
+            Some((algorithm, compressed)) => {
+                let mut reader = algorithm.decoder(self.inner.get(user, &compressed, 0).await?);
+                // Offsets of resumed downloads refer to the decompressed content
+                if start_pos > 0 {
+                    tokio::io::copy(&mut (&mut reader).take(start_pos), &mut tokio::io::sink())
+                        .await
+                        .map_err(|e| storage::Error::new(ErrorKind::LocalError, e))?;
+                }
+                Box::new(reader)
+            }
+            None => self.inner.get(user, path, start_pos).await?,
+        };
+        // The decompressed size of a compressed upload is not known
+        let total = metadata.ok().map(|m| m.len().saturating_sub(start_pos));
+        let transfer = TransferGuard::start(
+            &self.state,
+            self.client(user),
+            path.display().to_string(),
+            false,
+            total,
+        );
+        Ok(Box::new(TrackedReader {
+            inner: reader,
+            transfer,
+        }))
+    }
+
+    async fn put<
+        P: AsRef<Path> + Send + Debug,
+        R: tokio::io::AsyncRead + Send + Sync + Unpin + 'static,
+    >(
+        &self,
+        user: &User,
+        input: R,
+        path: P,
+        start_pos: u64,
+    ) -> storage::Result<u64> {
+        self.record_activity();
+        let mut path = path.as_ref().to_path_buf();
+        let input = TrackedReader {
+            inner: input,
+            transfer: TransferGuard::start(
+                &self.state,
+                self.client(user),
+                path.display().to_string(),
+                true,
+                None,
+            ),
+        };
+        // A resumed upload cannot be appended to a compressed stream at a raw offset
+        let compression = self.compress_uploads;
+        if let Some(algorithm) = compression {
+            if start_pos > 0 {
+                self.state.write().add_log(LogMessage::error(format!(
+                    "Resumed upload to {} refused: uploads are stored compressed",
+                    path.display()
+                )));
+                return Err(ErrorKind::CommandNotImplemented.into());
+            }
+            path = algorithm.compressed_path(&path);
+        }
+
+        // Resumed uploads (REST) intentionally write into an existing file
+        if start_pos == 0 && self.overwrite_policy != OverwritePolicy::Allow {
+            let exists = self.inner.metadata(user, &path).await.is_ok();
+            if exists && self.overwrite_policy == OverwritePolicy::Deny {
+                self.state.write().add_log(LogMessage::error(format!(
+                    "Upload to existing file {} denied",
+                    path.display()
+                )));
+                return Err(ErrorKind::FileNameNotAllowedError.into());
+            }
+            if exists {
+                let renamed = Self::free_name(&path, |candidate| async move {
+                    self.inner.metadata(user, candidate).await.is_ok()
+                })
+                .await;
+                self.state.write().add_log(LogMessage::info(format!(
+                    "Upload to existing file {} stored as {}",
+                    path.display(),
+                    renamed.display()
+                )));
+                path = renamed;
+            }
+        }
+        let result = match compression {
+            Some(algorithm) => {
+                let original = Arc::new(AtomicU64::new(0));
+                let input = CountingReader {
+                    inner: input,
+                    count: original.clone(),
+                };
+                let result = self
+                    .inner
+                    .put(user, algorithm.encoder(input), &path, 0)
+                    .await;
+                if let Ok(compressed) = &result {
+                    let original = original.load(Ordering::Relaxed);
+                    let ratio = if original == 0 {
+                        100.0
+                    } else {
+                        *compressed as f64 / original as f64 * 100.0
+                    };
+                    self.state.write().add_log(LogMessage::info(format!(
+                        "Stored {} with {}: {} bytes compressed to {} ({:.1}%)",
+                        path.display(),
+                        algorithm.name(),
+                        original,
+                        compressed,
+                        ratio
+                    )));
+                }
+                // The client sent the uncompressed size
+                result.map(|_| original.load(Ordering::Relaxed))
+            }
+            None => self.inner.put(user, input, path, start_pos).await,
+        };
+        // A long upload counts as activity until it ends
+        self.record_activity();
+        result
+    }
+
+    async fn del<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        path: P,
+    ) -> storage::Result<()> {
+        self.record_activity();
+        let path = path.as_ref();
+        let trash_root = Path::new("/").join(TRASH_DIR);
+        // Deleting from the trash itself is final
+        let Some(trash) = self
+            .trash
+            .as_ref()
+            .filter(|_| !path.starts_with(&trash_root))
+        else {
+            return self.inner.del(user, path).await;
+        };
+
+        let mut target = trash_root.join(trash_name(path));
+        if self.inner.metadata(user, &target).await.is_ok() {
+            target = Self::free_name(&target, |candidate| async move {
+                self.inner.metadata(user, candidate).await.is_ok()
+            })
+            .await;
+        }
+        self.inner
+            .rename(user, path.to_path_buf(), target.clone())
+            .await?;
+        // Retention counts from the deletion, the move kept the file's own time
+        if let Some(name) = target.file_name() {
+            let trashed = trash.join(name);
+            let _ = tokio::task::spawn_blocking(move || {
+                std::fs::File::options()
+                    .write(true)
+                    .open(trashed)
+                    .and_then(|file| file.set_modified(SystemTime::now()))
+            })
+            .await;
+        }
+        self.state.write().add_log(LogMessage::info(format!(
+            "Deleted {}, moved to {}",
+            path.display(),
+            target.display()
+        )));
+        Ok(())
+    }
+
+    async fn mkd<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        path: P,
+    ) -> storage::Result<()> {
+        self.record_activity();
+        self.inner.mkd(user, path).await
+    }
+
+    async fn rename<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        from: P,
+        to: P,
+    ) -> storage::Result<()> {
+        self.record_activity();
+        self.inner.rename(user, from, to).await
+    }
+
+    async fn rmd<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        path: P,
+    ) -> storage::Result<()> {
+        self.record_activity();
+        self.inner.rmd(user, path).await
+    }
+
+    async fn cwd<P: AsRef<Path> + Send + Debug>(
+        &self,
+        user: &User,
+        path: P,
+    ) -> storage::Result<()> {
+        self.record_activity();
+        self.inner.cwd(user, path).await
+    }
+}
+
+/// Name of a deleted file in the trash, `name.<deletion time>.ext`
+fn trash_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    format!(
+        "{}.{}{}",
+        stem,
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        ext
+    )
+}
+
+/// Remove items deleted more than `retention_days` ago from the trash, every hour
+async fn purge_trash(dir: PathBuf, retention_days: u64, state: SharedState) {
+    let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let dir = dir.clone();
+        let state = state.clone();
+        // Removing a large trashed directory can take a while
+        let _ =
+            tokio::task::spawn_blocking(move || purge_expired(&dir, retention_days, &state)).await;
+    }
+}
+
+/// One pass of [`purge_trash`], blocking
+fn purge_expired(dir: &Path, retention_days: u64, state: &SharedState) {
+    let retention = Duration::from_secs(retention_days * 24 * 3600);
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > retention);
+        if !expired {
+            continue;
+        }
+        let path = entry.path();
+        let removed = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match removed {
+            Ok(()) => state.write().add_log(LogMessage::info(format!(
+                "Removed {} from the trash after {} days",
+                path.display(),
+                retention_days
+            ))),
+            Err(e) => state.write().add_log(LogMessage::warning(format!(
+                "Failed to remove {} from the trash: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+}
+
+/// Read a (possibly multi-line) FTP reply, returning its code and last line
+async fn read_reply<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> std::io::Result<(u16, String)> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        // The final line of a reply is `<code> <text>`, continuation lines use `<code>-`
+        let is_final = line.as_bytes().get(3) == Some(&b' ');
+        match line.get(..3).map(str::parse::<u16>) {
+            Some(Ok(code)) if is_final => return Ok((code, line.trim_end().to_string())),
+            _ => {}
+        }
+    }
+}
+
+/// Log in to the local server over the control connection and log out again
+async fn loopback_login(port: u16, username: &str, password: &str) -> Result<(), String> {
+    let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+        .await
+        .map_err(|e| format!("connection failed: {}", e))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let (code, reply) = read_reply(&mut reader).await.map_err(|e| e.to_string())?;
+    if code != 220 {
+        return Err(format!("unexpected greeting: {}", reply));
+    }
+
+    writer
+        .write_all(format!("USER {}\r\n", username).as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let (mut code, mut reply) = read_reply(&mut reader).await.map_err(|e| e.to_string())?;
+    if code == 331 {
+        writer
+            .write_all(format!("PASS {}\r\n", password).as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        (code, reply) = read_reply(&mut reader).await.map_err(|e| e.to_string())?;
+    }
+    let _ = writer.write_all(b"QUIT\r\n").await;
+
+    if code == 230 { Ok(()) } else { Err(reply) }
+}
+
+/// Check the configured credentials against the running server, logging the outcome
+pub async fn test_login(config: FtpConfig, state: SharedState) -> Result<(), String> {
+    state.write().add_log(LogMessage::info(format!(
+        "Testing login as '{}' on port {}...",
+        config.username, config.port
+    )));
+    let result = tokio::time::timeout(
+        TEST_LOGIN_TIMEOUT,
+        loopback_login(config.port, &config.username, &config.password),
+    )
+    .await
+    .unwrap_or_else(|_| Err("timed out".to_string()));
+
+    let msg = match &result {
+        Ok(()) => LogMessage::info(format!("Test login as '{}' succeeded", config.username)),
+        Err(e) => LogMessage::error(format!("Test login as '{}' failed: {}", config.username, e)),
+    };
+    state.write().add_log(msg);
+    result
+}
+
 /// Start FTP server
 pub async fn start_server(
     config: FtpConfig,
@@ -92,12 +859,57 @@ pub async fn start_server(
             port
         )));
     }
+    if !wait_for_root(
+        &config.root_dir,
+        config.startup.wait_for_root_secs,
+        &mut shutdown_rx,
+        &state,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    // A reversed range would build a server that logs in but cannot transfer
+    let (passive_start, passive_end) = config.passive_ports;
+    if config.passive_mode && passive_start > passive_end {
+        let msg = format!(
+            "Passive port range {}-{} is empty, the first port must not be above the last",
+            passive_start, passive_end
+        );
+        let mut s = state.write();
+        s.status = ServerStatus::Error(msg.clone());
+        s.add_log(LogMessage::error(&msg));
+        return Err(ServerError::ConfigError(msg));
+    }
+
+    // libunftp can only replace the greeting, other replies would silently stay standard
+    let mut unsupported: Vec<u16> = config
+        .custom_messages
+        .keys()
+        .copied()
+        .filter(|&code| code != GREETING_REPLY)
+        .collect();
+    if !unsupported.is_empty() {
+        unsupported.sort_unstable();
+        let codes: Vec<String> = unsupported.iter().map(u16::to_string).collect();
+        let msg = format!(
+            "Custom replies for {} are not supported, only the 220 greeting can be changed",
+            codes.join(", ")
+        );
+        let mut s = state.write();
+        s.status = ServerStatus::Error(msg.clone());
+        s.add_log(LogMessage::error(&msg));
+        return Err(ServerError::ConfigError(msg));
+    }
 
     // Create authenticator
+    let client_ips = ClientIps::default();
     let authenticator = SimpleAuthenticator {
         username: config.username.clone(),
         password: config.password.clone(),
         allow_anonymous: config.anonymous_access,
+        client_ips: client_ips.clone(),
     };
 
     // Determine transfer mode
@@ -107,11 +919,77 @@ pub async fn start_server(
         ActivePassiveMode::ActiveOnly
     };
 
+    // Wrap the file system in the upload policies
+    let prepared = Filesystem::new(root.clone())
+        .map_err(|e| format!("Root directory {}: {}", root.display(), e))
+        .and_then(|filesystem| {
+            if !config.safe_delete {
+                return Ok((filesystem, None));
+            }
+            let dir = root.join(TRASH_DIR);
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("Trash directory {}: {}", dir.display(), e))?;
+            Ok((filesystem, Some(dir)))
+        });
+    let (filesystem, trash) = match prepared {
+        Ok(prepared) => prepared,
+        Err(msg) => {
+            let mut s = state.write();
+            s.status = ServerStatus::Error(msg.clone());
+            s.add_log(LogMessage::error(&msg));
+            return Err(ServerError::ConfigError(msg));
+        }
+    };
+    let storage = PolicyStorage {
+        inner: Arc::new(filesystem),
+        overwrite_policy: config.overwrite_policy,
+        compress_uploads: config.compress_uploads,
+        decompress_on_download: config.decompress_on_download,
+        trash: trash.clone(),
+        client_ips: client_ips.clone(),
+        state: state.clone(),
+    };
+
     // Build server with transfer mode
-    let server = libunftp::Server::with_fs(root.clone())
-        .authenticator(Arc::new(authenticator))
-        .passive_ports(config.passive_ports.0..=config.passive_ports.1)
-        .active_passive_mode(transfer_mode)
+    let builder = libunftp::ServerBuilder::with_authenticator(
+        Box::new(move || storage.clone()),
+        Arc::new(authenticator),
+    )
+    .passive_ports(config.passive_ports.0..=config.passive_ports.1)
+    .active_passive_mode(transfer_mode);
+    // Behind NAT or in a container the address clients reached is not the one to connect to
+    let passive_host = config
+        .passive_host
+        .as_deref()
+        .map(str::trim)
+        .filter(|host| !host.is_empty());
+    let builder = match passive_host {
+        Some(host) => builder.passive_host(match host.parse::<Ipv4Addr>() {
+            Ok(ip) => PassiveHost::Ip(ip),
+            Err(_) => PassiveHost::Dns(host.to_string()),
+        }),
+        None => builder,
+    };
+    // libunftp only takes a custom greeting, and only as a static string
+    let greeting = config
+        .custom_messages
+        .get(&GREETING_REPLY)
+        .map(|text| sanitize_reply(text))
+        .filter(|text| !text.is_empty());
+    let builder = match &greeting {
+        Some(text) => builder.greeting(static_greeting(text)),
+        None => builder,
+    };
+    // The sender stops once the server, which owns the notifier, is dropped
+    let builder = match config.transfer_notifications.clone() {
+        Some(notifications) => builder.notify_data(TransferNotifier::spawn(
+            notifications,
+            client_ips,
+            state.clone(),
+        )),
+        None => builder,
+    };
+    let server = builder
         .build()
         .map_err(|e| ServerError::Other(e.to_string()))?;
 
@@ -141,8 +1019,97 @@ pub async fn start_server(
             "Transfer mode: {} (passive ports: {}-{})",
             mode_desc, config.passive_ports.0, config.passive_ports.1
         )));
+        if let Some(host) = passive_host.filter(|_| config.passive_mode) {
+            s.add_log(LogMessage::info(format!("Passive host: {}", host)));
+        }
+        if config.passive_mode && passive_start == passive_end {
+            s.add_log(LogMessage::warning(format!(
+                "Only passive port {} is available, clients can run one transfer at a time",
+                passive_start
+            )));
+        }
+        if config.overwrite_policy != OverwritePolicy::Allow {
+            s.add_log(LogMessage::info(format!(
+                "Overwriting existing files: {}",
+                config.overwrite_policy.name()
+            )));
+        }
+        if let Some(algorithm) = config.compress_uploads {
+            s.add_log(LogMessage::info(format!(
+                "Compressing uploads: {}",
+                algorithm.name()
+            )));
+        }
+        if let Some(dir) = &trash {
+            let retention = match config.trash_retention_days {
+                0 => "kept until removed by hand".to_string(),
+                days => format!("kept for {} days", days),
+            };
+            s.add_log(LogMessage::info(format!(
+                "Safe delete: deleted files go to {}, {}",
+                dir.display(),
+                retention
+            )));
+        }
+        if let Some(notifications) = &config.transfer_notifications {
+            let target = match &notifications.mode {
+                NotificationMode::Email(smtp) => format!("email to {}", smtp.to),
+                NotificationMode::Webhook(url) => format!("webhook {}", url),
+            };
+            s.add_log(LogMessage::info(format!(
+                "Transfer notifications: {} of at least {} bytes, by {}",
+                notifications.trigger.name().to_lowercase(),
+                notifications.min_size_bytes,
+                target
+            )));
+        }
+        if let Some(text) = &greeting {
+            s.add_log(LogMessage::info(format!("Greeting: 220 {}", text)));
+        }
+        if let Some(secs) = config.auto_stop_seconds {
+            s.add_log(LogMessage::info(format!(
+                "Auto stop: after {}s of inactivity",
+                secs
+            )));
+        }
+        if config.enable_discovery_broadcast {
+            s.add_log(LogMessage::info(format!(
+                "Discovery broadcast: every {}s to 255.255.255.255:{}",
+                DISCOVERY_INTERVAL.as_secs(),
+                config.discovery_port
+            )));
+        }
     }
 
+    // libunftp binds inside listen() and does not tell the port it got
+    state.write().bound_port = (port != 0).then_some(port);
+    startup_report::log(&state, "FTP", &root, port, None);
+    export_log_on_stop(&state, config.export_log_on_stop.as_deref(), "ftp");
+
+    let discovery = async {
+        if config.enable_discovery_broadcast {
+            discovery_broadcast(config.discovery_port, port, state.clone()).await
+        } else {
+            std::future::pending().await
+        }
+    };
+
+    let trash_purge = async {
+        match &trash {
+            Some(dir) if config.trash_retention_days > 0 => {
+                purge_trash(dir.clone(), config.trash_retention_days, state.clone()).await
+            }
+            _ => std::future::pending().await,
+        }
+    };
+
+    // Withdrawn when dropped, however the server stops
+    let _announcement = if config.mdns_announce {
+        mdns::announce("_ftp._tcp", "FTP", port, &[], &state)
+    } else {
+        None
+    };
+
     // Run server with shutdown signal
     tokio::select! {
         result = server.listen(addr) => {
@@ -153,8 +1120,10 @@ pub async fn start_server(
                 return Err(ServerError::Other(e.to_string()));
             }
         }
-        _ = shutdown_rx.recv() => {
-            // Shutdown requested
+        _ = discovery => {}
+        _ = trash_purge => {}
+        _ = wait_for_shutdown(&mut shutdown_rx, &state, config.auto_stop_seconds) => {
+            // Shutdown requested or idle for too long
         }
     }
 
@@ -164,6 +1133,7 @@ pub async fn start_server(
         s.status = ServerStatus::Stopped;
         s.add_log(LogMessage::info("FTP server stopped"));
     }
+    finish_log_export(&state);
 
     Ok(())
 }
@@ -173,3 +1143,155 @@ pub async fn start_server(
 pub fn create_handle(config: FtpConfig) -> ServerHandle {
     ServerHandle::new(config.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{check_port_available, find_free_port, test_util};
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn shutdown_releases_the_port() {
+        let root = tempfile::tempdir().unwrap();
+        // libunftp does not tell which port it got, so pick one up front
+        let port = find_free_port(21000, false).unwrap();
+        let config = FtpConfig {
+            root_dir: root.path().to_path_buf(),
+            port,
+            auto_stop_seconds: None,
+            ..FtpConfig::default()
+        };
+        let state = test_util::new_state();
+        let (tx, rx) = mpsc::channel(1);
+        let server = tokio::spawn(start_server(config, state.clone(), rx));
+        test_util::wait_until(&state, "the server runs", |s| {
+            s.status == ServerStatus::Running
+        })
+        .await;
+
+        let mut control = test_util::connect(port).await;
+        let mut greeting = [0u8; 3];
+        control.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(&greeting, b"220");
+        drop(control);
+
+        test_util::stop(&state, &tx, server).await;
+        assert!(check_port_available(port, false));
+    }
+
+    #[tokio::test]
+    async fn unusable_trash_directory_is_reported() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join(TRASH_DIR), "not a directory").unwrap();
+        let config = FtpConfig {
+            root_dir: root.path().to_path_buf(),
+            port: find_free_port(21000, false).unwrap(),
+            safe_delete: true,
+            auto_stop_seconds: None,
+            ..FtpConfig::default()
+        };
+        let state = test_util::new_state();
+        let (_tx, rx) = mpsc::channel(1);
+        let result = start_server(config, state.clone(), rx).await;
+        assert!(matches!(result, Err(ServerError::ConfigError(_))));
+        let s = state.read();
+        assert!(matches!(s.status, ServerStatus::Error(_)));
+        assert!(
+            s.logs
+                .iter()
+                .any(|l| l.to_string().contains("Trash directory"))
+        );
+    }
+
+    #[tokio::test]
+    async fn resumed_upload_is_refused_while_compressing() {
+        let root = tempfile::tempdir().unwrap();
+        let storage = PolicyStorage {
+            inner: Arc::new(Filesystem::new(root.path().to_path_buf()).unwrap()),
+            overwrite_policy: OverwritePolicy::Allow,
+            compress_uploads: Some(CompressionAlgorithm::Gzip),
+            decompress_on_download: false,
+            trash: None,
+            client_ips: ClientIps::default(),
+            state: test_util::new_state(),
+        };
+        let user = FtpUser {
+            username: "anonymous".to_string(),
+        };
+        let result = storage.put(&user, &b"tail"[..], "file.txt", 100).await;
+        let error = result.expect_err("resumed upload accepted");
+        assert_eq!(error.kind(), ErrorKind::CommandNotImplemented);
+        assert!(!root.path().join("file.txt").exists());
+        assert!(!root.path().join("file.txt.gz").exists());
+    }
+
+    #[test]
+    fn transfers_show_the_login_address() {
+        let root = tempfile::tempdir().unwrap();
+        let storage = PolicyStorage {
+            inner: Arc::new(Filesystem::new(root.path().to_path_buf()).unwrap()),
+            overwrite_policy: OverwritePolicy::Allow,
+            compress_uploads: None,
+            decompress_on_download: false,
+            trash: None,
+            client_ips: ClientIps::default(),
+            state: test_util::new_state(),
+        };
+        storage
+            .client_ips
+            .lock()
+            .insert("alice".to_string(), IpAddr::from([192, 0, 2, 7]));
+        let user = |name: &str| FtpUser {
+            username: name.to_string(),
+        };
+        assert_eq!(storage.client(&user("alice")), "192.0.2.7");
+        assert_eq!(storage.client(&user("bob")), "unknown");
+    }
+
+    #[tokio::test]
+    async fn replies_other_than_the_greeting_are_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let config = FtpConfig {
+            root_dir: root.path().to_path_buf(),
+            port: find_free_port(21000, false).unwrap(),
+            custom_messages: HashMap::from([
+                (220, "Welcome".to_string()),
+                (530, "Go away".to_string()),
+            ]),
+            auto_stop_seconds: None,
+            ..FtpConfig::default()
+        };
+        let state = test_util::new_state();
+        let (_tx, rx) = mpsc::channel(1);
+        let result = start_server(config, state.clone(), rx).await;
+        assert!(matches!(result, Err(ServerError::ConfigError(msg)) if msg.contains("530")));
+        assert!(matches!(state.read().status, ServerStatus::Error(_)));
+    }
+
+    #[test]
+    fn greeting_is_leaked_once_per_text() {
+        let first = static_greeting("Welcome back");
+        let again = static_greeting(&format!("Welcome {}", "back"));
+        assert!(std::ptr::eq(first, again));
+        assert_eq!(static_greeting("Other"), "Other");
+    }
+
+    #[test]
+    fn purge_removes_only_expired_items() {
+        let trash = tempfile::tempdir().unwrap();
+        let old = trash.path().join("old.20240101-000000.txt");
+        std::fs::write(&old, "old").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(8 * 24 * 3600))
+            .unwrap();
+        let recent = trash.path().join("recent.20240108-000000.txt");
+        std::fs::write(&recent, "recent").unwrap();
+
+        purge_expired(trash.path(), 7, &test_util::new_state());
+        assert!(!old.exists());
+        assert!(recent.exists());
+    }
+}
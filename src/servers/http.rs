@@ -1,124 +1,2320 @@
 //! HTTP Server implementation using warp
 
-use super::{LogMessage, ServerConfig, ServerError, ServerHandle, ServerStatus, SharedState};
-use std::net::SocketAddr;
-use std::path::PathBuf;
-use tokio::sync::mpsc;
-use warp::Filter;
+use super::backend::{LocalFs, StorageBackend};
+use super::client_lookup::ClientLookup;
+use super::integrity::{self, IntegrityConfig};
+use super::kv::{self, KvStore};
+use super::mdns;
+use super::proxy_protocol;
+use super::response_signing::{ResponseSigner, SigningConfig};
+use super::signed_url;
+use super::startup_report;
+use super::tls::{self, TicketKeys};
+use super::tor::OnionService;
+use super::webhook::{self, WebhookConfig, WebhookTrigger};
+use super::{
+    LogMessage, Metrics, RestartPolicy, ServerConfig, ServerError, ServerHandle, ServerStatus,
+    SharedState, StartupConfig, TransferGuard, export_log_on_stop, finish_log_export, glob_match,
+    local_ip, wait_for_root, wait_for_shutdown,
+};
+use base64::Engine;
+use futures_util::future::Either;
+use futures_util::{FutureExt, StreamExt, stream};
+use ipnet::IpNet;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use warp::filters::BoxedFilter;
+use warp::hyper::body::{Body, Bytes};
+use warp::{Filter, Reply};
 
 /// HTTP server specific configuration
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct HttpConfig {
+    pub mode: HttpMode,
     pub root_dir: PathBuf,
     pub port: u16,
     pub allow_directory_listing: bool,
     pub auto_stop_seconds: Option<u64>,
+    /// Scan the root directory for sensitive files before starting
+    pub scan_sensitive: bool,
+    /// File name patterns considered sensitive, a trailing `/` matches directories
+    pub sensitive_patterns: Vec<String>,
+    /// Page served for directories without an index when listing is disabled
+    pub idle_page_html: Option<String>,
+    /// Template files replacing the built-in server-generated pages
+    pub pages: Pages,
+    /// Answer every request except the statistics page with the maintenance page
+    pub maintenance_mode: bool,
+    /// Per file type bandwidth limits, the first matching rule applies
+    pub qos_rules: Vec<QosRule>,
+    /// URLs notified when server events occur
+    pub webhooks: Vec<WebhookConfig>,
+    /// In-memory files served ahead of the root directory
+    pub virtual_files: Vec<VirtualFile>,
+    /// Maximum number of files transferred at once, further downloads are queued
+    pub max_concurrent_downloads: Option<usize>,
+    /// Seconds a queued download waits for a slot before failing with 503
+    pub download_queue_timeout_secs: u64,
+    /// Answer requests with more path segments than this with 400
+    pub max_path_depth: Option<usize>,
+    /// Seconds a generated directory listing is reused, 0 disables caching
+    pub listing_cache_ttl_secs: u64,
+    /// Notice shown above every directory listing, plain text
+    pub listing_banner: Option<String>,
+    /// Send directory listings with a Content-Security-Policy allowing only their own styles
+    pub csp_enabled: bool,
+    /// URL path of the statistics page, e.g. `/server-status`
+    pub stats_page_path: Option<String>,
+    /// Basic auth user name for the statistics page, required when the page is enabled
+    pub stats_username: String,
+    /// Basic auth password for the statistics page, required when the page is enabled
+    pub stats_password: String,
+    /// URL path answering 200 while the server runs, e.g. `/health`, for container health checks
+    pub health_path: Option<String>,
+    /// Key for signing time-limited links under `/__signed`, none disables them
+    pub signed_url_secret: Option<String>,
+    /// Bearer token for making signed links at `/__admin/sign`, none disables that
+    pub signed_url_admin_token: Option<String>,
+    /// Offer directories as a ZIP download from the listing
+    pub zip_downloads: bool,
+    /// Answer listing requests sent with `Accept: application/json` with JSON
+    pub json_listing: bool,
+    /// Redirect `/dir` to `/dir/` so relative links in listings and index pages resolve
+    pub redirect_directory_slash: bool,
+    /// Path the share is published under by a reverse proxy, e.g. `/files`, which
+    /// strips it before forwarding; prefixed to generated links and redirects
+    pub base_path: Option<String>,
+    /// Reverse proxies whose `X-Forwarded-For` is believed for the client address
+    pub trusted_proxies: Vec<IpNet>,
+    /// Largest total file size a directory may have to be zipped
+    pub max_zip_bytes: u64,
+    /// Serve language variants like `page.zh.html` for `page.html` based on `Accept-Language`
+    pub enable_content_negotiation: bool,
+    /// Periodically verify served files against a hash manifest
+    pub integrity_check: Option<IntegrityConfig>,
+    /// Expect a PROXY protocol v1/v2 header on every connection, e.g. behind HAProxy
+    pub proxy_protocol: bool,
+    /// Certificate and key to serve HTTPS with
+    pub tls: Option<TlsConfig>,
+    /// Let returning HTTPS clients resume their session with a ticket instead
+    /// of a full handshake
+    pub tls_session_resumption: bool,
+    /// Sign status and entity headers of every response with a private key
+    pub sign_responses: Option<SigningConfig>,
+    /// Serve HTTPS on this port next to plain HTTP on `port`, without it `port` serves HTTPS
+    pub https_port: Option<u16>,
+    /// Limit each connection's outgoing traffic to this many kilobits per second
+    pub egress_throttle_kbps: Option<u32>,
+    /// Size of the chunks files are sent in, larger means fewer writes and smaller
+    /// gets the first bytes out sooner when the disk is slow
+    pub stream_buffer_size_bytes: usize,
+    /// Fraction of successful requests written to the log, errors are always logged
+    pub log_sample_rate: f32,
+    /// Paths whose successful requests are not logged, e.g. polled by monitoring;
+    /// exact matches or prefixes when ending in `*`
+    pub access_log_exclude_paths: Vec<String>,
+    /// Split visitors between the root directory and a second one
+    pub ab_test: Option<AbTestConfig>,
+    /// Annotate request log entries with the client's host name
+    pub reverse_dns: bool,
+    /// MaxMind GeoIP2/GeoLite2 database for annotating log entries with the client's country
+    pub geoip_db: Option<PathBuf>,
+    /// Decode request bodies sent with `Content-Encoding` gzip, deflate or br
+    pub accept_compressed_uploads: bool,
+    /// Publish the server as a Tor onion service through a local Tor
+    pub tor_hidden_service: bool,
+    /// Tor control port used to create the onion service
+    pub tor_control_port: u16,
+    /// Announce the server on the LAN as a DNS-SD `_http._tcp` service
+    pub mdns_announce: bool,
+    /// Free-form markdown notes about this server
+    pub notes: String,
+    /// Directory the session log is written to when the server stops
+    pub export_log_on_stop: Option<PathBuf>,
+    /// Auto-start, ordering and waiting for the root directory
+    pub startup: StartupConfig,
+    /// Restarting the server when it stops on its own
+    pub restart: RestartPolicy,
+    /// Serve only this file instead of the root directory (not persisted)
+    #[serde(skip)]
+    pub single_file: Option<PathBuf>,
+}
+
+/// What the HTTP server serves
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum HttpMode {
+    /// Files and directory listings from the root directory
+    #[default]
+    FileServer,
+    /// A key-value store under `/kv`, persisted to `store_path`
+    KeyValueStore { store_path: PathBuf },
+}
+
+/// Bandwidth limit for files matching a name pattern
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct QosRule {
+    /// File name pattern, e.g. `*.mp4`
+    pub extension_pattern: String,
+    /// Maximum transfer rate in kilobits per second
+    pub max_kbps: u32,
+}
+
+/// File served from memory at a fixed URL path, e.g. a `robots.txt`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct VirtualFile {
+    /// URL path, e.g. `/robots.txt`
+    pub path: String,
+    /// Body, with `{{server_port}}` and `{{server_ip}}` filled in when the server starts
+    pub content: String,
+    pub content_type: String,
+}
+
+impl Default for VirtualFile {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            content: String::new(),
+            content_type: "text/plain; charset=utf-8".to_string(),
+        }
+    }
+}
+
+/// PEM files for serving HTTPS
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// Certificate chain, the server certificate first
+    pub cert_path: PathBuf,
+    /// Private key of the certificate
+    pub key_path: PathBuf,
+}
+
+/// A/B test serving a share of visitors from a second root directory
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AbTestConfig {
+    /// Cookie remembering a visitor's group
+    pub cookie_name: String,
+    /// Percentage of new visitors put in group A, served from the root directory
+    pub group_a_weight: u8,
+    /// Root directory group B is served from
+    pub group_b_root: PathBuf,
+}
+
+impl Default for AbTestConfig {
+    fn default() -> Self {
+        Self {
+            cookie_name: "oservers_ab".to_string(),
+            group_a_weight: 50,
+            group_b_root: PathBuf::new(),
+        }
+    }
 }
 
 impl Default for HttpConfig {
     fn default() -> Self {
         Self {
+            mode: HttpMode::FileServer,
             root_dir: std::env::current_dir().unwrap_or_default(),
             port: 7777,
             allow_directory_listing: true,
             auto_stop_seconds: Some(360),
+            scan_sensitive: true,
+            sensitive_patterns: [
+                ".env",
+                "id_rsa",
+                "id_ed25519",
+                "*.pem",
+                "*.key",
+                ".git/",
+                ".ssh/",
+                "web.config",
+            ]
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
+            idle_page_html: None,
+            pages: Pages::default(),
+            maintenance_mode: false,
+            qos_rules: Vec::new(),
+            webhooks: Vec::new(),
+            virtual_files: Vec::new(),
+            max_concurrent_downloads: None,
+            download_queue_timeout_secs: 30,
+            max_path_depth: None,
+            listing_cache_ttl_secs: 0,
+            listing_banner: None,
+            csp_enabled: true,
+            stats_page_path: None,
+            stats_username: String::new(),
+            stats_password: String::new(),
+            health_path: None,
+            signed_url_secret: None,
+            signed_url_admin_token: None,
+            zip_downloads: true,
+            json_listing: true,
+            redirect_directory_slash: true,
+            base_path: None,
+            trusted_proxies: Vec::new(),
+            max_zip_bytes: 1024 * 1024 * 1024,
+            enable_content_negotiation: false,
+            integrity_check: None,
+            proxy_protocol: false,
+            tls: None,
+            tls_session_resumption: true,
+            sign_responses: None,
+            https_port: None,
+            egress_throttle_kbps: None,
+            stream_buffer_size_bytes: DEFAULT_STREAM_BUFFER_SIZE,
+            log_sample_rate: 1.0,
+            access_log_exclude_paths: vec!["/health".to_string(), "/metrics".to_string()],
+            ab_test: None,
+            reverse_dns: false,
+            geoip_db: None,
+            accept_compressed_uploads: true,
+            tor_hidden_service: false,
+            tor_control_port: 9051,
+            mdns_announce: false,
+            notes: String::new(),
+            export_log_on_stop: None,
+            startup: StartupConfig::default(),
+            restart: RestartPolicy::default(),
+            single_file: None,
+        }
+    }
+}
+
+impl From<HttpConfig> for ServerConfig {
+    fn from(cfg: HttpConfig) -> Self {
+        ServerConfig {
+            root_dir: cfg.root_dir,
+            port: cfg.port,
+            auto_stop_seconds: cfg.auto_stop_seconds,
+            egress_throttle_kbps: cfg.egress_throttle_kbps,
+        }
+    }
+}
+
+/// Template files for the pages the server generates itself, unset ones use the built-in page
+///
+/// Templates are read on every request, so edits apply without a restart.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Pages {
+    /// Landing page for directories without an index, takes precedence over `idle_page_html`
+    pub index: Option<PathBuf>,
+    pub not_found: Option<PathBuf>,
+    pub forbidden: Option<PathBuf>,
+    /// Served while `maintenance_mode` is on
+    pub maintenance: Option<PathBuf>,
+}
+
+/// A server-generated page
+#[derive(Debug, Clone, Copy)]
+enum Page {
+    Index,
+    NotFound,
+    Forbidden,
+    Maintenance,
+}
+
+impl Page {
+    fn status(self) -> warp::http::StatusCode {
+        match self {
+            Page::Index => warp::http::StatusCode::OK,
+            Page::NotFound => warp::http::StatusCode::NOT_FOUND,
+            Page::Forbidden => warp::http::StatusCode::FORBIDDEN,
+            Page::Maintenance => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn builtin(self) -> &'static str {
+        match self {
+            Page::Index => DEFAULT_IDLE_PAGE,
+            Page::NotFound => DEFAULT_NOT_FOUND_PAGE,
+            Page::Forbidden => DEFAULT_FORBIDDEN_PAGE,
+            Page::Maintenance => DEFAULT_MAINTENANCE_PAGE,
+        }
+    }
+
+    fn file(self, pages: &Pages) -> Option<&PathBuf> {
+        match self {
+            Page::Index => pages.index.as_ref(),
+            Page::NotFound => pages.not_found.as_ref(),
+            Page::Forbidden => pages.forbidden.as_ref(),
+            Page::Maintenance => pages.maintenance.as_ref(),
+        }
+    }
+}
+
+/// Default idle page, see [`render_page`] for the supported placeholders
+pub const DEFAULT_IDLE_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>{{server_name}}</title>
+    <style>
+        body { font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif; margin: 20px; background: #f5f5f5; }
+        h1 { color: #333; border-bottom: 2px solid #4CAF50; padding-bottom: 10px; }
+        td { padding: 4px 15px 4px 0; color: #555; }
+    </style>
+</head>
+<body>
+    <h1>OServers is running</h1>
+    <table>
+        <tr><td>Server</td><td>{{server_name}}</td></tr>
+        <tr><td>Port</td><td>{{port}}</td></tr>
+        <tr><td>Uptime</td><td>{{uptime}}</td></tr>
+        <tr><td>Requests served</td><td>{{requests}}</td></tr>
+    </table>
+</body>
+</html>"#;
+
+const DEFAULT_NOT_FOUND_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>404 Not Found</title></head>
+<body>
+    <h1>Not Found</h1>
+    <p>{{path}} does not exist on this server.</p>
+    <hr><small>{{server_name}}</small>
+</body>
+</html>"#;
+
+const DEFAULT_FORBIDDEN_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>403 Forbidden</title></head>
+<body>
+    <h1>Forbidden</h1>
+    <p>You do not have permission to access {{path}}.</p>
+    <hr><small>{{server_name}}</small>
+</body>
+</html>"#;
+
+const DEFAULT_MAINTENANCE_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Maintenance</title></head>
+<body>
+    <h1>Down for maintenance</h1>
+    <p>{{server_name}} is temporarily unavailable, please try again later.</p>
+</body>
+</html>"#;
+
+/// Fill in the page template tokens `{{server_name}}`, `{{port}}`, `{{uptime}}`,
+/// `{{requests}}` and `{{path}}`
+fn render_page(template: &str, port: u16, uptime: Duration, requests: u64, path: &str) -> String {
+    template
+        .replace("{{server_name}}", "OServers HTTP Server")
+        .replace("{{port}}", &port.to_string())
+        .replace("{{uptime}}", &format_duration(uptime))
+        .replace("{{requests}}", &requests.to_string())
+        .replace("{{path}}", &escape_html(path))
+}
+
+/// Renders the server-generated pages with the running server's figures
+#[derive(Clone)]
+struct PageRenderer {
+    pages: Pages,
+    /// Inline landing page template from `idle_page_html`
+    index_html: Option<String>,
+    port: u16,
+    started_at: Instant,
+    requests: Arc<AtomicU64>,
+}
+
+impl PageRenderer {
+    /// The configured template file, falling back to the built-in page if it cannot be read
+    fn template(&self, page: Page) -> String {
+        if let Some(content) = page
+            .file(&self.pages)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+        {
+            return content;
+        }
+        match (page, &self.index_html) {
+            (Page::Index, Some(html)) => html.clone(),
+            _ => page.builtin().to_string(),
+        }
+    }
+
+    fn reply(&self, page: Page, path: &str) -> warp::reply::Response {
+        let html = render_page(
+            &self.template(page),
+            self.port,
+            self.started_at.elapsed(),
+            self.requests.load(Ordering::Relaxed),
+            path,
+        );
+        warp::reply::with_status(warp::reply::html(html), page.status()).into_response()
+    }
+}
+
+/// Whether the file or directory at a request path exists but cannot be read
+fn permission_denied(root: &Path, request_path: &str) -> bool {
+    let Ok(decoded) = percent_encoding::percent_decode_str(request_path).decode_utf8() else {
+        return false;
+    };
+    if decoded.split('/').any(|segment| segment == "..") {
+        return false;
+    }
+    let path = root.join(decoded.trim_start_matches('/'));
+    let result = if path.is_dir() {
+        std::fs::read_dir(&path).map(drop)
+    } else {
+        std::fs::File::open(&path).map(drop)
+    };
+    matches!(result, Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied)
+}
+
+/// Answer unreadable paths under `root` with the forbidden page and
+/// unmatched ones with the not found page
+///
+/// Other rejections, e.g. a wrong method, keep warp's response.
+fn with_error_pages(
+    routes: BoxedFilter<(warp::reply::Response,)>,
+    pages: PageRenderer,
+    root: Option<PathBuf>,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    // Turn the rejection into a value so the handler below also sees the path
+    let routes = routes
+        .map(Ok)
+        .or_else(|rejection| async move { Ok::<_, warp::Rejection>((Err(rejection),)) });
+    warp::path::full()
+        .and(routes)
+        .and_then(
+            move |path: warp::path::FullPath,
+                  result: Result<warp::reply::Response, warp::Rejection>| {
+                let pages = pages.clone();
+                let root = root.clone();
+                async move {
+                    let rejection = match result {
+                        Ok(response) => return Ok(response),
+                        Err(rejection) => rejection,
+                    };
+                    if root.is_some_and(|root| permission_denied(&root, path.as_str())) {
+                        Ok(pages.reply(Page::Forbidden, path.as_str()))
+                    } else if rejection.is_not_found() {
+                        Ok(pages.reply(Page::NotFound, path.as_str()))
+                    } else {
+                        Err(rejection)
+                    }
+                }
+            },
+        )
+        .boxed()
+}
+
+/// Format a duration as e.g. `1h 02m 03s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Most distinct paths or clients tracked by the statistics page
+const MAX_TRACKED_STATS_KEYS: usize = 10_000;
+
+/// Request statistics shared between the log filter and the statistics page
+#[derive(Default)]
+struct HttpStats {
+    bytes_sent: AtomicU64,
+    active_requests: AtomicU64,
+    paths: Arc<Mutex<HashMap<String, u64>>>,
+    clients: Arc<Mutex<HashMap<IpAddr, u64>>>,
+    /// Requests served to each A/B test group while a test runs
+    ab_requests: Option<AbCounters>,
+}
+
+/// Requests served to groups A and B of an A/B test
+#[derive(Default)]
+struct AbCounters {
+    a: AtomicU64,
+    b: AtomicU64,
+}
+
+impl HttpStats {
+    /// Count a completed request for `path` from `client`
+    fn record(&self, path: &str, client: Option<IpAddr>) {
+        bump(&mut self.paths.lock(), path.to_string());
+        if let Some(client) = client {
+            bump(&mut self.clients.lock(), client);
+        }
+    }
+}
+
+/// Increment the counter for `key`, ignoring new keys once the map is full
+fn bump<K: std::hash::Hash + Eq>(map: &mut HashMap<K, u64>, key: K) {
+    let len = map.len();
+    match map.get_mut(&key) {
+        Some(count) => *count += 1,
+        None if len < MAX_TRACKED_STATS_KEYS => {
+            map.insert(key, 1);
+        }
+        None => {}
+    }
+}
+
+/// The `n` keys with the highest counts, highest first
+fn top_counts<K: Clone + Ord>(map: &HashMap<K, u64>, n: usize) -> Vec<(K, u64)> {
+    let mut counts: Vec<_> = map.iter().map(|(k, v)| (k.clone(), *v)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(n);
+    counts
+}
+
+/// Escape text for inclusion in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Listing banner as HTML, escaped with line breaks kept
+fn render_listing_banner(text: &str) -> String {
+    text.lines()
+        .map(|line| escape_html(line.trim()))
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+/// Render the statistics page
+fn render_stats_page(stats: &HttpStats, uptime: Duration, requests: u64) -> String {
+    let mut rows = String::new();
+    for (label, value) in [
+        ("Uptime", format_duration(uptime)),
+        ("Requests served", requests.to_string()),
+        (
+            "Bytes transferred",
+            format_size(stats.bytes_sent.load(Ordering::Relaxed)),
+        ),
+        (
+            "Active requests",
+            stats.active_requests.load(Ordering::Relaxed).to_string(),
+        ),
+    ] {
+        rows.push_str(&format!(
+            "        <tr><td>{}</td><td>{}</td></tr>\n",
+            label, value
+        ));
+    }
+    if let Some(ab) = &stats.ab_requests {
+        for (label, counter) in [("A/B group A", &ab.a), ("A/B group B", &ab.b)] {
+            rows.push_str(&format!(
+                "        <tr><td>{}</td><td>{}</td></tr>\n",
+                label,
+                counter.load(Ordering::Relaxed)
+            ));
+        }
+    }
+
+    let mut top_paths = String::new();
+    for (path, count) in top_counts(&stats.paths.lock(), 10) {
+        top_paths.push_str(&format!(
+            "        <tr><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&path),
+            count
+        ));
+    }
+
+    let mut top_clients = String::new();
+    for (ip, count) in top_counts(&stats.clients.lock(), 5) {
+        top_clients.push_str(&format!(
+            "        <tr><td>{}</td><td>{}</td></tr>\n",
+            ip, count
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <meta http-equiv="refresh" content="10">
+    <title>Server status</title>
+    <style>
+        body {{ font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif; margin: 20px; background: #f5f5f5; }}
+        h1, h2 {{ color: #333; border-bottom: 2px solid #4CAF50; padding-bottom: 10px; }}
+        table {{ border-collapse: collapse; background: white; box-shadow: 0 1px 3px rgba(0,0,0,0.1); }}
+        th, td {{ padding: 8px 15px; text-align: left; border-bottom: 1px solid #ddd; }}
+        th {{ background: #4CAF50; color: white; }}
+    </style>
+</head>
+<body>
+    <h1>📊 Server status</h1>
+    <table>
+{}    </table>
+    <h2>Top paths</h2>
+    <table>
+        <tr><th>Path</th><th>Requests</th></tr>
+{}    </table>
+    <h2>Top clients</h2>
+    <table>
+        <tr><th>Address</th><th>Requests</th></tr>
+{}    </table>
+    <p style="color:#888;margin-top:20px;font-size:12px;">OServers HTTP Server</p>
+</body>
+</html>"#,
+        rows, top_paths, top_clients
+    )
+}
+
+/// Picks the requests that get logged when only a fraction of them should
+struct LogSampler {
+    rate: f64,
+    seed: u64,
+    counter: AtomicU64,
+}
+
+impl LogSampler {
+    fn new(rate: f32) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self {
+            rate: rate.clamp(0.0, 1.0) as f64,
+            seed,
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether to log the next request, a lock-free splitmix64 draw
+    fn sample(&self) -> bool {
+        if self.rate >= 1.0 {
+            return true;
+        }
+        let mut z = self
+            .counter
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(self.seed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < self.rate
+    }
+}
+
+/// Response sizes waiting for the log filter, keyed by client address and path
+type ResponseSizes = Arc<Mutex<HashMap<(Option<SocketAddr>, String), u64>>>;
+
+/// Marks a request as active in the statistics until its body is done,
+/// then adds the bytes actually sent to the server metrics
+struct ActiveRequest {
+    stats: Arc<HttpStats>,
+    state: SharedState,
+    bytes: u64,
+    /// Entry in the live transfers view, for successful responses
+    transfer: Option<TransferGuard>,
+}
+
+impl ActiveRequest {
+    fn new(stats: Arc<HttpStats>, state: SharedState, transfer: Option<TransferGuard>) -> Self {
+        stats.active_requests.fetch_add(1, Ordering::Relaxed);
+        Self {
+            stats,
+            state,
+            bytes: 0,
+            transfer,
+        }
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.stats.active_requests.fetch_sub(1, Ordering::Relaxed);
+        self.state.write().metrics.bytes_sent += self.bytes;
+    }
+}
+
+/// Size of a response body, from its `Content-Length` or the body itself
+fn response_size(response: &warp::reply::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(warp::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .or_else(|| warp::hyper::body::HttpBody::size_hint(response.body()).exact())
+}
+
+/// Count the bytes of a response body and track it as active while it is sent
+fn count_response(
+    mut response: warp::reply::Response,
+    stats: Arc<HttpStats>,
+    state: SharedState,
+    remote: Option<SocketAddr>,
+    path: &str,
+) -> warp::reply::Response {
+    // Streaming the body hides its size from hyper, keep it as a header
+    let size = response_size(&response);
+    if let Some(size) = size {
+        response
+            .headers_mut()
+            .insert(warp::http::header::CONTENT_LENGTH, size.into());
+    }
+    let transfer = response.status().is_success().then(|| {
+        let client = remote.map_or_else(|| "unknown".to_string(), |addr| addr.to_string());
+        let file = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+        TransferGuard::start(&state, client, file, false, size)
+    });
+    let mut active = ActiveRequest::new(stats, state, transfer);
+    let body = std::mem::take(response.body_mut());
+    *response.body_mut() = Body::wrap_stream(body.map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            active.bytes += chunk.len() as u64;
+            if let Some(transfer) = &active.transfer {
+                transfer.add(chunk.len() as u64);
+            }
+            active
+                .stats
+                .bytes_sent
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+        chunk
+    }));
+    response
+}
+
+/// Whether an `Authorization` header carries the expected Basic credentials
+fn check_basic_auth(header: &str, username: &str, password: &str) -> bool {
+    let Some(encoded) = header.strip_prefix("Basic ") else {
+        return false;
+    };
+    let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded.trim()) else {
+        return false;
+    };
+    signed_url::constant_time_eq(&decoded, format!("{}:{}", username, password).as_bytes())
+}
+
+/// Why the statistics login cannot be used, refusing empty and the former default credentials
+pub fn stats_login_problem(username: &str, password: &str) -> Option<&'static str> {
+    if username.is_empty() || password.is_empty() {
+        Some("the statistics page needs a username and password")
+    } else if username == "admin" && password == "admin" {
+        Some("the statistics page cannot use the default login admin/admin")
+    } else {
+        None
+    }
+}
+
+/// Route serving the statistics page at `path` behind its own Basic auth
+#[allow(clippy::too_many_arguments)]
+fn stats_route(
+    path: String,
+    username: String,
+    password: String,
+    stats: Arc<HttpStats>,
+    started_at: Instant,
+    requests: Arc<AtomicU64>,
+    webhooks: Vec<WebhookConfig>,
+    state: SharedState,
+    addr_source: ClientAddrSource,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    warp::path::full()
+        .and_then(move |full: warp::path::FullPath| {
+            let matches = full.as_str() == path;
+            async move {
+                if matches {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }
+        })
+        .untuple_one()
+        .and(warp::header::optional::<String>("authorization"))
+        .and(client_addr(&addr_source))
+        .map(move |auth: Option<String>, remote: Option<SocketAddr>| {
+            let authorized = auth
+                .as_deref()
+                .is_some_and(|header| check_basic_auth(header, &username, &password));
+            if authorized {
+                let html = render_stats_page(
+                    &stats,
+                    started_at.elapsed(),
+                    requests.load(Ordering::Relaxed),
+                );
+                return warp::reply::html(html).into_response();
+            }
+
+            // A missing header is the browser's first attempt, only report wrong credentials
+            if auth.is_some() {
+                let remote = remote.map(|addr| addr.to_string());
+                state.write().add_log(LogMessage::error(format!(
+                    "Statistics page: authentication failed from {}",
+                    remote.as_deref().unwrap_or("unknown")
+                )));
+                webhook::fire(
+                    &webhooks,
+                    WebhookTrigger::OnAuthFailure,
+                    serde_json::json!({ "path": "stats", "remote_addr": remote }),
+                    &state,
+                );
+            }
+            warp::reply::with_header(
+                warp::reply::with_status("Unauthorized", warp::http::StatusCode::UNAUTHORIZED),
+                "WWW-Authenticate",
+                r#"Basic realm="OServers statistics""#,
+            )
+            .into_response()
+        })
+        .boxed()
+}
+
+/// Unauthenticated liveness check at `path`, answered even in maintenance mode
+fn health_route(path: String, started_at: Instant) -> BoxedFilter<(warp::reply::Response,)> {
+    warp::path::full()
+        .and_then(move |full: warp::path::FullPath| {
+            let matches = full.as_str() == path;
+            async move {
+                if matches {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }
+        })
+        .untuple_one()
+        .map(move || {
+            let body = serde_json::json!({
+                "status": "ok",
+                "uptime_secs": started_at.elapsed().as_secs(),
+            });
+            warp::reply::with_header(warp::reply::json(&body), "Cache-Control", "no-store")
+                .into_response()
+        })
+        .boxed()
+}
+
+/// URL path a shared single file is served at
+pub fn single_file_url_path(file: &Path) -> String {
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!(
+        "/{}",
+        percent_encoding::utf8_percent_encode(&name, percent_encoding::NON_ALPHANUMERIC)
+    )
+}
+
+/// Route serving just `file` at `/<file name>`, everything else is not found
+fn single_file_route(file: PathBuf) -> BoxedFilter<(warp::reply::Response,)> {
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    warp::path::tail()
+        .and_then(move |tail: warp::path::Tail| {
+            let matches = percent_encoding::percent_decode_str(tail.as_str()).decode_utf8_lossy()
+                == name.as_str();
+            async move {
+                if matches {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }
+        })
+        .untuple_one()
+        .and(warp::fs::file(file))
+        .map(Reply::into_response)
+        .boxed()
+}
+
+/// Map a request path onto a file below `root`, using `index.html` for directories
+fn resolve_request_file(root: &Path, tail: &str) -> Option<PathBuf> {
+    let decoded = percent_encoding::percent_decode_str(tail)
+        .decode_utf8()
+        .ok()?;
+    let relative = Path::new(decoded.as_ref());
+    if !relative
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)))
+    {
+        return None;
+    }
+    let path = root.join(relative);
+    if path.is_dir() {
+        Some(path.join("index.html"))
+    } else {
+        Some(path)
+    }
+}
+
+/// Language tags from an `Accept-Language` header, most preferred first
+fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let tag = params.next()?.trim().to_lowercase();
+            let quality = params
+                .find_map(|p| p.trim().strip_prefix("q=")?.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable sort keeps header order between equal weights
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Pick the language variant of `file` best matching `accept_language`
+///
+/// Variants are siblings named `<stem>.<language>.<ext>`. Returns `None` when
+/// `file` has no variants, so the regular file server handles it.
+fn negotiate_language(file: &Path, accept_language: Option<&str>) -> Option<PathBuf> {
+    let name = file.file_name()?.to_str()?;
+    let (stem, ext) = name.rsplit_once('.')?;
+    let variants: Vec<(String, PathBuf)> = std::fs::read_dir(file.parent()?)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let language = name
+                .strip_prefix(stem)?
+                .strip_prefix('.')?
+                .strip_suffix(ext)?
+                .strip_suffix('.')?
+                .to_lowercase();
+            let valid = !language.is_empty()
+                && language.len() <= 35
+                && language
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-');
+            (valid && entry.path().is_file()).then(|| (language, entry.path()))
+        })
+        .collect();
+    if variants.is_empty() {
+        return None;
+    }
+
+    let primary = |tag: &str| tag.split('-').next().unwrap_or_default().to_string();
+    for wanted in parse_accept_language(accept_language.unwrap_or_default()) {
+        let exact = variants.iter().find(|(language, _)| *language == wanted);
+        let related = || {
+            variants
+                .iter()
+                .find(|(language, _)| primary(language) == primary(&wanted))
+        };
+        if let Some((_, path)) = exact.or_else(related) {
+            return Some(path.clone());
+        }
+    }
+    file.is_file().then(|| file.to_path_buf())
+}
+
+/// Whether `path` leads to a location inside the canonical `root`
+///
+/// Symbolic links inside the share are fine as long as they do not
+/// lead out of it.
+pub fn inside_root(root: &Path, path: &Path) -> bool {
+    path.canonicalize().is_ok_and(|real| real.starts_with(root))
+}
+
+/// Language variant of the requested file with the path it was read from
+///
+/// The variant is requested from `warp::fs` like any file, so range and
+/// conditional requests work on it, and marked `Vary: Accept-Language`.
+/// Files without variants are rejected for the regular file route.
+fn negotiated_files(root: PathBuf) -> BoxedFilter<(PathBuf, warp::reply::Response)> {
+    let variants = warp::service(warp::fs::dir(root.clone()));
+    warp::get()
+        .or(warp::head())
+        .unify()
+        .and(warp::method())
+        .and(warp::path::tail())
+        .and(warp::header::headers_cloned())
+        .and_then(
+            move |method: warp::http::Method,
+                  tail: warp::path::Tail,
+                  headers: warp::http::HeaderMap| {
+                let file = resolve_request_file(&root, tail.as_str());
+                let root = root.clone();
+                let mut variants = variants.clone();
+                async move {
+                    let file = file.ok_or_else(warp::reject::not_found)?;
+                    let accept_language = headers
+                        .get(warp::http::header::ACCEPT_LANGUAGE)
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string);
+                    let chosen_root = root.clone();
+                    let chosen = tokio::task::spawn_blocking(move || {
+                        negotiate_language(&file, accept_language.as_deref())
+                            .filter(|chosen| inside_root(&chosen_root, chosen))
+                    })
+                    .await
+                    .ok()
+                    .flatten()
+                    .ok_or_else(warp::reject::not_found)?;
+                    let uri = chosen
+                        .strip_prefix(&root)
+                        .ok()
+                        .and_then(|relative| {
+                            relative
+                                .iter()
+                                .map(|part| {
+                                    part.to_str().map(|part| {
+                                        percent_encoding::utf8_percent_encode(
+                                            part,
+                                            percent_encoding::NON_ALPHANUMERIC,
+                                        )
+                                        .to_string()
+                                    })
+                                })
+                                .collect::<Option<Vec<_>>>()
+                        })
+                        .map(|parts| format!("/{}", parts.join("/")))
+                        .ok_or_else(warp::reject::not_found)?;
+                    let mut request = warp::http::Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .body(Body::empty())
+                        .map_err(|_| warp::reject::not_found())?;
+                    *request.headers_mut() = headers;
+                    // A task of its own, warp cannot route a request while routing another
+                    let mut response = tokio::spawn(async move {
+                        warp::hyper::service::Service::call(&mut variants, request).await
+                    })
+                    .await
+                    .ok()
+                    .and_then(Result::ok)
+                    .ok_or_else(warp::reject::not_found)?;
+                    response.headers_mut().append(
+                        warp::http::header::VARY,
+                        warp::http::HeaderValue::from_static("Accept-Language"),
+                    );
+                    Ok::<_, warp::Rejection>((chosen, response))
+                }
+            },
+        )
+        .untuple_one()
+        .boxed()
+}
+
+/// Group of an A/B test visitor
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AbGroup {
+    A,
+    B,
+}
+
+impl AbGroup {
+    fn cookie_value(self) -> &'static str {
+        match self {
+            AbGroup::A => "a",
+            AbGroup::B => "b",
+        }
+    }
+}
+
+/// Group a visitor's `Cookie` header assigns, if it names one
+fn ab_group_from_cookie(header: &str, cookie_name: &str) -> Option<AbGroup> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == cookie_name)
+        .and_then(|(_, value)| match value.trim() {
+            "a" => Some(AbGroup::A),
+            "b" => Some(AbGroup::B),
+            _ => None,
+        })
+}
+
+/// Serve visitors from `group_a` or `group_b` by their group cookie
+///
+/// New visitors are drawn at random, hashed from their address and user
+/// agent so the parallel requests of a first page load land in the same
+/// group, and get the cookie set on the response.
+fn ab_test_routes(
+    ab: &AbTestConfig,
+    group_a: BoxedFilter<(warp::reply::Response,)>,
+    group_b: BoxedFilter<(warp::reply::Response,)>,
+    stats: Arc<HttpStats>,
+    state: SharedState,
+    addr_source: ClientAddrSource,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    let cookie_name = ab.cookie_name.clone();
+    let weight = ab.group_a_weight.min(100) as u64;
+    let seed = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    let assign = client_addr(&addr_source)
+        .and(warp::header::optional::<String>("cookie"))
+        .and(warp::header::optional::<String>("user-agent"))
+        .map(
+            move |remote: Option<SocketAddr>, cookie: Option<String>, agent: Option<String>| {
+                if let Some(group) = cookie
+                    .as_deref()
+                    .and_then(|c| ab_group_from_cookie(c, &cookie_name))
+                {
+                    return (group, false);
+                }
+                let mut hasher = std::hash::DefaultHasher::new();
+                std::hash::Hash::hash(&(seed, remote.map(|a| a.ip()), agent), &mut hasher);
+                let draw = std::hash::Hasher::finish(&hasher) % 100;
+                let group = if draw < weight {
+                    AbGroup::A
+                } else {
+                    AbGroup::B
+                };
+                (group, true)
+            },
+        )
+        .untuple_one()
+        .boxed();
+
+    let cookie_name = ab.cookie_name.clone();
+    let branch = move |group: AbGroup, routes: BoxedFilter<(warp::reply::Response,)>| {
+        let stats = stats.clone();
+        let state = state.clone();
+        let cookie_name = cookie_name.clone();
+        assign
+            .clone()
+            .and_then(move |assigned: AbGroup, fresh: bool| async move {
+                if assigned == group {
+                    Ok(fresh)
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            })
+            .and(routes)
+            .map(move |fresh: bool, mut response: warp::reply::Response| {
+                if let Some(ab) = &stats.ab_requests {
+                    let counter = if group == AbGroup::A { &ab.a } else { &ab.b };
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+                state.write().metrics.ab_requests[group as usize] += 1;
+                if fresh {
+                    let cookie = format!(
+                        "{}={}; Path=/; Max-Age=2592000; SameSite=Lax",
+                        cookie_name,
+                        group.cookie_value()
+                    );
+                    if let Ok(value) = warp::http::HeaderValue::from_str(&cookie) {
+                        response
+                            .headers_mut()
+                            .append(warp::http::header::SET_COOKIE, value);
+                    }
+                }
+                response
+            })
+            .boxed()
+    };
+    branch(AbGroup::A, group_a)
+        .or(branch(AbGroup::B, group_b))
+        .unify()
+        .boxed()
+}
+
+/// Where the client address of a request comes from
+#[derive(Clone)]
+pub struct ClientAddrSource {
+    /// The peer address is in our accept loop's header instead of the connection
+    from_header: bool,
+    /// Peers whose `X-Forwarded-For` names the real client
+    trusted_proxies: Arc<Vec<IpNet>>,
+    /// Untrusted peers already warned about, so each is logged once
+    warned: Arc<Mutex<HashSet<IpAddr>>>,
+    state: SharedState,
+}
+
+impl ClientAddrSource {
+    pub fn new(from_header: bool, trusted_proxies: Vec<IpNet>, state: SharedState) -> Self {
+        Self {
+            from_header,
+            trusted_proxies: Arc::new(trusted_proxies),
+            warned: Arc::default(),
+            state,
+        }
+    }
+
+    fn trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Client address given the connection's `peer` and its `X-Forwarded-For` header
+    ///
+    /// Behind trusted proxies this is the rightmost forwarded address that is
+    /// not a trusted proxy itself, without a port as the header has none.
+    fn resolve(&self, peer: Option<SocketAddr>, forwarded_for: Option<&str>) -> Option<SocketAddr> {
+        let (Some(peer), Some(forwarded_for)) = (peer, forwarded_for) else {
+            return peer;
+        };
+        if !self.trusted(peer.ip()) {
+            if self.warned.lock().insert(peer.ip()) {
+                self.state.write().add_log(LogMessage::warning(format!(
+                    "X-Forwarded-For from untrusted {} ignored, it may be spoofed",
+                    peer.ip()
+                )));
+            }
+            return Some(peer);
+        }
+        let forwarded: Vec<IpAddr> = forwarded_for
+            .split(',')
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect();
+        let client = forwarded
+            .iter()
+            .rev()
+            .find(|ip| !self.trusted(**ip))
+            .or(forwarded.first());
+        Some(client.map_or(peer, |ip| SocketAddr::new(*ip, 0)))
+    }
+}
+
+/// Client address of a request, see [`ClientAddrSource::resolve`]
+pub fn client_addr(source: &ClientAddrSource) -> BoxedFilter<(Option<SocketAddr>,)> {
+    let peer = if source.from_header {
+        warp::header::optional::<SocketAddr>(proxy_protocol::CLIENT_ADDR_HEADER).boxed()
+    } else {
+        warp::addr::remote().boxed()
+    };
+    let source = source.clone();
+    peer.and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            move |peer: Option<SocketAddr>, forwarded_for: Option<String>| {
+                source.resolve(peer, forwarded_for.as_deref())
+            },
+        )
+        .boxed()
+}
+
+/// Route serving the virtual files, dated with the server start time
+fn virtual_files_route(
+    files: &[VirtualFile],
+    port: u16,
+    started: SystemTime,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    let ip = local_ip().map_or_else(|| "127.0.0.1".to_string(), |ip| ip.to_string());
+    let last_modified = chrono::DateTime::<chrono::Utc>::from(started)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let files: Arc<HashMap<String, (Bytes, String)>> = Arc::new(
+        files
+            .iter()
+            .filter(|file| !file.path.trim().is_empty())
+            .map(|file| {
+                let content = file
+                    .content
+                    .replace("{{server_port}}", &port.to_string())
+                    .replace("{{server_ip}}", &ip);
+                let path = format!("/{}", file.path.trim().trim_start_matches('/'));
+                (path, (Bytes::from(content), file.content_type.clone()))
+            })
+            .collect(),
+    );
+    warp::get()
+        .and(warp::path::full())
+        .and_then(move |full: warp::path::FullPath| {
+            let file = files.get(full.as_str()).cloned();
+            let last_modified = last_modified.clone();
+            async move {
+                let (content, content_type) = file.ok_or_else(warp::reject::not_found)?;
+                let response = warp::http::Response::builder()
+                    .header(warp::http::header::CONTENT_TYPE, content_type)
+                    .header(warp::http::header::LAST_MODIFIED, last_modified)
+                    .body(Body::from(content))
+                    .unwrap_or_default();
+                Ok::<_, warp::Rejection>(response)
+            }
+        })
+        .boxed()
+}
+
+/// Answer `OPTIONS` for any path, including `OPTIONS *`, with the methods `mode` serves
+fn options_route(mode: &HttpMode) -> BoxedFilter<(warp::reply::Response,)> {
+    let allowed = match mode {
+        HttpMode::FileServer => "GET, HEAD, OPTIONS",
+        HttpMode::KeyValueStore { .. } => "GET, PUT, DELETE, OPTIONS",
+    };
+    // `warp::options()` would reject other methods with 405, outranking the
+    // 404 of the routes behind it, so reject with not found instead
+    warp::method()
+        .and_then(|method: warp::http::Method| async move {
+            if method == warp::http::Method::OPTIONS {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .map(move || {
+            warp::http::Response::builder()
+                .status(warp::http::StatusCode::NO_CONTENT)
+                .header(warp::http::header::ALLOW, allowed)
+                .body(Body::empty())
+                .unwrap_or_default()
+        })
+        .boxed()
+}
+
+/// Answer requests whose path has more than `max` segments with 400, reject others
+fn path_depth_guard(max: usize) -> BoxedFilter<(warp::reply::Response,)> {
+    warp::path::full()
+        .and_then(move |path: warp::path::FullPath| async move {
+            let depth = path.as_str().split('/').filter(|s| !s.is_empty()).count();
+            if depth <= max {
+                return Err(warp::reject::not_found());
+            }
+            Ok(warp::reply::with_status(
+                format!("Path too deep: {} segments, at most {} allowed", depth, max),
+                warp::http::StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        })
+        .boxed()
+}
+
+/// Token bucket refills per second for throttled responses
+const THROTTLE_TICKS_PER_SEC: u64 = 10;
+
+/// Find the QoS rule for a served file, matching case-insensitively on the file name
+fn match_qos_rule<'a>(rules: &'a [QosRule], path: &Path) -> Option<&'a QosRule> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    rules
+        .iter()
+        .find(|rule| glob_match(&rule.extension_pattern.to_lowercase(), &name))
+}
+
+/// Wrap a response body in a token bucket limiting it to `max_kbps`
+fn throttle_body(body: Body, max_kbps: u32) -> Body {
+    let bytes_per_sec = (max_kbps as u64 * 1000 / 8).max(THROTTLE_TICKS_PER_SEC);
+    let mut interval = tokio::time::interval(Duration::from_millis(1000 / THROTTLE_TICKS_PER_SEC));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let throttled = stream::unfold(
+        (body, interval, 0u64, Bytes::new()),
+        move |(mut body, mut interval, mut tokens, mut pending)| async move {
+            loop {
+                if pending.is_empty() {
+                    match body.next().await {
+                        Some(Ok(chunk)) => pending = chunk,
+                        Some(Err(e)) => return Some((Err(e), (body, interval, tokens, pending))),
+                        None => return None,
+                    }
+                    continue;
+                }
+                if tokens == 0 {
+                    interval.tick().await;
+                    tokens = bytes_per_sec / THROTTLE_TICKS_PER_SEC;
+                    continue;
+                }
+                let n = pending.len().min(tokens as usize);
+                let chunk = pending.split_to(n);
+                tokens -= n as u64;
+                return Some((Ok(chunk), (body, interval, tokens, pending)));
+            }
+        },
+    );
+    Body::wrap_stream(throttled)
+}
+
+/// Chunk size files are sent in unless configured otherwise
+pub const DEFAULT_STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Range the configured stream buffer size is clamped to
+const STREAM_BUFFER_SIZES: std::ops::RangeInclusive<usize> = 4 * 1024..=16 * 1024 * 1024;
+
+/// Whether `path` matches one of the access log exclusions, where a
+/// trailing `*` matches any rest of the path
+fn excluded_from_log(patterns: &[String], path: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == pattern,
+        })
+}
+
+/// Send a response body in chunks of `size` bytes, only the last one may be shorter
+///
+/// Files are read in blocks of the file system's choosing, this gathers or
+/// splits them before they are written to the connection.
+fn rebuffer_body(body: Body, size: usize) -> Body {
+    let rebuffered = stream::unfold(
+        (Some(body), Vec::with_capacity(size)),
+        move |(body, mut buf)| async move {
+            let mut body = body?;
+            loop {
+                if buf.len() >= size {
+                    let rest = buf.split_off(size);
+                    let chunk = std::mem::replace(&mut buf, rest);
+                    return Some((Ok(Bytes::from(chunk)), (Some(body), buf)));
+                }
+                match body.next().await {
+                    Some(Ok(chunk)) => buf.extend_from_slice(&chunk),
+                    Some(Err(e)) => return Some((Err(e), (None, buf))),
+                    None if buf.is_empty() => return None,
+                    None => {
+                        let chunk = std::mem::take(&mut buf);
+                        return Some((Ok(Bytes::from(chunk)), (None, buf)));
+                    }
+                }
+            }
+        },
+    );
+    Body::wrap_stream(rebuffered)
+}
+
+/// Files at least this large are watched for changes while they are sent
+const WATCHED_FILE_MIN_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How often a watched file is checked during a transfer
+const FILE_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Size and modification time a file is compared by while it is sent
+fn file_fingerprint(metadata: &std::fs::Metadata) -> (u64, Option<SystemTime>) {
+    (metadata.len(), metadata.modified().ok())
+}
+
+/// End the transfer of `path` with an error once the file no longer matches `fingerprint`
+///
+/// A file truncated or replaced mid-download would otherwise reach the
+/// client corrupted without any sign of it. Aborting the response makes
+/// the client report an incomplete download instead.
+fn abort_on_change(
+    body: Body,
+    path: PathBuf,
+    fingerprint: (u64, Option<SystemTime>),
+    state: SharedState,
+) -> Body {
+    type BoxError = Box<dyn std::error::Error + Send + Sync>;
+    let checked = stream::unfold(
+        Some((body, path, state, Instant::now())),
+        move |watch| async move {
+            let (mut body, path, state, mut last_check) = watch?;
+            let chunk = body.next().await;
+            // Always check at the end, a truncated file just ends early
+            if chunk.is_none() || last_check.elapsed() >= FILE_WATCH_INTERVAL {
+                last_check = Instant::now();
+                let current = tokio::fs::metadata(&path)
+                    .await
+                    .ok()
+                    .map(|m| file_fingerprint(&m));
+                if current != Some(fingerprint) {
+                    state.write().add_log(LogMessage::warning(format!(
+                        "Download of {} aborted: file changed during transfer",
+                        path.display()
+                    )));
+                    let e: BoxError = "file changed during transfer".into();
+                    return Some((Err(e), None));
+                }
+            }
+            match chunk? {
+                Ok(chunk) => Some((Ok(chunk), Some((body, path, state, last_check)))),
+                Err(e) => Some((Err(e.into()), None)),
+            }
+        },
+    );
+    Body::wrap_stream(checked)
+}
+
+/// Size of the chunks a ZIP archive is streamed in
+const ZIP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `Write` adapter sending buffered output to a response body channel
+struct ChannelWriter {
+    tx: mpsc::Sender<std::io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= ZIP_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buf));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client disconnected"))
+    }
+}
+
+/// A file to add to a ZIP archive
+struct ZipEntry {
+    /// Path in the storage backend
+    path: PathBuf,
+    /// Path inside the archive, `/` separated
+    name: String,
+}
+
+/// List the files under `dir` for zipping, returning the entries and their total size
+///
+/// Directories are implied by the file paths, streamed archives with explicit
+/// directory entries are rejected by some unzip tools.
+fn collect_zip_entries(backend: &dyn StorageBackend, dir: &Path) -> (Vec<ZipEntry>, u64) {
+    let mut entries = Vec::new();
+    let mut total = 0;
+    let mut pending = vec![(dir.to_path_buf(), String::new())];
+
+    while let Some((dir, prefix)) = pending.pop() {
+        let Ok(items) = backend.list(&dir) else {
+            continue;
+        };
+        for item in items {
+            // Don't follow symlinks, they may point outside the root
+            if item.is_symlink {
+                continue;
+            }
+            let name = format!("{}{}", prefix, item.name);
+            let path = dir.join(&item.name);
+            if item.metadata.is_dir {
+                pending.push((path, format!("{}/", name)));
+            } else {
+                total += item.metadata.size;
+                entries.push(ZipEntry { path, name });
+            }
+        }
+    }
+    (entries, total)
+}
+
+/// Write the entries as a ZIP archive into `writer`, blocking
+fn write_zip(
+    backend: &dyn StorageBackend,
+    entries: &[ZipEntry],
+    total: u64,
+    writer: ChannelWriter,
+) -> zip::result::ZipResult<()> {
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(total > u32::MAX as u64);
+    let mut zip = zip::ZipWriter::new_stream(writer);
+    for entry in entries {
+        zip.start_file(entry.name.as_str(), options)?;
+        std::io::copy(&mut backend.read(&entry.path)?, &mut zip)?;
+    }
+    zip.finish()?.into_inner().flush()?;
+    Ok(())
+}
+
+/// Respond with `dir` zipped on the fly, or 413 if it is larger than `max_bytes`
+async fn zip_response(
+    backend: Arc<dyn StorageBackend>,
+    dir: &Path,
+    max_bytes: u64,
+    state: SharedState,
+) -> warp::reply::Response {
+    // Walking a large tree blocks, keep it off the runtime
+    let walk_backend = backend.clone();
+    let walk_dir = dir.to_path_buf();
+    let walked = tokio::task::spawn_blocking(move || {
+        let (entries, total) = collect_zip_entries(&*walk_backend, &walk_dir);
+        if total > max_bytes {
+            return Err(total);
+        }
+        Ok((entries, total))
+    })
+    .await;
+    let (entries, total) = match walked {
+        Ok(Ok(walked)) => walked,
+        Ok(Err(total)) => {
+            state.write().add_log(LogMessage::error(format!(
+                "ZIP download of {} refused: {} exceeds the {} limit",
+                dir.display(),
+                format_size(total),
+                format_size(max_bytes)
+            )));
+            return warp::reply::with_status(
+                "Directory too large to download as ZIP",
+                warp::http::StatusCode::PAYLOAD_TOO_LARGE,
+            )
+            .into_response();
+        }
+        Err(_) => {
+            return warp::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let name = dir
+        .file_name()
+        .or_else(|| backend.local_root()?.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "download".to_string());
+    let (tx, rx) = mpsc::channel(4);
+    let dir_display = dir.display().to_string();
+    tokio::task::spawn_blocking(move || {
+        let writer = ChannelWriter {
+            tx: tx.clone(),
+            buf: Vec::new(),
+        };
+        if let Err(e) = write_zip(&*backend, &entries, total, writer) {
+            // A closed channel means the client went away, nothing to report
+            if !tx.is_closed() {
+                state.write().add_log(LogMessage::error(format!(
+                    "ZIP download of {} failed: {}",
+                    dir_display, e
+                )));
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+        }
+    });
+
+    let body = Body::wrap_stream(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+    let disposition = format!(
+        "attachment; filename=\"{}.zip\"; filename*=UTF-8''{}.zip",
+        name.replace(['"', '\\'], "_"),
+        percent_encoding::utf8_percent_encode(&name, percent_encoding::NON_ALPHANUMERIC)
+    );
+    let mut response = warp::reply::Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(
+        warp::http::header::CONTENT_TYPE,
+        warp::http::HeaderValue::from_static("application/zip"),
+    );
+    if let Ok(value) = warp::http::HeaderValue::from_str(&disposition) {
+        headers.insert(warp::http::header::CONTENT_DISPOSITION, value);
+    }
+    response
+}
+
+/// Serve files from a storage backend that has no local directory
+///
+/// Unlike `warp::fs::dir` this answers neither range nor conditional requests.
+fn backend_files(
+    backend: Arc<dyn StorageBackend>,
+) -> BoxedFilter<(PathBuf, warp::reply::Response)> {
+    warp::get()
+        .and(warp::path::tail())
+        .and_then(move |tail: warp::path::Tail| {
+            let backend = backend.clone();
+            async move {
+                let path = percent_encoding::percent_decode_str(tail.as_str())
+                    .decode_utf8()
+                    .map(|p| PathBuf::from(p.as_ref()))
+                    .map_err(|_| warp::reject::not_found())?;
+                let open_path = path.clone();
+                let opened = tokio::task::spawn_blocking(move || {
+                    let metadata = backend.stat(&open_path)?;
+                    if metadata.is_dir {
+                        return Err(std::io::ErrorKind::NotFound.into());
+                    }
+                    Ok::<_, std::io::Error>((metadata, backend.read(&open_path)?))
+                })
+                .await;
+                let Ok(Ok((metadata, mut reader))) = opened else {
+                    return Err(warp::reject::not_found());
+                };
+
+                let (tx, rx) = mpsc::channel(4);
+                tokio::task::spawn_blocking(move || {
+                    let mut writer = ChannelWriter {
+                        tx: tx.clone(),
+                        buf: Vec::new(),
+                    };
+                    if let Err(e) =
+                        std::io::copy(&mut reader, &mut writer).and_then(|_| writer.flush())
+                    {
+                        let _ = tx.blocking_send(Err(e));
+                    }
+                });
+                let body = Body::wrap_stream(stream::unfold(rx, |mut rx| async move {
+                    rx.recv().await.map(|chunk| (chunk, rx))
+                }));
+                let mime = mime_guess::from_path(&path).first_or_octet_stream();
+                let response = warp::http::Response::builder()
+                    .header(warp::http::header::CONTENT_TYPE, mime.as_ref())
+                    .header(warp::http::header::CONTENT_LENGTH, metadata.size)
+                    .body(body)
+                    .unwrap_or_default();
+                Ok::<_, warp::Rejection>((path, response))
+            }
+        })
+        .untuple_one()
+        .boxed()
+}
+
+/// Counts a download as queued in the server state while alive
+struct QueuedDownload(SharedState);
+
+impl QueuedDownload {
+    fn new(state: SharedState) -> Self {
+        state.write().queued_downloads += 1;
+        Self(state)
+    }
+}
+
+impl Drop for QueuedDownload {
+    fn drop(&mut self) {
+        self.0.write().queued_downloads -= 1;
+    }
+}
+
+/// A download slot, counted as active in the server state until the body is done
+struct ActiveDownload {
+    _permit: OwnedSemaphorePermit,
+    state: SharedState,
+}
+
+impl ActiveDownload {
+    fn new(permit: OwnedSemaphorePermit, state: SharedState) -> Self {
+        state.write().active_downloads += 1;
+        Self {
+            _permit: permit,
+            state,
+        }
+    }
+}
+
+impl Drop for ActiveDownload {
+    fn drop(&mut self) {
+        self.state.write().active_downloads -= 1;
+    }
+}
+
+/// Wait for a download slot, giving up after `timeout`
+async fn acquire_download_slot(
+    slots: Arc<Semaphore>,
+    timeout: Duration,
+    state: SharedState,
+) -> Option<ActiveDownload> {
+    let _queued = QueuedDownload::new(state.clone());
+    let permit = tokio::time::timeout(timeout, slots.acquire_owned())
+        .await
+        .ok()?
+        .ok()?;
+    Some(ActiveDownload::new(permit, state))
+}
+
+/// Keep `slot` alive until the body has been fully sent or dropped
+fn hold_until_done(body: Body, slot: ActiveDownload) -> Body {
+    Body::wrap_stream(body.map(move |chunk| {
+        let _ = &slot;
+        chunk
+    }))
+}
+
+/// Maximum number of sensitive files reported by a scan
+const MAX_SENSITIVE_RESULTS: usize = 100;
+
+/// Find files and directories under `root` matching any of the sensitive patterns
+pub fn scan_sensitive(root: &Path, patterns: &[String]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            if found.len() >= MAX_SENSITIVE_RESULTS {
+                return found;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Don't follow symlinks, they may point outside the root
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+
+            let matched = patterns
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('/') {
+                    Some(dir_pattern) => is_dir && glob_match(dir_pattern, &name),
+                    None => glob_match(pattern, &name),
+                });
+            if matched {
+                found.push(entry.path());
+            } else if is_dir {
+                pending.push(entry.path());
+            }
+        }
+    }
+    found
+}
+
+/// A generated directory listing and the directory state it was built from
+struct CachedListing {
+    modified: SystemTime,
+    cached_at: Instant,
+    html: String,
+}
+
+/// Listings keyed by directory, request path and sort order
+type ListingCache = Arc<Mutex<HashMap<(PathBuf, String, ListingSort), CachedListing>>>;
+
+/// Cookie remembering the last sort order chosen in a listing
+const SORT_COOKIE: &str = "listing_sort";
+
+/// Column a directory listing is sorted by, directories always come first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum SortField {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    Type,
+}
+
+impl SortField {
+    const ALL: [SortField; 4] = [
+        SortField::Name,
+        SortField::Size,
+        SortField::Modified,
+        SortField::Type,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortField::Name => "name",
+            SortField::Size => "size",
+            SortField::Modified => "modified",
+            SortField::Type => "type",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            SortField::Name => "Name",
+            SortField::Size => "Size",
+            SortField::Modified => "Modified",
+            SortField::Type => "Type",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl SortOrder {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Ascending => "asc",
+            SortOrder::Descending => "desc",
+        }
+    }
+}
+
+/// Sort order of a directory listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+struct ListingSort {
+    field: SortField,
+    order: SortOrder,
+}
+
+impl ListingSort {
+    /// Parse `sort` and `order` values such as `size` and `desc`
+    fn parse(field: &str, order: Option<&str>) -> Option<Self> {
+        let field = SortField::ALL
+            .into_iter()
+            .find(|f| f.as_str().eq_ignore_ascii_case(field))?;
+        let order = match order.map(str::to_ascii_lowercase).as_deref() {
+            None | Some("asc") => SortOrder::Ascending,
+            Some("desc") => SortOrder::Descending,
+            Some(_) => return None,
+        };
+        Some(Self { field, order })
+    }
+
+    fn from_cookie(value: &str) -> Option<Self> {
+        let (field, order) = value.split_once('.')?;
+        Self::parse(field, Some(order))
+    }
+
+    fn cookie_value(&self) -> String {
+        format!("{}.{}", self.field.as_str(), self.order.as_str())
+    }
+
+    /// Column header linking to this column sorted ascending, or reversed when already so
+    fn header(&self, field: SortField) -> String {
+        let (order, arrow) = match (self.field == field, self.order) {
+            (true, SortOrder::Ascending) => (SortOrder::Descending, " ▲"),
+            (true, SortOrder::Descending) => (SortOrder::Ascending, " ▼"),
+            (false, _) => (SortOrder::Ascending, ""),
+        };
+        format!(
+            r#"<th><a href="?sort={}&amp;order={}">{}{}</a></th>"#,
+            field.as_str(),
+            order.as_str(),
+            field.title(),
+            arrow
+        )
+    }
+
+    fn compare(&self, a: &ListingItem, b: &ListingItem) -> std::cmp::Ordering {
+        let by_field = match self.field {
+            SortField::Name => std::cmp::Ordering::Equal,
+            SortField::Size => a.size.cmp(&b.size),
+            SortField::Modified => a.modified.cmp(&b.modified),
+            SortField::Type => a.file_type.cmp(&b.file_type),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+        // Directories stay above files whatever the order
+        b.is_dir.cmp(&a.is_dir).then(match self.order {
+            SortOrder::Ascending => by_field,
+            SortOrder::Descending => by_field.reverse(),
+        })
+    }
+}
+
+/// Directory entry shown in a listing
+struct ListingItem {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+    /// Lowercase extension, empty for directories and files without one
+    file_type: String,
+}
+
+/// Generate a directory listing off the async runtime
+///
+/// With a non-zero `ttl` the listing is reused until it expires or the
+/// directory's modification time changes.
+#[allow(clippy::too_many_arguments)]
+async fn cached_directory_listing(
+    backend: Arc<dyn StorageBackend>,
+    cache: ListingCache,
+    ttl: Duration,
+    path: PathBuf,
+    request_path: String,
+    base_path: Arc<str>,
+    sort: ListingSort,
+    zip_link: bool,
+    banner: Option<Arc<str>>,
+) -> Option<String> {
+    tokio::task::spawn_blocking(move || {
+        let modified = backend
+            .stat(&path)
+            .ok()
+            .and_then(|m| m.modified)
+            .filter(|_| !ttl.is_zero());
+        let key = (path, request_path, sort);
+
+        let cached = modified.and_then(|modified| {
+            cache
+                .lock()
+                .get(&key)
+                .filter(|c| c.modified == modified && c.cached_at.elapsed() < ttl)
+                .map(|c| c.html.clone())
+        });
+        if cached.is_some() {
+            return cached;
+        }
+
+        let html = generate_directory_listing(
+            &*backend,
+            &key.0,
+            &key.1,
+            &base_path,
+            sort,
+            zip_link,
+            banner.as_deref(),
+        )?;
+        if let Some(modified) = modified {
+            let mut cache = cache.lock();
+            cache.retain(|_, c| c.cached_at.elapsed() < ttl);
+            cache.insert(
+                key,
+                CachedListing {
+                    modified,
+                    cached_at: Instant::now(),
+                    html: html.clone(),
+                },
+            );
+        }
+        Some(html)
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Read and sort the entries of a directory, statting each one only once
+fn listing_items(
+    backend: &dyn StorageBackend,
+    path: &Path,
+    sort: ListingSort,
+) -> Option<Vec<ListingItem>> {
+    let mut items: Vec<_> = backend
+        .list(path)
+        .ok()?
+        .into_iter()
+        .map(|entry| {
+            let is_dir = entry.metadata.is_dir;
+            ListingItem {
+                file_type: if is_dir {
+                    String::new()
+                } else {
+                    Path::new(&entry.name)
+                        .extension()
+                        .map(|e| e.to_string_lossy().to_lowercase())
+                        .unwrap_or_default()
+                },
+                name: entry.name,
+                is_dir,
+                size: entry.metadata.size,
+                modified: entry.metadata.modified,
+            }
+        })
+        .collect();
+    items.sort_by(|a, b| sort.compare(a, b));
+    Some(items)
+}
+
+/// Text table formats a directory listing can be exported as
+#[derive(Debug, Clone, Copy)]
+pub enum ListingFormat {
+    Markdown,
+    Csv,
+}
+
+/// Export the entries of `path` as a table of name, size and modification time
+///
+/// Directories come first and get a trailing `/`. CSV sizes are in bytes
+/// so spreadsheets can sum them.
+pub fn export_listing(path: &Path, format: ListingFormat) -> Option<String> {
+    let items = listing_items(&LocalFs::new(path), Path::new(""), ListingSort::default())?;
+    let mut out = match format {
+        ListingFormat::Markdown => "| Name | Size | Modified |\n|---|---:|---|\n".to_string(),
+        ListingFormat::Csv => "name,size,modified\n".to_string(),
+    };
+    for item in items {
+        let name = if item.is_dir {
+            format!("{}/", item.name)
+        } else {
+            item.name
+        };
+        let modified = item
+            .modified
+            .map(|t| {
+                let datetime: chrono::DateTime<chrono::Local> = t.into();
+                datetime.format("%Y-%m-%d %H:%M").to_string()
+            })
+            .unwrap_or_default();
+        match format {
+            ListingFormat::Markdown => {
+                let size = if item.is_dir {
+                    "-".to_string()
+                } else {
+                    format_size(item.size)
+                };
+                out.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    name.replace('|', "\\|"),
+                    size,
+                    modified
+                ));
+            }
+            ListingFormat::Csv => {
+                let size = if item.is_dir {
+                    String::new()
+                } else {
+                    item.size.to_string()
+                };
+                out.push_str(&format!("{},{},{}\n", csv_field(&name), size, modified));
+            }
         }
     }
+    Some(out)
 }
 
-impl From<HttpConfig> for ServerConfig {
-    fn from(cfg: HttpConfig) -> Self {
-        ServerConfig {
-            root_dir: cfg.root_dir,
-            port: cfg.port,
-            auto_stop_seconds: cfg.auto_stop_seconds,
-        }
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
 }
 
-/// Generate HTML for directory listing
-fn generate_directory_listing(path: &std::path::Path, request_path: &str) -> Option<String> {
-    let entries = std::fs::read_dir(path).ok()?;
+/// Default and largest number of entries per page of a JSON listing
+const JSON_PAGE_SIZE: usize = 100;
+const MAX_JSON_PAGE_SIZE: usize = 1000;
+
+/// Generate a JSON directory listing for API clients, `page` counting from 1
+fn generate_json_listing(
+    backend: &dyn StorageBackend,
+    path: &Path,
+    request_path: &str,
+    sort: ListingSort,
+    page: usize,
+    page_size: usize,
+) -> Option<String> {
+    let items = listing_items(backend, path, sort)?;
+    let total = items.len();
+    let entries: Vec<_> = items
+        .into_iter()
+        .skip(page.saturating_sub(1).saturating_mul(page_size))
+        .take(page_size)
+        .map(|item| {
+            serde_json::json!({
+                "name": item.name,
+                "type": if item.is_dir { "dir" } else { "file" },
+                "size": (!item.is_dir).then_some(item.size),
+                "modified": item.modified.map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339()),
+            })
+        })
+        .collect();
+    serde_json::to_string(&serde_json::json!({
+        "path": request_path,
+        "page": page,
+        "page_size": page_size,
+        "total": total,
+        "entries": entries,
+    }))
+    .ok()
+}
+
+/// Random base64 nonce for the `style-src` of one listing response
+fn csp_nonce() -> Option<String> {
+    let mut nonce = [0u8; 16];
+    getrandom::getrandom(&mut nonce).ok()?;
+    Some(base64::engine::general_purpose::STANDARD.encode(nonce))
+}
+
+/// Put `nonce` on the listing's style sheet and build the matching policy
+///
+/// The `<style>` tag comes before anything taken from the request or the
+/// file names, so only the first one is ours to mark.
+fn apply_csp_nonce(html: &str, nonce: &str) -> (String, String) {
+    let html = html.replacen("<style>", &format!("<style nonce=\"{}\">", nonce), 1);
+    let policy = format!("default-src 'none'; style-src 'nonce-{}'", nonce);
+    (html, policy)
+}
+
+/// `base_path` from the config as `/prefix`, or empty when the share is at the root
+fn normalize_base_path(base_path: Option<&str>) -> String {
+    match base_path.map(|p| p.trim().trim_matches('/')) {
+        Some(p) if !p.is_empty() => format!("/{}", p),
+        _ => String::new(),
+    }
+}
+
+/// Generate HTML for directory listing, with `banner` HTML above it and
+/// links under `base_path`
+fn generate_directory_listing(
+    backend: &dyn StorageBackend,
+    path: &Path,
+    request_path: &str,
+    base_path: &str,
+    sort: ListingSort,
+    zip_link: bool,
+    banner: Option<&str>,
+) -> Option<String> {
+    let items = listing_items(backend, path, sort)?;
+    let dir_url = format!("{}{}/", base_path, request_path.trim_end_matches('/'));
 
     let mut html = format!(
         r#"<!DOCTYPE html>
 <html>
 <head>
     <meta charset="utf-8">
-    <title>Index of {}</title>
     <style>
         body {{ font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif; margin: 20px; background: #f5f5f5; }}
         h1 {{ color: #333; border-bottom: 2px solid #4CAF50; padding-bottom: 10px; }}
         table {{ border-collapse: collapse; width: 100%; background: white; box-shadow: 0 1px 3px rgba(0,0,0,0.1); }}
         th, td {{ padding: 12px 15px; text-align: left; border-bottom: 1px solid #ddd; }}
         th {{ background: #4CAF50; color: white; }}
+        th a {{ color: white; }}
         tr:hover {{ background: #f1f1f1; }}
         a {{ color: #1976D2; text-decoration: none; }}
         a:hover {{ text-decoration: underline; }}
         .icon {{ margin-right: 8px; }}
         .size {{ color: #666; }}
         .date {{ color: #888; }}
+        .banner {{ background: #fff3cd; border: 1px solid #ffe08a; border-radius: 4px; color: #664d03; padding: 12px 15px; font-weight: 500; }}
+        .footer {{ color: #888; margin-top: 20px; font-size: 12px; }}
     </style>
+    <title>Index of {}</title>
 </head>
 <body>
-    <h1>📁 Index of {}</h1>
+{}    <h1>📁 Index of {}</h1>
     <table>
-        <tr><th>Name</th><th>Size</th><th>Modified</th></tr>
+        <tr>{}</tr>
 "#,
-        request_path, request_path
+        dir_url,
+        banner
+            .map(|banner| format!("    <div class=\"banner\">{}</div>\n", banner))
+            .unwrap_or_default(),
+        dir_url,
+        SortField::ALL
+            .iter()
+            .map(|&field| sort.header(field))
+            .collect::<String>()
     );
 
     // Add parent directory link if not at root
     if request_path != "/" {
-        html.push_str(r#"        <tr><td><span class="icon">📂</span><a href="../">..</a></td><td>-</td><td>-</td></tr>
-"#);
+        let parent = dir_url
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .map_or("", |(parent, _)| parent);
+        html.push_str(&format!(
+            r#"        <tr><td><span class="icon">📂</span><a href="{}/">..</a></td><td>-</td><td>-</td><td>-</td></tr>
+"#,
+            parent
+        ));
     }
 
-    // Collect and sort entries
-    let mut items: Vec<_> = entries.filter_map(|e| e.ok()).collect();
-    items.sort_by(|a, b| {
-        let a_is_dir = a.path().is_dir();
-        let b_is_dir = b.path().is_dir();
-        match (a_is_dir, b_is_dir) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.file_name().cmp(&b.file_name()),
-        }
-    });
-
-    for entry in items {
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
-        let entry_path = entry.path();
-        let is_dir = entry_path.is_dir();
-
-        let (icon, href, size_str) = if is_dir {
-            ("📂", format!("{}/", file_name_str), "-".to_string())
+    for item in items {
+        let (icon, href, size_str) = if item.is_dir {
+            ("📂", format!("{}{}/", dir_url, item.name), "-".to_string())
         } else {
-            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
-            let size_str = format_size(size);
-            ("📄", file_name_str.to_string(), size_str)
+            (
+                "📄",
+                format!("{}{}", dir_url, item.name),
+                format_size(item.size),
+            )
         };
 
-        let modified = entry
-            .metadata()
-            .and_then(|m| m.modified())
+        let modified = item
+            .modified
             .map(|t| {
                 let datetime: chrono::DateTime<chrono::Local> = t.into();
                 datetime.format("%Y-%m-%d %H:%M").to_string()
             })
-            .unwrap_or_else(|_| "-".to_string());
+            .unwrap_or_else(|| "-".to_string());
+        let file_type = if item.file_type.is_empty() {
+            "-"
+        } else {
+            &item.file_type
+        };
 
         html.push_str(&format!(
-            r#"        <tr><td><span class="icon">{}</span><a href="{}">{}</a></td><td class="size">{}</td><td class="date">{}</td></tr>
+            r#"        <tr><td><span class="icon">{}</span><a href="{}">{}</a></td><td class="size">{}</td><td class="date">{}</td><td class="type">{}</td></tr>
 "#,
-            icon, href, file_name_str, size_str, modified
+            icon, href, item.name, size_str, modified, file_type
         ));
     }
 
+    html.push_str("    </table>\n");
+    if zip_link {
+        html.push_str(
+            r#"    <p><a href="?zip=1">⬇ Download as ZIP</a></p>
+"#,
+        );
+    }
     html.push_str(
-        r#"    </table>
-    <p style="color:#888;margin-top:20px;font-size:12px;">OServers HTTP Server</p>
+        r#"    <p class="footer">OServers HTTP Server</p>
 </body>
 </html>"#,
     );
@@ -127,7 +2323,7 @@ fn generate_directory_listing(path: &std::path::Path, request_path: &str) -> Opt
 }
 
 /// Format file size in human readable format
-fn format_size(bytes: u64) -> String {
+pub fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
     const GB: u64 = MB * 1024;
@@ -143,6 +2339,9 @@ fn format_size(bytes: u64) -> String {
     }
 }
 
+/// Time Tor gets to set up the onion service before the server runs without it
+const TOR_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Start HTTP server
 pub async fn start_server(
     config: HttpConfig,
@@ -157,107 +2356,1052 @@ pub async fn start_server(
     {
         let mut s = state.write();
         s.status = ServerStatus::Starting;
+        s.metrics = Metrics::default();
         s.add_log(LogMessage::info(format!(
             "Starting HTTP server on port {}...",
             port
         )));
     }
+    if !wait_for_root(
+        &config.root_dir,
+        config.startup.wait_for_root_secs,
+        &mut shutdown_rx,
+        &state,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    // Without a separate HTTPS port a TLS server serves HTTPS on the main port
+    let (plain_port, tls_port) = match (&config.tls, config.https_port) {
+        (Some(_), Some(https_port)) => (Some(port), Some(https_port)),
+        (Some(_), None) => (None, Some(port)),
+        (None, _) => (Some(port), None),
+    };
+    let tls_conflict = if config.tls.is_some() && config.proxy_protocol {
+        Some("the PROXY protocol is not supported over HTTPS".to_string())
+    } else if plain_port.is_some() && plain_port == tls_port {
+        Some(format!("HTTP and HTTPS cannot share port {}", port))
+    } else {
+        None
+    };
+    let invalid_exclusion = config
+        .access_log_exclude_paths
+        .iter()
+        .find(|pattern| !pattern.starts_with('/'))
+        .map(|pattern| format!("access log exclusion '{}' must start with /", pattern));
+    // An empty group B root would resolve requests against the working directory
+    let invalid_ab_test = match (&config.ab_test, &config.mode, &config.single_file) {
+        (Some(ab), HttpMode::FileServer, None) if ab.group_b_root.as_os_str().is_empty() => {
+            Some("A/B test: no group B root directory set".to_string())
+        }
+        (Some(ab), HttpMode::FileServer, None) if !ab.group_b_root.is_dir() => Some(format!(
+            "A/B test: group B root {} is not a directory",
+            ab.group_b_root.display()
+        )),
+        _ => None,
+    };
+    let invalid_stats_login = config.stats_page_path.as_ref().and_then(|_| {
+        stats_login_problem(&config.stats_username, &config.stats_password).map(str::to_string)
+    });
+    if let Some(conflict) = tls_conflict
+        .or(invalid_exclusion)
+        .or(invalid_ab_test)
+        .or(invalid_stats_login)
+    {
+        let mut s = state.write();
+        s.status = ServerStatus::Error(conflict.clone());
+        s.add_log(LogMessage::error(format!("Cannot start: {}", conflict)));
+        return Err(ServerError::ConfigError(conflict));
+    }
+    // Session tickets need a key of our own, which warp's TLS server cannot take
+    let ticket_keys = match &config.tls {
+        Some(_) if config.tls_session_resumption => {
+            let keys = TicketKeys::generate().map(Arc::new);
+            if keys.is_none() {
+                state.write().add_log(LogMessage::warning(
+                    "TLS session resumption disabled: no randomness available for a ticket key",
+                ));
+            }
+            keys
+        }
+        _ => None,
+    };
+    let tls_acceptor = match &config.tls {
+        Some(tls) => match tls::server_config(tls, ticket_keys.clone()) {
+            Ok(tls_config) => Some(tokio_rustls::TlsAcceptor::from(Arc::new(tls_config))),
+            Err(e) => {
+                let msg = format!(
+                    "Failed to serve HTTPS on port {}: {}",
+                    tls_port.unwrap_or(port),
+                    e
+                );
+                let mut s = state.write();
+                s.status = ServerStatus::Error(msg.clone());
+                s.add_log(LogMessage::error(&msg));
+                return Err(ServerError::ConfigError(msg));
+            }
+        },
+        None => None,
+    };
+    // Connections from our own accept loop carry the client address in a header
+    let addr_header =
+        config.proxy_protocol || config.egress_throttle_kbps.is_some() || tls_acceptor.is_some();
+    let base_path = normalize_base_path(config.base_path.as_deref());
+    let addr_source =
+        ClientAddrSource::new(addr_header, config.trusted_proxies.clone(), state.clone());
+
+    // Load the key-value store before serving anything
+    let kv_store = match (&config.mode, &config.single_file) {
+        (HttpMode::KeyValueStore { store_path }, None) => match KvStore::open(store_path) {
+            Ok(store) => Some(store),
+            Err(e) => {
+                let mut s = state.write();
+                s.status = ServerStatus::Error(e.to_string());
+                s.add_log(LogMessage::error(format!(
+                    "Failed to load key-value store {}: {}",
+                    store_path.display(),
+                    e
+                )));
+                return Err(e.into());
+            }
+        },
+        _ => None,
+    };
+
+    // Refuse to start with a bad key rather than serve unsigned responses
+    let signer = match &config.sign_responses {
+        Some(signing) => match ResponseSigner::load(signing) {
+            Ok(signer) => {
+                state.write().add_log(LogMessage::info(format!(
+                    "Response signing: {} key {}",
+                    signing.algorithm.name(),
+                    signer.fingerprint()
+                )));
+                Some(Arc::new(signer))
+            }
+            Err(e) => {
+                let msg = format!("Failed to load response signing key: {}", e);
+                let mut s = state.write();
+                s.status = ServerStatus::Error(msg.clone());
+                s.add_log(LogMessage::error(&msg));
+                return Err(ServerError::ConfigError(msg));
+            }
+        },
+        None => None,
+    };
 
-    // Clone root for use in filters
-    let root_for_listing = root.clone();
+    let started_at = Instant::now();
+    let requests_served = Arc::new(AtomicU64::new(0));
+    let pages = PageRenderer {
+        pages: config.pages.clone(),
+        index_html: config.idle_page_html.clone(),
+        port,
+        started_at,
+        requests: requests_served.clone(),
+    };
+    // Download slots are shared by all roots, A/B test groups included
+    let download_slots = config
+        .max_concurrent_downloads
+        .map(|max| Arc::new(Semaphore::new(max.max(1))));
 
-    // Directory listing handler
-    let listing_root = root.clone();
-    let dir_listing =
-        warp::path::tail()
+    // Listing and file routes for a root directory, built once per A/B test group
+    let file_routes = |root: PathBuf| -> BoxedFilter<(warp::reply::Response,)> {
+        // Resolved once, so paths in a symlinked root compare against its real location
+        let root = root.canonicalize().unwrap_or(root);
+        // Files are read through the storage backend, the local root directory for now
+        let backend: Arc<dyn StorageBackend> = Arc::new(LocalFs::new(root.clone()));
+
+        // Directory listing handler
+        let listing_backend = backend.clone();
+        let listing_pages = pages.clone();
+        let idle_page = config.idle_page_html.is_some() || config.pages.index.is_some();
+        let zip_downloads = allow_listing && config.zip_downloads;
+        let json_listing = config.json_listing;
+        let slash_redirect = config.redirect_directory_slash;
+        let listing_base: Arc<str> = base_path.clone().into();
+        let max_zip_bytes = config.max_zip_bytes;
+        let zip_state = state.clone();
+        let listing_cache = ListingCache::default();
+        let listing_cache_ttl = Duration::from_secs(config.listing_cache_ttl_secs);
+        let listing_csp = config.csp_enabled;
+        let listing_banner: Option<Arc<str>> = config
+            .listing_banner
+            .as_deref()
+            .map(str::trim)
+            .filter(|banner| !banner.is_empty())
+            .map(|banner| render_listing_banner(banner).into());
+        let dir_listing = warp::path::tail()
             .and(warp::get())
-            .and_then(move |tail: warp::path::Tail| {
-                let root = listing_root.clone();
-                let allow = allow_listing;
-                async move {
-                    let request_path = format!("/{}", tail.as_str());
-                    let full_path = root.join(tail.as_str());
-
-                    // Check if it's a directory
-                    if full_path.is_dir() {
-                        // Check for index.html first
-                        let index_path = full_path.join("index.html");
-                        if index_path.exists() {
-                            // Let the file server handle index.html
-                            return Err(warp::reject::not_found());
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+            .and(warp::cookie::optional::<String>(SORT_COOKIE))
+            .and(warp::header::optional::<String>("accept"))
+            .and_then(
+                move |tail: warp::path::Tail,
+                      query: HashMap<String, String>,
+                      raw_query: String,
+                      sort_cookie: Option<String>,
+                      accept: Option<String>| {
+                    let backend = listing_backend.clone();
+                    let allow = allow_listing;
+                    let pages = listing_pages.clone();
+                    let zip_state = zip_state.clone();
+                    let listing_cache = listing_cache.clone();
+                    let listing_banner = listing_banner.clone();
+                    let listing_base = listing_base.clone();
+                    async move {
+                        let request_path = format!("/{}", tail.as_str());
+                        let dir = PathBuf::from(tail.as_str());
+
+                        // Check if it's a directory
+                        if backend.stat(&dir).is_ok_and(|m| m.is_dir) {
+                            // `?listing=1` forces the generated listing even if an index exists
+                            let force_listing = query.get("listing").is_some_and(|v| v == "1");
+
+                            if zip_downloads && query.get("zip").is_some_and(|v| v == "1") {
+                                return Ok(
+                                    zip_response(backend, &dir, max_zip_bytes, zip_state).await
+                                );
+                            }
+
+                            // Check for index.html first
+                            let has_index = backend.stat(&dir.join("index.html")).is_ok();
+
+                            // Relative links would resolve against the parent without the slash
+                            let missing_slash =
+                                !tail.as_str().is_empty() && !tail.as_str().ends_with('/');
+                            if slash_redirect && missing_slash && (allow || has_index) {
+                                let location = if raw_query.is_empty() {
+                                    format!("{}{}/", listing_base, request_path)
+                                } else {
+                                    format!("{}{}/?{}", listing_base, request_path, raw_query)
+                                };
+                                if let Ok(location) = warp::http::HeaderValue::from_str(&location) {
+                                    let mut response = warp::reply::with_status(
+                                        warp::reply(),
+                                        warp::http::StatusCode::MOVED_PERMANENTLY,
+                                    )
+                                    .into_response();
+                                    response
+                                        .headers_mut()
+                                        .insert(warp::http::header::LOCATION, location);
+                                    return Ok(response);
+                                }
+                            }
+
+                            if has_index && !(allow && force_listing) {
+                                // Let the file server handle index.html
+                                return Err(warp::reject::not_found());
+                            }
+
+                            if allow {
+                                // `?sort=size&order=desc` picks the order and is remembered in a cookie
+                                let requested_sort = query.get("sort").and_then(|f| {
+                                    ListingSort::parse(f, query.get("order").map(String::as_str))
+                                });
+                                let sort = requested_sort
+                                    .or_else(|| {
+                                        sort_cookie.as_deref().and_then(ListingSort::from_cookie)
+                                    })
+                                    .unwrap_or_default();
+                                if json_listing
+                                    && accept.is_some_and(|a| a.contains("application/json"))
+                                {
+                                    let page = query
+                                        .get("page")
+                                        .and_then(|p| p.parse().ok())
+                                        .unwrap_or(1usize)
+                                        .max(1);
+                                    let page_size = query
+                                        .get("page_size")
+                                        .and_then(|p| p.parse().ok())
+                                        .unwrap_or(JSON_PAGE_SIZE)
+                                        .clamp(1, MAX_JSON_PAGE_SIZE);
+                                    let json = tokio::task::spawn_blocking(move || {
+                                        generate_json_listing(
+                                            &*backend,
+                                            &dir,
+                                            &request_path,
+                                            sort,
+                                            page,
+                                            page_size,
+                                        )
+                                    })
+                                    .await
+                                    .ok()
+                                    .flatten();
+                                    if let Some(json) = json {
+                                        let response = warp::reply::with_header(
+                                            warp::reply::with_header(
+                                                json,
+                                                warp::http::header::CONTENT_TYPE,
+                                                "application/json",
+                                            ),
+                                            warp::http::header::VARY,
+                                            "Accept",
+                                        );
+                                        return Ok(response.into_response());
+                                    }
+                                    return Err(warp::reject::not_found());
+                                }
+                                if let Some(html) = cached_directory_listing(
+                                    backend,
+                                    listing_cache,
+                                    listing_cache_ttl,
+                                    dir,
+                                    request_path,
+                                    listing_base.clone(),
+                                    sort,
+                                    zip_downloads,
+                                    listing_banner,
+                                )
+                                .await
+                                {
+                                    // A fresh nonce per response, cached listings are shared
+                                    let csp = listing_csp
+                                        .then(csp_nonce)
+                                        .flatten()
+                                        .map(|nonce| apply_csp_nonce(&html, &nonce));
+                                    let mut response = match csp {
+                                        Some((html, policy)) => {
+                                            let mut response =
+                                                warp::reply::html(html).into_response();
+                                            if let Ok(value) =
+                                                warp::http::HeaderValue::from_str(&policy)
+                                            {
+                                                response.headers_mut().insert(
+                                                    warp::http::header::CONTENT_SECURITY_POLICY,
+                                                    value,
+                                                );
+                                            }
+                                            response
+                                        }
+                                        None => warp::reply::html(html).into_response(),
+                                    };
+                                    if json_listing {
+                                        response.headers_mut().insert(
+                                            warp::http::header::VARY,
+                                            warp::http::HeaderValue::from_static("Accept"),
+                                        );
+                                    }
+                                    if let Some(sort) = requested_sort {
+                                        let cookie = format!(
+                                            "{}={}; Path={}/; Max-Age=31536000; SameSite=Lax",
+                                            SORT_COOKIE,
+                                            sort.cookie_value(),
+                                            listing_base
+                                        );
+                                        if let Ok(value) =
+                                            warp::http::HeaderValue::from_str(&cookie)
+                                        {
+                                            response
+                                                .headers_mut()
+                                                .insert(warp::http::header::SET_COOKIE, value);
+                                        }
+                                    }
+                                    return Ok(response);
+                                }
+                            } else if idle_page {
+                                return Ok(pages.reply(Page::Index, &request_path));
+                            }
                         }
+                        Err(warp::reject::not_found())
+                    }
+                },
+            );
 
-                        if allow {
-                            if let Some(html) =
-                                generate_directory_listing(&full_path, &request_path)
-                            {
-                                return Ok(warp::reply::html(html));
+        // Serve files, throttling those matching a QoS rule and queueing
+        // downloads beyond the concurrency limit
+        let qos_rules = config.qos_rules.clone();
+        let qos_state = state.clone();
+        let download_slots = download_slots.clone();
+        let queue_timeout = Duration::from_secs(config.download_queue_timeout_secs);
+        let stream_buffer_size = config
+            .stream_buffer_size_bytes
+            .clamp(*STREAM_BUFFER_SIZES.start(), *STREAM_BUFFER_SIZES.end());
+        // Only local files can be checked for changes during a transfer
+        let watch_changes = backend.local_root().is_some();
+        let served = match backend.local_root() {
+            // Local files go through warp for its range and conditional request support
+            Some(root) => {
+                let root = root.to_path_buf();
+                let escape_state = state.clone();
+                warp::fs::dir(root.clone())
+                    .and_then(move |file: warp::fs::File| {
+                        let path = file.path().to_path_buf();
+                        let inside = inside_root(&root, &path);
+                        let state = escape_state.clone();
+                        async move {
+                            if !inside {
+                                state.write().add_log(LogMessage::warning(format!(
+                                    "Blocked {}: link leads outside the root directory",
+                                    path.display()
+                                )));
+                                return Err(warp::reject::not_found());
+                            }
+                            Ok((path, file.into_response()))
+                        }
+                    })
+                    .untuple_one()
+                    .boxed()
+            }
+            None => backend_files(backend.clone()),
+        };
+        // Variants are picked from local files only
+        let served = match backend.local_root() {
+            Some(root) if config.enable_content_negotiation => negotiated_files(root.to_path_buf())
+                .or(served)
+                .unify()
+                .boxed(),
+            _ => served,
+        };
+        let files = served.and_then(move |path: PathBuf, response: warp::reply::Response| {
+            let rule = match_qos_rule(&qos_rules, &path).cloned();
+            let state = qos_state.clone();
+            let slots = download_slots.clone();
+            async move {
+                // Taken before waiting for a slot, the file is already open
+                let fingerprint = if watch_changes {
+                    tokio::fs::metadata(&path)
+                        .await
+                        .ok()
+                        .filter(|m| m.len() >= WATCHED_FILE_MIN_SIZE)
+                        .map(|m| file_fingerprint(&m))
+                } else {
+                    None
+                };
+                let slot = match slots {
+                    Some(slots) => {
+                        match acquire_download_slot(slots, queue_timeout, state.clone()).await {
+                            Some(slot) => Some(slot),
+                            None => {
+                                state.write().add_log(LogMessage::error(format!(
+                                    "Download of {} timed out waiting for a free slot",
+                                    path.display()
+                                )));
+                                return Ok::<_, warp::Rejection>(
+                                    warp::reply::with_status(
+                                        "Too many downloads in progress, try again later",
+                                        warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                                    )
+                                    .into_response(),
+                                );
                             }
                         }
                     }
-                    Err(warp::reject::not_found())
+                    None => None,
+                };
+
+                let mut response = response;
+                let body = std::mem::take(response.body_mut());
+                *response.body_mut() = rebuffer_body(body, stream_buffer_size);
+                if let Some(fingerprint) = fingerprint {
+                    let body = std::mem::take(response.body_mut());
+                    *response.body_mut() =
+                        abort_on_change(body, path.clone(), fingerprint, state.clone());
                 }
-            });
+                if let Some(rule) = rule {
+                    state.write().add_log(LogMessage::debug(format!(
+                        "QoS: limiting to {} kbps (rule {})",
+                        rule.max_kbps, rule.extension_pattern
+                    )));
+                    let body = std::mem::take(response.body_mut());
+                    *response.body_mut() = throttle_body(body, rule.max_kbps);
+                }
+                if let Some(slot) = slot {
+                    let body = std::mem::take(response.body_mut());
+                    *response.body_mut() = hold_until_done(body, slot);
+                }
+                Ok(response)
+            }
+        });
 
-    // Serve files
-    let files = warp::fs::dir(root_for_listing);
+        let files = files.map(Reply::into_response).boxed();
+        dir_listing.or(files).map(Reply::into_response).boxed()
+    };
 
-    // Add logging
+    // Add logging, response sizes are handed over from the route wrapper below
+    // keyed by client address and path since `warp::log::Info` lacks them
+    let response_sizes = ResponseSizes::default();
+    let log_sizes = response_sizes.clone();
+    let stats = Arc::new(HttpStats {
+        ab_requests: config.ab_test.as_ref().map(|_| AbCounters::default()),
+        ..HttpStats::default()
+    });
+    let log_stats = stats.clone();
+    let log_requests = requests_served.clone();
     let log_state = state.clone();
+    let webhooks = config.webhooks.clone();
+    let client_lookup = if config.reverse_dns || config.geoip_db.is_some() {
+        match ClientLookup::new(config.reverse_dns, config.geoip_db.as_deref()) {
+            Ok(lookup) => Some(lookup),
+            Err(e) => {
+                state.write().add_log(LogMessage::warning(format!(
+                    "Client lookups disabled, failed to open GeoIP database: {}",
+                    e
+                )));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let log_lookups = client_lookup.is_some();
+    let log_sampler = Arc::new(LogSampler::new(config.log_sample_rate));
+    let log_addr_source = addr_source.clone();
+    let log_exclusions = config.access_log_exclude_paths.clone();
     let log = warp::log::custom(move |info| {
+        let peer = if addr_header {
+            info.request_headers()
+                .get(proxy_protocol::CLIENT_ADDR_HEADER)
+                .and_then(|v| v.to_str().ok()?.parse().ok())
+        } else {
+            info.remote_addr()
+        };
+        let forwarded_for = info
+            .request_headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok());
+        let remote_addr = log_addr_source.resolve(peer, forwarded_for);
+        log_requests.fetch_add(1, Ordering::Relaxed);
+        log_stats.record(info.path(), remote_addr.map(|addr| addr.ip()));
+        let size = log_sizes
+            .lock()
+            .remove(&(remote_addr, info.path().to_string()));
         let msg = format!(
-            "{} {} {} {}ms",
+            "{} {} {} {} {}ms",
             info.method(),
             info.path(),
             info.status().as_u16(),
+            size.map(format_size).unwrap_or_else(|| "-".to_string()),
             info.elapsed().as_millis()
         );
-        log_state.write().add_log(LogMessage::info(msg));
+        {
+            let mut s = log_state.write();
+            s.metrics.requests += 1;
+            s.record_activity();
+        }
+        let failed = info.status().is_client_error() || info.status().is_server_error();
+        let logged =
+            failed || (!excluded_from_log(&log_exclusions, info.path()) && log_sampler.sample());
+        if logged {
+            match (&client_lookup, remote_addr) {
+                (Some(lookup), Some(addr)) => {
+                    lookup.log(addr.ip(), format!("{} from {}", msg, addr.ip()), &log_state)
+                }
+                _ => log_state.write().add_log(LogMessage::info(msg)),
+            }
+        }
+
+        if !webhooks.is_empty() {
+            let details = serde_json::json!({
+                "method": info.method().as_str(),
+                "path": info.path(),
+                "status": info.status().as_u16(),
+                "remote_addr": remote_addr.map(|addr| addr.to_string()),
+                "elapsed_ms": info.elapsed().as_millis() as u64,
+            });
+            if failed {
+                webhook::fire(
+                    &webhooks,
+                    WebhookTrigger::OnError,
+                    details.clone(),
+                    &log_state,
+                );
+            }
+            webhook::fire(&webhooks, WebhookTrigger::OnAnyRequest, details, &log_state);
+        }
     });
 
+    let signed_url_secret = config.signed_url_secret.clone().filter(|s| !s.is_empty());
     // Combine routes: try dir listing first, then files
-    let routes = dir_listing.or(files).with(log);
-
-    let addr: SocketAddr = ([0, 0, 0, 0], port).into();
+    let routes = match (config.single_file.clone(), &kv_store) {
+        (Some(file), _) => single_file_route(file),
+        (None, Some(store)) => kv::routes(
+            store.clone(),
+            config.webhooks.clone(),
+            config.accept_compressed_uploads,
+            state.clone(),
+        ),
+        (None, None) => {
+            let routes = file_routes(root.clone());
+            let routes = match &config.ab_test {
+                Some(ab) => ab_test_routes(
+                    ab,
+                    routes,
+                    file_routes(ab.group_b_root.clone()),
+                    stats.clone(),
+                    state.clone(),
+                    addr_source.clone(),
+                ),
+                None => routes,
+            };
+            let routes = if config.virtual_files.is_empty() {
+                routes
+            } else {
+                virtual_files_route(&config.virtual_files, port, SystemTime::now())
+                    .or(routes)
+                    .unify()
+                    .boxed()
+            };
+            match signed_url_secret.clone() {
+                Some(secret) => signed_url::routes(
+                    secret,
+                    config
+                        .signed_url_admin_token
+                        .clone()
+                        .filter(|t| !t.is_empty()),
+                    root.canonicalize().unwrap_or_else(|_| root.clone()),
+                    base_path.clone(),
+                    config.webhooks.clone(),
+                    state.clone(),
+                    addr_source.clone(),
+                )
+                .or(routes)
+                .unify()
+                .boxed(),
+                None => routes,
+            }
+        }
+    };
+    let routes = match config.max_path_depth {
+        Some(max) => path_depth_guard(max).or(routes).unify().boxed(),
+        None => routes,
+    };
+    let routes = if config.maintenance_mode {
+        let pages = pages.clone();
+        warp::path::full()
+            .map(move |path: warp::path::FullPath| pages.reply(Page::Maintenance, path.as_str()))
+            .boxed()
+    } else {
+        routes
+    };
+    let stats_path = config
+        .stats_page_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("/{}", p.trim_start_matches('/')));
+    let routes = match stats_path.clone() {
+        Some(path) => stats_route(
+            path,
+            config.stats_username.clone(),
+            config.stats_password.clone(),
+            stats.clone(),
+            started_at,
+            requests_served.clone(),
+            config.webhooks.clone(),
+            state.clone(),
+            addr_source.clone(),
+        )
+        .or(routes)
+        .unify()
+        .boxed(),
+        None => routes,
+    };
+    let health_path = config
+        .health_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("/{}", p.trim_start_matches('/')));
+    let routes = match health_path {
+        Some(path) => health_route(path, started_at).or(routes).unify().boxed(),
+        None => routes,
+    };
+    let routes = options_route(&config.mode).or(routes).unify().boxed();
+    let file_root =
+        (config.single_file.is_none() && config.mode == HttpMode::FileServer).then(|| root.clone());
+    let routes = with_error_pages(routes, pages, file_root);
+    let count_state = state.clone();
+    let routes = client_addr(&addr_source)
+        .and(warp::path::full())
+        .and(routes)
+        .map(
+            move |remote: Option<SocketAddr>,
+                  path: warp::path::FullPath,
+                  response: warp::reply::Response| {
+                if let Some(size) = response_size(&response) {
+                    response_sizes
+                        .lock()
+                        .insert((remote, path.as_str().to_string()), size);
+                }
+                let mut response = count_response(
+                    response,
+                    stats.clone(),
+                    count_state.clone(),
+                    remote,
+                    path.as_str(),
+                );
+                if let Some(signer) = &signer {
+                    signer.sign(&mut response);
+                }
+                response
+            },
+        )
+        .with(log);
 
     // Update status to running
     {
         let mut s = state.write();
         s.status = ServerStatus::Running;
-        s.add_log(LogMessage::info(format!(
-            "HTTP server started on http://0.0.0.0:{}",
-            port
-        )));
-        s.add_log(LogMessage::info(format!(
-            "Serving files from: {}",
-            root.display()
-        )));
+        if let Some(port) = plain_port {
+            s.add_log(LogMessage::info(format!(
+                "HTTP server started on http://0.0.0.0:{}",
+                port
+            )));
+        }
+        if let Some(port) = tls_port {
+            s.add_log(LogMessage::info(format!(
+                "HTTPS server started on https://0.0.0.0:{}",
+                port
+            )));
+            s.add_log(LogMessage::info(if ticket_keys.is_some() {
+                "TLS session resumption: enabled, ticket key rotated daily"
+            } else {
+                "TLS session resumption: disabled"
+            }));
+        }
+        match (&config.single_file, &config.mode) {
+            (Some(file), _) => s.add_log(LogMessage::info(format!(
+                "Sharing single file: {}",
+                file.display()
+            ))),
+            (None, HttpMode::KeyValueStore { store_path }) => s.add_log(LogMessage::info(format!(
+                "Key-value store at /kv: {} ({} keys)",
+                store_path.display(),
+                kv_store.as_ref().map_or(0, |store| store.len())
+            ))),
+            (None, HttpMode::FileServer) => s.add_log(LogMessage::info(format!(
+                "Serving files from: {}",
+                root.display()
+            ))),
+        }
+        if let Some(path) = &stats_path {
+            s.add_log(LogMessage::info(format!("Statistics page: {}", path)));
+        }
+        if !base_path.is_empty() {
+            s.add_log(LogMessage::info(format!(
+                "Base path: links and redirects under {}",
+                base_path
+            )));
+        }
+        if signed_url_secret.is_some() && config.single_file.is_none() && kv_store.is_none() {
+            s.add_log(LogMessage::info(format!(
+                "Signed URLs: /__signed{}",
+                if config
+                    .signed_url_admin_token
+                    .as_deref()
+                    .is_some_and(|t| !t.is_empty())
+                {
+                    ", issued at /__admin/sign"
+                } else {
+                    ""
+                }
+            )));
+        }
+        if let Some(secs) = config.auto_stop_seconds {
+            s.add_log(LogMessage::info(format!(
+                "Auto stop: after {}s of inactivity",
+                secs
+            )));
+        }
+        if let Some(max) = config.max_path_depth {
+            s.add_log(LogMessage::info(format!(
+                "Path depth: at most {} segments",
+                max
+            )));
+        }
+        if let Some(max) = config.max_concurrent_downloads {
+            s.add_log(LogMessage::info(format!(
+                "Concurrent downloads: up to {} (queue timeout {}s)",
+                max, config.download_queue_timeout_secs
+            )));
+        }
+        for file in &config.virtual_files {
+            s.add_log(LogMessage::info(format!(
+                "Virtual file: {} ({})",
+                file.path, file.content_type
+            )));
+        }
+        for rule in &config.qos_rules {
+            s.add_log(LogMessage::info(format!(
+                "Bandwidth limit: {} at {} kbps",
+                rule.extension_pattern, rule.max_kbps
+            )));
+        }
+        if config.proxy_protocol {
+            s.add_log(LogMessage::info(
+                "PROXY protocol: required on every connection",
+            ));
+        }
+        if let Some(kbps) = config.egress_throttle_kbps {
+            s.add_log(LogMessage::info(format!(
+                "Egress limit: {} kbps per connection",
+                kbps
+            )));
+        }
+        if config.enable_content_negotiation {
+            s.add_log(LogMessage::info("Language negotiation: enabled"));
+        }
+        if let Some(ab) = &config.ab_test {
+            s.add_log(LogMessage::info(format!(
+                "A/B test: {}% of visitors from the root directory, the rest from {} (cookie {})",
+                ab.group_a_weight.min(100),
+                ab.group_b_root.display(),
+                ab.cookie_name
+            )));
+        }
+        if config.stream_buffer_size_bytes != DEFAULT_STREAM_BUFFER_SIZE {
+            s.add_log(LogMessage::info(format!(
+                "Files are sent in chunks of {}",
+                format_size(
+                    config
+                        .stream_buffer_size_bytes
+                        .clamp(*STREAM_BUFFER_SIZES.start(), *STREAM_BUFFER_SIZES.end())
+                        as u64
+                )
+            )));
+        }
+        if config.log_sample_rate < 1.0 {
+            s.add_log(LogMessage::info(format!(
+                "Request log: {:.0}% of requests sampled, errors always logged",
+                config.log_sample_rate.clamp(0.0, 1.0) * 100.0
+            )));
+        }
+        if log_lookups {
+            let mut lookups = Vec::new();
+            if config.reverse_dns {
+                lookups.push("reverse DNS".to_string());
+            }
+            if let Some(db) = &config.geoip_db {
+                lookups.push(format!("GeoIP ({})", db.display()));
+            }
+            s.add_log(LogMessage::info(format!(
+                "Client lookups: {}",
+                lookups.join(", ")
+            )));
+        }
+        if let Some(check) = &config.integrity_check {
+            s.add_log(LogMessage::info(format!(
+                "Integrity check: every {}s using {}",
+                check.check_interval_secs,
+                check.algorithm.name()
+            )));
+        }
         if allow_listing {
             s.add_log(LogMessage::info("Directory listing: enabled"));
+            if config.listing_cache_ttl_secs > 0 {
+                s.add_log(LogMessage::info(format!(
+                    "Listing cache: {}s",
+                    config.listing_cache_ttl_secs
+                )));
+            }
+            if config.csp_enabled {
+                s.add_log(LogMessage::info(
+                    "Listing CSP: styles limited to a per-request nonce",
+                ));
+            }
+            if config.zip_downloads {
+                s.add_log(LogMessage::info(format!(
+                    "ZIP downloads: enabled (up to {})",
+                    format_size(config.max_zip_bytes)
+                )));
+            }
+            if config.json_listing {
+                s.add_log(LogMessage::info(
+                    "JSON listing: for requests accepting application/json",
+                ));
+            }
+        } else if config.idle_page_html.is_some() || config.pages.index.is_some() {
+            s.add_log(LogMessage::info("Idle page: enabled"));
+        }
+        if config.maintenance_mode {
+            s.add_log(LogMessage::warning(
+                "Maintenance mode: answering all requests with 503",
+            ));
+        }
+        for (name, path) in [
+            ("Landing", &config.pages.index),
+            ("Not found", &config.pages.not_found),
+            ("Forbidden", &config.pages.forbidden),
+            ("Maintenance", &config.pages.maintenance),
+        ] {
+            let Some(path) = path else { continue };
+            if path.is_file() {
+                s.add_log(LogMessage::info(format!(
+                    "{} page: {}",
+                    name,
+                    path.display()
+                )));
+            } else {
+                s.add_log(LogMessage::warning(format!(
+                    "{} page {} not found, using the built-in page",
+                    name,
+                    path.display()
+                )));
+            }
         }
     }
 
     // Create server with graceful shutdown
-    let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(addr, async move {
-        shutdown_rx.recv().await;
+    let shutdown_state = state.clone();
+    let auto_stop_seconds = config.auto_stop_seconds;
+    let shutdown = async move {
+        wait_for_shutdown(&mut shutdown_rx, &shutdown_state, auto_stop_seconds).await;
+    }
+    .shared();
+    // Port the OS actually assigned, unknown where the listener binds later
+    let mut bound_port = None;
+    let plain = plain_port.map(|port| {
+        let addr: SocketAddr = ([0, 0, 0, 0], port).into();
+        if addr_header {
+            Either::Left(proxy_protocol::serve(
+                routes.clone(),
+                addr,
+                shutdown.clone(),
+                config.proxy_protocol,
+                config.egress_throttle_kbps,
+                None,
+                state.clone(),
+            ))
+        } else {
+            let (bound, server) =
+                warp::serve(routes.clone()).bind_with_graceful_shutdown(addr, shutdown.clone());
+            bound_port = Some(bound.port());
+            Either::Right(server.map(Ok))
+        }
     });
+    // Both listeners serve the same routes and stop on the same signal
+    let secure = match (tls_acceptor, tls_port) {
+        (Some(acceptor), Some(tls_port)) => Some(proxy_protocol::serve(
+            routes,
+            ([0, 0, 0, 0], tls_port).into(),
+            shutdown,
+            false,
+            config.egress_throttle_kbps,
+            Some(acceptor),
+            state.clone(),
+        )),
+        _ => None,
+    };
+    state.write().bound_port = bound_port;
+    startup_report::log(&state, "HTTP", &root, port, bound_port);
+    export_log_on_stop(&state, config.export_log_on_stop.as_deref(), "http");
+
+    let server = async move {
+        let plain = async move {
+            match plain {
+                Some(server) => server.await,
+                None => Ok(()),
+            }
+        };
+        let secure = async move {
+            match secure {
+                Some(server) => server.await,
+                None => Ok(()),
+            }
+        };
+        futures_util::future::try_join(plain, secure)
+            .await
+            .map(|_| ())
+    };
+
+    // Persist key-value store changes in the background
+    let kv_flush_task = kv_store
+        .clone()
+        .map(|store| tokio::spawn(store.flush_loop(state.clone())));
+
+    let ticket_rotation_task = ticket_keys.map(|keys| tokio::spawn(keys.rotate(state.clone())));
+
+    // Verify the served files in the background while running
+    let integrity_task = match (&config.integrity_check, &config.single_file, &kv_store) {
+        (Some(check), None, None) => Some(tokio::spawn(integrity::run(
+            root.clone(),
+            check.clone(),
+            state.clone(),
+        ))),
+        _ => None,
+    };
 
-    // Handle auto-stop timeout
-    if let Some(timeout_secs) = config.auto_stop_seconds {
-        let state_clone = state.clone();
-        tokio::spawn(async move {
-            tokio::time::sleep(tokio::time::Duration::from_secs(timeout_secs)).await;
-            let mut s = state_clone.write();
-            if matches!(s.status, ServerStatus::Running) {
+    // Withdrawn when dropped, however the server stops
+    let _announcement = if config.mdns_announce {
+        let (service, label) = match plain_port {
+            Some(_) => ("_http._tcp", "HTTP"),
+            None => ("_https._tcp", "HTTPS"),
+        };
+        mdns::announce(service, label, port, &[("path", "/")], &state)
+    } else {
+        None
+    };
+
+    let onion = if config.tor_hidden_service {
+        let create = OnionService::create(config.tor_control_port, port);
+        let created = match tokio::time::timeout(TOR_TIMEOUT, create).await {
+            Ok(created) => created,
+            Err(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "no answer from the Tor control port",
+            )),
+        };
+        match created {
+            Ok(onion) => {
+                let mut s = state.write();
                 s.add_log(LogMessage::info(format!(
-                    "Auto-stopping after {} seconds of inactivity",
-                    timeout_secs
+                    "Tor onion service: http://{}",
+                    onion.address()
                 )));
+                s.onion_address = Some(onion.address());
+                Some(onion)
             }
-        });
-    }
+            Err(e) => {
+                state.write().add_log(LogMessage::warning(format!(
+                    "Tor onion service not created (control port {}): {}",
+                    config.tor_control_port, e
+                )));
+                None
+            }
+        }
+    } else {
+        None
+    };
 
-    server.await;
+    let result = server.await;
+    if let Some(onion) = onion {
+        state.write().onion_address = None;
+        match onion.remove().await {
+            Ok(()) => state
+                .write()
+                .add_log(LogMessage::info("Tor onion service removed")),
+            Err(e) => state.write().add_log(LogMessage::warning(format!(
+                "Failed to remove Tor onion service, it goes away with the control connection: {}",
+                e
+            ))),
+        }
+    }
+    if let Some(task) = integrity_task {
+        task.abort();
+    }
+    if let Some(task) = ticket_rotation_task {
+        task.abort();
+    }
+    if let Some(task) = kv_flush_task {
+        task.abort();
+    }
+    if let Some(Err(e)) = kv_store.as_ref().map(|store| store.flush()) {
+        state.write().add_log(LogMessage::error(format!(
+            "Failed to save key-value store: {}",
+            e
+        )));
+    }
+    if let Err(e) = result {
+        let mut s = state.write();
+        s.status = ServerStatus::Error(e.to_string());
+        s.add_log(LogMessage::error(format!("HTTP server error: {}", e)));
+        return Err(e.into());
+    }
 
     // Update status
     {
@@ -265,6 +3409,7 @@ pub async fn start_server(
         s.status = ServerStatus::Stopped;
         s.add_log(LogMessage::info("HTTP server stopped"));
     }
+    finish_log_export(&state);
 
     Ok(())
 }
@@ -274,3 +3419,414 @@ pub async fn start_server(
 pub fn create_handle(config: HttpConfig) -> ServerHandle {
     ServerHandle::new(config.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{check_port_available, test_util};
+    use super::*;
+
+    #[tokio::test]
+    async fn options_allow_follows_the_mode() {
+        let file_server = options_route(&HttpMode::FileServer);
+        for path in ["/some/file.txt", "*"] {
+            let response = warp::test::request()
+                .method("OPTIONS")
+                .path(path)
+                .reply(&file_server)
+                .await;
+            assert_eq!(response.status(), warp::http::StatusCode::NO_CONTENT);
+            assert_eq!(response.headers()["allow"], "GET, HEAD, OPTIONS");
+        }
+
+        let kv_store = options_route(&HttpMode::KeyValueStore {
+            store_path: PathBuf::from("store.json"),
+        });
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/kv/key")
+            .reply(&kv_store)
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["allow"], "GET, PUT, DELETE, OPTIONS");
+    }
+
+    #[tokio::test]
+    async fn options_route_leaves_other_methods_alone() {
+        let route = options_route(&HttpMode::FileServer);
+        assert!(
+            !warp::test::request()
+                .method("GET")
+                .path("/")
+                .matches(&route)
+                .await
+        );
+    }
+
+    /// Run `config` in the background, returning the port the OS picked
+    async fn start(
+        config: HttpConfig,
+    ) -> (
+        u16,
+        SharedState,
+        mpsc::Sender<()>,
+        tokio::task::JoinHandle<Result<(), ServerError>>,
+    ) {
+        let state = test_util::new_state();
+        let (tx, rx) = mpsc::channel(1);
+        let server = tokio::spawn(start_server(config, state.clone(), rx));
+        test_util::wait_until(&state, "the server listens", |s| s.bound_port.is_some()).await;
+        let port = state.read().bound_port.unwrap_or_default();
+        (port, state, tx, server)
+    }
+
+    fn test_config(root: &Path) -> HttpConfig {
+        HttpConfig {
+            root_dir: root.to_path_buf(),
+            port: 0,
+            auto_stop_seconds: None,
+            ..HttpConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn directories_without_slash_redirect() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("docs")).unwrap();
+        std::fs::write(root.path().join("docs/readme.txt"), "readme").unwrap();
+        std::fs::write(root.path().join("file.txt"), "file").unwrap();
+        let (port, state, tx, server) = start(test_config(root.path())).await;
+
+        let dir = test_util::http_get(port, "/docs").await;
+        assert_eq!(dir.status, 301);
+        assert_eq!(dir.header("location"), Some("/docs/"));
+        let with_query = test_util::http_get(port, "/docs?sort=size").await;
+        assert_eq!(with_query.status, 301);
+        assert_eq!(with_query.header("location"), Some("/docs/?sort=size"));
+        let listing = test_util::http_get(port, "/docs/").await;
+        assert_eq!(listing.status, 200);
+
+        let file = test_util::http_get(port, "/file.txt").await;
+        assert_eq!(file.status, 200);
+        assert_eq!(file.header("location"), None);
+        assert_eq!(file.body, b"file");
+
+        let missing = test_util::http_get(port, "/missing").await;
+        assert_eq!(missing.status, 404);
+        assert_eq!(missing.header("location"), None);
+
+        test_util::stop(&state, &tx, server).await;
+    }
+
+    #[tokio::test]
+    async fn shutdown_releases_the_port() {
+        let root = tempfile::tempdir().unwrap();
+        let (port, state, tx, server) = start(test_config(root.path())).await;
+        assert_eq!(state.read().status, ServerStatus::Running);
+        assert_eq!(test_util::http_get(port, "/").await.status, 200);
+
+        test_util::stop(&state, &tx, server).await;
+        assert!(check_port_available(port, false));
+    }
+
+    #[tokio::test]
+    async fn shutdown_during_a_download_ends_it_cleanly() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let root = tempfile::tempdir().unwrap();
+        let size = 32 * 1024 * 1024;
+        std::fs::write(root.path().join("big.bin"), vec![7u8; size]).unwrap();
+        let (port, state, tx, server) = start(test_config(root.path())).await;
+
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        stream
+            .write_all(b"GET /big.bin HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut raw = vec![0u8; 64 * 1024];
+        let first = stream.read(&mut raw).await.unwrap();
+        raw.truncate(first);
+
+        // The download is still running as the server is told to stop
+        tx.send(()).await.unwrap();
+        tokio::time::timeout(test_util::TIMEOUT, stream.read_to_end(&mut raw))
+            .await
+            .expect("download hung after shutdown")
+            .expect("download failed");
+        let response = test_util::parse_response(&raw);
+        assert_eq!(response.status, 200);
+        // Either finished or cut off at a clean end of the connection
+        assert!(response.body.len() <= size);
+        assert!(response.body.iter().all(|&b| b == 7));
+
+        let result = tokio::time::timeout(test_util::TIMEOUT, server)
+            .await
+            .expect("server did not stop in time")
+            .unwrap();
+        assert!(result.is_ok());
+        assert_eq!(state.read().status, ServerStatus::Stopped);
+        assert!(check_port_available(port, false));
+    }
+
+    #[test]
+    fn listing_links_stay_under_base_path() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("sub/inner")).unwrap();
+        std::fs::write(root.path().join("a.txt"), "a").unwrap();
+        std::fs::write(root.path().join("sub/b.txt"), "b").unwrap();
+        let backend = LocalFs::new(root.path());
+
+        for (dir, request_path) in [("", "/"), ("sub", "/sub/")] {
+            let html = generate_directory_listing(
+                &backend,
+                Path::new(dir),
+                request_path,
+                "/files",
+                ListingSort::default(),
+                true,
+                None,
+            )
+            .unwrap();
+            // Sort and ZIP links are queries on the page itself
+            let links: Vec<_> = html
+                .split("href=\"")
+                .skip(1)
+                .filter_map(|rest| rest.split('"').next())
+                .filter(|href| !href.starts_with('?'))
+                .collect();
+            assert!(!links.is_empty());
+            for link in links {
+                assert!(
+                    link.starts_with("/files/"),
+                    "{} links to {}",
+                    request_path,
+                    link
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn language_variants_are_served_like_files() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("page.html"), "default").unwrap();
+        std::fs::write(root.path().join("page.de.html"), "deutsch").unwrap();
+        let config = HttpConfig {
+            enable_content_negotiation: true,
+            ..test_config(root.path())
+        };
+        let (port, state, tx, server) = start(config).await;
+        let get = |headers: &'static str| async move {
+            let mut stream = test_util::connect(port).await;
+            let request = format!(
+                "GET /page.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n",
+                headers
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut raw = Vec::new();
+            stream.read_to_end(&mut raw).await.unwrap();
+            test_util::parse_response(&raw)
+        };
+
+        let german = get("Accept-Language: de-AT, en;q=0.5\r\n").await;
+        assert_eq!(german.status, 200);
+        assert_eq!(german.body, b"deutsch");
+        assert_eq!(german.header("vary"), Some("Accept-Language"));
+
+        let other = get("Accept-Language: fr\r\n").await;
+        assert_eq!(other.body, b"default");
+        assert_eq!(other.header("vary"), Some("Accept-Language"));
+
+        let range = get("Accept-Language: de\r\nRange: bytes=0-2\r\n").await;
+        assert_eq!(range.status, 206);
+        assert_eq!(range.body, b"deu");
+
+        let unchanged =
+            get("Accept-Language: de\r\nIf-Modified-Since: Fri, 01 Jan 2100 00:00:00 GMT\r\n")
+                .await;
+        assert_eq!(unchanged.status, 304);
+
+        test_util::stop(&state, &tx, server).await;
+    }
+
+    #[tokio::test]
+    async fn stats_page_refuses_default_login() {
+        let root = tempfile::tempdir().unwrap();
+        for (username, password) in [("", ""), ("stats", ""), ("admin", "admin")] {
+            let config = HttpConfig {
+                stats_page_path: Some("/server-status".to_string()),
+                stats_username: username.to_string(),
+                stats_password: password.to_string(),
+                ..test_config(root.path())
+            };
+            let state = test_util::new_state();
+            let (_tx, rx) = mpsc::channel(1);
+            let result = start_server(config, state.clone(), rx).await;
+            assert!(matches!(result, Err(ServerError::ConfigError(_))));
+            assert!(matches!(state.read().status, ServerStatus::Error(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn stats_page_checks_the_login() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let root = tempfile::tempdir().unwrap();
+        let config = HttpConfig {
+            stats_page_path: Some("/server-status".to_string()),
+            stats_username: "stats".to_string(),
+            stats_password: "s3cret".to_string(),
+            ..test_config(root.path())
+        };
+        let (port, state, tx, server) = start(config).await;
+        let get = |credentials: &'static str| async move {
+            let mut stream = test_util::connect(port).await;
+            let request = format!(
+                "GET /server-status HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                 Authorization: Basic {}\r\n\r\n",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut raw = Vec::new();
+            stream.read_to_end(&mut raw).await.unwrap();
+            test_util::parse_response(&raw).status
+        };
+
+        assert_eq!(
+            test_util::http_get(port, "/server-status").await.status,
+            401
+        );
+        assert_eq!(get("stats:wrong").await, 401);
+        assert_eq!(get("stats:s3cret!").await, 401);
+        assert_eq!(get("stats:s3cret").await, 200);
+
+        test_util::stop(&state, &tx, server).await;
+    }
+
+    #[tokio::test]
+    async fn zip_download_respects_the_size_limit() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("small/nested")).unwrap();
+        std::fs::write(root.path().join("small/nested/a.txt"), "a").unwrap();
+        std::fs::create_dir(root.path().join("large")).unwrap();
+        std::fs::write(root.path().join("large/b.bin"), vec![0; 2048]).unwrap();
+        let config = HttpConfig {
+            max_zip_bytes: 1024,
+            ..test_config(root.path())
+        };
+        let (port, state, tx, server) = start(config).await;
+
+        let small = test_util::http_get(port, "/small/?zip=1").await;
+        assert_eq!(small.status, 200);
+        assert_eq!(small.header("content-type"), Some("application/zip"));
+        // Chunked, but the entry name is stored uncompressed in the archive
+        assert!(small.body.windows(12).any(|w| w == b"nested/a.txt"));
+        let large = test_util::http_get(port, "/large/?zip=1").await;
+        assert_eq!(large.status, 413);
+
+        test_util::stop(&state, &tx, server).await;
+    }
+
+    #[tokio::test]
+    async fn stop_ends_the_wait_for_the_root() {
+        let root = tempfile::tempdir().unwrap();
+        let config = HttpConfig {
+            startup: StartupConfig {
+                wait_for_root_secs: Some(600),
+                ..StartupConfig::default()
+            },
+            ..test_config(&root.path().join("not-mounted-yet"))
+        };
+        let state = test_util::new_state();
+        let (tx, rx) = mpsc::channel(1);
+        let server = tokio::spawn(start_server(config, state.clone(), rx));
+        test_util::wait_until(&state, "the server waits for the root", |s| {
+            s.logs.iter().any(|l| l.message.contains("Waiting up to"))
+        })
+        .await;
+
+        test_util::stop(&state, &tx, server).await;
+    }
+
+    #[tokio::test]
+    async fn shutdown_lets_throttled_downloads_finish() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let root = tempfile::tempdir().unwrap();
+        let size = 8 * 1024 * 1024;
+        std::fs::write(root.path().join("big.bin"), vec![7u8; size]).unwrap();
+        // The throttled listener is our own accept loop, which does not report its port
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = HttpConfig {
+            port,
+            // About 2 seconds for the file
+            egress_throttle_kbps: Some(32_000),
+            ..test_config(root.path())
+        };
+        let state = test_util::new_state();
+        let (tx, rx) = mpsc::channel(1);
+        let server = tokio::spawn(start_server(config, state.clone(), rx));
+
+        let mut stream = test_util::connect(port).await;
+        stream
+            .write_all(b"GET /big.bin HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut raw = vec![0u8; 64 * 1024];
+        let first = stream.read(&mut raw).await.unwrap();
+        raw.truncate(first);
+
+        tx.send(()).await.unwrap();
+        tokio::time::timeout(test_util::TIMEOUT, stream.read_to_end(&mut raw))
+            .await
+            .expect("download hung after shutdown")
+            .expect("download failed");
+        let response = test_util::parse_response(&raw);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.len(), size, "download cut off");
+
+        let result = tokio::time::timeout(test_util::TIMEOUT, server)
+            .await
+            .expect("server did not stop in time")
+            .unwrap();
+        assert!(result.is_ok());
+        assert_eq!(state.read().status, ServerStatus::Stopped);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_may_not_lead_out_of_a_symlinked_root() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let share = dir.path().join("share");
+        std::fs::create_dir(&share).unwrap();
+        std::fs::write(share.join("file.txt"), "shared").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        symlink(share.join("file.txt"), share.join("inside.txt")).unwrap();
+        symlink(dir.path().join("secret.txt"), share.join("escape.txt")).unwrap();
+        // The configured root is itself a link to the share
+        let root = dir.path().join("root");
+        symlink(&share, &root).unwrap();
+        let (port, state, tx, server) = start(test_config(&root)).await;
+
+        let file = test_util::http_get(port, "/file.txt").await;
+        assert_eq!(file.status, 200);
+        assert_eq!(file.body, b"shared");
+        let inside = test_util::http_get(port, "/inside.txt").await;
+        assert_eq!(inside.status, 200);
+        assert_eq!(inside.body, b"shared");
+        let escape = test_util::http_get(port, "/escape.txt").await;
+        assert_eq!(escape.status, 404);
+        assert!(!escape.body.windows(6).any(|w| w == b"secret"));
+
+        test_util::stop(&state, &tx, server).await;
+    }
+}
@@ -0,0 +1,206 @@
+//! Periodic integrity check of a served directory against a hash manifest
+
+use super::{LogMessage, SharedState};
+use sha2::Digest;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Hash function used for the manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha1,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 2] = [HashAlgorithm::Sha256, HashAlgorithm::Sha1];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "SHA-256",
+            HashAlgorithm::Sha1 => "SHA-1",
+        }
+    }
+
+    fn hash_file(&self, path: &Path) -> std::io::Result<String> {
+        match self {
+            HashAlgorithm::Sha256 => digest_file::<sha2::Sha256>(path),
+            HashAlgorithm::Sha1 => digest_file::<sha1::Sha1>(path),
+        }
+    }
+}
+
+/// When and how to check the served files
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IntegrityConfig {
+    /// Seconds between scans
+    pub check_interval_secs: u64,
+    pub algorithm: HashAlgorithm,
+    /// Manifest compared against, written from the first scan if missing
+    pub manifest_path: PathBuf,
+}
+
+/// Stored hashes keyed by path relative to the root, using `/` separators
+#[derive(Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    algorithm: HashAlgorithm,
+    files: BTreeMap<String, String>,
+}
+
+fn digest_file<D: Digest>(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = D::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Hash every file below `root` except the manifest itself
+fn scan(root: &Path, algorithm: HashAlgorithm, manifest_path: &Path) -> Manifest {
+    let manifest_path = manifest_path.canonicalize().ok();
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() && path.canonicalize().ok() != manifest_path {
+                let Ok(relative) = path.strip_prefix(root) else {
+                    continue;
+                };
+                let key = relative.to_string_lossy().replace('\\', "/");
+                if let Ok(hash) = algorithm.hash_file(&path) {
+                    files.insert(key, hash);
+                }
+            }
+        }
+    }
+    Manifest { algorithm, files }
+}
+
+fn load_manifest(path: &Path) -> Option<Manifest> {
+    let content = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save_manifest(path: &Path, manifest: &Manifest) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(manifest).map_err(std::io::Error::other)?;
+    std::fs::write(path, content)
+}
+
+/// Changes between the manifest and a scan, as `(path, description)`
+fn compare(expected: &Manifest, actual: &Manifest) -> Vec<(String, &'static str)> {
+    let mut changes = Vec::new();
+    for (path, hash) in &expected.files {
+        match actual.files.get(path) {
+            Some(current) if current != hash => changes.push((path.clone(), "modified")),
+            Some(_) => {}
+            None => changes.push((path.clone(), "removed")),
+        }
+    }
+    for path in actual.files.keys() {
+        if !expected.files.contains_key(path) {
+            changes.push((path.clone(), "added"));
+        }
+    }
+    changes
+}
+
+/// Scan `root` every interval and warn about files differing from the manifest
+///
+/// Each change is reported once until it is reverted. Runs until the task is aborted.
+pub async fn run(root: PathBuf, config: IntegrityConfig, state: SharedState) {
+    let algorithm = config.algorithm;
+    let manifest_path = config.manifest_path.clone();
+    let scan_root = root.clone();
+    let scan_manifest = manifest_path.clone();
+    let scan_now = move || {
+        let root = scan_root.clone();
+        let manifest_path = scan_manifest.clone();
+        tokio::task::spawn_blocking(move || scan(&root, algorithm, &manifest_path))
+    };
+
+    let expected = match load_manifest(&manifest_path) {
+        Some(manifest) if manifest.algorithm == algorithm => {
+            state.write().add_log(LogMessage::info(format!(
+                "Integrity check: loaded {} hashes from {}",
+                manifest.files.len(),
+                manifest_path.display()
+            )));
+            manifest
+        }
+        existing => {
+            if existing.is_some() {
+                state.write().add_log(LogMessage::warning(format!(
+                    "Integrity check: manifest {} uses another algorithm, rebuilding it",
+                    manifest_path.display()
+                )));
+            }
+            let Ok(manifest) = scan_now().await else {
+                return;
+            };
+            match save_manifest(&manifest_path, &manifest) {
+                Ok(()) => state.write().add_log(LogMessage::info(format!(
+                    "Integrity check: wrote {} hashes to {}",
+                    manifest.files.len(),
+                    manifest_path.display()
+                ))),
+                Err(e) => state.write().add_log(LogMessage::error(format!(
+                    "Integrity check: failed to write manifest {}: {}",
+                    manifest_path.display(),
+                    e
+                ))),
+            }
+            manifest
+        }
+    };
+
+    let mut reported: HashSet<(String, &'static str)> = HashSet::new();
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(config.check_interval_secs.max(1)));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately, the manifest was just read or built
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let Ok(actual) = scan_now().await else {
+            return;
+        };
+        let changes: HashSet<_> = compare(&expected, &actual).into_iter().collect();
+        let mut new_changes: Vec<_> = changes.difference(&reported).collect();
+        new_changes.sort();
+        if !new_changes.is_empty() {
+            let mut s = state.write();
+            for (path, change) in new_changes {
+                s.add_log(LogMessage::warning(format!(
+                    "Integrity check: {} {}",
+                    path, change
+                )));
+            }
+        }
+        reported = changes;
+    }
+}
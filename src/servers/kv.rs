@@ -0,0 +1,309 @@
+//! Key-value store served over HTTP under `/kv`
+//!
+//! `GET /kv` lists the keys, `GET`, `PUT` and `DELETE /kv/<key>` read,
+//! write and remove a value. Writes honour `If-Match` against the value's
+//! `ETag`, and the store is flushed to a JSON file in the background.
+//! Values may be sent compressed with `Content-Encoding: gzip`, `deflate` or `br`.
+
+use super::webhook::{self, WebhookConfig, WebhookTrigger};
+use super::{LogMessage, SharedState};
+use parking_lot::Mutex;
+use sha2::Digest;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::Notify;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::{Filter, Reply};
+
+/// Largest value accepted by `PUT`
+const MAX_VALUE_BYTES: u64 = 1024 * 1024;
+
+/// Values persisted to a JSON file
+pub struct KvStore {
+    path: PathBuf,
+    values: Mutex<BTreeMap<String, String>>,
+    dirty: Notify,
+}
+
+impl KvStore {
+    /// Load the store from `path`, starting empty if the file does not exist
+    pub fn open(path: &Path) -> std::io::Result<Arc<Self>> {
+        let values = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Arc::new(Self {
+            path: path.to_path_buf(),
+            values: Mutex::new(values),
+            dirty: Notify::new(),
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.lock().is_empty()
+    }
+
+    /// Write the store to disk via a temporary file so readers never see a partial file
+    pub fn flush(&self) -> std::io::Result<()> {
+        let content =
+            serde_json::to_string_pretty(&*self.values.lock()).map_err(std::io::Error::other)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+
+    /// Flush after every change until the task is aborted
+    pub async fn flush_loop(self: Arc<Self>, state: SharedState) {
+        loop {
+            self.dirty.notified().await;
+            let store = self.clone();
+            let result = tokio::task::spawn_blocking(move || store.flush()).await;
+            if let Ok(Err(e)) = result {
+                state.write().add_log(LogMessage::error(format!(
+                    "Failed to save key-value store {}: {}",
+                    self.path.display(),
+                    e
+                )));
+            }
+        }
+    }
+}
+
+/// Strong `ETag` of a value
+fn etag(value: &str) -> String {
+    let hash = sha2::Sha256::digest(value.as_bytes());
+    let hex: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// Whether an `If-Match` header allows changing a key whose current value is `current`
+fn if_match_allows(if_match: Option<&str>, current: Option<&str>) -> bool {
+    let Some(if_match) = if_match else {
+        return true;
+    };
+    let Some(current) = current else {
+        return false;
+    };
+    let tag = etag(current);
+    if_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate == tag)
+}
+
+fn status(code: StatusCode, body: &'static str) -> warp::reply::Response {
+    warp::reply::with_status(body, code).into_response()
+}
+
+/// Undo the `Content-Encoding` of a request body, refusing bodies that decode to more than `limit`
+async fn decode_body(
+    encoding: &str,
+    body: Bytes,
+    limit: u64,
+) -> Result<Bytes, (StatusCode, &'static str)> {
+    use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+    let mut data = body.to_vec();
+    // Codings are listed in the order they were applied
+    for coding in encoding.split(',').map(str::trim).rev() {
+        let input = &data[..];
+        let mut reader: Box<dyn AsyncRead + Send + Unpin + '_> =
+            match coding.to_ascii_lowercase().as_str() {
+                "" | "identity" => continue,
+                "gzip" | "x-gzip" => Box::new(GzipDecoder::new(input)),
+                // HTTP's deflate is the zlib format
+                "deflate" => Box::new(ZlibDecoder::new(input)),
+                "br" => Box::new(BrotliDecoder::new(input)),
+                _ => {
+                    return Err((
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        "Unsupported Content-Encoding",
+                    ));
+                }
+            };
+        let mut decoded = Vec::new();
+        (&mut reader)
+            .take(limit + 1)
+            .read_to_end(&mut decoded)
+            .await
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Malformed compressed body"))?;
+        if decoded.len() as u64 > limit {
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, "Value too large"));
+        }
+        drop(reader);
+        data = decoded;
+    }
+    Ok(Bytes::from(data))
+}
+
+/// Request body of at most [`MAX_VALUE_BYTES`], decompressed if `accept_compressed` is set
+///
+/// Yields the response to send instead for bodies that cannot be decoded.
+fn request_body(
+    accept_compressed: bool,
+    state: SharedState,
+) -> BoxedFilter<(Result<Bytes, warp::reply::Response>,)> {
+    warp::header::optional::<String>("content-encoding")
+        .and(warp::body::content_length_limit(MAX_VALUE_BYTES))
+        .and(warp::body::bytes())
+        .and_then(move |encoding: Option<String>, body: Bytes| {
+            let state = state.clone();
+            async move {
+                let encoding = encoding.filter(|_| accept_compressed);
+                let Some(encoding) = encoding else {
+                    return Ok::<_, warp::Rejection>(Ok(body));
+                };
+                let size = body.len();
+                let decoded = match decode_body(&encoding, body, MAX_VALUE_BYTES).await {
+                    Ok(decoded) => decoded,
+                    Err((code, message)) => return Ok(Err(status(code, message))),
+                };
+                state.write().add_log(LogMessage::debug(format!(
+                    "Decoded {} request body: {} bytes to {}",
+                    encoding,
+                    size,
+                    decoded.len()
+                )));
+                Ok(Ok(decoded))
+            }
+        })
+        .boxed()
+}
+
+/// Decode the key from the path after `/kv/`, `None` for an empty or invalid key
+fn decode_key(tail: &str) -> Option<String> {
+    let key = percent_encoding::percent_decode_str(tail)
+        .decode_utf8()
+        .ok()?
+        .into_owned();
+    (!key.is_empty()).then_some(key)
+}
+
+/// Routes for the store under `/kv`
+pub fn routes(
+    store: Arc<KvStore>,
+    webhooks: Vec<WebhookConfig>,
+    accept_compressed: bool,
+    state: SharedState,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    let list_store = store.clone();
+    let list = warp::path("kv")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || {
+            let keys: Vec<String> = list_store.values.lock().keys().cloned().collect();
+            warp::reply::json(&keys).into_response()
+        });
+
+    let get_store = store.clone();
+    let get = warp::path("kv")
+        .and(warp::path::tail())
+        .and(warp::get())
+        .map(move |tail: warp::path::Tail| {
+            let Some(key) = decode_key(tail.as_str()) else {
+                return status(StatusCode::NOT_FOUND, "Not found");
+            };
+            match get_store.values.lock().get(&key) {
+                Some(value) => {
+                    warp::reply::with_header(value.clone(), warp::http::header::ETAG, etag(value))
+                        .into_response()
+                }
+                None => status(StatusCode::NOT_FOUND, "Not found"),
+            }
+        });
+
+    let put_store = store.clone();
+    let put_state = state.clone();
+    let put = warp::path("kv")
+        .and(warp::path::tail())
+        .and(warp::put())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(request_body(accept_compressed, state.clone()))
+        .map(
+            move |tail: warp::path::Tail,
+                  if_match: Option<String>,
+                  body: Result<Bytes, warp::reply::Response>| {
+                let Some(key) = decode_key(tail.as_str()) else {
+                    return status(StatusCode::BAD_REQUEST, "Missing key");
+                };
+                let body = match body {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+                let Ok(value) = String::from_utf8(body.to_vec()) else {
+                    return status(StatusCode::BAD_REQUEST, "Value must be UTF-8 text");
+                };
+                let tag = etag(&value);
+                let created = {
+                    let mut values = put_store.values.lock();
+                    let current = values.get(&key).map(String::as_str);
+                    if !if_match_allows(if_match.as_deref(), current) {
+                        return status(StatusCode::PRECONDITION_FAILED, "ETag does not match");
+                    }
+                    values.insert(key.clone(), value.clone()).is_none()
+                };
+                put_store.dirty.notify_one();
+
+                webhook::fire(
+                    &webhooks,
+                    WebhookTrigger::OnUpload,
+                    serde_json::json!({ "key": key, "bytes": value.len() }),
+                    &put_state,
+                );
+                let code = if created {
+                    StatusCode::CREATED
+                } else {
+                    StatusCode::NO_CONTENT
+                };
+                warp::reply::with_header(
+                    warp::reply::with_status(warp::reply(), code),
+                    warp::http::header::ETAG,
+                    tag,
+                )
+                .into_response()
+            },
+        );
+
+    let delete_store = store;
+    let delete = warp::path("kv")
+        .and(warp::path::tail())
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("if-match"))
+        .map(move |tail: warp::path::Tail, if_match: Option<String>| {
+            let Some(key) = decode_key(tail.as_str()) else {
+                return status(StatusCode::NOT_FOUND, "Not found");
+            };
+            {
+                let mut values = delete_store.values.lock();
+                let Some(current) = values.get(&key) else {
+                    return status(StatusCode::NOT_FOUND, "Not found");
+                };
+                if !if_match_allows(if_match.as_deref(), Some(current)) {
+                    return status(StatusCode::PRECONDITION_FAILED, "ETag does not match");
+                }
+                values.remove(&key);
+            }
+            delete_store.dirty.notify_one();
+            status(StatusCode::NO_CONTENT, "")
+        });
+
+    list.or(get)
+        .unify()
+        .or(put)
+        .unify()
+        .or(delete)
+        .unify()
+        .boxed()
+}
@@ -0,0 +1,108 @@
+//! DNS-SD service announcements over multicast DNS
+//!
+//! Lets Finder, the iOS Files app and Avahi-enabled desktops find the
+//! servers on the LAN without typing an address.
+
+use super::{LogMessage, SharedState};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::path::Path;
+use std::time::Duration;
+
+/// Time the goodbye packets get to go out when a service is withdrawn
+const UNREGISTER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A registered service, withdrawn from the network when dropped
+pub struct Announcement {
+    daemon: ServiceDaemon,
+    fullname: String,
+    state: SharedState,
+}
+
+/// Register `service_type`, e.g. `_http._tcp`, on `port` and log the outcome
+pub fn announce(
+    service_type: &str,
+    label: &str,
+    port: u16,
+    txt: &[(&str, &str)],
+    state: &SharedState,
+) -> Option<Announcement> {
+    if cfg!(target_os = "linux") && !avahi_running() {
+        state.write().add_log(LogMessage::warning(
+            "mDNS: avahi-daemon does not seem to be running, some distributions need it for .local discovery",
+        ));
+    }
+
+    // Only the first label, mDNS host names live directly under `.local`
+    let host = hostname::get()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let host = match host.split('.').next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => "oservers".to_string(),
+    };
+
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(e) => {
+            state.write().add_log(LogMessage::warning(format!(
+                "mDNS: failed to start responder: {}",
+                e
+            )));
+            return None;
+        }
+    };
+    let registered = ServiceInfo::new(
+        &format!("{}.local.", service_type),
+        &format!("OServers {} on {}", label, host),
+        &format!("{}.local.", host),
+        (),
+        port,
+        txt,
+    )
+    .and_then(|info| {
+        let info = info.enable_addr_auto();
+        let fullname = info.get_fullname().to_string();
+        daemon.register(info).map(|()| fullname)
+    });
+
+    match registered {
+        Ok(fullname) => {
+            state
+                .write()
+                .add_log(LogMessage::info(format!("mDNS: announced as {}", fullname)));
+            Some(Announcement {
+                daemon,
+                fullname,
+                state: state.clone(),
+            })
+        }
+        Err(e) => {
+            let _ = daemon.shutdown();
+            state.write().add_log(LogMessage::warning(format!(
+                "mDNS: failed to register {}: {}",
+                service_type, e
+            )));
+            None
+        }
+    }
+}
+
+/// Whether the Avahi daemon's socket exists
+fn avahi_running() -> bool {
+    ["/run/avahi-daemon/socket", "/var/run/avahi-daemon/socket"]
+        .iter()
+        .any(|path| Path::new(path).exists())
+}
+
+impl Drop for Announcement {
+    fn drop(&mut self) {
+        if let Ok(status) = self.daemon.unregister(&self.fullname) {
+            let _ = status.recv_timeout(UNREGISTER_TIMEOUT);
+        }
+        let _ = self.daemon.shutdown();
+        self.state.write().add_log(LogMessage::info(format!(
+            "mDNS: withdrew {}",
+            self.fullname
+        )));
+    }
+}
@@ -1,13 +1,34 @@
 //! Server trait and common types for the multi-server manager
 
+pub mod backend;
+pub mod client_lookup;
 pub mod ftp;
 pub mod http;
+pub mod integrity;
+pub mod kv;
+pub mod mdns;
+pub mod proxy_protocol;
+pub mod response_signing;
+pub mod signed_url;
 pub mod ssh;
+pub mod startup_report;
 pub mod tftp;
+pub mod throttle;
+pub mod tls;
+pub mod tor;
+pub mod transfer_notify;
+pub mod webhook;
 
-use parking_lot::RwLock;
-use std::path::PathBuf;
+#[cfg(test)]
+mod test_util;
+
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::net::{IpAddr, TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::mpsc;
 
@@ -27,6 +48,85 @@ pub enum ServerError {
     Other(String),
 }
 
+/// What to do when an upload targets a file that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum OverwritePolicy {
+    /// Replace the existing file
+    #[default]
+    Allow,
+    /// Reject the upload
+    Deny,
+    /// Store the upload next to it as `name (1).ext`
+    Rename,
+}
+
+impl OverwritePolicy {
+    pub const ALL: [OverwritePolicy; 3] = [
+        OverwritePolicy::Allow,
+        OverwritePolicy::Deny,
+        OverwritePolicy::Rename,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverwritePolicy::Allow => "Allow",
+            OverwritePolicy::Deny => "Deny",
+            OverwritePolicy::Rename => "Rename",
+        }
+    }
+}
+
+/// Simple glob matching where `*` matches any substring
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+    // Checking both ends first keeps the slice below on char boundaries
+    if text.len() < first.len() + last.len() || !text.starts_with(first) || !text.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Best guess at this machine's LAN address, for building shareable links
+pub fn local_ip() -> Option<IpAddr> {
+    // Connecting a UDP socket sends nothing, it only selects the outgoing interface
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// Ports `find_free_port` tries above the starting one before giving up
+const FREE_PORT_SCAN_RANGE: u16 = 200;
+
+/// Whether `port` can be bound on all interfaces right now, over UDP or TCP
+pub fn check_port_available(port: u16, udp: bool) -> bool {
+    if udp {
+        UdpSocket::bind(("0.0.0.0", port)).is_ok()
+    } else {
+        TcpListener::bind(("0.0.0.0", port)).is_ok()
+    }
+}
+
+/// First port from `start` upwards that can be bound
+pub fn find_free_port(start: u16, udp: bool) -> Option<u16> {
+    let start = start.max(1);
+    (start..=start.saturating_add(FREE_PORT_SCAN_RANGE))
+        .find(|&port| check_port_available(port, udp))
+}
+
 /// Server status
 #[derive(Debug, Clone, PartialEq)]
 pub enum ServerStatus {
@@ -48,6 +148,7 @@ pub struct LogMessage {
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LogLevel {
+    Debug,
     Info,
     Warning,
     Error,
@@ -62,6 +163,22 @@ impl LogMessage {
         }
     }
 
+    pub fn debug(message: impl Into<String>) -> Self {
+        Self {
+            timestamp: chrono::Local::now(),
+            level: LogLevel::Debug,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            timestamp: chrono::Local::now(),
+            level: LogLevel::Warning,
+            message: message.into(),
+        }
+    }
+
     pub fn error(message: impl Into<String>) -> Self {
         Self {
             timestamp: chrono::Local::now(),
@@ -71,12 +188,102 @@ impl LogMessage {
     }
 }
 
+impl std::fmt::Display for LogMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:?} {}",
+            self.timestamp.format("[%Y-%m-%d %H:%M:%S%.3f]"),
+            self.level,
+            self.message
+        )
+    }
+}
+
+/// Logs to be written to a file once their server stops
+static LOG_EXPORTS: Mutex<Vec<LogExport>> = Mutex::new(Vec::new());
+
+/// A server log waiting to be written to `<dir>/<server>-<timestamp>.log`
+struct LogExport {
+    state: SharedState,
+    dir: PathBuf,
+    server: &'static str,
+}
+
+/// Write the log of a running server to `dir` when it stops, or from
+/// [`flush_all_logs`] if the application exits first
+pub fn export_log_on_stop(state: &SharedState, dir: Option<&Path>, server: &'static str) {
+    let mut exports = LOG_EXPORTS.lock();
+    exports.retain(|export| !Arc::ptr_eq(&export.state, state));
+    if let Some(dir) = dir {
+        exports.push(LogExport {
+            state: state.clone(),
+            dir: dir.to_path_buf(),
+            server,
+        });
+    }
+}
+
+/// The server of `state` stopped, write its log if it is to be exported
+pub fn finish_log_export(state: &SharedState) {
+    let export = {
+        let mut exports = LOG_EXPORTS.lock();
+        let pos = exports
+            .iter()
+            .position(|export| Arc::ptr_eq(&export.state, state));
+        pos.map(|pos| exports.remove(pos))
+    };
+    if let Some(export) = export {
+        export_log(&export.state, &export.dir, export.server);
+    }
+}
+
+/// Write every log still waiting for its server to stop, on shutdown paths
+/// that do not wait for the servers so the last lines are not lost
+pub fn flush_all_logs() {
+    let exports = std::mem::take(&mut *LOG_EXPORTS.lock());
+    for export in exports {
+        export_log(&export.state, &export.dir, export.server);
+    }
+}
+
+/// Write the retained log of a server to `<dir>/<server>-<timestamp>.log`
+fn export_log(state: &SharedState, dir: &Path, server: &str) {
+    let path = dir.join(format!(
+        "{}-{}.log",
+        server,
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    let content: String = state
+        .read()
+        .logs
+        .iter()
+        .map(|log| format!("{}\n", log))
+        .collect();
+    let result = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, content));
+
+    let mut s = state.write();
+    match result {
+        Ok(()) => s.add_log(LogMessage::info(format!(
+            "Log exported to {}",
+            path.display()
+        ))),
+        Err(e) => s.add_log(LogMessage::error(format!(
+            "Failed to export log to {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
 /// Common server configuration
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ServerConfig {
     pub root_dir: PathBuf,
     pub port: u16,
     pub auto_stop_seconds: Option<u64>,
+    /// Outgoing traffic limit per connection in kilobits per second
+    pub egress_throttle_kbps: Option<u32>,
 }
 
 impl Default for ServerConfig {
@@ -85,16 +292,251 @@ impl Default for ServerConfig {
             root_dir: std::env::current_dir().unwrap_or_default(),
             port: 8080,
             auto_stop_seconds: None,
+            egress_throttle_kbps: None,
         }
     }
 }
 
+/// Kinds of server the application runs, one of each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ServerType {
+    Http,
+    Ftp,
+    Tftp,
+    Ssh,
+}
+
+impl ServerType {
+    /// Every server type, in the order the application lists them
+    pub const ALL: [ServerType; 4] = [
+        ServerType::Http,
+        ServerType::Ftp,
+        ServerType::Tftp,
+        ServerType::Ssh,
+    ];
+
+    /// Port a server of this type listens on unless configured otherwise
+    pub fn default_port(&self) -> u16 {
+        match self {
+            ServerType::Http => 7777,
+            ServerType::Ftp => 2121,
+            ServerType::Tftp => 69,
+            ServerType::Ssh => 2222,
+        }
+    }
+
+    /// Protocol name for log messages
+    pub fn label(&self) -> &'static str {
+        match self {
+            ServerType::Http => "HTTP",
+            ServerType::Ftp => "FTP",
+            ServerType::Tftp => "TFTP",
+            ServerType::Ssh => "SSH",
+        }
+    }
+}
+
+/// When a server is started without the user clicking Start
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+    /// Start the server when the application launches
+    pub auto_start: bool,
+    /// Servers that must be running before this one is auto-started
+    pub start_after: Vec<ServerType>,
+    /// Seconds to wait before auto-starting, counted once `start_after` are running
+    pub startup_delay_secs: u64,
+    /// Wait up to this many seconds for the root directory to appear before
+    /// binding, e.g. when it lives on a network mount
+    pub wait_for_root_secs: Option<u64>,
+}
+
+/// What to do when a server ends on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum RestartBehavior {
+    #[default]
+    DoNothing,
+    RestartImmediately,
+    /// Wait this many seconds, doubled with each restart within the hour
+    RestartWithBackoff(u64),
+}
+
+impl RestartBehavior {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RestartBehavior::DoNothing => "Do nothing",
+            RestartBehavior::RestartImmediately => "Restart immediately",
+            RestartBehavior::RestartWithBackoff(_) => "Restart with backoff",
+        }
+    }
+}
+
+/// Restarting a server that stopped without being asked to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RestartPolicy {
+    /// The server task failed, e.g. it could not bind or panicked
+    pub on_crash: RestartBehavior,
+    /// The server task ended normally but left an error status behind
+    pub on_error_status: RestartBehavior,
+    /// Restarts allowed within any hour before giving up
+    pub max_restarts_per_hour: u8,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            on_crash: RestartBehavior::DoNothing,
+            on_error_status: RestartBehavior::DoNothing,
+            max_restarts_per_hour: 5,
+        }
+    }
+}
+
+/// Poll until `root` is a directory for up to `timeout_secs`, if given
+///
+/// Returns `false` if the server was stopped while waiting, its status is
+/// then already `Stopped`.
+pub async fn wait_for_root(
+    root: &Path,
+    timeout_secs: Option<u64>,
+    shutdown_rx: &mut mpsc::Receiver<()>,
+    state: &SharedState,
+) -> Result<bool, ServerError> {
+    let Some(timeout_secs) = timeout_secs else {
+        return Ok(true);
+    };
+    if root.is_dir() {
+        return Ok(true);
+    }
+    state.write().add_log(LogMessage::info(format!(
+        "Waiting up to {}s for root directory {}",
+        timeout_secs,
+        root.display()
+    )));
+    let started = Instant::now();
+    let timeout = Duration::from_secs(timeout_secs);
+    while started.elapsed() < timeout {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                let mut s = state.write();
+                s.status = ServerStatus::Stopped;
+                s.add_log(LogMessage::info("Stopped while waiting for the root directory"));
+                return Ok(false);
+            }
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+        if tokio::fs::metadata(root).await.is_ok_and(|m| m.is_dir()) {
+            state.write().add_log(LogMessage::info(format!(
+                "Root directory available after {}s",
+                started.elapsed().as_secs()
+            )));
+            return Ok(true);
+        }
+    }
+    let msg = format!(
+        "Root directory {} not available after {}s",
+        root.display(),
+        timeout_secs
+    );
+    let mut s = state.write();
+    s.status = ServerStatus::Error(msg.clone());
+    s.add_log(LogMessage::error(&msg));
+    Err(ServerError::ConfigError(msg))
+}
+
+/// Traffic counters for the current server session
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Metrics {
+    pub requests: u64,
+    pub bytes_sent: u64,
+    /// Requests served to groups A and B while an A/B test runs
+    pub ab_requests: [u64; 2],
+    /// Last request or transfer seen, for the inactivity auto-stop
+    pub last_activity: Option<Instant>,
+}
+
+/// A file being sent or received, for the live transfers view
+#[derive(Debug, Clone)]
+pub struct TransferProgress {
+    pub client: String,
+    pub file: String,
+    pub upload: bool,
+    /// Bytes moved so far, updated without taking the state lock
+    pub bytes: Arc<AtomicU64>,
+    /// Size of the whole transfer when known
+    pub total: Option<u64>,
+    pub started: Instant,
+}
+
+/// Keeps a transfer in [`ServerState::transfers`] until dropped
+pub struct TransferGuard {
+    id: u64,
+    bytes: Arc<AtomicU64>,
+    state: SharedState,
+}
+
+impl TransferGuard {
+    /// Track a transfer of `file` with `client` while the guard lives
+    pub fn start(
+        state: &SharedState,
+        client: impl Into<String>,
+        file: impl Into<String>,
+        upload: bool,
+        total: Option<u64>,
+    ) -> Self {
+        let bytes = Arc::new(AtomicU64::new(0));
+        let mut s = state.write();
+        s.next_transfer_id += 1;
+        let id = s.next_transfer_id;
+        s.transfers.insert(
+            id,
+            TransferProgress {
+                client: client.into(),
+                file: file.into(),
+                upload,
+                bytes: bytes.clone(),
+                total,
+                started: Instant::now(),
+            },
+        );
+        Self {
+            id,
+            bytes,
+            state: state.clone(),
+        }
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+impl Drop for TransferGuard {
+    fn drop(&mut self) {
+        self.state.write().transfers.remove(&self.id);
+    }
+}
+
 /// Shared server state
 #[allow(dead_code)]
 pub struct ServerState {
     pub status: ServerStatus,
     pub logs: Vec<LogMessage>,
     pub config: ServerConfig,
+    /// Port the server is listening on when known, differs from the
+    /// configured one when that was 0 and the OS picked it
+    pub bound_port: Option<u16>,
+    /// Downloads currently transferring, for servers that limit them
+    pub active_downloads: usize,
+    /// Downloads waiting for a free transfer slot
+    pub queued_downloads: usize,
+    pub metrics: Metrics,
+    /// Address of the Tor onion service while one is published
+    pub onion_address: Option<String>,
+    /// Transfers in progress, keyed by an id unique to this server
+    pub transfers: HashMap<u64, TransferProgress>,
+    next_transfer_id: u64,
 }
 
 impl ServerState {
@@ -103,6 +545,13 @@ impl ServerState {
             status: ServerStatus::Stopped,
             logs: Vec::new(),
             config,
+            bound_port: None,
+            active_downloads: 0,
+            queued_downloads: 0,
+            metrics: Metrics::default(),
+            onion_address: None,
+            transfers: HashMap::new(),
+            next_transfer_id: 0,
         }
     }
 
@@ -113,10 +562,78 @@ impl ServerState {
             self.logs.remove(0);
         }
     }
+
+    /// Note client activity, postponing the inactivity auto-stop
+    pub fn record_activity(&mut self) {
+        self.metrics.last_activity = Some(Instant::now());
+    }
+
+    /// Replace the text of the most recent log entry reading `message`
+    pub fn replace_log(&mut self, message: &str, replacement: String) {
+        if let Some(entry) = self.logs.iter_mut().rev().find(|m| m.message == message) {
+            entry.message = replacement;
+        }
+    }
 }
 
 pub type SharedState = Arc<RwLock<ServerState>>;
 
+/// Wait for a stop request, or until the server has been idle for `auto_stop_seconds`
+pub async fn wait_for_shutdown(
+    shutdown_rx: &mut mpsc::Receiver<()>,
+    state: &SharedState,
+    auto_stop_seconds: Option<u64>,
+) {
+    let idle = async {
+        match auto_stop_seconds {
+            Some(secs) => idle_timeout(state, Duration::from_secs(secs)).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = shutdown_rx.recv() => {}
+        _ = idle => {}
+    }
+}
+
+/// Complete once no activity has been recorded for `idle` and no download
+/// or transfer is running
+async fn idle_timeout(state: &SharedState, idle: Duration) {
+    // Activity from a previous run of the server does not count
+    let mut idle_since = Instant::now();
+    let mut was_busy = false;
+    loop {
+        let (last_activity, busy) = {
+            let s = state.read();
+            // FTP and TFTP record activity only as a transfer starts and ends
+            (
+                s.metrics.last_activity,
+                s.active_downloads > 0 || !s.transfers.is_empty(),
+            )
+        };
+        if busy {
+            was_busy = true;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        if was_busy {
+            // Idle time counts from the end of the transfer, not its start
+            idle_since = Instant::now();
+            was_busy = false;
+        }
+        let last = last_activity.map_or(idle_since, |t| t.max(idle_since));
+        let idle_for = last.elapsed();
+        if idle_for >= idle {
+            break;
+        }
+        tokio::time::sleep(idle.saturating_sub(idle_for).max(Duration::from_secs(1))).await;
+    }
+    state.write().add_log(LogMessage::info(format!(
+        "Auto-stopping after {} seconds of inactivity",
+        idle.as_secs()
+    )));
+}
+
 /// Server control handle
 #[allow(dead_code)]
 pub struct ServerHandle {
@@ -157,3 +674,59 @@ impl ServerHandle {
         matches!(self.state.read().status, ServerStatus::Running)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        RwLock, ServerConfig, ServerState, SharedState, TransferGuard, glob_match, idle_timeout,
+    };
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn glob_match_ascii() {
+        assert!(glob_match("*.mp4", "movie.mp4"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(glob_match("report", "report"));
+        assert!(!glob_match("*.mp4", "movie.mkv"));
+        assert!(!glob_match("a*a", "a"));
+    }
+
+    #[test]
+    fn glob_match_non_ascii() {
+        assert!(!glob_match("*.mp4", "中中"));
+        assert!(!glob_match("ab*", "中"));
+        assert!(!glob_match("*.key", "密钥文件"));
+        assert!(glob_match("*.mp4", "电影.mp4"));
+        assert!(glob_match("中*文", "中间的文"));
+        assert!(glob_match("*间*", "中间的文"));
+        assert!(!glob_match("中*.txt", "中文"));
+    }
+
+    #[tokio::test]
+    async fn running_transfer_holds_off_auto_stop() {
+        let state: SharedState = Arc::new(RwLock::new(ServerState::new(ServerConfig::default())));
+        let transfer = TransferGuard::start(&state, "client", "big.iso", false, None);
+        let idle = idle_timeout(&state, Duration::from_secs(1));
+        tokio::pin!(idle);
+        assert!(
+            tokio::time::timeout(Duration::from_secs(3), &mut idle)
+                .await
+                .is_err(),
+            "stopped during a transfer"
+        );
+        drop(transfer);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), &mut idle)
+                .await
+                .is_err(),
+            "stopped right after the transfer instead of after the idle time"
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_secs(3), &mut idle)
+                .await
+                .is_ok(),
+            "did not stop once idle"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! PROXY protocol (v1 and v2) support for serving HTTP behind a load balancer
+//!
+//! Also home of the accept loop used whenever connections need handling
+//! warp's own server cannot do, like throttling the raw stream or TLS with
+//! our own session ticket keys.
+
+use super::throttle::ThrottledStream;
+use super::{LogMessage, SharedState};
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use warp::hyper::service::{Service, service_fn};
+use warp::{Filter, Reply};
+
+/// Request header carrying the client address, from the PROXY header if one is required
+pub const CLIENT_ADDR_HEADER: &str = "x-oservers-client-addr";
+
+/// Time a new connection has to send its PROXY header
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a new HTTPS connection has to complete its TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time requests in flight get to finish once the server is stopping
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait before accepting again after a failed accept, e.g. out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest v1 header including the trailing CRLF
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Read a PROXY header from the start of `stream`
+///
+/// Returns the client address, or `None` for `UNKNOWN`/`LOCAL` headers where
+/// the connection's own peer address applies. Reads exactly the header so
+/// the HTTP request that follows is left in the stream.
+async fn read_header(stream: &mut TcpStream) -> std::io::Result<Option<SocketAddr>> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+
+    let mut prefix = [0u8; 6];
+    stream.read_exact(&mut prefix).await?;
+    if &prefix == b"PROXY " {
+        let mut line = prefix.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= V1_MAX_LEN {
+                return Err(invalid("PROXY v1 header too long"));
+            }
+            line.push(stream.read_u8().await?);
+        }
+        let line = std::str::from_utf8(&line).map_err(|_| invalid("PROXY v1 header not ASCII"))?;
+        return parse_v1(line.trim_end()).ok_or_else(|| invalid("malformed PROXY v1 header"));
+    }
+
+    if prefix != V2_SIGNATURE[..6] {
+        return Err(invalid("missing PROXY header"));
+    }
+    let mut header = [0u8; 10];
+    stream.read_exact(&mut header).await?;
+    if header[..6] != V2_SIGNATURE[6..] {
+        return Err(invalid("missing PROXY header"));
+    }
+    let version_command = header[6];
+    let family = header[7];
+    let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY version"));
+    }
+    match version_command & 0x0f {
+        // LOCAL, e.g. health checks from the proxy itself
+        0x0 => Ok(None),
+        0x1 => {
+            parse_v2_address(family, &payload).ok_or_else(|| invalid("malformed PROXY v2 address"))
+        }
+        _ => Err(invalid("unsupported PROXY v2 command")),
+    }
+}
+
+/// Parse `PROXY TCP4 <src> <dst> <sport> <dport>`
+fn parse_v1(line: &str) -> Option<Option<SocketAddr>> {
+    let mut parts = line.split(' ');
+    if parts.next()? != "PROXY" {
+        return None;
+    }
+    match parts.next()? {
+        "UNKNOWN" => Some(None),
+        protocol @ ("TCP4" | "TCP6") => {
+            let source: IpAddr = parts.next()?.parse().ok()?;
+            let _destination: IpAddr = parts.next()?.parse().ok()?;
+            let source_port: u16 = parts.next()?.parse().ok()?;
+            let _destination_port: u16 = parts.next()?.parse().ok()?;
+            if parts.next().is_some() || (protocol == "TCP4") != source.is_ipv4() {
+                return None;
+            }
+            Some(Some(SocketAddr::new(source, source_port)))
+        }
+        _ => None,
+    }
+}
+
+/// Source address of a v2 `PROXY` command, `None` for families without one
+fn parse_v2_address(family: u8, payload: &[u8]) -> Option<Option<SocketAddr>> {
+    match family >> 4 {
+        // AF_INET
+        0x1 => {
+            let bytes: [u8; 12] = payload.get(..12)?.try_into().ok()?;
+            let ip = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+            let port = u16::from_be_bytes([bytes[8], bytes[9]]);
+            Some(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // AF_INET6
+        0x2 => {
+            let bytes: [u8; 36] = payload.get(..36)?.try_into().ok()?;
+            let ip: [u8; 16] = bytes[..16].try_into().ok()?;
+            let port = u16::from_be_bytes([bytes[32], bytes[33]]);
+            Some(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        // AF_UNSPEC and AF_UNIX carry no usable client address
+        _ => Some(None),
+    }
+}
+
+/// A connection's stream, whichever of TLS and throttling it ended up with
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// Complete the TLS handshake on `stream`, `None` if it fails or takes too long
+async fn handshake<S>(tls: &TlsAcceptor, stream: S) -> Option<TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Failed handshakes are scanners and clients rejecting the certificate
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.accept(stream))
+        .await
+        .ok()?
+        .ok()
+}
+
+/// Serve `routes` on `addr` from an accept loop of our own
+///
+/// The client address is passed to the routes in [`CLIENT_ADDR_HEADER`].
+/// With `proxy_header` every connection must start with a PROXY header,
+/// connections without a valid one are logged and closed. With
+/// `egress_kbps` each connection's outgoing traffic is limited to that rate.
+/// With `tls` connections are served over HTTPS. Once `shutdown` completes,
+/// requests in flight get [`DRAIN_TIMEOUT`] to finish before their
+/// connections are closed.
+pub async fn serve<F>(
+    routes: F,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+    proxy_header: bool,
+    egress_kbps: Option<u32>,
+    tls: Option<TlsAcceptor>,
+    state: SharedState,
+) -> std::io::Result<()>
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let listener = TcpListener::bind(addr).await?;
+    let service = warp::service(routes);
+    let mut connections = JoinSet::new();
+    let (stopping_tx, stopping) = watch::channel(false);
+    tokio::pin!(shutdown);
+
+    loop {
+        let (mut stream, peer) = tokio::select! {
+            _ = &mut shutdown => break,
+            // Reap finished connections so the set does not grow unbounded
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    state.write().add_log(LogMessage::warning(format!(
+                        "Failed to accept connection: {}",
+                        e
+                    )));
+                    // Accept fails again right away while the cause lasts
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            },
+        };
+
+        let service = service.clone();
+        let state = state.clone();
+        let tls = tls.clone();
+        let mut stopping = stopping.clone();
+        connections.spawn(async move {
+            let header = async {
+                match proxy_header {
+                    true => read_header(&mut stream).await,
+                    false => Ok(None),
+                }
+            };
+            let client = match tokio::time::timeout(HEADER_TIMEOUT, header).await {
+                Ok(Ok(client)) => client.unwrap_or(peer),
+                Ok(Err(e)) => {
+                    state.write().add_log(LogMessage::warning(format!(
+                        "Rejected connection from {}: {}",
+                        peer, e
+                    )));
+                    return;
+                }
+                Err(_) => {
+                    state.write().add_log(LogMessage::warning(format!(
+                        "Rejected connection from {}: no PROXY header within {}s",
+                        peer,
+                        HEADER_TIMEOUT.as_secs()
+                    )));
+                    return;
+                }
+            };
+
+            let Ok(client) = warp::http::HeaderValue::from_str(&client.to_string()) else {
+                return;
+            };
+            let handler = service_fn(move |mut request| {
+                // Overwrite whatever the client sent so the address cannot be spoofed
+                request
+                    .headers_mut()
+                    .insert(CLIENT_ADDR_HEADER, client.clone());
+                service.clone().call(request)
+            });
+            let stream: Box<dyn Io> = match (tls, egress_kbps) {
+                // Throttled below TLS, so the limit counts the bytes on the wire
+                (Some(tls), Some(kbps)) => {
+                    match handshake(&tls, ThrottledStream::new(stream, kbps)).await {
+                        Some(stream) => Box::new(stream),
+                        None => return,
+                    }
+                }
+                (Some(tls), None) => match handshake(&tls, stream).await {
+                    Some(stream) => Box::new(stream),
+                    None => return,
+                },
+                (None, Some(kbps)) => Box::new(ThrottledStream::new(stream, kbps)),
+                (None, None) => Box::new(stream),
+            };
+            let connection =
+                warp::hyper::server::conn::Http::new().serve_connection(stream, handler);
+            tokio::pin!(connection);
+            let _ = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = stopping.changed() => {
+                    // Finish the request in flight, then close instead of waiting for the next
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+        });
+    }
+
+    let _ = stopping_tx.send(true);
+    let drained = tokio::time::timeout(DRAIN_TIMEOUT, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        state.write().add_log(LogMessage::warning(format!(
+            "Closed {} connection(s) still busy {}s after stopping",
+            connections.len(),
+            DRAIN_TIMEOUT.as_secs()
+        )));
+        connections.abort_all();
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+//! Signatures over HTTP response headers
+//!
+//! Every response gets an `X-Content-Signature` header holding the base64
+//! signature of `<status>\n<Content-Length>\n<Content-Type>\n<ETag>`, a
+//! missing header giving an empty line, and `X-Content-Signature-Key`
+//! naming the key as `SHA256:<base64 of the public key's SHA-256>`.
+
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, RSA_PSS_SHA256, RsaKeyPair};
+use sha2::Digest;
+use std::path::{Path, PathBuf};
+use warp::http::HeaderValue;
+use warp::http::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG};
+
+/// Header carrying the signature
+const SIGNATURE_HEADER: &str = "x-content-signature";
+
+/// Header carrying the fingerprint of the signing key
+const KEY_HEADER: &str = "x-content-signature-key";
+
+/// Signature scheme used for responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum SigningAlgorithm {
+    #[default]
+    Ed25519,
+    RsaPss,
+}
+
+impl SigningAlgorithm {
+    pub const ALL: [SigningAlgorithm; 2] = [SigningAlgorithm::Ed25519, SigningAlgorithm::RsaPss];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SigningAlgorithm::Ed25519 => "Ed25519",
+            SigningAlgorithm::RsaPss => "RSA-PSS (SHA-256)",
+        }
+    }
+}
+
+/// Key and scheme for signing responses
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SigningConfig {
+    /// PEM file with a PKCS#8 private key, or PKCS#1 for RSA
+    pub private_key_path: PathBuf,
+    pub algorithm: SigningAlgorithm,
+}
+
+enum SigningKey {
+    Ed25519(Ed25519KeyPair),
+    Rsa(RsaKeyPair),
+}
+
+/// Loaded key signing the responses of one server
+pub struct ResponseSigner {
+    key: SigningKey,
+    fingerprint: HeaderValue,
+    rng: SystemRandom,
+}
+
+impl ResponseSigner {
+    /// Load the private key named by `config`
+    pub fn load(config: &SigningConfig) -> Result<Self, String> {
+        let der = read_pem(&config.private_key_path)?;
+        let key = match config.algorithm {
+            SigningAlgorithm::Ed25519 => Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der)
+                .map(SigningKey::Ed25519)
+                .map_err(|e| format!("not an Ed25519 PKCS#8 key: {}", e))?,
+            SigningAlgorithm::RsaPss => RsaKeyPair::from_pkcs8(&der)
+                .or_else(|_| RsaKeyPair::from_der(&der))
+                .map(SigningKey::Rsa)
+                .map_err(|e| format!("not an RSA PKCS#8 or PKCS#1 key: {}", e))?,
+        };
+        let public_key = match &key {
+            SigningKey::Ed25519(pair) => pair.public_key().as_ref().to_vec(),
+            SigningKey::Rsa(pair) => pair.public_key().as_ref().to_vec(),
+        };
+        let fingerprint = format!(
+            "SHA256:{}",
+            base64::engine::general_purpose::STANDARD_NO_PAD
+                .encode(sha2::Sha256::digest(&public_key))
+        );
+        Ok(Self {
+            key,
+            fingerprint: HeaderValue::from_str(&fingerprint).map_err(|e| e.to_string())?,
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Fingerprint of the public key, as sent in `X-Content-Signature-Key`
+    pub fn fingerprint(&self) -> &str {
+        self.fingerprint.to_str().unwrap_or_default()
+    }
+
+    /// Add the signature headers to `response`
+    pub fn sign(&self, response: &mut warp::reply::Response) {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+        };
+        let canonical = format!(
+            "{}\n{}\n{}\n{}",
+            response.status().as_u16(),
+            header(CONTENT_LENGTH),
+            header(CONTENT_TYPE),
+            header(ETAG)
+        );
+        let signature = match &self.key {
+            SigningKey::Ed25519(pair) => pair.sign(canonical.as_bytes()).as_ref().to_vec(),
+            SigningKey::Rsa(pair) => {
+                let mut signature = vec![0; pair.public().modulus_len()];
+                if pair
+                    .sign(
+                        &RSA_PSS_SHA256,
+                        &self.rng,
+                        canonical.as_bytes(),
+                        &mut signature,
+                    )
+                    .is_err()
+                {
+                    return;
+                }
+                signature
+            }
+        };
+        let encoded = base64::engine::general_purpose::STANDARD.encode(signature);
+        if let Ok(value) = HeaderValue::from_str(&encoded) {
+            let headers = response.headers_mut();
+            headers.insert(SIGNATURE_HEADER, value);
+            headers.insert(KEY_HEADER, self.fingerprint.clone());
+        }
+    }
+}
+
+/// DER contents of the first block in a PEM file
+fn read_pem(path: &Path) -> Result<Vec<u8>, String> {
+    let pem = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let body: String = pem
+        .lines()
+        .skip_while(|line| !line.starts_with("-----BEGIN"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END"))
+        .map(str::trim)
+        .collect();
+    if body.is_empty() {
+        return Err(format!("{}: no PEM block found", path.display()));
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(body)
+        .map_err(|e| format!("{}: invalid PEM: {}", path.display(), e))
+}
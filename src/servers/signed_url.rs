@@ -0,0 +1,290 @@
+//! Time-limited download links for files below the HTTP root
+//!
+//! `GET /__signed?file=<path>&expires=<unix time>&sig=<hex>` serves `file`
+//! until `expires` when `sig` is the HMAC-SHA256 of `<path>\n<expires>`
+//! keyed with the configured secret. The newline keeps a path ending in
+//! digits from borrowing them from the expiry time. Links are made by
+//! `POST /__admin/sign` with `Authorization: Bearer <admin token>` and a
+//! JSON body like `{"file": "docs/report.pdf", "ttl_secs": 3600}`.
+
+use super::http::{ClientAddrSource, client_addr, inside_root};
+use super::webhook::{self, WebhookConfig, WebhookTrigger};
+use super::{LogMessage, SharedState};
+use futures_util::stream;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::hyper::body::{Body, Bytes};
+use warp::{Filter, Reply};
+
+/// Lifetime of a link when the sign request names none
+const DEFAULT_TTL_SECS: u64 = 3600;
+
+/// Size of the chunks a signed file is streamed in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Request body of `POST /__admin/sign`
+#[derive(serde::Deserialize)]
+struct SignRequest {
+    file: String,
+    #[serde(default)]
+    ttl_secs: Option<u64>,
+}
+
+/// HMAC-SHA256 as in RFC 2104
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner = Sha256::new()
+        .chain_update(block.map(|b| b ^ 0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(block.map(|b| b ^ 0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// Hex signature of `file` valid until `expires`
+fn signature(secret: &str, file: &str, expires: u64) -> String {
+    let message = format!("{}\n{}", file, expires);
+    hmac_sha256(secret.as_bytes(), message.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compare without returning early, so timing does not reveal the matching prefix
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// File path as signed, relative to the root and without a leading slash
+fn normalize(file: &str) -> String {
+    file.trim().trim_start_matches('/').to_string()
+}
+
+/// Map a signed path onto a regular file inside `root`
+fn resolve(root: &Path, file: &str) -> Option<PathBuf> {
+    let relative = Path::new(file);
+    if file.is_empty()
+        || !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    let path = root.join(relative);
+    (path.is_file() && inside_root(root, &path)).then_some(path)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn status(code: StatusCode, body: &'static str) -> warp::reply::Response {
+    warp::reply::with_status(body, code).into_response()
+}
+
+/// Stream `path` as the response body
+async fn file_response(path: &Path) -> std::io::Result<warp::reply::Response> {
+    let file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let body = Body::wrap_stream(stream::unfold(file, |mut file| async move {
+        let mut buf = vec![0; CHUNK_SIZE];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok::<_, std::io::Error>(Bytes::from(buf)), file))
+            }
+            Err(e) => Some((Err(e), file)),
+        }
+    }));
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().replace('"', ""))
+        .unwrap_or_default();
+    Ok(warp::http::Response::builder()
+        .header(warp::http::header::CONTENT_TYPE, mime.as_ref())
+        .header(warp::http::header::CONTENT_LENGTH, size)
+        .header(
+            warp::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", name),
+        )
+        .header(warp::http::header::CACHE_CONTROL, "private, no-store")
+        .body(body)
+        .unwrap_or_default())
+}
+
+/// Routes for serving signed links and, with an admin token, making them
+pub fn routes(
+    secret: String,
+    admin_token: Option<String>,
+    root: PathBuf,
+    base_path: String,
+    webhooks: Vec<WebhookConfig>,
+    state: SharedState,
+    addr_source: ClientAddrSource,
+) -> BoxedFilter<(warp::reply::Response,)> {
+    let secret = Arc::new(secret);
+
+    let serve_secret = secret.clone();
+    let serve_state = state.clone();
+    let serve = warp::path("__signed")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(client_addr(&addr_source))
+        .and_then(
+            move |query: HashMap<String, String>, remote: Option<SocketAddr>| {
+                let secret = serve_secret.clone();
+                let root = root.clone();
+                let state = serve_state.clone();
+                async move {
+                    let remote = remote.map_or_else(|| "unknown".to_string(), |a| a.to_string());
+                    let file = query.get("file").map(|f| normalize(f)).unwrap_or_default();
+                    let expires = query.get("expires").and_then(|e| e.parse::<u64>().ok());
+                    let sig = query.get("sig").map(String::as_str).unwrap_or_default();
+
+                    let refusal = match expires {
+                        None => Some("missing or malformed expiry"),
+                        Some(expires) => {
+                            let expected = signature(&secret, &file, expires);
+                            if !constant_time_eq(expected.as_bytes(), sig.as_bytes()) {
+                                Some("invalid signature")
+                            } else if expires <= unix_now() {
+                                Some("link expired")
+                            } else {
+                                None
+                            }
+                        }
+                    };
+                    if let Some(reason) = refusal {
+                        state.write().add_log(LogMessage::warning(format!(
+                            "Signed URL for {} refused from {}: {}",
+                            file, remote, reason
+                        )));
+                        return Ok::<_, warp::Rejection>(status(
+                            StatusCode::FORBIDDEN,
+                            "Forbidden",
+                        ));
+                    }
+
+                    let resolve_root = root.clone();
+                    let resolve_file = file.clone();
+                    let path =
+                        tokio::task::spawn_blocking(move || resolve(&resolve_root, &resolve_file))
+                            .await
+                            .ok()
+                            .flatten();
+                    let response = match path {
+                        Some(path) => file_response(&path).await.ok(),
+                        None => None,
+                    };
+                    match response {
+                        Some(response) => {
+                            state.write().add_log(LogMessage::info(format!(
+                                "Signed URL for {} served to {}",
+                                file, remote
+                            )));
+                            Ok(response)
+                        }
+                        None => {
+                            state.write().add_log(LogMessage::warning(format!(
+                                "Signed URL for {} from {}: no such file",
+                                file, remote
+                            )));
+                            Ok(status(StatusCode::NOT_FOUND, "Not found"))
+                        }
+                    }
+                }
+            },
+        );
+
+    let Some(admin_token) = admin_token else {
+        return serve.boxed();
+    };
+    let sign = warp::path!("__admin" / "sign")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(client_addr(&addr_source))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::bytes())
+        .map(
+            move |auth: Option<String>, remote: Option<SocketAddr>, body: Bytes| {
+                let remote = remote.map(|addr| addr.to_string());
+                let authorized = auth
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .is_some_and(|token| {
+                        constant_time_eq(token.trim().as_bytes(), admin_token.as_bytes())
+                    });
+                if !authorized {
+                    state.write().add_log(LogMessage::error(format!(
+                        "Signed URL admin: authentication failed from {}",
+                        remote.as_deref().unwrap_or("unknown")
+                    )));
+                    webhook::fire(
+                        &webhooks,
+                        WebhookTrigger::OnAuthFailure,
+                        serde_json::json!({ "path": "__admin/sign", "remote_addr": remote }),
+                        &state,
+                    );
+                    return warp::reply::with_header(
+                        status(StatusCode::UNAUTHORIZED, "Unauthorized"),
+                        "WWW-Authenticate",
+                        "Bearer",
+                    )
+                    .into_response();
+                }
+
+                let Ok(request) = serde_json::from_slice::<SignRequest>(&body) else {
+                    return status(
+                        StatusCode::BAD_REQUEST,
+                        "Expected {\"file\": ..., \"ttl_secs\": ...}",
+                    );
+                };
+                let file = normalize(&request.file);
+                let expires =
+                    unix_now().saturating_add(request.ttl_secs.unwrap_or(DEFAULT_TTL_SECS));
+                let url = format!(
+                    "{}/__signed?file={}&expires={}&sig={}",
+                    base_path,
+                    percent_encoding::utf8_percent_encode(
+                        &file,
+                        percent_encoding::NON_ALPHANUMERIC
+                    ),
+                    expires,
+                    signature(&secret, &file, expires)
+                );
+                state.write().add_log(LogMessage::info(format!(
+                    "Signed URL for {} issued to {}, valid until {}",
+                    file,
+                    remote.as_deref().unwrap_or("unknown"),
+                    chrono::DateTime::from_timestamp(expires as i64, 0)
+                        .map(|t| t.to_rfc3339())
+                        .unwrap_or_else(|| expires.to_string())
+                )));
+                warp::reply::json(&serde_json::json!({ "url": url, "expires": expires }))
+                    .into_response()
+            },
+        );
+    serve.or(sign).unify().boxed()
+}
@@ -1,7 +1,11 @@
 //! SSH/SFTP Server implementation (placeholder)
 //! Note: Full SSH implementation is complex. This is a simplified version.
 
-use super::{LogMessage, ServerConfig, ServerError, ServerHandle, ServerStatus, SharedState};
+use super::startup_report;
+use super::{
+    LogMessage, RestartPolicy, ServerConfig, ServerError, ServerHandle, ServerStatus, SharedState,
+    StartupConfig, export_log_on_stop, finish_log_export, wait_for_root, wait_for_shutdown,
+};
 use std::path::PathBuf;
 use tokio::sync::mpsc;
 
@@ -12,6 +16,21 @@ pub struct SshConfig {
     pub port: u16,
     pub username: String,
     pub password: String,
+    /// Free-form markdown notes about this server
+    #[serde(default)]
+    pub notes: String,
+    /// Directory the session log is written to when the server stops
+    #[serde(default)]
+    pub export_log_on_stop: Option<PathBuf>,
+    /// Stop the server after this many seconds without client activity
+    #[serde(default)]
+    pub auto_stop_seconds: Option<u64>,
+    /// Auto-start, ordering and waiting for the root directory
+    #[serde(default)]
+    pub startup: StartupConfig,
+    /// Restarting the server when it stops on its own
+    #[serde(default)]
+    pub restart: RestartPolicy,
 }
 
 impl Default for SshConfig {
@@ -21,6 +40,11 @@ impl Default for SshConfig {
             port: 2222,
             username: "admin".to_string(),
             password: "admin".to_string(),
+            notes: String::new(),
+            export_log_on_stop: None,
+            auto_stop_seconds: None,
+            startup: StartupConfig::default(),
+            restart: RestartPolicy::default(),
         }
     }
 }
@@ -30,7 +54,8 @@ impl From<SshConfig> for ServerConfig {
         ServerConfig {
             root_dir: cfg.root_dir,
             port: cfg.port,
-            auto_stop_seconds: None,
+            auto_stop_seconds: cfg.auto_stop_seconds,
+            egress_throttle_kbps: None,
         }
     }
 }
@@ -53,6 +78,16 @@ pub async fn start_server(
             port
         )));
     }
+    if !wait_for_root(
+        &config.root_dir,
+        config.startup.wait_for_root_secs,
+        &mut shutdown_rx,
+        &state,
+    )
+    .await?
+    {
+        return Ok(());
+    }
 
     // For now, we'll just mark it as running and wait for shutdown
     // Full SSH implementation would use russh here
@@ -68,10 +103,20 @@ pub async fn start_server(
             config.root_dir.display()
         )));
         s.add_log(LogMessage::info("Note: SSH server is in simplified mode"));
+        if let Some(secs) = config.auto_stop_seconds {
+            s.add_log(LogMessage::info(format!(
+                "Auto stop: after {}s of inactivity",
+                secs
+            )));
+        }
     }
 
-    // Wait for shutdown signal
-    shutdown_rx.recv().await;
+    state.write().bound_port = (port != 0).then_some(port);
+    startup_report::log(&state, "SSH", &config.root_dir, port, None);
+    export_log_on_stop(&state, config.export_log_on_stop.as_deref(), "ssh");
+
+    // Wait for shutdown signal, the placeholder sees no client activity
+    wait_for_shutdown(&mut shutdown_rx, &state, config.auto_stop_seconds).await;
 
     // Update status
     {
@@ -79,6 +124,7 @@ pub async fn start_server(
         s.status = ServerStatus::Stopped;
         s.add_log(LogMessage::info("SSH server stopped"));
     }
+    finish_log_export(&state);
 
     Ok(())
 }
@@ -88,3 +134,29 @@ pub async fn start_server(
 pub fn create_handle(config: SshConfig) -> ServerHandle {
     ServerHandle::new(config.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util;
+    use super::*;
+
+    /// The placeholder binds no port, only the status has to follow
+    #[tokio::test]
+    async fn shutdown_stops_the_server() {
+        let root = tempfile::tempdir().unwrap();
+        let config = SshConfig {
+            root_dir: root.path().to_path_buf(),
+            auto_stop_seconds: None,
+            ..SshConfig::default()
+        };
+        let state = test_util::new_state();
+        let (tx, rx) = mpsc::channel(1);
+        let server = tokio::spawn(start_server(config, state.clone(), rx));
+        test_util::wait_until(&state, "the server runs", |s| {
+            s.status == ServerStatus::Running
+        })
+        .await;
+
+        test_util::stop(&state, &tx, server).await;
+    }
+}
@@ -0,0 +1,105 @@
+//! System summary logged once a server is running
+//!
+//! The block is framed so it stands out in the log and can be pasted into
+//! bug reports as it is.
+
+use super::http::format_size;
+use super::{LogMessage, SharedState};
+use std::path::Path;
+use sysinfo::{Disks, System};
+
+/// Width of the text inside the frame
+const WIDTH: usize = 60;
+
+/// Log the report for `server`, listening on `bound_port` when known
+pub fn log(
+    state: &SharedState,
+    server: &str,
+    root_dir: &Path,
+    requested_port: u16,
+    bound_port: Option<u16>,
+) {
+    let report = report(server, root_dir, requested_port, bound_port);
+    state.write().add_log(LogMessage::info(report));
+}
+
+fn report(server: &str, root_dir: &Path, requested_port: u16, bound_port: Option<u16>) -> String {
+    let mut system = System::new();
+    system.refresh_memory();
+
+    let os = match (System::long_os_version(), System::kernel_version()) {
+        (Some(os), Some(kernel)) => format!("{} (kernel {})", os, kernel),
+        (Some(os), None) => os,
+        (None, _) => std::env::consts::OS.to_string(),
+    };
+    let cores = std::thread::available_parallelism().map_or(0, |n| n.get());
+    let port = match (requested_port, bound_port) {
+        (0, Some(port)) => format!("{} (assigned by the OS)", port),
+        (0, None) => "assigned by the OS".to_string(),
+        (port, _) => port.to_string(),
+    };
+
+    let mut rows = vec![
+        ("OS", os),
+        ("CPU cores", cores.to_string()),
+        (
+            "Memory",
+            format!(
+                "{} available of {}",
+                format_size(system.available_memory()),
+                format_size(system.total_memory())
+            ),
+        ),
+        (
+            "Disk free",
+            match free_space(root_dir) {
+                Some(free) => format!("{} on {}", format_size(free), root_dir.display()),
+                None => format!("unknown for {}", root_dir.display()),
+            },
+        ),
+    ];
+    if cfg!(unix) {
+        let load = System::load_average();
+        rows.push((
+            "Load average",
+            format!("{:.2} {:.2} {:.2}", load.one, load.five, load.fifteen),
+        ));
+    }
+    rows.push(("PID", std::process::id().to_string()));
+    rows.push(("Port", port));
+
+    let border = format!("+{}+", "-".repeat(WIDTH + 2));
+    let mut lines = vec![
+        border.clone(),
+        framed(&format!(
+            "OServers {} {} server",
+            env!("CARGO_PKG_VERSION"),
+            server
+        )),
+        border.clone(),
+    ];
+    lines.extend(
+        rows.iter()
+            .map(|(name, value)| framed(&format!("{:<14}{}", format!("{}:", name), value))),
+    );
+    lines.push(border);
+    lines.join("\n")
+}
+
+/// `text` between the frame's side borders, cut off if it does not fit
+fn framed(text: &str) -> String {
+    let text: String = text.chars().take(WIDTH).collect();
+    let padding = WIDTH - text.chars().count();
+    format!("| {}{} |", text, " ".repeat(padding))
+}
+
+/// Free space on the file system holding `path`, the one with the longest matching mount point
+fn free_space(path: &Path) -> Option<u64> {
+    let path = path.canonicalize().ok()?;
+    Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
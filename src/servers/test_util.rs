@@ -0,0 +1,108 @@
+//! Helpers for tests running a real server
+
+use super::{ServerConfig, ServerError, ServerState, ServerStatus, SharedState};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How long a server gets to start or stop before a test fails
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State for a server a test is about to start
+pub fn new_state() -> SharedState {
+    Arc::new(RwLock::new(ServerState::new(ServerConfig::default())))
+}
+
+/// Wait for `check` to hold for the state, panicking with `what` after [`TIMEOUT`]
+pub async fn wait_until(state: &SharedState, what: &str, check: impl Fn(&ServerState) -> bool) {
+    let started = Instant::now();
+    while !check(&state.read()) {
+        if started.elapsed() > TIMEOUT {
+            let logs: Vec<_> = state.read().logs.iter().map(|l| l.to_string()).collect();
+            panic!(
+                "timed out waiting until {}, log:\n{}",
+                what,
+                logs.join("\n")
+            );
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Connect to a TCP server on `port` that may not be listening yet
+pub async fn connect(port: u16) -> tokio::net::TcpStream {
+    let started = Instant::now();
+    loop {
+        match tokio::net::TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => return stream,
+            Err(e) if started.elapsed() > TIMEOUT => panic!("cannot connect to {}: {}", port, e),
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+}
+
+/// Send the shutdown signal and check the server task ends with the status `Stopped`
+pub async fn stop(
+    state: &SharedState,
+    shutdown_tx: &mpsc::Sender<()>,
+    server: JoinHandle<Result<(), ServerError>>,
+) {
+    shutdown_tx.send(()).await.expect("server still listening");
+    let result = tokio::time::timeout(TIMEOUT, server)
+        .await
+        .expect("server did not stop in time")
+        .expect("server task panicked");
+    assert!(result.is_ok(), "server failed: {:?}", result);
+    assert_eq!(state.read().status, ServerStatus::Stopped);
+}
+
+/// Status line, headers and body of an HTTP response
+pub struct RawResponse {
+    pub status: u16,
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl RawResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// Send `GET path` to the server on `port` and read the whole response
+pub async fn http_get(port: u16, path: &str) -> RawResponse {
+    let mut stream = connect(port).await;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await.expect("send");
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).await.expect("receive");
+    parse_response(&raw)
+}
+
+/// Split a raw response into its parts, the body left as sent
+pub fn parse_response(raw: &[u8]) -> RawResponse {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("end of headers");
+    let head = String::from_utf8_lossy(&raw[..end]).to_string();
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .expect("status code");
+    RawResponse {
+        status,
+        head,
+        body: raw[end + 4..].to_vec(),
+    }
+}
@@ -1,15 +1,51 @@
 //! TFTP Server implementation
 
-use super::{LogMessage, ServerConfig, ServerError, ServerHandle, ServerStatus, SharedState};
-use std::path::PathBuf;
+use super::startup_report;
+use super::{
+    LogMessage, RestartPolicy, ServerConfig, ServerError, ServerHandle, ServerStatus, SharedState,
+    StartupConfig, TransferGuard, export_log_on_stop, finish_log_export, wait_for_root,
+    wait_for_shutdown,
+};
+use async_tftp::packet;
+use async_tftp::server::{DirHandler, DirHandlerMode, Handler, TftpServerBuilder};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio::sync::mpsc;
 
+/// Block numbers are 16 bits, so they start over after this many blocks
+const BLOCKS_PER_WRAP: u64 = 1 << 16;
+
 /// TFTP server specific configuration
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct TftpConfig {
     pub root_dir: PathBuf,
     pub port: u16,
     pub read_only: bool,
+    /// Time to wait for an ACK before retransmitting a block
+    pub retry_timeout_ms: u64,
+    /// Retransmissions of a single block before giving up on the client
+    pub max_retries: u32,
+    /// Bind `[::]` instead of `0.0.0.0`, dual-stack where the OS allows it
+    pub ipv6: bool,
+    /// Glob patterns of file names that may be transferred, empty allows all
+    pub allow_patterns: Vec<String>,
+    /// Free-form markdown notes about this server
+    pub notes: String,
+    /// Directory the session log is written to when the server stops
+    pub export_log_on_stop: Option<PathBuf>,
+    /// Auto-start, ordering and waiting for the root directory
+    pub startup: StartupConfig,
+    /// Restarting the server when it stops on its own
+    pub restart: RestartPolicy,
+    /// Directory uploads are received into before being moved to `root_dir`,
+    /// should be on the same filesystem so the move is atomic
+    pub staging_dir: Option<PathBuf>,
+    /// Stop the server after this many seconds without client activity
+    pub auto_stop_seconds: Option<u64>,
 }
 
 impl Default for TftpConfig {
@@ -18,6 +54,16 @@ impl Default for TftpConfig {
             root_dir: std::env::current_dir().unwrap_or_default(),
             port: 69,
             read_only: false,
+            retry_timeout_ms: 3000,
+            max_retries: 100,
+            ipv6: false,
+            allow_patterns: Vec::new(),
+            notes: String::new(),
+            export_log_on_stop: None,
+            startup: StartupConfig::default(),
+            restart: RestartPolicy::default(),
+            staging_dir: None,
+            auto_stop_seconds: None,
         }
     }
 }
@@ -27,11 +73,347 @@ impl From<TftpConfig> for ServerConfig {
         ServerConfig {
             root_dir: cfg.root_dir,
             port: cfg.port,
-            auto_stop_seconds: None,
+            auto_stop_seconds: cfg.auto_stop_seconds,
+            egress_throttle_kbps: None,
+        }
+    }
+}
+
+/// Handler that only lets files matching the allow patterns through to `DirHandler`
+struct FilteredHandler {
+    inner: DirHandler,
+    root: PathBuf,
+    read_only: bool,
+    /// Handler writing into the staging directory, and that directory
+    staging: Option<(DirHandler, PathBuf)>,
+    allow_patterns: Vec<glob::Pattern>,
+    state: SharedState,
+}
+
+impl FilteredHandler {
+    /// Reject requests for files not matching any allow pattern, logging the denial
+    fn check(
+        &self,
+        client: &SocketAddr,
+        path: &Path,
+        operation: &str,
+    ) -> Result<(), packet::Error> {
+        if self.allow_patterns.is_empty() {
+            return Ok(());
+        }
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // Patterns with a separator match the whole requested path, others the file name
+        let allowed = self.allow_patterns.iter().any(|pattern| {
+            if pattern.as_str().contains('/') {
+                pattern.matches_path(path)
+            } else {
+                pattern.matches(&name)
+            }
+        });
+        if allowed {
+            return Ok(());
+        }
+        self.state.write().add_log(LogMessage::error(format!(
+            "Denied {} of {} from {}: not an allowed file pattern",
+            operation,
+            path.display(),
+            client
+        )));
+        Err(packet::Error::PermissionDenied)
+    }
+
+    /// Open an upload, in the staging directory if one is set so it is
+    /// moved to the root directory only when complete
+    async fn open_upload(
+        &mut self,
+        client: &SocketAddr,
+        path: &Path,
+        size: Option<u64>,
+    ) -> Result<UploadWriter<<DirHandler as Handler>::Writer>, packet::Error> {
+        let Some((handler, dir)) = &mut self.staging else {
+            return Ok(UploadWriter {
+                inner: Some(self.inner.write_req_open(client, path, size).await?),
+                staged: None,
+                transfer: None,
+            });
+        };
+        // Both locations are built from the path, so it must stay below them
+        let relative: PathBuf = path
+            .components()
+            .filter(|c| !matches!(c, Component::RootDir | Component::CurDir))
+            .collect();
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(packet::Error::PermissionDenied);
+        }
+        let target = self.root.join(&relative);
+        if target.exists() {
+            return Err(packet::Error::FileAlreadyExists);
+        }
+        let staged = dir.join(&relative);
+        if let Some(parent) = staged.parent() {
+            std::fs::create_dir_all(parent).map_err(|_| packet::Error::PermissionDenied)?;
+        }
+        let inner = handler.write_req_open(client, &relative, size).await?;
+
+        self.state.write().add_log(LogMessage::info(format!(
+            "Receiving {} from {} into {}",
+            target.display(),
+            client,
+            staged.display()
+        )));
+        Ok(UploadWriter {
+            inner: Some(inner),
+            staged: Some(StagedUpload {
+                staged,
+                target,
+                client: *client,
+                complete: false,
+                state: self.state.clone(),
+            }),
+            transfer: None,
+        })
+    }
+}
+
+#[async_tftp::async_trait]
+impl Handler for FilteredHandler {
+    type Reader = WrapLogger<<DirHandler as Handler>::Reader>;
+    type Writer = UploadWriter<<DirHandler as Handler>::Writer>;
+
+    async fn read_req_open(
+        &mut self,
+        client: &SocketAddr,
+        path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        self.check(client, path, "read")?;
+        self.state.write().record_activity();
+        let (reader, size) = self.inner.read_req_open(client, path).await?;
+        let reader = WrapLogger {
+            inner: reader,
+            path: path.to_path_buf(),
+            client: *client,
+            block_size: 0,
+            bytes: 0,
+            wraps: 0,
+            state: self.state.clone(),
+            transfer: TransferGuard::start(
+                &self.state,
+                client.to_string(),
+                path.display().to_string(),
+                false,
+                size,
+            ),
+        };
+        Ok((reader, size))
+    }
+
+    async fn write_req_open(
+        &mut self,
+        client: &SocketAddr,
+        path: &Path,
+        size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        self.check(client, path, "write")?;
+        self.state.write().record_activity();
+        if self.read_only {
+            self.state.write().add_log(LogMessage::error(format!(
+                "Denied write of {} from {}: server is read-only",
+                path.display(),
+                client
+            )));
+            return Err(packet::Error::PermissionDenied);
+        }
+        let mut writer = self.open_upload(client, path, size).await?;
+        writer.transfer = Some(TransferGuard::start(
+            &self.state,
+            client.to_string(),
+            path.display().to_string(),
+            true,
+            size,
+        ));
+        Ok(writer)
+    }
+}
+
+/// Reader for a download, logging each time the block number starts over
+///
+/// async-tftp wraps block numbers itself and fills each block with reads
+/// the size of one block, so the first read gives the negotiated size.
+struct WrapLogger<R> {
+    inner: R,
+    path: PathBuf,
+    client: SocketAddr,
+    block_size: u64,
+    bytes: u64,
+    wraps: u64,
+    state: SharedState,
+    transfer: TransferGuard,
+}
+
+impl<R: futures_io::AsyncRead + Unpin> futures_io::AsyncRead for WrapLogger<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if this.block_size == 0 {
+            this.block_size = buf.len().max(1) as u64;
+        }
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = result {
+            this.bytes += read as u64;
+            this.transfer.add(read as u64);
+            // Block 65536 goes out as block 0
+            let blocks = this.bytes.div_ceil(this.block_size);
+            let wraps = blocks / BLOCKS_PER_WRAP;
+            if wraps > this.wraps {
+                this.wraps = wraps;
+                this.state.write().add_log(LogMessage::debug(format!(
+                    "Block number of {} to {} wrapped to 0 ({} times, {} bytes read)",
+                    this.path.display(),
+                    this.client,
+                    wraps,
+                    this.bytes
+                )));
+            }
+        }
+        result
+    }
+}
+
+/// Upload received into the staging directory
+struct StagedUpload {
+    staged: PathBuf,
+    target: PathBuf,
+    client: SocketAddr,
+    /// Flushed with nothing written since, i.e. the last block has been stored
+    complete: bool,
+    state: SharedState,
+}
+
+impl StagedUpload {
+    /// Move a complete upload into place, delete an incomplete one
+    fn finish(self) {
+        if !self.complete {
+            let _ = std::fs::remove_file(&self.staged);
+            self.state.write().add_log(LogMessage::warning(format!(
+                "Upload of {} from {} did not complete, removed {}",
+                self.target.display(),
+                self.client,
+                self.staged.display()
+            )));
+            return;
+        }
+        match std::fs::rename(&self.staged, &self.target) {
+            Ok(()) => self.state.write().add_log(LogMessage::info(format!(
+                "Moved {} to {}",
+                self.staged.display(),
+                self.target.display()
+            ))),
+            Err(e) => {
+                let _ = std::fs::remove_file(&self.staged);
+                self.state.write().add_log(LogMessage::error(format!(
+                    "Failed to move {} to {}: {}",
+                    self.staged.display(),
+                    self.target.display(),
+                    e
+                )));
+            }
         }
     }
 }
 
+/// Writer for an upload, moving staged uploads into place once dropped
+struct UploadWriter<W> {
+    /// Always `Some` until dropped
+    inner: Option<W>,
+    staged: Option<StagedUpload>,
+    /// Entry in the live transfers view
+    transfer: Option<TransferGuard>,
+}
+
+impl<W: futures_io::AsyncWrite + Unpin> UploadWriter<W> {
+    fn inner(&mut self) -> Pin<&mut W> {
+        Pin::new(self.inner.as_mut().expect("writer used after drop"))
+    }
+
+    fn set_complete(&mut self, complete: bool) {
+        if let Some(staged) = &mut self.staged {
+            staged.complete = complete;
+        }
+    }
+}
+
+impl<W: futures_io::AsyncWrite + Unpin> futures_io::AsyncWrite for UploadWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let result = this.inner().poll_write(cx, buf);
+        if let Poll::Ready(Ok(written @ 1..)) = result {
+            this.set_complete(false);
+            if let Some(transfer) = &this.transfer {
+                transfer.add(written as u64);
+            }
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let result = this.inner().poll_flush(cx);
+        if let Poll::Ready(Ok(())) = result {
+            this.set_complete(true);
+        }
+        result
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let result = this.inner().poll_close(cx);
+        if let Poll::Ready(Ok(())) = result {
+            this.set_complete(true);
+        }
+        result
+    }
+}
+
+impl<W> Drop for UploadWriter<W> {
+    fn drop(&mut self) {
+        // Close the staged file first, an open file cannot be renamed on Windows
+        drop(self.inner.take());
+        if let Some(staged) = self.staged.take() {
+            staged.finish();
+        }
+    }
+}
+
+/// Describe a bind failure, with hints for the common UDP cases
+fn describe_build_error(e: &async_tftp::Error, port: u16) -> String {
+    match e {
+        async_tftp::Error::Io(io) if io.kind() == std::io::ErrorKind::AddrInUse => format!(
+            "UDP port {} is already in use, is another TFTP server running?",
+            port
+        ),
+        async_tftp::Error::Io(io) if io.kind() == std::io::ErrorKind::PermissionDenied => {
+            format!(
+                "Permission denied binding UDP port {}, ports below 1024 need administrator/root privileges",
+                port
+            )
+        }
+        _ => format!("Failed to build TFTP server: {}", e),
+    }
+}
+
 /// Start TFTP server
 pub async fn start_server(
     config: TftpConfig,
@@ -50,15 +432,81 @@ pub async fn start_server(
             port
         )));
     }
+    if !wait_for_root(
+        &config.root_dir,
+        config.startup.wait_for_root_secs,
+        &mut shutdown_rx,
+        &state,
+    )
+    .await?
+    {
+        return Ok(());
+    }
 
-    let addr: std::net::SocketAddr = format!("0.0.0.0:{}", port).parse().unwrap();
+    let ip = if config.ipv6 {
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    };
+    let addr = SocketAddr::new(ip, port);
+
+    // Dropping a bad pattern could leave the list empty, which allows every file
+    let mut allow_patterns = Vec::new();
+    for pattern in &config.allow_patterns {
+        match glob::Pattern::new(pattern) {
+            Ok(p) => allow_patterns.push(p),
+            Err(e) => {
+                let msg = format!("Invalid file pattern {}: {}", pattern, e);
+                let mut s = state.write();
+                s.status = ServerStatus::Error(msg.clone());
+                s.add_log(LogMessage::error(format!("Cannot start: {}", msg)));
+                return Err(ServerError::ConfigError(msg));
+            }
+        }
+    }
+    let filter_state = state.clone();
+    let staging_dir = config.staging_dir.clone().filter(|_| !config.read_only);
 
     // Create TFTP server
-    let server_result = async_tftp::server::TftpServerBuilder::with_dir_ro(&root);
+    let mode = if config.read_only {
+        DirHandlerMode::ReadOnly
+    } else {
+        DirHandlerMode::ReadWrite
+    };
+    let server_result = DirHandler::new(&root, mode)
+        .and_then(|inner| {
+            let staging = match &staging_dir {
+                Some(dir) => {
+                    std::fs::create_dir_all(dir).map_err(async_tftp::Error::Io)?;
+                    Some((
+                        DirHandler::new(dir, DirHandlerMode::WriteOnly)?,
+                        dir.clone(),
+                    ))
+                }
+                None => None,
+            };
+            Ok((inner, staging))
+        })
+        .map(|(inner, staging)| {
+            TftpServerBuilder::with_handler(FilteredHandler {
+                inner,
+                root: root.clone(),
+                read_only: config.read_only,
+                staging,
+                allow_patterns,
+                state: filter_state,
+            })
+        });
 
     match server_result {
         Ok(builder) => {
-            let server = builder.bind(addr).build().await;
+            // No block size limit, clients may negotiate any size the RFC allows
+            let server = builder
+                .bind(addr)
+                .timeout(Duration::from_millis(config.retry_timeout_ms))
+                .max_send_retries(config.max_retries)
+                .build()
+                .await;
             match server {
                 Ok(srv) => {
                     // Update status to running
@@ -66,8 +514,8 @@ pub async fn start_server(
                         let mut s = state.write();
                         s.status = ServerStatus::Running;
                         s.add_log(LogMessage::info(format!(
-                            "TFTP server started on tftp://0.0.0.0:{}",
-                            port
+                            "TFTP server started on tftp://{}",
+                            addr
                         )));
                         s.add_log(LogMessage::info(format!(
                             "Root directory: {}",
@@ -81,7 +529,33 @@ pub async fn start_server(
                                 "read-write"
                             }
                         )));
+                        if let Some(dir) = &staging_dir {
+                            s.add_log(LogMessage::info(format!(
+                                "Staging directory: {}",
+                                dir.display()
+                            )));
+                        }
+                        s.add_log(LogMessage::info(format!(
+                            "Retransmission: {}ms timeout, {} retries",
+                            config.retry_timeout_ms, config.max_retries
+                        )));
+                        if let Some(secs) = config.auto_stop_seconds {
+                            s.add_log(LogMessage::info(format!(
+                                "Auto stop: after {}s of inactivity",
+                                secs
+                            )));
+                        }
+                        if !config.allow_patterns.is_empty() {
+                            s.add_log(LogMessage::info(format!(
+                                "Allowed files: {}",
+                                config.allow_patterns.join(", ")
+                            )));
+                        }
                     }
+                    let bound_port = srv.listen_addr().ok().map(|addr| addr.port());
+                    state.write().bound_port = bound_port;
+                    startup_report::log(&state, "TFTP", &root, port, bound_port);
+                    export_log_on_stop(&state, config.export_log_on_stop.as_deref(), "tftp");
 
                     // Run server with shutdown signal
                     tokio::select! {
@@ -93,19 +567,17 @@ pub async fn start_server(
                                 return Err(ServerError::Other(e.to_string()));
                             }
                         }
-                        _ = shutdown_rx.recv() => {
-                            // Shutdown requested
+                        _ = wait_for_shutdown(&mut shutdown_rx, &state, config.auto_stop_seconds) => {
+                            // Shutdown requested or idle for too long
                         }
                     }
                 }
                 Err(e) => {
                     let mut s = state.write();
-                    s.status = ServerStatus::Error(e.to_string());
-                    s.add_log(LogMessage::error(format!(
-                        "Failed to build TFTP server: {}",
-                        e
-                    )));
-                    return Err(ServerError::Other(e.to_string()));
+                    let msg = describe_build_error(&e, port);
+                    s.status = ServerStatus::Error(msg.clone());
+                    s.add_log(LogMessage::error(&msg));
+                    return Err(ServerError::Other(msg));
                 }
             }
         }
@@ -126,6 +598,7 @@ pub async fn start_server(
         s.status = ServerStatus::Stopped;
         s.add_log(LogMessage::info("TFTP server stopped"));
     }
+    finish_log_export(&state);
 
     Ok(())
 }
@@ -135,3 +608,99 @@ pub async fn start_server(
 pub fn create_handle(config: TftpConfig) -> ServerHandle {
     ServerHandle::new(config.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{check_port_available, test_util};
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_releases_the_port() {
+        let root = tempfile::tempdir().unwrap();
+        let config = TftpConfig {
+            root_dir: root.path().to_path_buf(),
+            port: 0,
+            auto_stop_seconds: None,
+            ..TftpConfig::default()
+        };
+        let state = test_util::new_state();
+        let (tx, rx) = mpsc::channel(1);
+        let server = tokio::spawn(start_server(config, state.clone(), rx));
+        test_util::wait_until(&state, "the server listens", |s| {
+            s.status == ServerStatus::Running && s.bound_port.is_some()
+        })
+        .await;
+        let port = state.read().bound_port.unwrap();
+        assert!(!check_port_available(port, true));
+
+        test_util::stop(&state, &tx, server).await;
+        assert!(check_port_available(port, true));
+    }
+
+    #[tokio::test]
+    async fn large_file_downloads_past_the_block_number_wrap() {
+        let root = tempfile::tempdir().unwrap();
+        // Over 65535 blocks of 1468 bytes, so the block number wraps
+        let size = 100 * 1024 * 1024;
+        let content: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        std::fs::write(root.path().join("big.bin"), &content).unwrap();
+        let config = TftpConfig {
+            root_dir: root.path().to_path_buf(),
+            port: 0,
+            auto_stop_seconds: None,
+            ..TftpConfig::default()
+        };
+        let state = test_util::new_state();
+        let (tx, rx) = mpsc::channel(1);
+        let server = tokio::spawn(start_server(config, state.clone(), rx));
+        test_util::wait_until(&state, "the server listens", |s| {
+            s.status == ServerStatus::Running && s.bound_port.is_some()
+        })
+        .await;
+        let port = state.read().bound_port.unwrap();
+
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket
+            .send_to(
+                b"\0\x01big.bin\0octet\0blksize\x001468\0",
+                ("127.0.0.1", port),
+            )
+            .await
+            .unwrap();
+        let mut received = Vec::with_capacity(size);
+        let mut packet = [0u8; 4 + 1468];
+        let mut expected_block: u16 = 1;
+        loop {
+            let (len, peer) =
+                tokio::time::timeout(test_util::TIMEOUT, socket.recv_from(&mut packet))
+                    .await
+                    .expect("transfer stalled")
+                    .unwrap();
+            match u16::from_be_bytes([packet[0], packet[1]]) {
+                // Option acknowledgement, the first block follows our ACK of block 0
+                6 => {
+                    assert!(packet[2..len].starts_with(b"blksize\x001468\0"));
+                    socket.send_to(&[0, 4, 0, 0], peer).await.unwrap();
+                }
+                3 => {
+                    let block = u16::from_be_bytes([packet[2], packet[3]]);
+                    assert_eq!(block, expected_block);
+                    received.extend_from_slice(&packet[4..len]);
+                    socket
+                        .send_to(&[0, 4, packet[2], packet[3]], peer)
+                        .await
+                        .unwrap();
+                    expected_block = expected_block.wrapping_add(1);
+                    if len < packet.len() {
+                        break;
+                    }
+                }
+                opcode => panic!("unexpected TFTP packet {}", opcode),
+            }
+        }
+        assert_eq!(received.len(), size);
+        assert!(received == content, "downloaded file differs");
+
+        test_util::stop(&state, &tx, server).await;
+    }
+}
@@ -0,0 +1,128 @@
+//! Egress bandwidth limit for whole connections
+//!
+//! Wraps the raw stream of an accepted connection, so everything a server
+//! sends is shaped regardless of protocol. Reads pass through untouched.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// Pause before checking again for send allowance once it is used up
+const REFILL_WAIT: Duration = Duration::from_millis(50);
+
+/// A stream whose writes are limited to a number of bytes per second
+pub struct ThrottledStream<S> {
+    inner: S,
+    /// Bytes per second
+    rate: u64,
+    /// Most bytes that may be sent in one burst, a quarter second's worth
+    burst: u64,
+    /// Bytes that may be sent before waiting
+    allowance: u64,
+    last_refill: Instant,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> ThrottledStream<S> {
+    /// Limit writes to `inner` to `kbps` kilobits per second
+    pub fn new(inner: S, kbps: u32) -> Self {
+        let rate = (kbps.max(1) as u64 * 1000 / 8).max(1);
+        let burst = (rate / 4).max(1);
+        Self {
+            inner,
+            rate,
+            burst,
+            allowance: burst,
+            last_refill: Instant::now(),
+            delay: None,
+        }
+    }
+
+    /// Add the allowance earned since the last refill
+    fn refill(&mut self) {
+        // In u128, as nanoseconds times bytes per second overflow u64 after minutes idle
+        let earned = self.last_refill.elapsed().as_nanos() * self.rate as u128 / 1_000_000_000;
+        let earned = earned.min(self.burst as u128) as u64;
+        // Keep the clock where it is until a whole byte has been earned
+        if earned > 0 {
+            self.allowance = (self.allowance + earned).min(self.burst);
+            self.last_refill = Instant::now();
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ThrottledStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ThrottledStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(delay) = &mut this.delay {
+                ready!(delay.as_mut().poll(cx));
+                this.delay = None;
+            }
+            this.refill();
+            if this.allowance > 0 {
+                break;
+            }
+            this.delay = Some(Box::pin(tokio::time::sleep(REFILL_WAIT)));
+        }
+        let len = buf.len().min(this.allowance as usize);
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..len]))?;
+        this.allowance -= written as u64;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_idle_gap_refills_to_the_burst() {
+        // 1 Gbit/s, where nanoseconds times rate passes u64::MAX after about 2.5 minutes
+        let mut stream = ThrottledStream::new((), 1_000_000);
+        stream.allowance = 0;
+        stream.last_refill = Instant::now() - Duration::from_secs(24 * 60 * 60);
+        stream.refill();
+        assert_eq!(stream.allowance, stream.burst);
+    }
+
+    #[test]
+    fn refill_adds_what_was_earned() {
+        // 8 kbit/s is 1000 bytes per second, bursting to 250
+        let mut stream = ThrottledStream::new((), 8);
+        stream.allowance = 0;
+        stream.last_refill = Instant::now() - Duration::from_millis(100);
+        stream.refill();
+        assert!(
+            (100..=110).contains(&stream.allowance),
+            "{}",
+            stream.allowance
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! HTTPS configuration with session resumption
+//!
+//! Returning clients skip the full handshake by presenting a session ticket
+//! encrypted with a key only the server holds. The key is replaced every
+//! [`KEY_ROTATION_INTERVAL`] and the previous one still accepted for
+//! [`OLD_KEY_GRACE`], so a leaked key exposes a limited window of sessions.
+
+use super::http::TlsConfig;
+use super::{LogMessage, SharedState};
+use parking_lot::RwLock;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::server::{NoServerSessionStorage, ProducesTickets};
+
+/// How long a ticket key is used for new tickets
+const KEY_ROTATION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long tickets of the previous key are still accepted after a rotation
+const OLD_KEY_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// Build the rustls configuration for `tls`, handing out session tickets
+/// encrypted with `ticket_keys` or, without them, not resuming sessions at all
+pub fn server_config(
+    tls: &TlsConfig,
+    ticket_keys: Option<Arc<TicketKeys>>,
+) -> Result<ServerConfig, String> {
+    let cert_pem =
+        std::fs::read(&tls.cert_path).map_err(|e| format!("{}: {}", tls.cert_path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", tls.cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!(
+            "{}: no PEM certificate found",
+            tls.cert_path.display()
+        ));
+    }
+    let key_pem =
+        std::fs::read(&tls.key_path).map_err(|e| format!("{}: {}", tls.key_path.display(), e))?;
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .map_err(|e| format!("{}: {}", tls.key_path.display(), e))?
+        .ok_or_else(|| format!("{}: no PEM private key found", tls.key_path.display()))?;
+
+    // Named explicitly, dependencies enabling another provider would make the default ambiguous
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("TLS setup failed: {}", e))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("invalid certificate or key: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    match ticket_keys {
+        Some(keys) => config.ticketer = keys,
+        None => {
+            config.session_storage = Arc::new(NoServerSessionStorage {});
+            config.send_tls13_tickets = 0;
+        }
+    }
+    Ok(config)
+}
+
+/// One key session tickets are encrypted with
+struct TicketKey {
+    key: LessSafeKey,
+    /// When tickets of this key stop being accepted, set once it is replaced
+    retired_at: Option<Instant>,
+}
+
+impl TicketKey {
+    fn generate(rng: &SystemRandom) -> Option<Self> {
+        let mut bytes = [0u8; 32];
+        rng.fill(&mut bytes).ok()?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes).ok()?;
+        Some(Self {
+            key: LessSafeKey::new(key),
+            retired_at: None,
+        })
+    }
+
+    /// `plain` sealed behind a random nonce, which leads the ticket
+    fn seal(&self, rng: &SystemRandom, plain: &[u8]) -> Option<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut nonce).ok()?;
+        let mut sealed = plain.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .ok()?;
+        let mut ticket = nonce.to_vec();
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    fn open(&self, ticket: &[u8]) -> Option<Vec<u8>> {
+        if self.retired_at.is_some_and(|at| at <= Instant::now()) {
+            return None;
+        }
+        let nonce = ticket.get(..NONCE_LEN)?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut sealed = ticket.get(NONCE_LEN..)?.to_vec();
+        let plain = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .ok()?;
+        Some(plain.to_vec())
+    }
+}
+
+/// Current and previous session ticket keys of one server
+pub struct TicketKeys {
+    current: RwLock<TicketKey>,
+    previous: RwLock<Option<TicketKey>>,
+    rng: SystemRandom,
+}
+
+impl std::fmt::Debug for TicketKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TicketKeys").finish_non_exhaustive()
+    }
+}
+
+impl TicketKeys {
+    /// Start with a freshly generated key, `None` if the system has no randomness to offer
+    pub fn generate() -> Option<Self> {
+        let rng = SystemRandom::new();
+        let current = TicketKey::generate(&rng)?;
+        Some(Self {
+            current: RwLock::new(current),
+            previous: RwLock::new(None),
+            rng,
+        })
+    }
+
+    /// Replace the key every [`KEY_ROTATION_INTERVAL`] for as long as the server runs
+    pub async fn rotate(self: Arc<Self>, state: SharedState) {
+        loop {
+            tokio::time::sleep(KEY_ROTATION_INTERVAL).await;
+            let Some(key) = TicketKey::generate(&self.rng) else {
+                state.write().add_log(LogMessage::warning(
+                    "TLS session ticket key not rotated: no randomness available",
+                ));
+                continue;
+            };
+            let mut old = std::mem::replace(&mut *self.current.write(), key);
+            old.retired_at = Some(Instant::now() + OLD_KEY_GRACE);
+            *self.previous.write() = Some(old);
+            state.write().add_log(LogMessage::info(format!(
+                "TLS session ticket key rotated, the old key is accepted for another {} minutes",
+                OLD_KEY_GRACE.as_secs() / 60
+            )));
+        }
+    }
+}
+
+impl ProducesTickets for TicketKeys {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        (KEY_ROTATION_INTERVAL + OLD_KEY_GRACE).as_secs() as u32
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.current.read().seal(&self.rng, plain)
+    }
+
+    fn decrypt(&self, ticket: &[u8]) -> Option<Vec<u8>> {
+        self.current.read().open(ticket).or_else(|| {
+            self.previous
+                .read()
+                .as_ref()
+                .and_then(|key| key.open(ticket))
+        })
+    }
+}
@@ -0,0 +1,147 @@
+//! Tor onion service for the HTTP server, set up over Tor's control port
+//!
+//! Speaks the control protocol directly: authenticate without credentials
+//! or with the cookie file, then `ADD_ONION` a new v3 service forwarding
+//! port 80 to the local server. Tor drops the service when the control
+//! connection closes, `remove` takes it down explicitly first.
+
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// An onion service, alive as long as the control connection is open
+pub struct OnionService {
+    control: BufReader<TcpStream>,
+    service_id: String,
+}
+
+impl OnionService {
+    /// Create a service forwarding onion port 80 to `local_port`
+    pub async fn create(control_port: u16, local_port: u16) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(("127.0.0.1", control_port)).await?;
+        let mut control = BufReader::new(stream);
+        authenticate(&mut control).await?;
+
+        let reply = command(
+            &mut control,
+            &format!(
+                "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK Port=80,127.0.0.1:{}",
+                local_port
+            ),
+        )
+        .await?;
+        let service_id = reply
+            .iter()
+            .find_map(|line| line.strip_prefix("ServiceID="))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Tor returned no service ID"))?
+            .to_string();
+        Ok(Self {
+            control,
+            service_id,
+        })
+    }
+
+    /// Host name of the service, e.g. `abc…xyz.onion`
+    pub fn address(&self) -> String {
+        format!("{}.onion", self.service_id)
+    }
+
+    /// Take the service down and close the control connection
+    pub async fn remove(mut self) -> std::io::Result<()> {
+        let del = format!("DEL_ONION {}", self.service_id);
+        command(&mut self.control, &del).await?;
+        Ok(())
+    }
+}
+
+/// Authenticate with the first method Tor offers that needs no password
+async fn authenticate(control: &mut BufReader<TcpStream>) -> std::io::Result<()> {
+    let info = command(control, "PROTOCOLINFO 1").await?;
+    let auth = info
+        .iter()
+        .find_map(|line| line.strip_prefix("AUTH "))
+        .unwrap_or_default();
+    let methods: Vec<&str> = auth
+        .split(' ')
+        .find_map(|field| field.strip_prefix("METHODS="))
+        .map(|m| m.split(',').collect())
+        .unwrap_or_default();
+
+    if methods.contains(&"NULL") {
+        command(control, "AUTHENTICATE").await?;
+        return Ok(());
+    }
+    if methods.contains(&"COOKIE") {
+        let path = auth
+            .split_once("COOKIEFILE=")
+            .map(|(_, rest)| unquote(rest))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Tor named no cookie file"))?;
+        let cookie = tokio::fs::read(&path).await.map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("cannot read Tor cookie file {}: {}", path, e),
+            )
+        })?;
+        let hex: String = cookie.iter().map(|b| format!("{:02x}", b)).collect();
+        command(control, &format!("AUTHENTICATE {}", hex)).await?;
+        return Ok(());
+    }
+    Err(Error::new(
+        ErrorKind::PermissionDenied,
+        format!(
+            "Tor control port requires {} authentication, enable CookieAuthentication",
+            methods.join("/")
+        ),
+    ))
+}
+
+/// Leading quoted string of `text`, with `\"` and `\\` unescaped
+fn unquote(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.strip_prefix('"').unwrap_or(text).chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Send a command and return the text of its reply lines, failing on a non-250 reply
+async fn command(control: &mut BufReader<TcpStream>, cmd: &str) -> std::io::Result<Vec<String>> {
+    control
+        .get_mut()
+        .write_all(format!("{}\r\n", cmd).as_bytes())
+        .await?;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if control.read_line(&mut line).await? == 0 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Tor closed the control connection",
+            ));
+        }
+        let line = line.trim_end();
+        let (code, rest) = line.split_at(line.len().min(3));
+        let (separator, text) = rest.split_at(rest.len().min(1));
+        if code != "250" {
+            return Err(Error::other(format!(
+                "Tor refused {}: {}",
+                cmd_name(cmd),
+                line
+            )));
+        }
+        lines.push(text.to_string());
+        if separator == " " {
+            return Ok(lines);
+        }
+    }
+}
+
+/// First word of a command, keeping cookies out of error messages
+fn cmd_name(cmd: &str) -> &str {
+    cmd.split(' ').next().unwrap_or(cmd)
+}
@@ -0,0 +1,281 @@
+//! Notifications about finished FTP transfers, by email or webhook
+//!
+//! libunftp reports each finished transfer to a [`TransferNotifier`], which
+//! queues matching ones for a background task so sending never holds up
+//! the transfer itself.
+
+use super::webhook;
+use super::{LogMessage, SharedState};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use libunftp::notification::{DataEvent, DataListener, EventMeta};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Notifications waiting to be sent before further ones are dropped
+const QUEUE_SIZE: usize = 256;
+
+/// Address each logged in user last connected from
+pub type ClientIps = Arc<Mutex<HashMap<String, IpAddr>>>;
+
+/// Transfers that cause a notification
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum NotificationTrigger {
+    #[default]
+    OnUpload,
+    OnDownload,
+    OnBoth,
+}
+
+impl NotificationTrigger {
+    pub const ALL: [NotificationTrigger; 3] = [
+        NotificationTrigger::OnUpload,
+        NotificationTrigger::OnDownload,
+        NotificationTrigger::OnBoth,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotificationTrigger::OnUpload => "Uploads",
+            NotificationTrigger::OnDownload => "Downloads",
+            NotificationTrigger::OnBoth => "Uploads and downloads",
+        }
+    }
+
+    fn matches(&self, direction: Direction) -> bool {
+        match self {
+            NotificationTrigger::OnUpload => direction == Direction::Upload,
+            NotificationTrigger::OnDownload => direction == Direction::Download,
+            NotificationTrigger::OnBoth => true,
+        }
+    }
+}
+
+/// Mail server the notifications are sent through
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    /// Connect with TLS right away instead of upgrading with STARTTLS
+    pub implicit_tls: bool,
+    /// Login for the mail server, empty to send without authenticating
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 587,
+            implicit_tls: false,
+            username: String::new(),
+            password: String::new(),
+            from: String::new(),
+            to: String::new(),
+        }
+    }
+}
+
+/// Where notifications are delivered
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum NotificationMode {
+    Email(SmtpConfig),
+    Webhook(String),
+}
+
+/// Which transfers are reported and how
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct NotificationConfig {
+    pub mode: NotificationMode,
+    pub trigger: NotificationTrigger,
+    /// Transfers smaller than this are not reported
+    pub min_size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Upload,
+    Download,
+}
+
+impl Direction {
+    fn name(&self) -> &'static str {
+        match self {
+            Direction::Upload => "upload",
+            Direction::Download => "download",
+        }
+    }
+}
+
+/// A finished transfer worth reporting
+#[derive(Debug)]
+struct Transfer {
+    username: String,
+    client_ip: Option<IpAddr>,
+    file: String,
+    size: u64,
+    direction: Direction,
+    timestamp: chrono::DateTime<chrono::Local>,
+}
+
+impl Transfer {
+    fn json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "event": "ftp_transfer",
+            "username": self.username,
+            "client_ip": self.client_ip.map(|ip| ip.to_string()),
+            "file": self.file,
+            "size": self.size,
+            "direction": self.direction.name(),
+            "timestamp": self.timestamp.to_rfc3339(),
+        })
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} of {} ({} bytes) by {}",
+            self.direction.name(),
+            self.file,
+            self.size,
+            self.username
+        )
+    }
+}
+
+/// Data listener queueing the transfers that match the configuration
+pub struct TransferNotifier {
+    trigger: NotificationTrigger,
+    min_size_bytes: u64,
+    client_ips: ClientIps,
+    queue: mpsc::Sender<Transfer>,
+    state: SharedState,
+}
+
+// libunftp wants a Debug listener, the server state has no Debug
+impl std::fmt::Debug for TransferNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransferNotifier")
+            .field("trigger", &self.trigger)
+            .field("min_size_bytes", &self.min_size_bytes)
+            .finish()
+    }
+}
+
+impl TransferNotifier {
+    /// Start the background sender, which ends once the notifier is dropped
+    pub fn spawn(config: NotificationConfig, client_ips: ClientIps, state: SharedState) -> Self {
+        let (queue, rx) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(send_all(rx, config.mode, state.clone()));
+        Self {
+            trigger: config.trigger,
+            min_size_bytes: config.min_size_bytes,
+            client_ips,
+            queue,
+            state,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DataListener for TransferNotifier {
+    async fn receive_data_event(&self, event: DataEvent, meta: EventMeta) {
+        let (direction, file, size) = match event {
+            DataEvent::Put { path, bytes } => (Direction::Upload, path, bytes),
+            DataEvent::Got { path, bytes } => (Direction::Download, path, bytes),
+            _ => return,
+        };
+        if !self.trigger.matches(direction) || size < self.min_size_bytes {
+            return;
+        }
+        let transfer = Transfer {
+            client_ip: self.client_ips.lock().get(&meta.username).copied(),
+            username: meta.username,
+            file,
+            size,
+            direction,
+            timestamp: chrono::Local::now(),
+        };
+        if let Err(mpsc::error::TrySendError::Full(transfer)) = self.queue.try_send(transfer) {
+            self.state.write().add_log(LogMessage::warning(format!(
+                "Notification queue full, not reporting {}",
+                transfer.summary()
+            )));
+        }
+    }
+}
+
+/// Deliver queued transfers one after another until the queue closes
+async fn send_all(mut rx: mpsc::Receiver<Transfer>, mode: NotificationMode, state: SharedState) {
+    while let Some(transfer) = rx.recv().await {
+        match &mode {
+            // Logs the outcome itself
+            NotificationMode::Webhook(url) => {
+                webhook::deliver(url, &transfer.json(), &state).await;
+            }
+            NotificationMode::Email(smtp) => {
+                let msg = match send_email(smtp, &transfer).await {
+                    Ok(()) => LogMessage::info(format!(
+                        "Notification sent to {}: {}",
+                        smtp.to,
+                        transfer.summary()
+                    )),
+                    Err(e) => LogMessage::error(format!(
+                        "Notification email to {} failed: {}",
+                        smtp.to, e
+                    )),
+                };
+                state.write().add_log(msg);
+            }
+        }
+    }
+}
+
+async fn send_email(smtp: &SmtpConfig, transfer: &Transfer) -> Result<(), String> {
+    let body = format!(
+        "Direction: {}\nFile: {}\nSize: {} bytes\nUser: {}\nClient: {}\nTime: {}\n",
+        transfer.direction.name(),
+        transfer.file,
+        transfer.size,
+        transfer.username,
+        transfer
+            .client_ip
+            .map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+        transfer.timestamp.format("%Y-%m-%d %H:%M:%S %:z")
+    );
+    let message = Message::builder()
+        .from(smtp.from.parse().map_err(|e| format!("sender: {}", e))?)
+        .to(smtp.to.parse().map_err(|e| format!("recipient: {}", e))?)
+        .subject(format!("FTP {}", transfer.summary()))
+        .body(body)
+        .map_err(|e| e.to_string())?;
+
+    let builder = if smtp.implicit_tls {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)
+    }
+    .map_err(|e| e.to_string())?
+    .port(smtp.port);
+    let builder = if smtp.username.is_empty() {
+        builder
+    } else {
+        builder.credentials(Credentials::new(
+            smtp.username.clone(),
+            smtp.password.clone(),
+        ))
+    };
+    builder
+        .build()
+        .send(message)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
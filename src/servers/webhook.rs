@@ -0,0 +1,163 @@
+//! Webhook notifications for server events
+
+use super::{LogMessage, SharedState};
+use std::time::Duration;
+
+/// Retries after a failed first delivery before an event is given up
+const MAX_RETRIES: u32 = 3;
+
+/// Event that triggers a webhook
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum WebhookTrigger {
+    OnAnyRequest,
+    OnUpload,
+    OnAuthFailure,
+    OnError,
+}
+
+impl WebhookTrigger {
+    pub const ALL: [WebhookTrigger; 4] = [
+        WebhookTrigger::OnAnyRequest,
+        WebhookTrigger::OnUpload,
+        WebhookTrigger::OnAuthFailure,
+        WebhookTrigger::OnError,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookTrigger::OnAnyRequest => "Any request",
+            WebhookTrigger::OnUpload => "Upload",
+            WebhookTrigger::OnAuthFailure => "Auth failure",
+            WebhookTrigger::OnError => "Error",
+        }
+    }
+
+    fn event_type(&self) -> &'static str {
+        match self {
+            WebhookTrigger::OnAnyRequest => "request",
+            WebhookTrigger::OnUpload => "upload",
+            WebhookTrigger::OnAuthFailure => "auth_failure",
+            WebhookTrigger::OnError => "error",
+        }
+    }
+}
+
+/// A URL to POST to when an event occurs
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct WebhookConfig {
+    pub trigger: WebhookTrigger,
+    pub url: String,
+    pub include_request_details: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            trigger: WebhookTrigger::OnError,
+            url: String::new(),
+            include_request_details: true,
+        }
+    }
+}
+
+/// Deliver `trigger` to every matching webhook in the background
+pub fn fire(
+    webhooks: &[WebhookConfig],
+    trigger: WebhookTrigger,
+    details: serde_json::Value,
+    state: &SharedState,
+) {
+    for webhook in webhooks.iter().filter(|w| w.trigger == trigger) {
+        let mut payload = serde_json::json!({
+            "event": trigger.event_type(),
+            "timestamp": chrono::Local::now().to_rfc3339(),
+        });
+        if webhook.include_request_details {
+            payload["details"] = details.clone();
+        }
+        let url = webhook.url.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            deliver(&url, &payload, &state).await;
+        });
+    }
+}
+
+/// POST the payload, retrying up to [`MAX_RETRIES`] times with exponential backoff
+pub async fn deliver(url: &str, payload: &serde_json::Value, state: &SharedState) {
+    let client = reqwest::Client::new();
+    let mut backoff = Duration::from_secs(1);
+    let attempts = MAX_RETRIES + 1;
+
+    for attempt in 1..=attempts {
+        let result = client
+            .post(url)
+            .json(payload)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(response) => {
+                state.write().add_log(LogMessage::info(format!(
+                    "Webhook delivered to {} ({})",
+                    url,
+                    response.status()
+                )));
+                return;
+            }
+            Err(e) => {
+                state.write().add_log(LogMessage::error(format!(
+                    "Webhook to {} failed (attempt {}/{}): {}",
+                    url, attempt, attempts, e
+                )));
+            }
+        }
+        if attempt <= MAX_RETRIES {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util;
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn failed_delivery_is_retried_three_times() {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicU32::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let state = test_util::new_state();
+        deliver(&url, &serde_json::json!({"event": "test"}), &state).await;
+
+        assert_eq!(requests.load(Ordering::SeqCst), MAX_RETRIES + 1);
+        let failures = state
+            .read()
+            .logs
+            .iter()
+            .filter(|l| l.to_string().contains("Webhook to"))
+            .count();
+        assert_eq!(failures, (MAX_RETRIES + 1) as usize);
+    }
+}
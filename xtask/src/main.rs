@@ -0,0 +1,107 @@
+//! Development tasks, run with `cargo xtask <task>`
+//!
+//! - `bundle-fonts`: copy a CJK system font to `assets/fonts/`, where
+//!   `build.rs` picks it up and embeds it into the binary
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+/// Font files tried on Windows, Microsoft YaHei first
+const WINDOWS_FONTS: [&str; 4] = [
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\msyhbd.ttc",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+];
+
+/// Font files tried on macOS, PingFang first
+const MACOS_FONTS: [&str; 3] = [
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/STHeiti Medium.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+];
+
+/// Font patterns asked from fontconfig on Linux
+const FONTCONFIG_PATTERNS: [&str; 2] = [":lang=zh-cn:style=Regular", ":lang=zh-cn"];
+
+fn main() -> ExitCode {
+    let task = std::env::args().nth(1);
+    let result = match task.as_deref() {
+        Some("bundle-fonts") => bundle_fonts(),
+        _ => Err("usage: cargo xtask bundle-fonts".to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Workspace root, the parent of this crate
+fn workspace_root() -> PathBuf {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    manifest_dir.parent().unwrap_or(manifest_dir).to_path_buf()
+}
+
+/// Copy the platform's CJK font to `assets/fonts/cjk.<ext>`
+fn bundle_fonts() -> Result<(), String> {
+    let source = find_cjk_font().ok_or("no CJK font found on this system")?;
+    let ext = source
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_else(|| "ttf".to_string());
+    let dir = workspace_root().join("assets").join("fonts");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+
+    // Only one bundled font, a stale one with another extension would win
+    for old in ["ttc", "ttf", "otf"] {
+        let _ = std::fs::remove_file(dir.join(format!("cjk.{}", old)));
+    }
+    let target = dir.join(format!("cjk.{}", ext));
+    std::fs::copy(&source, &target).map_err(|e| {
+        format!(
+            "cannot copy {} to {}: {}",
+            source.display(),
+            target.display(),
+            e
+        )
+    })?;
+    println!("Bundled {} as {}", source.display(), target.display());
+    Ok(())
+}
+
+fn find_cjk_font() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        first_existing(&WINDOWS_FONTS)
+    } else if cfg!(target_os = "macos") {
+        first_existing(&MACOS_FONTS)
+    } else {
+        FONTCONFIG_PATTERNS
+            .iter()
+            .find_map(|pattern| fc_match(pattern))
+    }
+}
+
+fn first_existing(paths: &[&str]) -> Option<PathBuf> {
+    paths.iter().map(PathBuf::from).find(|path| path.is_file())
+}
+
+/// File of the font fontconfig picks for `pattern`, if it covers Chinese
+fn fc_match(pattern: &str) -> Option<PathBuf> {
+    let output = Command::new("fc-match")
+        .args(["--format=%{file}\n%{lang}", pattern])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let (file, langs) = output.split_once('\n')?;
+    // fc-match always answers, with a Latin font when nothing better exists
+    let covers_chinese = langs.split('|').any(|lang| lang.starts_with("zh"));
+    let path = PathBuf::from(file.trim());
+    (covers_chinese && path.is_file()).then_some(path)
+}